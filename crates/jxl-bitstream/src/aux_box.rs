@@ -0,0 +1,205 @@
+use std::io::Read;
+use std::sync::OnceLock;
+
+use jxl_grid::{AllocHandle, AllocTracker};
+
+use crate::{ContainerBoxType, Error, Result};
+
+/// Auxiliary box found in the container, such as `Exif`, `xml ` and `jumb` boxes.
+///
+/// Brotli-compressed (`brob`) boxes are reported with the type of the inner box, and decompressed
+/// when the content is accessed for the first time.
+#[derive(Debug)]
+pub struct AuxBox {
+    ty: ContainerBoxType,
+    raw: Vec<u8>,
+    brotli_compressed: bool,
+    tracker: Option<AllocTracker>,
+    decompressed: OnceLock<Decompressed>,
+}
+
+#[derive(Debug)]
+struct Decompressed {
+    data: Vec<u8>,
+    _handles: Vec<AllocHandle>,
+}
+
+impl AuxBox {
+    pub(crate) fn new(ty: ContainerBoxType, data: Vec<u8>) -> Self {
+        Self {
+            ty,
+            raw: data,
+            brotli_compressed: false,
+            tracker: None,
+            decompressed: OnceLock::new(),
+        }
+    }
+
+    /// Creates a box from the content of a `brob` box, without decompressing it.
+    ///
+    /// Memory used by the decompressed content is recorded to `tracker`, if given, for the
+    /// lifetime of the returned box. If the content is too short to have the inner box type, the
+    /// box is kept as a `brob` box.
+    pub(crate) fn from_brotli_compressed(data: Vec<u8>, tracker: Option<AllocTracker>) -> Self {
+        let &[t0, t1, t2, t3, ..] = &*data else {
+            tracing::warn!("brob box is too short, keeping it as is");
+            return Self::new(ContainerBoxType::BROTLI_COMPRESSED, data);
+        };
+
+        Self {
+            ty: ContainerBoxType([t0, t1, t2, t3]),
+            raw: data,
+            brotli_compressed: true,
+            tracker,
+            decompressed: OnceLock::new(),
+        }
+    }
+
+    fn decompress(&self) -> Result<Decompressed> {
+        let ty = self.ty;
+        if !ty.is_compressible() {
+            tracing::error!(?ty, "Box type cannot be Brotli-compressed");
+            return Err(Error::ValidationFailed(
+                "brob box contains a box type that cannot be compressed",
+            ));
+        }
+
+        const CHUNK_SIZE: usize = 65536;
+        let compressed = &self.raw[4..];
+        let mut decompressor = brotli_decompressor::Decompressor::new(compressed, CHUNK_SIZE);
+        let mut decompressed = Vec::new();
+        let mut handles = Vec::new();
+        let mut tracked_bytes = 0usize;
+        loop {
+            let len = decompressed.len();
+            if let Some(tracker) = &self.tracker {
+                if len + CHUNK_SIZE > tracked_bytes {
+                    // Grow the tracked region exponentially, so that the number of handles stays
+                    // small.
+                    let additional = tracked_bytes.max(CHUNK_SIZE);
+                    handles.push(tracker.alloc::<u8>(additional).map_err(|e| {
+                        tracing::error!(%e, "Brotli-compressed box is too large");
+                        Error::Io(std::io::Error::new(std::io::ErrorKind::OutOfMemory, e))
+                    })?);
+                    tracked_bytes += additional;
+                }
+            }
+
+            decompressed.resize(len + CHUNK_SIZE, 0);
+            let count = decompressor.read(&mut decompressed[len..])?;
+            decompressed.truncate(len + count);
+            if count == 0 {
+                break;
+            }
+        }
+        decompressed.shrink_to_fit();
+        tracing::debug!(
+            ?ty,
+            compressed_size = compressed.len(),
+            decompressed_size = decompressed.len(),
+            "Decompressed brob box"
+        );
+
+        Ok(Decompressed {
+            data: decompressed,
+            _handles: handles,
+        })
+    }
+
+    /// Returns the type of the box.
+    ///
+    /// If the box was Brotli-compressed, the type of the inner box is returned.
+    #[inline]
+    pub fn box_type(&self) -> ContainerBoxType {
+        self.ty
+    }
+
+    /// Returns the content of the box, without box header.
+    ///
+    /// If the box was Brotli-compressed, the decompressed content is returned. Decompression is
+    /// done on the first successful call, and the result is kept with the box.
+    ///
+    /// # Errors
+    /// Returns an error if the Brotli-compressed content cannot be decompressed, the inner box type
+    /// cannot be compressed, or the decompressed content exceeds the allocation limit.
+    pub fn data(&self) -> Result<&[u8]> {
+        if !self.brotli_compressed {
+            return Ok(&self.raw);
+        }
+
+        if let Some(decompressed) = self.decompressed.get() {
+            return Ok(&decompressed.data);
+        }
+        let decompressed = self.decompress()?;
+        Ok(&self.decompressed.get_or_init(|| decompressed).data)
+    }
+
+    /// Returns the content of the box as stored in the container, without box header.
+    ///
+    /// If the box was Brotli-compressed, the content of the `brob` box is returned, which starts
+    /// with the inner box type.
+    #[inline]
+    pub fn raw_data(&self) -> &[u8] {
+        &self.raw
+    }
+
+    /// Returns whether the box was stored as a Brotli-compressed (`brob`) box.
+    #[inline]
+    pub fn is_brotli_compressed(&self) -> bool {
+        self.brotli_compressed
+    }
+}
+
+/// Raw Exif metadata, read from an `Exif` box.
+///
+/// Content of the `Exif` box starts with the offset of the TIFF header, in big-endian 32-bit
+/// unsigned integer. This struct takes care of the offset.
+#[derive(Debug, Copy, Clone)]
+pub struct RawExif<'data> {
+    tiff_header_offset: u32,
+    payload: &'data [u8],
+}
+
+impl<'data> RawExif<'data> {
+    /// Parses the content of an `Exif` box.
+    ///
+    /// # Errors
+    /// Returns `Error::ValidationFailed` if the box is too short, or the TIFF header offset points
+    /// outside of the box.
+    pub fn new(box_data: &'data [u8]) -> Result<Self> {
+        let &[o0, o1, o2, o3, ..] = box_data else {
+            return Err(Error::ValidationFailed("Exif box is too short"));
+        };
+
+        let tiff_header_offset = u32::from_be_bytes([o0, o1, o2, o3]);
+        let payload = &box_data[4..];
+        if tiff_header_offset as usize > payload.len() {
+            return Err(Error::ValidationFailed(
+                "invalid TIFF header offset of Exif box",
+            ));
+        }
+
+        Ok(Self {
+            tiff_header_offset,
+            payload,
+        })
+    }
+
+    /// Returns the offset of the TIFF header, relative to the start of the Exif payload.
+    #[inline]
+    pub fn tiff_header_offset(&self) -> u32 {
+        self.tiff_header_offset
+    }
+
+    /// Returns the whole Exif payload, including bytes before the TIFF header.
+    #[inline]
+    pub fn raw_payload(&self) -> &'data [u8] {
+        self.payload
+    }
+
+    /// Returns the Exif payload, starting from the TIFF header.
+    #[inline]
+    pub fn payload(&self) -> &'data [u8] {
+        &self.payload[self.tiff_header_offset as usize..]
+    }
+}
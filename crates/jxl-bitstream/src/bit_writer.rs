@@ -0,0 +1,259 @@
+use crate::{BundleWrite, Error, Result};
+
+/// Distribution of a `U32` value, used by [`BitWriter::write_u32`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum U32Distribution {
+    /// Constant value, encoded with the selector only.
+    Constant(u32),
+    /// `offset + u(bits)`.
+    BitsOffset { bits: usize, offset: u32 },
+}
+
+impl U32Distribution {
+    /// Returns the number of bits and the raw bits representing `value`, if it can be encoded.
+    #[inline]
+    fn encode(self, value: u32) -> Option<(usize, u32)> {
+        match self {
+            Self::Constant(c) => (value == c).then_some((0, 0)),
+            Self::BitsOffset { bits, offset } => {
+                let raw = value.wrapping_sub(offset);
+                (bits >= 32 || raw >> bits == 0).then_some((bits, raw))
+            }
+        }
+    }
+}
+
+/// Bitstream writer, counterpart of [`Bitstream`][crate::Bitstream].
+///
+/// Bits are packed from the least significant bit of each byte, in the same way `Bitstream` reads
+/// them.
+#[derive(Debug, Clone, Default)]
+pub struct BitWriter {
+    bytes: Vec<u8>,
+    buf: u64,
+    buf_bits: usize,
+}
+
+impl BitWriter {
+    /// Creates a new bit writer with empty buffer.
+    #[inline]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of bits written.
+    #[inline]
+    pub fn num_written_bits(&self) -> usize {
+        self.bytes.len() * 8 + self.buf_bits
+    }
+
+    /// Pads the output with zero bits to byte boundary, and returns the written bytes.
+    pub fn finish(mut self) -> Vec<u8> {
+        self.zero_pad_to_byte();
+        self.bytes
+    }
+}
+
+impl BitWriter {
+    /// Writes lower `n` bits of `value`.
+    ///
+    /// # Errors
+    /// Returns `Error::Unrepresentable` if `value` doesn't fit in `n` bits.
+    #[inline]
+    pub fn write_bits(&mut self, n: usize, value: u32) -> Result<()> {
+        debug_assert!(n <= 32);
+        if n < 32 && value >> n != 0 {
+            return Err(Error::Unrepresentable(
+                "value doesn't fit in the given bits",
+            ));
+        }
+
+        self.buf |= (value as u64) << self.buf_bits;
+        self.buf_bits += n;
+        while self.buf_bits >= 8 {
+            self.bytes.push(self.buf as u8);
+            self.buf >>= 8;
+            self.buf_bits -= 8;
+        }
+        Ok(())
+    }
+
+    /// Performs `ZeroPadToByte` as defined in the JPEG XL specification.
+    #[inline]
+    pub fn zero_pad_to_byte(&mut self) {
+        if self.buf_bits != 0 {
+            self.bytes.push(self.buf as u8);
+            self.buf = 0;
+            self.buf_bits = 0;
+        }
+    }
+}
+
+impl BitWriter {
+    /// Writes a `U32` with the given distributions.
+    ///
+    /// The distribution with the shortest encoding is selected, preferring the first one if
+    /// there are multiple of them.
+    ///
+    /// # Errors
+    /// Returns `Error::Unrepresentable` if none of the distributions can represent `value`.
+    pub fn write_u32(&mut self, value: u32, dists: [U32Distribution; 4]) -> Result<()> {
+        let mut selected = None;
+        for (selector, dist) in dists.into_iter().enumerate() {
+            let Some((bits, raw)) = dist.encode(value) else {
+                continue;
+            };
+            if !matches!(selected, Some((_, best_bits, _)) if best_bits <= bits) {
+                selected = Some((selector as u32, bits, raw));
+            }
+        }
+
+        let (selector, bits, raw) = selected.ok_or(Error::Unrepresentable(
+            "value cannot be represented with U32 distributions",
+        ))?;
+        self.write_bits(2, selector)?;
+        self.write_bits(bits, raw)
+    }
+
+    /// Writes an `U64` as defined in the JPEG XL specification.
+    pub fn write_u64(&mut self, value: u64) -> Result<()> {
+        match value {
+            0 => self.write_bits(2, 0),
+            1..=16 => {
+                self.write_bits(2, 1)?;
+                self.write_bits(4, (value - 1) as u32)
+            }
+            17..=272 => {
+                self.write_bits(2, 2)?;
+                self.write_bits(8, (value - 17) as u32)
+            }
+            _ => {
+                self.write_bits(2, 3)?;
+                self.write_bits(12, (value & 0xfff) as u32)?;
+                let mut value = value >> 12;
+                let mut shift = 12u32;
+                while value > 0 && shift < 60 {
+                    self.write_bits(1, 1)?;
+                    self.write_bits(8, (value & 0xff) as u32)?;
+                    value >>= 8;
+                    shift += 8;
+                }
+                if value > 0 {
+                    self.write_bits(1, 1)?;
+                    self.write_bits(4, value as u32)
+                } else {
+                    self.write_bits(1, 0)
+                }
+            }
+        }
+    }
+
+    /// Writes a `Bool` as defined in the JPEG XL specification.
+    #[inline]
+    pub fn write_bool(&mut self, value: bool) -> Result<()> {
+        self.write_bits(1, value as u32)
+    }
+
+    /// Writes an `f32` as `F16` as defined in the JPEG XL specification, rounding to nearest.
+    ///
+    /// # Errors
+    /// Returns `Error::Unrepresentable` if the value is `NaN`, or if it's out of range of `F16`.
+    pub fn write_f32_as_f16(&mut self, value: f32) -> Result<()> {
+        if !value.is_finite() || value.abs() >= 65520.0 {
+            return Err(Error::Unrepresentable("value is out of range of F16"));
+        }
+
+        let bits = value.to_bits();
+        let sign = (bits >> 16) & 0x8000;
+        let exponent = (bits >> 23) & 0xff;
+        let mantissa = bits & 0x7fffff;
+        let (mut v, rem_bits, rem) = if exponent >= 113 {
+            // Normal
+            let v = ((exponent - 112) << 10) | (mantissa >> 13);
+            (v, 13, mantissa & 0x1fff)
+        } else if exponent >= 102 {
+            // Subnormal
+            let mantissa = mantissa | 0x800000;
+            let shift = 126 - exponent;
+            (mantissa >> shift, shift, mantissa & ((1 << shift) - 1))
+        } else {
+            // Rounds to zero
+            (0, 1, 0)
+        };
+
+        // Round half to even. Carry may propagate to exponent, which is still correct.
+        let half = 1u32 << (rem_bits - 1);
+        if rem > half || (rem == half && v & 1 != 0) {
+            v += 1;
+        }
+        self.write_bits(16, sign | v)
+    }
+
+    #[inline]
+    pub fn write_bundle<B: BundleWrite<()>>(&mut self, bundle: &B) -> Result<()> {
+        bundle.write(self, ())
+    }
+
+    #[inline]
+    pub fn write_bundle_with_ctx<B: BundleWrite<Ctx>, Ctx>(
+        &mut self,
+        bundle: &B,
+        ctx: Ctx,
+    ) -> Result<()> {
+        bundle.write(self, ctx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Bitstream;
+
+    #[test]
+    fn u64_round_trip() {
+        let values = [
+            0,
+            1,
+            16,
+            17,
+            272,
+            273,
+            4095,
+            4096,
+            1 << 20,
+            u64::MAX >> 1,
+            u64::MAX,
+        ];
+        let mut writer = BitWriter::new();
+        for value in values {
+            writer.write_u64(value).unwrap();
+        }
+        let bytes = writer.finish();
+
+        let mut bitstream = Bitstream::new(&bytes);
+        for value in values {
+            assert_eq!(bitstream.read_u64().unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn f16_round_trip() {
+        let mut writer = BitWriter::new();
+        for bits in 0..=0xffffu32 {
+            if (bits >> 10) & 0x1f != 0x1f {
+                writer.write_bits(16, bits).unwrap();
+            }
+        }
+        let bytes = writer.finish();
+
+        let mut bitstream = Bitstream::new(&bytes);
+        let mut writer = BitWriter::new();
+        while let Ok(value) = bitstream.read_f16_as_f32() {
+            writer.write_f32_as_f16(value).unwrap();
+        }
+        let written = writer.finish();
+
+        // Negative zero is written as is.
+        assert_eq!(written, bytes);
+    }
+}
@@ -95,3 +95,20 @@ impl ContainerBoxType {
     pub const PARTIAL_CODESTREAM: Self = Self(*b"jxlp");
     pub const JPEG_RECONSTRUCTION: Self = Self(*b"jbrd");
 }
+
+impl ContainerBoxType {
+    /// Returns whether the box type can be stored in a Brotli-compressed (`brob`) box.
+    pub fn is_compressible(self) -> bool {
+        !matches!(
+            self,
+            Self::JXL
+                | Self::FILE_TYPE
+                | Self::JXL_LEVEL
+                | Self::FRAME_INDEX
+                | Self::CODESTREAM
+                | Self::PARTIAL_CODESTREAM
+                | Self::BROTLI_COMPRESSED
+                | Self::JPEG_RECONSTRUCTION
+        )
+    }
+}
@@ -0,0 +1,328 @@
+use std::io::Write;
+
+use crate::{ContainerBoxType, ContainerDetectingReader, Error, FrameIndex, RawExif, Result};
+
+/// Writer of JPEG XL containers.
+///
+/// The codestream is written as-is, either in a single `jxlc` box or split into multiple `jxlp`
+/// boxes. The `ftyp` box and the optional `jxll` box are written first, followed by auxiliary
+/// boxes in the order they're added, and then the codestream.
+///
+/// # Examples
+/// ```
+/// # use jxl_bitstream::ContainerWriter;
+/// # let codestream = vec![0xff, 0x0a];
+/// let mut writer = ContainerWriter::new(codestream);
+/// writer.set_xmp(Some(b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/>".to_vec())).unwrap();
+/// writer.split_codestream(Some(65536));
+///
+/// let mut out = Vec::new();
+/// writer.write(&mut out).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ContainerWriter {
+    level: Option<u8>,
+    aux_boxes: Vec<(ContainerBoxType, Vec<u8>)>,
+    codestream: Vec<u8>,
+    partial_codestream_size: Option<usize>,
+}
+
+impl ContainerWriter {
+    const CONTAINER_SIG: [u8; 12] = [0, 0, 0, 0xc, b'J', b'X', b'L', b' ', 0xd, 0xa, 0x87, 0xa];
+    const FILE_TYPE: [u8; 12] = *b"jxl \0\0\0\0jxl ";
+
+    /// Creates a writer that wraps the given codestream, without any auxiliary boxes.
+    pub fn new(codestream: Vec<u8>) -> Self {
+        Self {
+            level: None,
+            aux_boxes: Vec::new(),
+            codestream,
+            partial_codestream_size: None,
+        }
+    }
+
+    /// Creates a writer with the codestream and auxiliary boxes read by `reader`.
+    ///
+    /// `codestream` should be the whole codestream read by the reader. Brotli-compressed (`brob`)
+    /// boxes are written uncompressed, except for those which cannot be decompressed, which are
+    /// copied as is. Codestream level is copied from the `jxll` box, if any.
+    pub fn from_reader(reader: &ContainerDetectingReader, codestream: Vec<u8>) -> Self {
+        let mut level = None;
+        let mut aux_boxes = Vec::new();
+        for aux_box in reader.aux_boxes() {
+            let ty = aux_box.box_type();
+            if ty == ContainerBoxType::FILE_TYPE {
+                continue;
+            }
+            if ty == ContainerBoxType::JXL_LEVEL {
+                level = aux_box.data().ok().and_then(|data| data.first().copied());
+                continue;
+            }
+            match aux_box.data() {
+                Ok(data) => aux_boxes.push((ty, data.to_vec())),
+                Err(e) => {
+                    tracing::warn!(%e, ?ty, "Failed to decompress brob box, copying it as is");
+                    let raw = aux_box.raw_data().to_vec();
+                    aux_boxes.push((ContainerBoxType::BROTLI_COMPRESSED, raw));
+                }
+            }
+        }
+
+        Self {
+            level,
+            aux_boxes,
+            codestream,
+            partial_codestream_size: None,
+        }
+    }
+
+    /// Returns the codestream to be written.
+    #[inline]
+    pub fn codestream(&self) -> &[u8] {
+        &self.codestream
+    }
+
+    /// Returns the codestream level to be written in the `jxll` box.
+    #[inline]
+    pub fn level(&self) -> Option<u8> {
+        self.level
+    }
+
+    /// Sets the codestream level written in the `jxll` box. `None` omits the box.
+    ///
+    /// # Errors
+    /// Returns `Error::ValidationFailed` if the level is not 5 or 10.
+    pub fn set_level(&mut self, level: Option<u8>) -> Result<&mut Self> {
+        if !matches!(level, None | Some(5) | Some(10)) {
+            return Err(Error::ValidationFailed("invalid codestream level"));
+        }
+        self.level = level;
+        Ok(self)
+    }
+
+    /// Returns an iterator over auxiliary boxes to be written, in order.
+    pub fn aux_boxes(&self) -> impl Iterator<Item = (ContainerBoxType, &[u8])> + '_ {
+        self.aux_boxes.iter().map(|(ty, data)| (*ty, &**data))
+    }
+
+    /// Appends an auxiliary box.
+    ///
+    /// # Errors
+    /// Returns `Error::ValidationFailed` if the box type is managed by the writer, such as `ftyp`,
+    /// `jxll` and codestream boxes, or has a dedicated setter, such as `jxli` and `jbrd`.
+    /// Brotli-compressed (`brob`) boxes cannot be added either.
+    pub fn add_box(&mut self, ty: ContainerBoxType, data: Vec<u8>) -> Result<&mut Self> {
+        check_aux_box_type(ty)?;
+        self.aux_boxes.push((ty, data));
+        Ok(self)
+    }
+
+    /// Replaces auxiliary boxes of the given type with a single box.
+    ///
+    /// The new box takes the place of the first existing box of the type, or is appended if there
+    /// isn't one.
+    ///
+    /// # Errors
+    /// Returns an error in the same condition as [`add_box`][Self::add_box].
+    pub fn replace_box(&mut self, ty: ContainerBoxType, data: Vec<u8>) -> Result<&mut Self> {
+        check_aux_box_type(ty)?;
+        Ok(self.replace_box_unchecked(ty, data))
+    }
+
+    fn replace_box_unchecked(&mut self, ty: ContainerBoxType, data: Vec<u8>) -> &mut Self {
+        let Some(idx) = self
+            .aux_boxes
+            .iter()
+            .position(|(t, data)| inner_box_type(*t, data) == ty)
+        else {
+            self.aux_boxes.push((ty, data));
+            return self;
+        };
+        self.aux_boxes[idx] = (ty, data);
+        let mut cur = 0usize;
+        self.aux_boxes.retain(|(t, data)| {
+            let keep = inner_box_type(*t, data) != ty || cur == idx;
+            cur += 1;
+            keep
+        });
+        self
+    }
+
+    /// Removes all auxiliary boxes of the given type.
+    pub fn remove_boxes(&mut self, ty: ContainerBoxType) -> &mut Self {
+        self.aux_boxes
+            .retain(|(t, data)| inner_box_type(*t, data) != ty);
+        self
+    }
+
+    /// Sets the content of the `Exif` box, including the TIFF header offset. `None` removes the
+    /// box.
+    ///
+    /// # Errors
+    /// Returns `Error::ValidationFailed` if the content is not a valid `Exif` box.
+    pub fn set_exif(&mut self, exif: Option<Vec<u8>>) -> Result<&mut Self> {
+        match exif {
+            Some(exif) => {
+                RawExif::new(&exif)?;
+                self.replace_box(ContainerBoxType::EXIF, exif)
+            }
+            None => Ok(self.remove_boxes(ContainerBoxType::EXIF)),
+        }
+    }
+
+    /// Sets the XMP packet stored in the `xml ` box. `None` removes the box.
+    ///
+    /// # Errors
+    /// Returns an error in the same condition as [`replace_box`][Self::replace_box], which doesn't
+    /// happen with the `xml ` box.
+    pub fn set_xmp(&mut self, xmp: Option<Vec<u8>>) -> Result<&mut Self> {
+        match xmp {
+            Some(xmp) => self.replace_box(ContainerBoxType::XML, xmp),
+            None => Ok(self.remove_boxes(ContainerBoxType::XML)),
+        }
+    }
+
+    /// Sets the frame index stored in the `jxli` box. `None` removes the box.
+    ///
+    /// Codestream offsets of the frame index are written as-is; they should be consistent with
+    /// the codestream of the writer.
+    pub fn set_frame_index(&mut self, frame_index: Option<&FrameIndex>) -> &mut Self {
+        match frame_index {
+            Some(frame_index) => {
+                self.replace_box_unchecked(ContainerBoxType::FRAME_INDEX, frame_index.to_box_data())
+            }
+            None => self.remove_boxes(ContainerBoxType::FRAME_INDEX),
+        }
+    }
+
+    /// Sets the JPEG bitstream reconstruction data stored in the `jbrd` box. `None` removes the
+    /// box.
+    ///
+    /// The content is written as-is; it should describe the JPEG image recompressed in the
+    /// codestream of the writer.
+    pub fn set_jpeg_reconstruction(&mut self, jbrd: Option<Vec<u8>>) -> &mut Self {
+        match jbrd {
+            Some(jbrd) => self.replace_box_unchecked(ContainerBoxType::JPEG_RECONSTRUCTION, jbrd),
+            None => self.remove_boxes(ContainerBoxType::JPEG_RECONSTRUCTION),
+        }
+    }
+
+    /// Appends a JUMBF (`jumb`) box.
+    pub fn add_jumbf(&mut self, jumbf: Vec<u8>) -> &mut Self {
+        self.aux_boxes.push((ContainerBoxType::JUMBF, jumbf));
+        self
+    }
+
+    /// Removes all Exif, XMP and JUMBF boxes.
+    pub fn strip_metadata(&mut self) -> &mut Self {
+        self.aux_boxes.retain(|(ty, data)| {
+            !matches!(
+                inner_box_type(*ty, data),
+                ContainerBoxType::EXIF | ContainerBoxType::XML | ContainerBoxType::JUMBF
+            )
+        });
+        self
+    }
+
+    /// Sets the maximum size of `jxlp` boxes. `None` writes the codestream in a single `jxlc`
+    /// box.
+    pub fn split_codestream(&mut self, max_size: Option<usize>) -> &mut Self {
+        self.partial_codestream_size = max_size.map(|size| size.max(1));
+        self
+    }
+
+    /// Writes the container to `writer`.
+    pub fn write(&self, mut writer: impl Write) -> std::io::Result<()> {
+        writer.write_all(&Self::CONTAINER_SIG)?;
+        write_box(&mut writer, ContainerBoxType::FILE_TYPE, &Self::FILE_TYPE)?;
+        if let Some(level) = self.level {
+            write_box(&mut writer, ContainerBoxType::JXL_LEVEL, &[level])?;
+        }
+        for (ty, data) in &self.aux_boxes {
+            write_box(&mut writer, *ty, data)?;
+        }
+
+        let Some(max_size) = self.partial_codestream_size else {
+            return write_box(&mut writer, ContainerBoxType::CODESTREAM, &self.codestream);
+        };
+
+        let num_chunks = self.codestream.len().div_ceil(max_size).max(1);
+        if num_chunks > 0x8000_0000 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "too many jxlp boxes",
+            ));
+        }
+        let mut chunks = self.codestream.chunks(max_size);
+        for idx in 0..num_chunks {
+            let chunk = chunks.next().unwrap_or(&[]);
+            let mut index = idx as u32;
+            if idx == num_chunks - 1 {
+                index |= 0x8000_0000;
+            }
+            write_box_header(
+                &mut writer,
+                ContainerBoxType::PARTIAL_CODESTREAM,
+                chunk.len() as u64 + 4,
+            )?;
+            writer.write_all(&index.to_be_bytes())?;
+            writer.write_all(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+/// Checks that boxes of the type can be added with [`ContainerWriter::add_box`].
+fn check_aux_box_type(ty: ContainerBoxType) -> Result<()> {
+    if matches!(
+        ty,
+        ContainerBoxType::JXL
+            | ContainerBoxType::FILE_TYPE
+            | ContainerBoxType::JXL_LEVEL
+            | ContainerBoxType::FRAME_INDEX
+            | ContainerBoxType::CODESTREAM
+            | ContainerBoxType::PARTIAL_CODESTREAM
+            | ContainerBoxType::BROTLI_COMPRESSED
+            | ContainerBoxType::JPEG_RECONSTRUCTION
+    ) {
+        return Err(Error::ValidationFailed(
+            "box type cannot be added as an auxiliary box",
+        ));
+    }
+    Ok(())
+}
+
+/// Returns the type of the box, or the type of the inner box if the box is a `brob` box copied
+/// as is.
+fn inner_box_type(ty: ContainerBoxType, data: &[u8]) -> ContainerBoxType {
+    match (ty, data) {
+        (ContainerBoxType::BROTLI_COMPRESSED, &[t0, t1, t2, t3, ..]) => {
+            ContainerBoxType([t0, t1, t2, t3])
+        }
+        _ => ty,
+    }
+}
+
+fn write_box_header(
+    writer: &mut impl Write,
+    ty: ContainerBoxType,
+    size: u64,
+) -> std::io::Result<()> {
+    match u32::try_from(size + 8) {
+        Ok(sbox) => {
+            writer.write_all(&sbox.to_be_bytes())?;
+            writer.write_all(&ty.0)?;
+        }
+        Err(_) => {
+            writer.write_all(&1u32.to_be_bytes())?;
+            writer.write_all(&ty.0)?;
+            writer.write_all(&(size + 16).to_be_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+fn write_box(writer: &mut impl Write, ty: ContainerBoxType, data: &[u8]) -> std::io::Result<()> {
+    write_box_header(writer, ty, data.len() as u64)?;
+    writer.write_all(data)
+}
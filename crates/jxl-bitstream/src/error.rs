@@ -17,6 +17,11 @@ pub enum Error {
     ValidationFailed(&'static str),
     /// The codestream does not conform to the current decoder profile.
     ProfileConformance(&'static str),
+    /// The codestream exceeds the limits of the codestream level being enforced.
+    LevelLimitExceeded {
+        level: u8,
+        limit: &'static str,
+    },
     /// The name couldn't be parsed as UTF-8 string.
     NonUtf8Name,
     /// The bitstream couldn't be skipped to the given position, mainly due to the direction being
@@ -24,12 +29,119 @@ pub enum Error {
     CannotSkip,
     /// The bistream offsed was not aligned to read byte-aligned data.
     NotAligned,
+    /// The value couldn't be represented in the bitstream.
+    Unrepresentable(&'static str),
+    /// An error with the location in the codestream where it occurred.
+    Located {
+        error: Box<Error>,
+        location: ErrorLocation,
+    },
+}
+
+/// Location in the codestream where an error occurred.
+///
+/// The location consists of the bit offset and the path of bundle fields being parsed, such as
+/// `FrameHeader.restoration_filter.epf`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ErrorLocation {
+    bit_offset: Option<u64>,
+    path: Vec<&'static str>,
+}
+
+impl ErrorLocation {
+    /// Creates a location at the given bit offset, without bundle path.
+    pub fn at_bit(bit_offset: u64) -> Self {
+        Self {
+            bit_offset: Some(bit_offset),
+            path: Vec::new(),
+        }
+    }
+
+    /// Returns the bit offset of the location.
+    ///
+    /// The offset is measured from the beginning of the codestream if the error went through
+    /// the frame decoder, otherwise it's relative to the bitstream being parsed.
+    #[inline]
+    pub fn bit_offset(&self) -> Option<u64> {
+        self.bit_offset
+    }
+
+    /// Returns the path of bundle fields, with the outermost bundle first.
+    #[inline]
+    pub fn path(&self) -> &[&'static str] {
+        &self.path
+    }
+
+    /// Records that the error occurred while parsing `field` of `bundle`, which started at
+    /// `bit_offset`.
+    ///
+    /// The innermost bit offset is kept, as it points to the most specific location.
+    pub fn push_field(&mut self, bundle: &'static str, field: &'static str, bit_offset: usize) {
+        self.bit_offset.get_or_insert(bit_offset as u64);
+        match self.path.first_mut() {
+            // The first element is the name of the inner bundle, which is replaced by the field.
+            Some(first) => *first = field,
+            None => self.path.push(field),
+        }
+        self.path.insert(0, bundle);
+    }
+
+    /// Sets the bit offset if it's not recorded yet.
+    pub fn set_bit_offset_if_missing(&mut self, bit_offset: u64) {
+        self.bit_offset.get_or_insert(bit_offset);
+    }
+
+    /// Adds `base_bits` to the bit offset, making it relative to the outer bitstream.
+    pub fn rebase(&mut self, base_bits: u64) {
+        if let Some(bit_offset) = &mut self.bit_offset {
+            *bit_offset += base_bits;
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if !self.path.is_empty() {
+            write!(f, "{}", self.path.join("."))?;
+            if self.bit_offset.is_some() {
+                write!(f, " ")?;
+            }
+        }
+        if let Some(bit_offset) = self.bit_offset {
+            write!(f, "at bit {} (byte {})", bit_offset, bit_offset / 8)?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors which can record where in the codestream they occurred.
+pub trait LocateError {
+    /// Records that the error occurred while parsing `field` of `bundle`, which started at
+    /// `bit_offset`.
+    fn in_field(self, bundle: &'static str, field: &'static str, bit_offset: usize) -> Self;
+}
+
+impl LocateError for Error {
+    fn in_field(mut self, bundle: &'static str, field: &'static str, bit_offset: usize) -> Self {
+        if let Self::Located { location, .. } = &mut self {
+            location.push_field(bundle, field, bit_offset);
+            return self;
+        }
+
+        let mut location = ErrorLocation::default();
+        location.push_field(bundle, field, bit_offset);
+        Self::Located {
+            error: Box::new(self),
+            location,
+        }
+    }
 }
 
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Self::Io(e) => Some(e),
+            Self::Located { error, .. } => error.source(),
             _ => None,
         }
     }
@@ -57,6 +169,9 @@ impl std::fmt::Display for Error {
             Self::ProfileConformance(msg) => {
                 write!(f, "not supported by current profile: {msg}")
             }
+            Self::LevelLimitExceeded { level, limit } => {
+                write!(f, "codestream exceeds the limits of level {level}: {limit}")
+            }
             Self::NonUtf8Name => {
                 write!(f, "read non-UTF-8 name")
             }
@@ -66,6 +181,12 @@ impl std::fmt::Display for Error {
             Self::NotAligned => {
                 write!(f, "bitstream is unaligned")
             }
+            Self::Unrepresentable(msg) => {
+                write!(f, "value cannot be written to the bitstream: {msg}")
+            }
+            Self::Located { error, location } => {
+                write!(f, "{error} ({location})")
+            }
         }
     }
 }
@@ -78,11 +199,86 @@ impl From<std::io::Error> for Error {
 
 impl Error {
     pub fn unexpected_eof(&self) -> bool {
-        if let Error::Io(e) = self {
+        if let Error::Io(e) = self.inner() {
             return e.kind() == std::io::ErrorKind::UnexpectedEof;
         }
         false
     }
+
+    /// Returns the error without the location.
+    pub fn inner(&self) -> &Error {
+        match self {
+            Self::Located { error, .. } => error.inner(),
+            _ => self,
+        }
+    }
+
+    /// Returns the location where the error occurred, if it's known.
+    pub fn location(&self) -> Option<&ErrorLocation> {
+        match self {
+            Self::Located { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Returns the mutable reference to the location where the error occurred, if it's known.
+    pub fn location_mut(&mut self) -> Option<&mut ErrorLocation> {
+        match self {
+            Self::Located { location, .. } => Some(location),
+            _ => None,
+        }
+    }
+
+    /// Records the bit offset where the error occurred, if it's not recorded yet.
+    pub fn at_bit(mut self, bit_offset: u64) -> Self {
+        if let Some(location) = self.location_mut() {
+            location.set_bit_offset_if_missing(bit_offset);
+            return self;
+        }
+        Self::Located {
+            error: Box::new(self),
+            location: ErrorLocation::at_bit(bit_offset),
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{define_bundle, BitWriter, Bitstream, Bundle};
+
+    define_bundle! {
+        #[derive(Debug, Clone)]
+        struct Inner {
+            value: ty(F16),
+        }
+
+        #[derive(Debug)]
+        struct Outer {
+            flag: ty(Bool),
+            inner: ty(Bundle(Inner)),
+        }
+    }
+
+    #[test]
+    fn bundle_path() {
+        let mut writer = BitWriter::new();
+        writer.write_bool(true).unwrap();
+        // Infinity
+        writer.write_bits(16, 0x7c00).unwrap();
+        let data = writer.finish();
+
+        let mut bitstream = Bitstream::new(&data);
+        let err = Outer::parse(&mut bitstream, ()).unwrap_err();
+        assert!(matches!(err.inner(), Error::InvalidFloat));
+        let location = err.location().unwrap();
+        assert_eq!(location.path(), ["Outer", "inner", "value"]);
+        assert_eq!(location.bit_offset(), Some(1));
+        assert_eq!(
+            err.to_string(),
+            "F16() read NaN or Infinity (Outer.inner.value at bit 1 (byte 0))"
+        );
+    }
+}
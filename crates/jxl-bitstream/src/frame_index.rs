@@ -0,0 +1,213 @@
+use crate::{Error, Result};
+
+/// Frame index, read from a `jxli` box.
+///
+/// Frame index lists frames that can be decoded without decoding the frames before them, which
+/// can be used to seek into the middle of an animation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FrameIndex {
+    tnum: u32,
+    tden: u32,
+    entries: Vec<FrameIndexEntry>,
+}
+
+/// An indexed frame in the [`FrameIndex`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameIndexEntry {
+    codestream_offset: u64,
+    duration_ticks: u64,
+    num_frames: u64,
+    start_ticks: u64,
+    keyframe_offset: u64,
+}
+
+impl FrameIndex {
+    /// Parses the content of a `jxli` box.
+    ///
+    /// # Errors
+    /// Returns `Error::ValidationFailed` if the box is truncated or contains invalid values.
+    pub fn parse(box_data: &[u8]) -> Result<Self> {
+        let mut data = box_data;
+        let nf = read_varint(&mut data)?;
+        let (tnum, tden) = match *data {
+            [n0, n1, n2, n3, d0, d1, d2, d3, ..] => {
+                data = &data[8..];
+                (
+                    u32::from_be_bytes([n0, n1, n2, n3]),
+                    u32::from_be_bytes([d0, d1, d2, d3]),
+                )
+            }
+            _ => return Err(Error::ValidationFailed("jxli box is too short")),
+        };
+        if tnum == 0 || tden == 0 {
+            return Err(Error::ValidationFailed("invalid tick unit of jxli box"));
+        }
+
+        // Each entry takes at least three bytes.
+        if nf > (data.len() / 3) as u64 {
+            return Err(Error::ValidationFailed("jxli box is too short"));
+        }
+
+        let mut entries = Vec::with_capacity(nf as usize);
+        let mut codestream_offset = 0u64;
+        let mut start_ticks = 0u64;
+        let mut keyframe_offset = 0u64;
+        for _ in 0..nf {
+            let off = read_varint(&mut data)?;
+            let duration_ticks = read_varint(&mut data)?;
+            let num_frames = read_varint(&mut data)?;
+            if !entries.is_empty() && off == 0 {
+                return Err(Error::ValidationFailed(
+                    "frame offsets in jxli box are not increasing",
+                ));
+            }
+
+            codestream_offset = codestream_offset
+                .checked_add(off)
+                .ok_or(Error::ValidationFailed("frame offset overflow"))?;
+            entries.push(FrameIndexEntry {
+                codestream_offset,
+                duration_ticks,
+                num_frames,
+                start_ticks,
+                keyframe_offset,
+            });
+            start_ticks = start_ticks.saturating_add(duration_ticks);
+            keyframe_offset = keyframe_offset.saturating_add(num_frames);
+        }
+
+        if !data.is_empty() {
+            tracing::warn!(len = data.len(), "Trailing data in jxli box");
+        }
+
+        Ok(Self {
+            tnum,
+            tden,
+            entries,
+        })
+    }
+
+    /// Encodes the frame index into the content of a `jxli` box.
+    pub fn to_box_data(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        write_varint(&mut out, self.entries.len() as u64);
+        out.extend_from_slice(&self.tnum.to_be_bytes());
+        out.extend_from_slice(&self.tden.to_be_bytes());
+        let mut prev_offset = 0u64;
+        for entry in &self.entries {
+            write_varint(&mut out, entry.codestream_offset - prev_offset);
+            write_varint(&mut out, entry.duration_ticks);
+            write_varint(&mut out, entry.num_frames);
+            prev_offset = entry.codestream_offset;
+        }
+        out
+    }
+
+    /// Moves codestream offsets of all indexed frames by `delta` bytes.
+    ///
+    /// This can be used to keep the index valid after the image header is resized.
+    ///
+    /// # Errors
+    /// Returns `Error::ValidationFailed` if an offset goes out of range.
+    pub fn shift_codestream_offsets(&mut self, delta: i64) -> Result<()> {
+        for entry in &mut self.entries {
+            entry.codestream_offset = entry
+                .codestream_offset
+                .checked_add_signed(delta)
+                .ok_or(Error::ValidationFailed("frame offset out of range"))?;
+        }
+        Ok(())
+    }
+
+    /// Returns the numerator of the tick unit, in seconds.
+    #[inline]
+    pub fn tnum(&self) -> u32 {
+        self.tnum
+    }
+
+    /// Returns the denominator of the tick unit, in seconds.
+    #[inline]
+    pub fn tden(&self) -> u32 {
+        self.tden
+    }
+
+    /// Returns the list of indexed frames, in the order they appear in the codestream.
+    #[inline]
+    pub fn entries(&self) -> &[FrameIndexEntry] {
+        &self.entries
+    }
+
+    /// Returns the last indexed frame whose keyframe offset is not greater than
+    /// `keyframe_offset`.
+    pub fn nearest_entry(&self, keyframe_offset: u64) -> Option<&FrameIndexEntry> {
+        let idx = self
+            .entries
+            .partition_point(|entry| entry.keyframe_offset <= keyframe_offset);
+        idx.checked_sub(1).map(|idx| &self.entries[idx])
+    }
+}
+
+impl FrameIndexEntry {
+    /// Returns the offset of the frame, in bytes, from the start of the codestream.
+    #[inline]
+    pub fn codestream_offset(&self) -> u64 {
+        self.codestream_offset
+    }
+
+    /// Returns the duration in ticks between the start of this frame and the start of the next
+    /// indexed frame, or the end of the stream if this is the last one.
+    #[inline]
+    pub fn duration_ticks(&self) -> u64 {
+        self.duration_ticks
+    }
+
+    /// Returns the number of displayed frames between this frame and the next indexed frame, or
+    /// the end of the stream if this is the last one.
+    #[inline]
+    pub fn num_frames(&self) -> u64 {
+        self.num_frames
+    }
+
+    /// Returns the start time of this frame in ticks, relative to the first indexed frame.
+    #[inline]
+    pub fn start_ticks(&self) -> u64 {
+        self.start_ticks
+    }
+
+    /// Returns the number of displayed frames between the first indexed frame and this frame.
+    ///
+    /// If the first indexed frame is the first keyframe of the image, this is the keyframe index
+    /// of this frame.
+    #[inline]
+    pub fn keyframe_offset(&self) -> u64 {
+        self.keyframe_offset
+    }
+}
+
+fn read_varint(data: &mut &[u8]) -> Result<u64> {
+    let mut value = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&b, rest) = data
+            .split_first()
+            .ok_or(Error::ValidationFailed("jxli box is too short"))?;
+        *data = rest;
+
+        let bits = (b & 0x7f) as u64;
+        if shift == 63 && bits > 1 {
+            return Err(Error::ValidationFailed("varint overflow"));
+        }
+        value |= bits << shift;
+        if b & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    Err(Error::ValidationFailed("varint overflow"))
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
@@ -1,17 +1,25 @@
-//! This crate provides a JPEG XL bitstream reader and helper macros. The bitstream reader supports both
+//! This crate provides a JPEG XL bitstream reader and writer, and helper macros. The bitstream reader supports both
 //! bare codestream and container format, and it can detect which format to read.
 
+mod aux_box;
+mod bit_writer;
 mod container;
+mod container_writer;
 mod error;
+mod frame_index;
 mod macros;
 mod memory;
 mod reader;
 
+pub use aux_box::{AuxBox, RawExif};
+pub use bit_writer::{BitWriter, U32Distribution};
 pub use container::*;
-pub use error::{Error, Result};
-pub use macros::{unpack_signed, unpack_signed_u64};
+pub use container_writer::ContainerWriter;
+pub use error::{Error, ErrorLocation, LocateError, Result};
+pub use frame_index::{FrameIndex, FrameIndexEntry};
+pub use macros::{pack_signed, pack_signed_u64, unpack_signed, unpack_signed_u64};
 pub use memory::Bitstream;
-pub use reader::ContainerDetectingReader;
+pub use reader::{BitstreamKind, ContainerDetectingReader};
 
 pub trait Bundle<Ctx = ()>: Sized {
     type Error;
@@ -20,6 +28,15 @@ pub trait Bundle<Ctx = ()>: Sized {
     fn parse(bitstream: &mut Bitstream<'_>, ctx: Ctx) -> std::result::Result<Self, Self::Error>;
 }
 
+/// Bundle types which can be written to the bitstream.
+pub trait BundleWrite<Ctx = ()> {
+    /// Writes the value to the bit writer with the given context.
+    ///
+    /// Fields whose conditions don't hold are not written, so they will be read back as their
+    /// default values.
+    fn write(&self, writer: &mut BitWriter, ctx: Ctx) -> Result<()>;
+}
+
 pub trait BundleDefault<Ctx = ()>: Sized {
     /// Creates a default value with the given context.
     fn default_with_context(ctx: Ctx) -> Self;
@@ -45,6 +62,18 @@ where
     }
 }
 
+impl<T, Ctx> BundleWrite<Ctx> for Option<T>
+where
+    T: BundleWrite<Ctx>,
+{
+    fn write(&self, writer: &mut BitWriter, ctx: Ctx) -> Result<()> {
+        match self {
+            Some(value) => value.write(writer, ctx),
+            None => Err(Error::Unrepresentable("cannot write absent bundle")),
+        }
+    }
+}
+
 /// Name type which is read by some JPEG XL headers.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
 pub struct Name(String);
@@ -63,6 +92,17 @@ impl<Ctx> Bundle<Ctx> for Name {
     }
 }
 
+impl<Ctx> BundleWrite<Ctx> for Name {
+    fn write(&self, writer: &mut BitWriter, _: Ctx) -> Result<()> {
+        let len = self.0.len() as u32;
+        write_bits!(writer, U32(0, u(4), 16 + u(5), 48 + u(10)), &len)?;
+        for &b in self.0.as_bytes() {
+            writer.write_bits(8, b as u32)?;
+        }
+        Ok(())
+    }
+}
+
 impl std::ops::Deref for Name {
     type Target = String;
 
@@ -106,6 +106,107 @@ macro_rules! read_bits {
     };
 }
 
+#[macro_export]
+macro_rules! expand_u32_distributions {
+    (@acc [$($acc:expr,)*];) => {
+        [$($acc,)*]
+    };
+    (@acc [$($acc:expr,)*]; $c:literal, $($rest:tt)*) => {
+        $crate::expand_u32_distributions!(
+            @acc [$($acc,)* $crate::U32Distribution::Constant($c),]; $($rest)*
+        )
+    };
+    (@acc [$($acc:expr,)*]; u($n:literal), $($rest:tt)*) => {
+        $crate::expand_u32_distributions!(
+            @acc [$($acc,)* $crate::U32Distribution::BitsOffset { bits: $n, offset: 0 },]; $($rest)*
+        )
+    };
+    (@acc [$($acc:expr,)*]; $c:literal + u($n:literal), $($rest:tt)*) => {
+        $crate::expand_u32_distributions!(
+            @acc [$($acc,)* $crate::U32Distribution::BitsOffset { bits: $n, offset: $c },]; $($rest)*
+        )
+    };
+    ($($args:tt)*) => {
+        $crate::expand_u32_distributions!(@acc []; $($args)*,)
+    };
+}
+
+/// Writes a value of the given type to the [`BitWriter`][crate::BitWriter].
+///
+/// Value should be given as a reference. This is the counterpart of [`read_bits`].
+#[macro_export]
+macro_rules! write_bits {
+    ($writer:ident, $c:literal, $value:expr $(, $ctx:expr)?) => {
+        if *$value == $c {
+            $crate::Result::Ok(())
+        } else {
+            $crate::Result::Err($crate::Error::Unrepresentable("value differs from the constant"))
+        }
+    };
+    ($writer:ident, u($n:literal), $value:expr $(, $ctx:expr)?) => {
+        $writer.write_bits($n, *$value)
+    };
+    ($writer:ident, u($n:literal); UnpackSigned, $value:expr $(, $ctx:expr)?) => {
+        $writer.write_bits($n, $crate::pack_signed(*$value))
+    };
+    ($writer:ident, $c:literal + u($n:literal), $value:expr $(, $ctx:expr)?) => {
+        $writer.write_bits($n, (*$value).wrapping_sub($c))
+    };
+    ($writer:ident, $c:literal + u($n:literal); UnpackSigned, $value:expr $(, $ctx:expr)?) => {
+        $writer.write_bits($n, $crate::pack_signed(*$value).wrapping_sub($c))
+    };
+    ($writer:ident, U32($($args:tt)+), $value:expr $(, $ctx:expr)?) => {
+        $writer.write_u32(*$value, $crate::expand_u32_distributions!($($args)+))
+    };
+    ($writer:ident, U32($($args:tt)+); UnpackSigned, $value:expr $(, $ctx:expr)?) => {
+        $writer.write_u32(
+            $crate::pack_signed(*$value),
+            $crate::expand_u32_distributions!($($args)+),
+        )
+    };
+    ($writer:ident, U64, $value:expr $(, $ctx:expr)?) => {
+        $writer.write_u64(*$value)
+    };
+    ($writer:ident, U64; UnpackSigned, $value:expr $(, $ctx:expr)?) => {
+        $writer.write_u64($crate::pack_signed_u64(*$value))
+    };
+    ($writer:ident, F16, $value:expr $(, $ctx:expr)?) => {
+        $writer.write_f32_as_f16(*$value)
+    };
+    ($writer:ident, Bool, $value:expr $(, $ctx:expr)?) => {
+        $writer.write_bool(*$value)
+    };
+    ($writer:ident, Enum($enumtype:ty), $value:expr $(, $ctx:expr)?) => {
+        $crate::write_bits!($writer, U32(0, 1, 2 + u(4), 18 + u(6)), &(*$value as u32))
+    };
+    ($writer:ident, ZeroPadToByte) => {
+        {
+            $writer.zero_pad_to_byte();
+            $crate::Result::Ok(())
+        }
+    };
+    ($writer:ident, Bundle($bundle:ty), $value:expr) => {
+        $writer.write_bundle::<$bundle>($value)
+    };
+    ($writer:ident, Bundle($bundle:ty), $value:expr, $ctx:expr) => {
+        $writer.write_bundle_with_ctx::<$bundle, _>($value, $ctx)
+    };
+    ($writer:ident, Vec[$($inner:tt)*]; $count:expr, $value:expr $(, $ctx:expr)?) => {
+        if $value.len() == $count as usize {
+            $value
+                .iter()
+                .try_for_each(|item| $crate::write_bits!($writer, $($inner)*, item $(, $ctx)?))
+        } else {
+            $crate::Result::Err($crate::Error::Unrepresentable("length of Vec differs from the count"))
+        }
+    };
+    ($writer:ident, Array[$($inner:tt)*]; $count:expr, $value:expr $(, $ctx:expr)?) => {
+        $value
+            .iter()
+            .try_for_each(|item| $crate::write_bits!($writer, $($inner)*, item $(, $ctx)?))
+    };
+}
+
 #[macro_export]
 macro_rules! make_def {
     (@ty; $c:literal) => { u32 };
@@ -135,22 +236,35 @@ macro_rules! make_def {
 
 #[macro_export]
 macro_rules! make_parse {
-    (@parse $bitstream:ident; cond($cond:expr); default($def_expr:expr); ty($($spec:tt)*); ctx($ctx:expr)) => {
+    (@parse $bitstream:ident; loc($($loc:tt)*); cond($cond:expr); default($def_expr:expr); ty($($spec:tt)*); ctx($ctx:expr)) => {
         if $cond {
-            $crate::read_bits!($bitstream, $($spec)*, $ctx)?
+            $crate::make_parse!(@read $bitstream; loc($($loc)*); ty($($spec)*); ctx($ctx))
         } else {
             $def_expr
         }
     };
-    (@parse $bitstream:ident; cond($cond:expr); ty($($spec:tt)*); ctx($ctx:expr)) => {
+    (@parse $bitstream:ident; loc($($loc:tt)*); cond($cond:expr); ty($($spec:tt)*); ctx($ctx:expr)) => {
         if $cond {
-            $crate::read_bits!($bitstream, $($spec)*, $ctx)?
+            $crate::make_parse!(@read $bitstream; loc($($loc)*); ty($($spec)*); ctx($ctx))
         } else {
             $crate::BundleDefault::default_with_context($ctx)
         }
     };
-    (@parse $bitstream:ident; $(default($def_expr:expr);)? ty($($spec:tt)*); ctx($ctx:expr)) => {
-        $crate::read_bits!($bitstream, $($spec)*, $ctx)?
+    (@parse $bitstream:ident; loc($($loc:tt)*); $(default($def_expr:expr);)? ty($($spec:tt)*); ctx($ctx:expr)) => {
+        $crate::make_parse!(@read $bitstream; loc($($loc)*); ty($($spec)*); ctx($ctx))
+    };
+    (@read $bitstream:ident; loc($bundle_name:ident, $field:ident); ty($($spec:tt)*); ctx($ctx:expr)) => {
+        {
+            let bit_offset = $bitstream.num_read_bits();
+            $crate::read_bits!($bitstream, $($spec)*, $ctx).map_err(|e| {
+                $crate::LocateError::in_field(
+                    <Self::Error as ::std::convert::From<_>>::from(e),
+                    stringify!($bundle_name),
+                    stringify!($field),
+                    bit_offset,
+                )
+            })?
+        }
     };
     (@default; ; $ctx:expr) => {
         $crate::BundleDefault::default_with_context($ctx)
@@ -181,6 +295,7 @@ macro_rules! make_parse {
                 $(
                     let $field: $crate::make_def!(@ty; $($expr)*) = $crate::make_parse!(
                         @parse bitstream;
+                        loc($bundle_name, $field);
                         $(cond($cond);)?
                         $(default($def_expr);)?
                         ty($($expr)*);
@@ -216,6 +331,7 @@ macro_rules! make_parse {
                 $(
                     let $field: $crate::make_def!(@ty; $($expr)*) = $crate::make_parse!(
                         @parse bitstream;
+                        loc($bundle_name, $field);
                         $(cond($cond);)?
                         $(default($def_expr);)?
                         ty($($expr)*);
@@ -242,6 +358,60 @@ macro_rules! make_parse {
     };
 }
 
+#[macro_export]
+macro_rules! make_write {
+    (@write $writer:ident; cond($cond:expr); ty($($spec:tt)*); value($value:expr); ctx($ctx:expr)) => {
+        if $cond {
+            $crate::write_bits!($writer, $($spec)*, $value, $ctx)?;
+        }
+    };
+    (@write $writer:ident; ty($($spec:tt)*); value($value:expr); ctx($ctx:expr)) => {
+        $crate::write_bits!($writer, $($spec)*, $value, $ctx)?;
+    };
+    ($bundle_name:ident {
+        $($(#[$fieldattrs:meta])* $v:vis $field:ident: ty($($expr:tt)*) $(ctx($ctx_for_field:expr))? $(cond($cond:expr))? $(default($def_expr:expr))? ,)*
+    }) => {
+        impl<Ctx: Copy> $crate::BundleWrite<Ctx> for $bundle_name {
+            #[allow(unused_variables, clippy::clone_on_copy)]
+            fn write(&self, writer: &mut $crate::BitWriter, ctx: Ctx) -> $crate::Result<()> {
+                $(
+                    $crate::make_write!(
+                        @write writer;
+                        $(cond($cond);)?
+                        ty($($expr)*);
+                        value(&self.$field);
+                        ctx($crate::make_parse!(@select_ctx; ctx; $($ctx_for_field)?))
+                    );
+                    let $field: $crate::make_def!(@ty; $($expr)*) =
+                        ::std::clone::Clone::clone(&self.$field);
+                )*
+                Ok(())
+            }
+        }
+    };
+    ($bundle_name:ident ctx($ctx_id:ident : $ctx:ty) {
+        $($(#[$fieldattrs:meta])* $v:vis $field:ident: ty($($expr:tt)*) $(ctx($ctx_for_field:expr))? $(cond($cond:expr))? $(default($def_expr:expr))? ,)*
+    }) => {
+        impl $crate::BundleWrite<$ctx> for $bundle_name {
+            #[allow(unused_variables, clippy::clone_on_copy)]
+            fn write(&self, writer: &mut $crate::BitWriter, $ctx_id: $ctx) -> $crate::Result<()> {
+                $(
+                    $crate::make_write!(
+                        @write writer;
+                        $(cond($cond);)?
+                        ty($($expr)*);
+                        value(&self.$field);
+                        ctx($crate::make_parse!(@select_ctx; $ctx_id; $($ctx_for_field)?))
+                    );
+                    let $field: $crate::make_def!(@ty; $($expr)*) =
+                        ::std::clone::Clone::clone(&self.$field);
+                )*
+                Ok(())
+            }
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! define_bundle {
     (
@@ -259,6 +429,7 @@ macro_rules! define_bundle {
         $(
             $crate::make_def!($(#[$attrs])* $v struct $bundle_name { $($body)* });
             $crate::make_parse!($bundle_name $(aligned($aligned))? $(ctx($ctx_id: $ctx))? $(error($err))? { $($body)* });
+            $crate::make_write!($bundle_name $(ctx($ctx_id: $ctx))? { $($body)* });
         )*
     };
 }
@@ -280,3 +451,15 @@ pub fn unpack_signed_u64(x: u64) -> i64 {
     let flip = 0u64.wrapping_sub(bit);
     (base ^ flip) as i64
 }
+
+/// Perform the inverse of `UnpackSigned` for `i32`.
+#[inline]
+pub fn pack_signed(x: i32) -> u32 {
+    ((x << 1) ^ (x >> 31)) as u32
+}
+
+/// Perform the inverse of `UnpackSigned` for `i64`.
+#[inline]
+pub fn pack_signed_u64(x: i64) -> u64 {
+    ((x << 1) ^ (x >> 63)) as u64
+}
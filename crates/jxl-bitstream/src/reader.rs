@@ -1,4 +1,7 @@
+use jxl_grid::AllocTracker;
+
 use super::container::*;
+use crate::AuxBox;
 
 /// Wrapper that detects container format from underlying reader.
 #[derive(Default)]
@@ -6,8 +9,9 @@ pub struct ContainerDetectingReader {
     state: DetectState,
     buf: Vec<u8>,
     codestream: Vec<u8>,
-    aux_boxes: Vec<(ContainerBoxType, Vec<u8>)>,
+    aux_boxes: Vec<AuxBox>,
     next_jxlp_index: u32,
+    tracker: Option<AllocTracker>,
 }
 
 impl std::fmt::Debug for ContainerDetectingReader {
@@ -58,6 +62,14 @@ impl ContainerDetectingReader {
         Self::default()
     }
 
+    /// Creates a reader which records memory used by decompressed `brob` boxes to `tracker`.
+    pub fn with_alloc_tracker(tracker: Option<AllocTracker>) -> Self {
+        Self {
+            tracker,
+            ..Self::default()
+        }
+    }
+
     pub fn kind(&self) -> BitstreamKind {
         match self.state {
             DetectState::WaitingSignature => BitstreamKind::Unknown,
@@ -229,8 +241,12 @@ impl ContainerDetectingReader {
                 } => {
                     if *bytes_left <= buf.len() {
                         data.extend(buf.drain(..*bytes_left));
-                        self.aux_boxes
-                            .push((header.box_type(), std::mem::take(data)));
+                        let aux_box = Self::create_aux_box(
+                            header.box_type(),
+                            std::mem::take(data),
+                            self.tracker.as_ref(),
+                        );
+                        self.aux_boxes.push(aux_box);
                         *state = DetectState::WaitingBoxHeader;
                     } else {
                         *bytes_left -= buf.len();
@@ -244,15 +260,49 @@ impl ContainerDetectingReader {
         }
     }
 
+    /// Returns the auxiliary boxes read so far, in the order they appear in the container.
+    ///
+    /// Boxes are added to the list after they're fully read. The last box without explicit size
+    /// is added when [`finish`][Self::finish] is called.
+    #[inline]
+    pub fn aux_boxes(&self) -> &[AuxBox] {
+        &self.aux_boxes
+    }
+
+    /// Returns the first auxiliary box of the given type, if any.
+    pub fn find_aux_box(&self, ty: ContainerBoxType) -> Option<&AuxBox> {
+        self.aux_boxes.iter().find(|b| b.box_type() == ty)
+    }
+
     pub fn take_bytes(&mut self) -> Vec<u8> {
         std::mem::take(&mut self.codestream)
     }
 
-    pub fn finish(&mut self) {
-        if let DetectState::InAuxBox { header, data, .. } = &mut self.state {
-            self.aux_boxes
-                .push((header.box_type(), std::mem::take(data)));
+    /// Signals the end of the input, and finishes reading the last box.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        let kind = self.kind();
+        let state = std::mem::replace(&mut self.state, DetectState::Done(kind));
+        if let DetectState::InAuxBox {
+            header,
+            data,
+            bytes_left: None,
+        } = state
+        {
+            let aux_box = Self::create_aux_box(header.box_type(), data, self.tracker.as_ref());
+            self.aux_boxes.push(aux_box);
+        }
+        Ok(())
+    }
+
+    fn create_aux_box(
+        ty: ContainerBoxType,
+        data: Vec<u8>,
+        tracker: Option<&AllocTracker>,
+    ) -> AuxBox {
+        if ty == ContainerBoxType::BROTLI_COMPRESSED {
+            AuxBox::from_brotli_compressed(data, tracker.cloned())
+        } else {
+            AuxBox::new(ty, data)
         }
-        self.state = DetectState::Done(self.kind());
     }
 }
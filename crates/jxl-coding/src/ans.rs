@@ -24,19 +24,8 @@ struct Bucket {
 impl Histogram {
     // log_alphabet_size: 5 + u(2)
     pub fn parse(bitstream: &mut Bitstream, log_alphabet_size: u32) -> Result<Self> {
-        #[derive(Debug)]
-        struct WorkingBucket {
-            dist: u16,
-            alias_symbol: u16,
-            alias_offset: u16,
-            alias_cutoff: u16,
-        }
-
         debug_assert!((5..=8).contains(&log_alphabet_size));
         let table_size = (1u16 << log_alphabet_size) as usize;
-        // 4 <= log_bucket_size <= 7
-        let log_bucket_size = 12 - log_alphabet_size;
-        let bucket_size = 1u16 << log_bucket_size;
 
         let alphabet_size;
         let mut dist = vec![0u16; table_size];
@@ -172,6 +161,23 @@ impl Histogram {
             dist[omit_pos] = (1 << 12) - acc;
         }
 
+        Ok(Self::from_dist(dist, alphabet_size, log_alphabet_size))
+    }
+
+    /// Builds alias tables from the distribution of which the sum is `1 << 12`.
+    pub(crate) fn from_dist(dist: Vec<u16>, alphabet_size: usize, log_alphabet_size: u32) -> Self {
+        #[derive(Debug)]
+        struct WorkingBucket {
+            dist: u16,
+            alias_symbol: u16,
+            alias_offset: u16,
+            alias_cutoff: u16,
+        }
+
+        // 4 <= log_bucket_size <= 7
+        let log_bucket_size = 12 - log_alphabet_size;
+        let bucket_size = 1u16 << log_bucket_size;
+
         if let Some(single_sym_idx) = dist.iter().position(|&d| d == 1 << 12) {
             let buckets = dist
                 .into_iter()
@@ -184,11 +190,11 @@ impl Histogram {
                     alias_dist_xor: dist ^ (1 << 12),
                 })
                 .collect();
-            return Ok(Self {
+            return Self {
                 buckets,
                 log_bucket_size,
                 single_symbol: Some(single_sym_idx as u16),
-            });
+            };
         }
 
         let mut buckets: Vec<_> = dist
@@ -247,11 +253,16 @@ impl Histogram {
             })
             .collect();
 
-        Ok(Self {
+        Self {
             buckets,
             log_bucket_size,
             single_symbol: None,
-        })
+        }
+    }
+
+    /// Returns the distribution of symbols, with frequencies summing up to `1 << 12`.
+    pub fn dist(&self) -> Vec<u16> {
+        self.buckets.iter().map(|bucket| bucket.dist).collect()
     }
 
     fn read_u8(bitstream: &mut Bitstream) -> Result<u8> {
@@ -334,6 +345,27 @@ impl Histogram {
     pub fn single_symbol(&self) -> Option<u16> {
         self.single_symbol
     }
+
+    /// Returns the state index for each `(symbol, offset)` pair, used by the encoder.
+    ///
+    /// Entries are grouped by symbol, and the `offset`-th entry of each symbol group is the state
+    /// index which is mapped to the symbol with the given offset.
+    pub(crate) fn encoding_slots(&self, dist: &[u16]) -> Vec<u16> {
+        let mut group_start = Vec::with_capacity(dist.len());
+        let mut acc = 0u16;
+        for &d in dist {
+            group_start.push(acc);
+            acc += d;
+        }
+
+        let mut slots = vec![0u16; 1 << 12];
+        for idx in 0..(1u32 << 12) {
+            // SAFETY: idx is 12 bits.
+            let (symbol, offset, _) = unsafe { self.map_alias(idx) };
+            slots[(group_start[symbol as usize] as u32 + offset) as usize] = idx as u16;
+        }
+        slots
+    }
 }
 
 fn read_prefix(bitstream: &mut Bitstream) -> Result<u16> {
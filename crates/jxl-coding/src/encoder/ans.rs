@@ -0,0 +1,351 @@
+//! ANS distribution and stream, encoder side
+use jxl_bitstream::BitWriter;
+
+use crate::ans::Histogram;
+use crate::Result;
+
+const TOTAL: u32 = 1 << 12;
+
+#[derive(Debug, Clone)]
+enum Header {
+    Single(usize),
+    Binary(usize, usize),
+    Compressed {
+        shift: i32,
+        omit_pos: usize,
+        alphabet_size: usize,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct AnsCode {
+    header: Header,
+    dist: Vec<u16>,
+    group_start: Vec<u16>,
+    slots: Vec<u16>,
+}
+
+impl AnsCode {
+    pub fn build(histogram: &[u32], log_alphabet_size: u32) -> Self {
+        let table_size = 1usize << log_alphabet_size;
+        let nonzero = histogram
+            .iter()
+            .enumerate()
+            .filter(|(_, &c)| c != 0)
+            .map(|(sym, _)| sym)
+            .collect::<Vec<_>>();
+        let total = histogram.iter().map(|&c| c as u64).sum::<u64>();
+
+        let mut dist = vec![0u16; table_size];
+        let (header, alphabet_size) = match *nonzero {
+            [] => {
+                dist[0] = TOTAL as u16;
+                (Header::Single(0), 1)
+            }
+            [sym] => {
+                dist[sym] = TOTAL as u16;
+                (Header::Single(sym), sym + 1)
+            }
+            [v0, v1] => {
+                let prob = (histogram[v0] as u64 * TOTAL as u64 + total / 2) / total;
+                let prob = prob.clamp(1, TOTAL as u64 - 1) as u16;
+                dist[v0] = prob;
+                dist[v1] = TOTAL as u16 - prob;
+                (Header::Binary(v0, v1), v1 + 1)
+            }
+            _ => {
+                let alphabet_size = (nonzero.last().unwrap() + 1).max(3);
+                let (shift, omit_pos, quantized) = (0..=13)
+                    .filter_map(|shift| {
+                        let (omit_pos, quantized) = quantize(histogram, total, shift)?;
+                        let mut scratch = BitWriter::new();
+                        write_compressed(&mut scratch, &quantized, shift, omit_pos, alphabet_size)
+                            .ok()?;
+                        let data_cost = histogram
+                            .iter()
+                            .zip(&quantized)
+                            .filter(|(&c, _)| c != 0)
+                            .map(|(&c, &q)| c as f64 * (TOTAL as f64 / q as f64).log2())
+                            .sum::<f64>();
+                        let cost = data_cost + scratch.num_written_bits() as f64;
+                        Some((cost, shift, omit_pos, quantized))
+                    })
+                    .min_by(|a, b| a.0.total_cmp(&b.0))
+                    .map(|(_, shift, omit_pos, quantized)| (shift, omit_pos, quantized))
+                    .expect("distribution should be quantized with full precision");
+                dist[..quantized.len()].copy_from_slice(&quantized);
+                let header = Header::Compressed {
+                    shift,
+                    omit_pos,
+                    alphabet_size,
+                };
+                (header, alphabet_size)
+            }
+        };
+
+        let decoder_histogram =
+            Histogram::from_dist(dist.clone(), alphabet_size, log_alphabet_size);
+        let slots = decoder_histogram.encoding_slots(&dist);
+        let mut group_start = Vec::with_capacity(dist.len());
+        let mut acc = 0u16;
+        for &d in &dist {
+            group_start.push(acc);
+            acc += d;
+        }
+
+        Self {
+            header,
+            dist,
+            group_start,
+            slots,
+        }
+    }
+
+    pub fn write_histogram(&self, writer: &mut BitWriter) -> Result<()> {
+        match self.header {
+            Header::Single(sym) => {
+                writer.write_bool(true)?;
+                writer.write_bool(false)?;
+                write_u8(writer, sym as u32)?;
+            }
+            Header::Binary(v0, v1) => {
+                writer.write_bool(true)?;
+                writer.write_bool(true)?;
+                write_u8(writer, v0 as u32)?;
+                write_u8(writer, v1 as u32)?;
+                writer.write_bits(12, self.dist[v0] as u32)?;
+            }
+            Header::Compressed {
+                shift,
+                omit_pos,
+                alphabet_size,
+            } => {
+                write_compressed(writer, &self.dist, shift, omit_pos, alphabet_size)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Encodes symbols in reverse order, and returns the initial state of the decoder with 16-bit
+/// chunks which should be written right after each symbol.
+pub(super) fn encode_symbols<'a>(
+    symbols: impl DoubleEndedIterator<Item = (&'a AnsCode, u32)> + ExactSizeIterator,
+) -> (u32, Vec<Option<u16>>) {
+    let mut chunks = vec![None; symbols.len()];
+    let mut state = 0x130000u32;
+    for (chunk, (code, symbol)) in chunks.iter_mut().zip(symbols).rev() {
+        let symbol = symbol as usize;
+        let freq = code.dist[symbol] as u32;
+        debug_assert_ne!(freq, 0);
+        if state as u64 >= (freq as u64) << 20 {
+            *chunk = Some(state as u16);
+            state >>= 16;
+        }
+        let slot = code.slots[code.group_start[symbol] as usize + (state % freq) as usize];
+        state = ((state / freq) << 12) + slot as u32;
+    }
+    (state, chunks)
+}
+
+fn log_count(q: u32) -> u32 {
+    if q == 0 {
+        0
+    } else {
+        32 - q.leading_zeros()
+    }
+}
+
+/// Returns the granularity of quantized counts in the range of `[2^n, 2^(n+1))` which contains
+/// `q`, where `q >= 2`.
+fn step(q: u32, shift: i32) -> u32 {
+    let zeros = 31 - q.leading_zeros() as i32;
+    let bitcount = (shift - ((12 - zeros) >> 1)).clamp(0, zeros);
+    1 << (zeros - bitcount)
+}
+
+fn round_to_representable(x: f64, shift: i32) -> u32 {
+    if x < 1.5 {
+        return 1;
+    }
+    let floor = x as u32;
+    if floor < 2 {
+        return 2;
+    }
+    let step = step(floor, shift);
+    let lo = floor / step * step;
+    let hi = lo + step;
+    let q = if x - lo as f64 <= hi as f64 - x {
+        lo
+    } else {
+        hi
+    };
+    q.min(TOTAL - 1)
+}
+
+fn prev_representable(q: u32, shift: i32) -> u32 {
+    if q.is_power_of_two() {
+        if q == 2 {
+            1
+        } else {
+            q - step(q / 2, shift)
+        }
+    } else {
+        q - step(q, shift)
+    }
+}
+
+/// Quantizes the histogram with the given shift. The symbol with the largest count is omitted,
+/// which receives what's left.
+fn quantize(histogram: &[u32], total: u64, shift: i32) -> Option<(usize, Vec<u16>)> {
+    let max_count = *histogram.iter().max()?;
+    let omit_pos = histogram.iter().position(|&c| c == max_count)?;
+
+    let mut quantized = histogram
+        .iter()
+        .enumerate()
+        .map(|(sym, &c)| {
+            if c == 0 || sym == omit_pos {
+                0
+            } else {
+                round_to_representable(c as f64 * TOTAL as f64 / total as f64, shift)
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Symbols before the omitted one should have smaller log counts, and the log count of the
+    // omitted symbol is at most 12.
+    for q in &mut quantized[..omit_pos] {
+        while log_count(*q) >= 12 {
+            *q = prev_representable(*q, shift);
+        }
+    }
+
+    loop {
+        let sum = quantized.iter().sum::<u32>();
+        if sum < TOTAL {
+            quantized[omit_pos] = TOTAL - sum;
+            break;
+        }
+        let (largest, _) = quantized
+            .iter()
+            .enumerate()
+            .filter(|(_, &q)| q > 1)
+            .max_by_key(|(_, &q)| q)?;
+        quantized[largest] = prev_representable(quantized[largest], shift);
+    }
+
+    Some((omit_pos, quantized.into_iter().map(|q| q as u16).collect()))
+}
+
+fn write_compressed(
+    writer: &mut BitWriter,
+    dist: &[u16],
+    shift: i32,
+    omit_pos: usize,
+    alphabet_size: usize,
+) -> Result<()> {
+    writer.write_bool(false)?;
+    writer.write_bool(false)?;
+
+    let len = 31 - (shift as u32 + 1).leading_zeros();
+    for _ in 0..len {
+        writer.write_bool(true)?;
+    }
+    if len < 3 {
+        writer.write_bool(false)?;
+    }
+    writer.write_bits(len as usize, shift as u32 + 1 - (1 << len))?;
+    write_u8(writer, alphabet_size as u32 - 3)?;
+
+    let omit_log = dist[..omit_pos]
+        .iter()
+        .map(|&q| log_count(q as u32) + 1)
+        .chain(
+            dist[omit_pos + 1..alphabet_size]
+                .iter()
+                .map(|&q| log_count(q as u32)),
+        )
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    let mut extra_bits = Vec::new();
+    let mut prev = 0u16;
+    let mut idx = 0usize;
+    while idx < alphabet_size {
+        if idx != omit_pos + 1 {
+            let run = dist[idx..alphabet_size]
+                .iter()
+                .enumerate()
+                .take_while(|&(offset, &q)| idx + offset != omit_pos && q == prev)
+                .count();
+            if run >= 4 {
+                let run = run.min(255 + 4);
+                write_prefix(writer, 13)?;
+                write_u8(writer, run as u32 - 4)?;
+                idx += run;
+                continue;
+            }
+        }
+
+        if idx == omit_pos {
+            write_prefix(writer, omit_log)?;
+            prev = 0;
+        } else {
+            let q = dist[idx];
+            write_prefix(writer, log_count(q as u32))?;
+            if q > 1 {
+                extra_bits.push(q as u32);
+            }
+            prev = q;
+        }
+        idx += 1;
+    }
+
+    for q in extra_bits {
+        let zeros = 31 - q.leading_zeros() as i32;
+        let bitcount = (shift - ((12 - zeros) >> 1)).clamp(0, zeros);
+        let bits = (q - (1 << zeros)) >> (zeros - bitcount);
+        writer.write_bits(bitcount as usize, bits)?;
+    }
+    Ok(())
+}
+
+fn write_u8(writer: &mut BitWriter, value: u32) -> Result<()> {
+    if value == 0 {
+        writer.write_bool(false)?;
+    } else {
+        let n = 31 - value.leading_zeros();
+        writer.write_bool(true)?;
+        writer.write_bits(3, n)?;
+        writer.write_bits(n as usize, value - (1 << n))?;
+    }
+    Ok(())
+}
+
+/// Writes log count with the prefix code which `read_prefix` reads.
+fn write_prefix(writer: &mut BitWriter, log_count: u32) -> Result<()> {
+    let (bits, tail): (u32, &[bool]) = match log_count {
+        10 => (0, &[]),
+        4 => (1, &[true]),
+        0 => (1, &[false, true]),
+        11 => (1, &[false, false, true]),
+        13 => (1, &[false, false, false, true]),
+        12 => (1, &[false, false, false, false]),
+        7 => (2, &[]),
+        1 => (3, &[true]),
+        3 => (3, &[false]),
+        6 => (4, &[]),
+        8 => (5, &[]),
+        9 => (6, &[]),
+        2 => (7, &[true]),
+        5 => (7, &[false]),
+        _ => unreachable!(),
+    };
+    writer.write_bits(3, bits)?;
+    for &b in tail {
+        writer.write_bool(b)?;
+    }
+    Ok(())
+}
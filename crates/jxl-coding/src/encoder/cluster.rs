@@ -0,0 +1,123 @@
+/// Minimum cost reduction in bits for a histogram to be split into a new cluster, which roughly
+/// accounts for the size of the distribution itself.
+const MIN_DISTANCE: f64 = 64.0;
+
+/// Estimated number of bits required to encode the histogram with its own distribution.
+fn cost(histogram: &[u32]) -> f64 {
+    let total = histogram.iter().map(|&c| c as u64).sum::<u64>();
+    if total == 0 {
+        return 0.0;
+    }
+    let total = total as f64;
+    histogram
+        .iter()
+        .filter(|&&c| c != 0)
+        .map(|&c| {
+            let c = c as f64;
+            c * (total / c).log2()
+        })
+        .sum()
+}
+
+/// Estimated number of bits required to encode the given symbols.
+pub(super) fn slice_cost(symbols: &[u8]) -> f64 {
+    let mut histogram = [0u32; 256];
+    for &s in symbols {
+        histogram[s as usize] += 1;
+    }
+    cost(&histogram)
+}
+
+fn merge(a: &[u32], b: &[u32]) -> Vec<u32> {
+    a.iter().zip(b).map(|(&a, &b)| a + b).collect()
+}
+
+/// Greedily selects histograms as cluster centers, farthest ones first, and assigns every
+/// histogram to the nearest center.
+///
+/// Returns the cluster map and histograms of clusters. Clusters are numbered in the order of
+/// first appearance.
+pub(super) fn cluster_histograms(
+    histograms: &[Vec<u32>],
+    max_clusters: usize,
+) -> (Vec<u8>, Vec<Vec<u32>>) {
+    let alphabet_size = histograms.first().map(|h| h.len()).unwrap_or(0);
+    let costs = histograms.iter().map(|h| cost(h)).collect::<Vec<_>>();
+    let nonempty = histograms
+        .iter()
+        .enumerate()
+        .filter(|(_, h)| h.iter().any(|&c| c != 0))
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+    let distance = |a: usize, b: usize| {
+        let merged = merge(&histograms[a], &histograms[b]);
+        cost(&merged) - costs[a] - costs[b]
+    };
+
+    let mut centers = Vec::new();
+    if let Some(&first) = nonempty
+        .iter()
+        .max_by_key(|&&idx| histograms[idx].iter().map(|&c| c as u64).sum::<u64>())
+    {
+        let mut distances = vec![f64::INFINITY; histograms.len()];
+        let mut next = first;
+        loop {
+            centers.push(next);
+            for &idx in &nonempty {
+                distances[idx] = distances[idx].min(distance(idx, next));
+            }
+            if centers.len() >= max_clusters {
+                break;
+            }
+
+            let (farthest, max_distance) = nonempty
+                .iter()
+                .map(|&idx| (idx, distances[idx]))
+                .max_by(|a, b| a.1.total_cmp(&b.1))
+                .unwrap();
+            if max_distance < MIN_DISTANCE {
+                break;
+            }
+            next = farthest;
+        }
+    }
+
+    let mut center_ids = vec![None; centers.len()];
+    let mut cluster_map = Vec::with_capacity(histograms.len());
+    let mut clustered = Vec::<Vec<u32>>::new();
+    let mut prev_cluster = 0u8;
+    for (idx, histogram) in histograms.iter().enumerate() {
+        if histogram.iter().all(|&c| c == 0) {
+            cluster_map.push(prev_cluster);
+            continue;
+        }
+
+        let (center_idx, _) = centers
+            .iter()
+            .enumerate()
+            .map(|(center_idx, &center)| {
+                let d = if center == idx {
+                    0.0
+                } else {
+                    distance(idx, center)
+                };
+                (center_idx, d)
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        let cluster = *center_ids[center_idx].get_or_insert_with(|| {
+            clustered.push(vec![0u32; alphabet_size]);
+            (clustered.len() - 1) as u8
+        });
+        for (acc, &c) in clustered[cluster as usize].iter_mut().zip(histogram) {
+            *acc += c;
+        }
+        cluster_map.push(cluster);
+        prev_cluster = cluster;
+    }
+
+    if clustered.is_empty() {
+        clustered.push(vec![0u32; alphabet_size]);
+    }
+    (cluster_map, clustered)
+}
@@ -0,0 +1,119 @@
+use super::Lz77Options;
+use crate::SPECIAL_DISTANCES;
+
+const WINDOW_LEN: usize = 1 << 20;
+const HASH_BITS: u32 = 15;
+const MAX_CHAIN_LEN: usize = 32;
+
+/// An LZ77 copy starting at `pos`.
+#[derive(Debug, Copy, Clone)]
+pub(super) struct Match {
+    pub pos: usize,
+    pub len: u32,
+    pub dist_code: u32,
+}
+
+fn hash(values: &[u32]) -> usize {
+    let h = values[0]
+        .wrapping_mul(0x9e3779b1)
+        .rotate_left(11)
+        .wrapping_add(values[1])
+        .wrapping_mul(0x9e3779b1)
+        .rotate_left(11)
+        .wrapping_add(values[2])
+        .wrapping_mul(0x9e3779b1);
+    (h >> (32 - HASH_BITS)) as usize
+}
+
+fn insert(values: &[u32], head: &mut [usize], chain: &mut [usize], pos: usize) {
+    if pos + 3 <= values.len() {
+        let h = hash(&values[pos..]);
+        chain[pos] = head[h];
+        head[h] = pos;
+    }
+}
+
+fn match_len(values: &[u32], pos: usize, distance: usize) -> usize {
+    values[pos..]
+        .iter()
+        .zip(&values[pos - distance..])
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+/// Finds non-overlapping LZ77 copies greedily, using hash chains of three consecutive values.
+pub(super) fn find_matches(values: &[u32], options: &Lz77Options) -> Vec<Match> {
+    let min_length = options.min_length.max(3) as usize;
+    let dist_multiplier = options.dist_multiplier;
+
+    // Distances which can be encoded with special distance codes, keeping the first code.
+    let mut special_distances = Vec::<(usize, u32)>::new();
+    if dist_multiplier != 0 {
+        for (code, &[offset, dist]) in SPECIAL_DISTANCES.iter().enumerate() {
+            let distance = (offset as i64 + dist_multiplier as i64 * dist as i64).max(1) as usize;
+            if !special_distances.iter().any(|&(d, _)| d == distance) {
+                special_distances.push((distance, code as u32));
+            }
+        }
+    }
+    let encode_distance = |distance: usize| -> u32 {
+        if dist_multiplier == 0 {
+            return distance as u32 - 1;
+        }
+        special_distances
+            .iter()
+            .find(|&&(d, _)| d == distance)
+            .map(|&(_, code)| code)
+            .unwrap_or(distance as u32 + 119)
+    };
+
+    let mut head = vec![usize::MAX; 1 << HASH_BITS];
+    let mut chain = vec![usize::MAX; values.len()];
+    let mut matches = Vec::new();
+    let mut pos = 0usize;
+    while pos < values.len() {
+        let max_distance = pos.min(WINDOW_LEN);
+        let mut best = (0usize, 0usize);
+        let mut try_distance = |distance: usize| {
+            if distance == 0 || distance > max_distance {
+                return;
+            }
+            let len = match_len(values, pos, distance);
+            if len > best.0 {
+                best = (len, distance);
+            }
+        };
+
+        try_distance(1);
+        for &(distance, _) in &special_distances {
+            try_distance(distance);
+        }
+        if pos + 3 <= values.len() {
+            let mut candidate = head[hash(&values[pos..])];
+            for _ in 0..MAX_CHAIN_LEN {
+                if candidate == usize::MAX {
+                    break;
+                }
+                try_distance(pos - candidate);
+                candidate = chain[candidate];
+            }
+        }
+
+        let (len, distance) = best;
+        if len >= min_length {
+            matches.push(Match {
+                pos,
+                len: len as u32,
+                dist_code: encode_distance(distance),
+            });
+            for p in pos..pos + len {
+                insert(values, &mut head, &mut chain, p);
+            }
+            pos += len;
+        } else {
+            insert(values, &mut head, &mut chain, pos);
+            pos += 1;
+        }
+    }
+    matches
+}
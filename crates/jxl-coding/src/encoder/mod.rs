@@ -0,0 +1,677 @@
+//! Entropy encoder, the counterpart of [`Decoder`](crate::Decoder).
+use jxl_bitstream::{BitWriter, U32Distribution};
+
+use crate::{add_log2_ceil, Error, Result};
+
+mod ans;
+mod cluster;
+mod lz77;
+mod prefix;
+
+/// Hybrid integer configuration, which splits integers into tokens and raw bits.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct HybridUintConfig {
+    pub split_exponent: u32,
+    pub msb_in_token: u32,
+    pub lsb_in_token: u32,
+}
+
+impl Default for HybridUintConfig {
+    fn default() -> Self {
+        Self::new(4, 2, 0)
+    }
+}
+
+impl HybridUintConfig {
+    pub const fn new(split_exponent: u32, msb_in_token: u32, lsb_in_token: u32) -> Self {
+        Self {
+            split_exponent,
+            msb_in_token,
+            lsb_in_token,
+        }
+    }
+
+    /// Returns the smallest log alphabet size which can be used with this configuration.
+    fn min_log_alphabet_size(&self) -> u32 {
+        if self.msb_in_token == 0 && self.lsb_in_token == 0 {
+            self.split_exponent
+        } else {
+            self.split_exponent + 1
+        }
+    }
+
+    fn validate(&self, log_alphabet_size: u32) -> Result<()> {
+        let Self {
+            split_exponent,
+            msb_in_token,
+            lsb_in_token,
+        } = *self;
+        if split_exponent < 32
+            && self.min_log_alphabet_size() <= log_alphabet_size
+            && msb_in_token + lsb_in_token <= split_exponent
+        {
+            Ok(())
+        } else {
+            Err(Error::InvalidIntegerConfig)
+        }
+    }
+
+    /// Splits `value` into a token, the number of raw bits and the raw bits.
+    pub fn encode(&self, value: u32) -> (u32, u32, u32) {
+        let Self {
+            split_exponent,
+            msb_in_token,
+            lsb_in_token,
+        } = *self;
+        let split = 1u32 << split_exponent;
+        if value < split {
+            return (value, 0, 0);
+        }
+
+        let n = 31 - value.leading_zeros();
+        let msb = (value >> (n - msb_in_token)) & ((1 << msb_in_token) - 1);
+        let lsb = value & ((1 << lsb_in_token) - 1);
+        let token = split
+            + ((n - split_exponent) << (msb_in_token + lsb_in_token))
+            + (msb << lsb_in_token)
+            + lsb;
+        let num_bits = n - msb_in_token - lsb_in_token;
+        let bits = (value >> lsb_in_token) & ((1u32 << num_bits) - 1);
+        (token, num_bits, bits)
+    }
+
+    fn write(&self, writer: &mut BitWriter, log_alphabet_size: u32) -> Result<()> {
+        let split_exponent_bits = add_log2_ceil(log_alphabet_size) as usize;
+        writer.write_bits(split_exponent_bits, self.split_exponent)?;
+        if self.split_exponent != log_alphabet_size {
+            let msb_bits = add_log2_ceil(self.split_exponent) as usize;
+            writer.write_bits(msb_bits, self.msb_in_token)?;
+            let lsb_bits = add_log2_ceil(self.split_exponent - self.msb_in_token) as usize;
+            writer.write_bits(lsb_bits, self.lsb_in_token)?;
+        }
+        Ok(())
+    }
+}
+
+/// LZ77 parameters of [`Encoder`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Lz77Options {
+    /// The first token which signals LZ77 copies. Every literal token should be smaller than this.
+    pub min_symbol: u32,
+    /// Minimum length of LZ77 copies.
+    pub min_length: u32,
+    /// Hybrid integer configuration of copy lengths.
+    pub length_config: HybridUintConfig,
+    /// Distance multiplier which will be passed to
+    /// [`Decoder::read_varint_with_multiplier`](crate::Decoder::read_varint_with_multiplier).
+    pub dist_multiplier: u32,
+}
+
+impl Default for Lz77Options {
+    fn default() -> Self {
+        Self {
+            min_symbol: 224,
+            min_length: 3,
+            length_config: HybridUintConfig::new(0, 0, 0),
+            dist_multiplier: 0,
+        }
+    }
+}
+
+/// Options of [`Encoder`].
+#[derive(Debug, Clone)]
+pub struct EncoderOptions {
+    /// Whether to use prefix codes instead of ANS.
+    pub use_prefix_code: bool,
+    /// Hybrid integer configuration used for every cluster.
+    pub uint_config: HybridUintConfig,
+    /// LZ77 parameters, `None` if LZ77 is disabled.
+    pub lz77: Option<Lz77Options>,
+    /// Maximum number of clusters, in range of `1..=256`.
+    pub max_clusters: usize,
+}
+
+impl Default for EncoderOptions {
+    fn default() -> Self {
+        Self {
+            use_prefix_code: false,
+            uint_config: HybridUintConfig::default(),
+            lz77: None,
+            max_clusters: 256,
+        }
+    }
+}
+
+/// An entropy encoder, which writes streams that can be read with [`Decoder`](crate::Decoder).
+///
+/// Integers are pushed with their contexts first, grouped into one or more streams. All streams
+/// share the same distributions, which are computed and clustered when [`build`](Self::build) is
+/// called.
+#[derive(Debug, Clone)]
+pub struct Encoder {
+    num_dist: u32,
+    options: EncoderOptions,
+    streams: Vec<Vec<(u32, u32)>>,
+}
+
+impl Encoder {
+    /// Creates an encoder with `num_dist` contexts and default options.
+    pub fn new(num_dist: u32) -> Self {
+        Self::with_options(num_dist, EncoderOptions::default())
+    }
+
+    /// Creates an encoder with `num_dist` contexts and the given options.
+    pub fn with_options(num_dist: u32, options: EncoderOptions) -> Self {
+        Self {
+            num_dist,
+            options,
+            streams: Vec::new(),
+        }
+    }
+
+    /// Starts a new stream. Integers pushed after this call belong to the new stream.
+    pub fn begin_stream(&mut self) {
+        self.streams.push(Vec::new());
+    }
+
+    /// Pushes an integer with the given context to the current stream, starting a stream if
+    /// there's none.
+    ///
+    /// # Panics
+    /// Panics if `ctx` is out of range.
+    pub fn push(&mut self, ctx: u32, value: u32) {
+        assert!(ctx < self.num_dist, "context out of range");
+        if self.streams.is_empty() {
+            self.begin_stream();
+        }
+        self.streams.last_mut().unwrap().push((ctx, value));
+    }
+
+    /// Computes distributions from pushed integers.
+    pub fn build(self) -> Result<EncodedStreams> {
+        let Self {
+            num_dist,
+            options:
+                EncoderOptions {
+                    use_prefix_code,
+                    uint_config,
+                    lz77,
+                    max_clusters,
+                },
+            streams,
+        } = self;
+
+        if let Some(lz77) = &lz77 {
+            lz77.length_config.validate(8)?;
+        }
+        let streams = streams
+            .iter()
+            .map(|stream| tokenize(stream, num_dist, &uint_config, lz77.as_ref()))
+            .collect::<Result<Vec<_>>>()?;
+
+        let alphabet_size = streams
+            .iter()
+            .flatten()
+            .map(|token| token.token as usize + 1)
+            .max()
+            .unwrap_or(1);
+        let log_alphabet_size = if use_prefix_code {
+            15
+        } else {
+            let log_alphabet_size = alphabet_size.next_power_of_two().trailing_zeros();
+            log_alphabet_size
+                .max(uint_config.min_log_alphabet_size())
+                .max(5)
+        };
+        if alphabet_size > 1 << log_alphabet_size || log_alphabet_size > 15 {
+            return Err(
+                jxl_bitstream::Error::Unrepresentable("too many symbols for the alphabet").into(),
+            );
+        }
+        if !use_prefix_code && log_alphabet_size > 8 {
+            return Err(jxl_bitstream::Error::Unrepresentable(
+                "too many symbols for ANS, consider using prefix code",
+            )
+            .into());
+        }
+        uint_config.validate(log_alphabet_size)?;
+
+        let num_ctx = num_dist as usize + lz77.is_some() as usize;
+        let mut histograms = vec![vec![0u32; alphabet_size]; num_ctx];
+        for token in streams.iter().flatten() {
+            histograms[token.ctx as usize][token.token as usize] += 1;
+        }
+        let (clusters, histograms) =
+            cluster::cluster_histograms(&histograms, max_clusters.clamp(1, 256));
+
+        let code = if use_prefix_code {
+            Code::Prefix(
+                histograms
+                    .iter()
+                    .map(|h| prefix::PrefixCode::build(h))
+                    .collect(),
+            )
+        } else {
+            Code::Ans {
+                log_alphabet_size,
+                codes: histograms
+                    .iter()
+                    .map(|h| ans::AnsCode::build(h, log_alphabet_size))
+                    .collect(),
+            }
+        };
+
+        Ok(EncodedStreams {
+            lz77,
+            uint_config,
+            clusters,
+            code,
+            streams,
+        })
+    }
+
+    /// Computes distributions, and writes them followed by every stream in order.
+    pub fn write(self, writer: &mut BitWriter) -> Result<()> {
+        let encoded = self.build()?;
+        encoded.write_header(writer)?;
+        for idx in 0..encoded.num_streams() {
+            encoded.write_stream(writer, idx)?;
+        }
+        Ok(())
+    }
+}
+
+/// Distributions computed by [`Encoder`], with tokenized streams.
+#[derive(Debug, Clone)]
+pub struct EncodedStreams {
+    lz77: Option<Lz77Options>,
+    uint_config: HybridUintConfig,
+    clusters: Vec<u8>,
+    code: Code,
+    streams: Vec<Vec<Token>>,
+}
+
+#[derive(Debug, Clone)]
+enum Code {
+    Prefix(Vec<prefix::PrefixCode>),
+    Ans {
+        log_alphabet_size: u32,
+        codes: Vec<ans::AnsCode>,
+    },
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Token {
+    ctx: u32,
+    token: u32,
+    num_bits: u32,
+    bits: u32,
+}
+
+impl Token {
+    fn new(ctx: u32, (token, num_bits, bits): (u32, u32, u32)) -> Self {
+        Self {
+            ctx,
+            token,
+            num_bits,
+            bits,
+        }
+    }
+}
+
+impl EncodedStreams {
+    /// Returns the number of streams.
+    #[inline]
+    pub fn num_streams(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Returns the cluster mapping of distributions.
+    #[inline]
+    pub fn cluster_map(&self) -> &[u8] {
+        &self.clusters
+    }
+
+    /// Writes LZ77 configuration, clustering information, integer configurations and symbol
+    /// distributions, which are read by [`Decoder::parse`](crate::Decoder::parse).
+    pub fn write_header(&self, writer: &mut BitWriter) -> Result<()> {
+        use U32Distribution::*;
+
+        if let Some(lz77) = &self.lz77 {
+            writer.write_bool(true)?;
+            writer.write_u32(
+                lz77.min_symbol,
+                [
+                    Constant(224),
+                    Constant(512),
+                    Constant(4096),
+                    BitsOffset {
+                        bits: 15,
+                        offset: 8,
+                    },
+                ],
+            )?;
+            writer.write_u32(
+                lz77.min_length,
+                [
+                    Constant(3),
+                    Constant(4),
+                    BitsOffset { bits: 2, offset: 5 },
+                    BitsOffset { bits: 8, offset: 9 },
+                ],
+            )?;
+            lz77.length_config.write(writer, 8)?;
+        } else {
+            writer.write_bool(false)?;
+        }
+
+        write_clusters(writer, &self.clusters)?;
+
+        match &self.code {
+            Code::Prefix(codes) => {
+                writer.write_bool(true)?;
+                for _ in codes {
+                    self.uint_config.write(writer, 15)?;
+                }
+                for code in codes {
+                    code.write_alphabet_size(writer)?;
+                }
+                for code in codes {
+                    code.write_histogram(writer)?;
+                }
+            }
+            Code::Ans {
+                log_alphabet_size,
+                codes,
+            } => {
+                writer.write_bool(false)?;
+                writer.write_bits(2, log_alphabet_size - 5)?;
+                for _ in codes {
+                    self.uint_config.write(writer, *log_alphabet_size)?;
+                }
+                for code in codes {
+                    code.write_histogram(writer)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the stream at the given index.
+    ///
+    /// Each stream should be read with a decoder in the initial state, e.g. a fresh clone of the
+    /// decoder returned by `Decoder::parse`. ANS streams always start with the initial state, so
+    /// [`Decoder::begin`](crate::Decoder::begin) should be called if the stream may be empty.
+    ///
+    /// # Panics
+    /// Panics if `idx` is out of range.
+    pub fn write_stream(&self, writer: &mut BitWriter, idx: usize) -> Result<()> {
+        let tokens = &self.streams[idx];
+        match &self.code {
+            Code::Prefix(codes) => {
+                for token in tokens {
+                    let code = &codes[self.clusters[token.ctx as usize] as usize];
+                    code.write_symbol(writer, token.token)?;
+                    writer.write_bits(token.num_bits as usize, token.bits)?;
+                }
+            }
+            Code::Ans { codes, .. } => {
+                let (state, chunks) = ans::encode_symbols(tokens.iter().map(|token| {
+                    let code = &codes[self.clusters[token.ctx as usize] as usize];
+                    (code, token.token)
+                }));
+                writer.write_bits(32, state)?;
+                for (token, chunk) in tokens.iter().zip(chunks) {
+                    if let Some(chunk) = chunk {
+                        writer.write_bits(16, chunk as u32)?;
+                    }
+                    writer.write_bits(token.num_bits as usize, token.bits)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn tokenize(
+    stream: &[(u32, u32)],
+    num_dist: u32,
+    uint_config: &HybridUintConfig,
+    lz77: Option<&Lz77Options>,
+) -> Result<Vec<Token>> {
+    let Some(lz77) = lz77 else {
+        return Ok(stream
+            .iter()
+            .map(|&(ctx, value)| Token::new(ctx, uint_config.encode(value)))
+            .collect());
+    };
+
+    let values = stream.iter().map(|&(_, value)| value).collect::<Vec<_>>();
+    let matches = lz77::find_matches(&values, lz77);
+    let mut tokens = Vec::with_capacity(stream.len());
+    let mut matches = matches.into_iter().peekable();
+    let mut idx = 0usize;
+    while idx < stream.len() {
+        let (ctx, value) = stream[idx];
+        if let Some(m) = matches.next_if(|m| m.pos == idx) {
+            let (token, num_bits, bits) = lz77.length_config.encode(m.len - lz77.min_length);
+            tokens.push(Token::new(ctx, (lz77.min_symbol + token, num_bits, bits)));
+            tokens.push(Token::new(num_dist, uint_config.encode(m.dist_code)));
+            idx += m.len as usize;
+            continue;
+        }
+
+        let token = Token::new(ctx, uint_config.encode(value));
+        if token.token >= lz77.min_symbol {
+            return Err(jxl_bitstream::Error::Unrepresentable(
+                "literal token collides with LZ77 length tokens",
+            )
+            .into());
+        }
+        tokens.push(token);
+        idx += 1;
+    }
+    Ok(tokens)
+}
+
+/// Writes clustering information of distributions, which is read by
+/// [`read_clusters`](crate::read_clusters).
+pub fn write_clusters(writer: &mut BitWriter, clusters: &[u8]) -> Result<()> {
+    if clusters.len() <= 1 {
+        return Ok(());
+    }
+
+    let num_clusters = *clusters.iter().max().unwrap() as u32 + 1;
+    let nbits = num_clusters.next_power_of_two().trailing_zeros();
+    if nbits <= 3 {
+        // simple dist
+        writer.write_bool(true)?;
+        writer.write_bits(2, nbits)?;
+        for &cluster in clusters {
+            writer.write_bits(nbits as usize, cluster as u32)?;
+        }
+        return Ok(());
+    }
+
+    let mut mtfmap = [0u8; 256];
+    for (idx, mtf) in mtfmap.iter_mut().enumerate() {
+        *mtf = idx as u8;
+    }
+    let mtf = clusters
+        .iter()
+        .map(|&cluster| {
+            let idx = mtfmap.iter().position(|&x| x == cluster).unwrap();
+            mtfmap.copy_within(0..idx, 1);
+            mtfmap[0] = cluster;
+            idx as u8
+        })
+        .collect::<Vec<_>>();
+    let use_mtf = cluster::slice_cost(&mtf) < cluster::slice_cost(clusters);
+
+    writer.write_bool(false)?;
+    writer.write_bool(use_mtf)?;
+    let mut encoder = Encoder::new(1);
+    for &cluster in if use_mtf { &mtf } else { clusters } {
+        encoder.push(0, cluster as u32);
+    }
+    encoder.write(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use jxl_bitstream::Bitstream;
+
+    use super::*;
+    use crate::Decoder;
+
+    fn pseudo_random_stream(len: usize, num_dist: u32, seed: u32) -> Vec<(u32, u32)> {
+        let mut state = seed;
+        (0..len)
+            .map(|idx| {
+                state = state.wrapping_mul(1103515245).wrapping_add(12345);
+                let r = state >> 16;
+                let ctx = (idx as u32 / 7) % num_dist;
+                let value = match ctx % 4 {
+                    0 => r % 3,
+                    1 => (r % (ctx * 8 + 2)) * r.is_multiple_of(7) as u32,
+                    2 => 1 << (r % 31),
+                    _ => ctx + (idx as u32 / 13) % 3,
+                };
+                (ctx, value)
+            })
+            .collect()
+    }
+
+    fn roundtrip(options: EncoderOptions, num_dist: u32) {
+        let streams = [
+            pseudo_random_stream(5000, num_dist, 1),
+            pseudo_random_stream(0, num_dist, 2),
+            pseudo_random_stream(300, num_dist, 3),
+        ];
+        let dist_multiplier = options.lz77.map(|lz77| lz77.dist_multiplier).unwrap_or(0);
+
+        let mut encoder = Encoder::with_options(num_dist, options);
+        for stream in &streams {
+            encoder.begin_stream();
+            for &(ctx, value) in stream {
+                encoder.push(ctx, value);
+            }
+        }
+        let mut writer = BitWriter::new();
+        encoder.write(&mut writer).unwrap();
+        let bytes = writer.finish();
+
+        let mut bitstream = Bitstream::new(&bytes);
+        let (data_bits, stats) = crate::collect_stats(|| {
+            let decoder = Decoder::parse(&mut bitstream, num_dist).unwrap();
+            let mut data_bits = 0;
+            for stream in &streams {
+                let mut decoder = decoder.clone();
+                decoder.begin(&mut bitstream).unwrap();
+                let start_bits = bitstream.num_read_bits();
+                for &(ctx, value) in stream {
+                    let decoded = decoder
+                        .read_varint_with_multiplier(&mut bitstream, ctx, dist_multiplier)
+                        .unwrap();
+                    assert_eq!(decoded, value);
+                }
+                decoder.finalize().unwrap();
+                data_bits += (bitstream.num_read_bits() - start_bits) as u64;
+            }
+            data_bits
+        });
+
+        let [stats] = &*stats else {
+            panic!("expected statistics of one decoder, got {}", stats.len());
+        };
+        let total = stats.total();
+        assert_eq!(total.num_bits, data_bits);
+        assert_eq!(
+            total.num_values,
+            streams.iter().map(|s| s.len() as u64).sum::<u64>()
+        );
+        for (ctx, ctx_stats) in stats.contexts().iter().enumerate() {
+            let expected = streams
+                .iter()
+                .flatten()
+                .filter(|&&(c, _)| c as usize == ctx)
+                .count();
+            assert_eq!(ctx_stats.num_values, expected as u64);
+        }
+    }
+
+    #[test]
+    fn roundtrip_ans() {
+        roundtrip(EncoderOptions::default(), 1);
+        roundtrip(EncoderOptions::default(), 40);
+    }
+
+    #[test]
+    fn roundtrip_prefix() {
+        let options = EncoderOptions {
+            use_prefix_code: true,
+            ..Default::default()
+        };
+        roundtrip(options.clone(), 1);
+        roundtrip(options, 40);
+    }
+
+    #[test]
+    fn bulk_read() {
+        for use_prefix_code in [false, true] {
+            let options = EncoderOptions {
+                use_prefix_code,
+                ..Default::default()
+            };
+            let stream = pseudo_random_stream(5000, 4, 4);
+            let mut encoder = Encoder::with_options(1, options);
+            for &(ctx, value) in &stream {
+                // Mix of short literals and integers with raw bits.
+                let value = if ctx == 2 { value % 100 } else { value };
+                encoder.push(0, value);
+            }
+            let mut writer = BitWriter::new();
+            encoder.write(&mut writer).unwrap();
+            let bytes = writer.finish();
+
+            let mut bitstream = Bitstream::new(&bytes);
+            let mut decoder = Decoder::parse(&mut bitstream, 1).unwrap();
+            decoder.begin(&mut bitstream).unwrap();
+            let mut no_lz77 = decoder.as_no_lz77().unwrap();
+            let mut decoded = vec![0u32; stream.len()];
+            for (idx, chunk) in decoded.chunks_mut(37).enumerate() {
+                let len = chunk.len().min(idx % 5 + 33);
+                let (bulk, single) = chunk.split_at_mut(len);
+                no_lz77
+                    .read_varints_clustered(&mut bitstream, 0, bulk)
+                    .unwrap();
+                for value in single {
+                    *value = no_lz77.read_varint_clustered(&mut bitstream, 0).unwrap();
+                }
+            }
+            decoder.finalize().unwrap();
+
+            for (&(ctx, value), decoded) in stream.iter().zip(decoded) {
+                let value = if ctx == 2 { value % 100 } else { value };
+                assert_eq!(decoded, value);
+            }
+        }
+    }
+
+    #[test]
+    fn roundtrip_lz77() {
+        for use_prefix_code in [false, true] {
+            for dist_multiplier in [0, 7] {
+                let options = EncoderOptions {
+                    use_prefix_code,
+                    lz77: Some(Lz77Options {
+                        dist_multiplier,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                };
+                roundtrip(options, 12);
+            }
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Prefix code based on Brotli, encoder side
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use jxl_bitstream::BitWriter;
+
+use crate::Result;
+
+const CODE_LENGTH_ORDER: [usize; 18] =
+    [1, 2, 3, 4, 0, 5, 17, 6, 16, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+
+#[derive(Debug, Clone)]
+pub(super) struct PrefixCode {
+    alphabet_size: u32,
+    used_symbols: Vec<u16>,
+    lengths: Vec<u8>,
+    /// Codes with bits reversed, so that they can be written with `BitWriter::write_bits`.
+    codes: Vec<u16>,
+}
+
+impl PrefixCode {
+    pub fn build(histogram: &[u32]) -> Self {
+        let alphabet_size = histogram
+            .iter()
+            .rposition(|&c| c != 0)
+            .map(|idx| idx + 1)
+            .unwrap_or(1);
+        let histogram = &histogram[..alphabet_size.min(histogram.len())];
+        let used_symbols = histogram
+            .iter()
+            .enumerate()
+            .filter(|(_, &c)| c != 0)
+            .map(|(sym, _)| sym as u16)
+            .collect();
+        let mut lengths = code_lengths(histogram, 15);
+        lengths.resize(alphabet_size, 0);
+        let codes = canonical_codes(&lengths);
+        Self {
+            alphabet_size: alphabet_size as u32,
+            used_symbols,
+            lengths,
+            codes,
+        }
+    }
+
+    pub fn write_alphabet_size(&self, writer: &mut BitWriter) -> Result<()> {
+        if self.alphabet_size == 1 {
+            writer.write_bool(false)?;
+        } else {
+            let v = self.alphabet_size - 1;
+            let n = 31 - v.leading_zeros();
+            writer.write_bool(true)?;
+            writer.write_bits(4, n)?;
+            writer.write_bits(n as usize, v - (1 << n))?;
+        }
+        Ok(())
+    }
+
+    pub fn write_histogram(&self, writer: &mut BitWriter) -> Result<()> {
+        if self.alphabet_size == 1 {
+            return Ok(());
+        }
+
+        if self.used_symbols.len() <= 4 {
+            self.write_simple(writer)
+        } else {
+            self.write_complex(writer)
+        }
+    }
+
+    fn write_simple(&self, writer: &mut BitWriter) -> Result<()> {
+        let alphabet_bits = self.alphabet_size.next_power_of_two().trailing_zeros() as usize;
+        let mut syms = self.used_symbols.clone();
+        syms.sort_by_key(|&sym| self.lengths[sym as usize]);
+
+        writer.write_bits(2, 1)?;
+        writer.write_bits(2, syms.len() as u32 - 1)?;
+        for &sym in &syms {
+            writer.write_bits(alphabet_bits, sym as u32)?;
+        }
+        if syms.len() == 4 {
+            // Lengths are either [2, 2, 2, 2] or [1, 2, 3, 3].
+            writer.write_bool(self.lengths[syms[0] as usize] == 1)?;
+        }
+        Ok(())
+    }
+
+    fn write_complex(&self, writer: &mut BitWriter) -> Result<()> {
+        // (symbol, number of extra bits, extra bits)
+        let mut seq = Vec::<(u8, usize, u32)>::new();
+        let mut last_nonzero = None;
+        let mut idx = 0usize;
+        while idx < self.lengths.len() {
+            let len = self.lengths[idx];
+            let run = self.lengths[idx..]
+                .iter()
+                .take_while(|&&l| l == len)
+                .count();
+            // Consecutive repeat codes are interpreted differently, avoid them.
+            let prev_sym = seq.last().map(|&(sym, _, _)| sym);
+            if len == 0 && run >= 3 && prev_sym != Some(17) {
+                let run = run.min(10);
+                seq.push((17, 3, run as u32 - 3));
+                idx += run;
+            } else if len != 0 && run >= 3 && last_nonzero == Some(len) && prev_sym != Some(16) {
+                let run = run.min(6);
+                seq.push((16, 2, run as u32 - 3));
+                idx += run;
+            } else {
+                seq.push((len, 0, 0));
+                if len != 0 {
+                    last_nonzero = Some(len);
+                }
+                idx += 1;
+            }
+        }
+
+        let mut histogram = [0u32; 18];
+        for &(sym, _, _) in &seq {
+            histogram[sym as usize] += 1;
+        }
+        let mut code_length_lengths = code_lengths(&histogram, 5);
+        let nonzero_count = histogram.iter().filter(|&&c| c != 0).count();
+        if nonzero_count == 1 {
+            let sym = histogram.iter().position(|&c| c != 0).unwrap();
+            code_length_lengths[sym] = 1;
+        }
+        let codes = canonical_codes(&code_length_lengths);
+
+        writer.write_bits(2, 0)?;
+        let last_idx = if nonzero_count == 1 {
+            CODE_LENGTH_ORDER.len() - 1
+        } else {
+            CODE_LENGTH_ORDER
+                .iter()
+                .rposition(|&sym| code_length_lengths[sym] != 0)
+                .unwrap()
+        };
+        for &sym in &CODE_LENGTH_ORDER[..=last_idx] {
+            match code_length_lengths[sym] {
+                0 => writer.write_bits(2, 0)?,
+                4 => writer.write_bits(2, 1)?,
+                3 => writer.write_bits(2, 2)?,
+                2 => writer.write_bits(3, 0b011)?,
+                1 => writer.write_bits(4, 0b0111)?,
+                5 => writer.write_bits(4, 0b1111)?,
+                _ => unreachable!(),
+            }
+        }
+
+        for (sym, num_bits, bits) in seq {
+            if nonzero_count != 1 {
+                let sym = sym as usize;
+                writer.write_bits(code_length_lengths[sym] as usize, codes[sym] as u32)?;
+            }
+            writer.write_bits(num_bits, bits)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    pub fn write_symbol(&self, writer: &mut BitWriter, symbol: u32) -> Result<()> {
+        let symbol = symbol as usize;
+        writer.write_bits(self.lengths[symbol] as usize, self.codes[symbol] as u32)?;
+        Ok(())
+    }
+}
+
+/// Computes Huffman code lengths limited to `max_len`.
+///
+/// Code lengths are all zero if there are less than two symbols used.
+fn code_lengths(histogram: &[u32], max_len: u8) -> Vec<u8> {
+    let mut lengths = vec![0u8; histogram.len()];
+    let used = histogram
+        .iter()
+        .enumerate()
+        .filter(|(_, &c)| c != 0)
+        .map(|(sym, &c)| (sym, c as u64))
+        .collect::<Vec<_>>();
+    if used.len() < 2 {
+        return lengths;
+    }
+
+    // Flatten the distribution until code lengths fit in the limit.
+    let mut min_count = 1u64;
+    loop {
+        let mut parents = vec![usize::MAX; used.len()];
+        let mut heap = used
+            .iter()
+            .enumerate()
+            .map(|(node, &(_, c))| Reverse((c.max(min_count), node)))
+            .collect::<BinaryHeap<_>>();
+        while heap.len() > 1 {
+            let Reverse((a, node_a)) = heap.pop().unwrap();
+            let Reverse((b, node_b)) = heap.pop().unwrap();
+            let node = parents.len();
+            parents.push(usize::MAX);
+            parents[node_a] = node;
+            parents[node_b] = node;
+            heap.push(Reverse((a + b, node)));
+        }
+
+        let mut depths = vec![0u8; parents.len()];
+        for node in (0..parents.len()).rev() {
+            if parents[node] != usize::MAX {
+                depths[node] = depths[parents[node]] + 1;
+            }
+        }
+
+        if depths[..used.len()].iter().all(|&d| d <= max_len) {
+            for (&(sym, _), &depth) in used.iter().zip(&depths) {
+                lengths[sym] = depth;
+            }
+            return lengths;
+        }
+        min_count *= 2;
+    }
+}
+
+/// Assigns canonical codes, in the way the decoder does.
+fn canonical_codes(lengths: &[u8]) -> Vec<u16> {
+    let mut count_per_length = [0u16; 16];
+    for &len in lengths {
+        count_per_length[len as usize] += 1;
+    }
+    count_per_length[0] = 0;
+
+    let mut next_code = [0u16; 16];
+    let mut code = 0u16;
+    for len in 1..16 {
+        code = (code + count_per_length[len - 1]) << 1;
+        next_code[len] = code;
+    }
+
+    lengths
+        .iter()
+        .map(|&len| {
+            if len == 0 {
+                return 0;
+            }
+            let code = next_code[len as usize];
+            next_code[len as usize] += 1;
+            code.reverse_bits() >> (16 - len)
+        })
+        .collect()
+}
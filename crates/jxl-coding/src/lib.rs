@@ -3,20 +3,32 @@
 //!
 //! This also provides [`read_permutation`] and [`read_clusters`], which are used in some parts of
 //! the specification.
+//!
+//! [`Encoder`] is the counterpart of `Decoder`, which writes entropy coded streams that can be read
+//! back with `Decoder`.
+//!
+//! Decoders can record statistics of decoded symbols, which can be used to break down the size of
+//! entropy coded streams. See [`collect_stats`] and [`Decoder::enable_stats`].
 
 use std::sync::Arc;
 
 use jxl_bitstream::{read_bits, Bitstream};
 
 mod ans;
+mod encoder;
 mod error;
 mod permutation;
 mod prefix;
+mod stats;
 
+pub use encoder::{
+    write_clusters, EncodedStreams, Encoder, EncoderOptions, HybridUintConfig, Lz77Options,
+};
 pub use error::Error;
 pub type Result<T> = std::result::Result<T, Error>;
 
 pub use permutation::read_permutation;
+pub use stats::{collect_stats, ClusterDistribution, DecoderStats, SymbolStats};
 
 /// An entropy decoder.
 #[derive(Debug, Clone)]
@@ -28,7 +40,17 @@ pub struct Decoder {
 impl Decoder {
     /// Create a decoder by reading symbol distribution, integer configurations and LZ77
     /// configuration from the bitstream.
+    ///
+    /// Statistics mode is enabled if this is called inside [`collect_stats`].
     pub fn parse(bitstream: &mut Bitstream, num_dist: u32) -> Result<Self> {
+        let mut decoder = Self::parse_inner(bitstream, num_dist)?;
+        if stats::is_collecting() {
+            decoder.enable_stats();
+        }
+        Ok(decoder)
+    }
+
+    fn parse_inner(bitstream: &mut Bitstream, num_dist: u32) -> Result<Self> {
         let lz77 = Lz77::parse(bitstream)?;
         let num_dist = if let Lz77::Disabled = &lz77 {
             num_dist
@@ -66,6 +88,15 @@ impl Decoder {
         dist_multiplier: u32,
     ) -> Result<u32> {
         let cluster = self.inner.clusters[ctx as usize];
+        if let Some(stats) = self.inner.stats.get() {
+            let before = stats.cluster(cluster);
+            let ret =
+                self.read_varint_with_multiplier_clustered(bitstream, cluster, dist_multiplier);
+            if let Some(stats) = self.inner.stats.get_mut() {
+                stats.record_context_since(ctx, cluster, &before);
+            }
+            return ret;
+        }
         self.read_varint_with_multiplier_clustered(bitstream, cluster, dist_multiplier)
     }
 
@@ -175,6 +206,61 @@ impl Decoder {
     pub fn cluster_map(&self) -> &[u8] {
         &self.inner.clusters
     }
+
+    /// Returns the number of clusters.
+    #[inline]
+    pub fn num_clusters(&self) -> usize {
+        self.inner.configs.len()
+    }
+
+    /// Returns the hybrid integer configuration of the given cluster.
+    pub fn integer_config(&self, cluster: u8) -> HybridUintConfig {
+        self.inner.configs[cluster as usize].to_hybrid_uint_config()
+    }
+
+    /// Returns the symbol distribution of the given cluster, as parsed from the bitstream.
+    pub fn distribution(&self, cluster: u8) -> ClusterDistribution {
+        match &self.inner.code {
+            Coder::PrefixCode(dist) => dist[cluster as usize].distribution(),
+            Coder::Ans { dist, .. } => ClusterDistribution::Ans(dist[cluster as usize].dist()),
+        }
+    }
+
+    /// Enables statistics mode, which records counters of decoded symbols per context and per
+    /// cluster.
+    ///
+    /// Clones of this decoder start with empty counters.
+    pub fn enable_stats(&mut self) {
+        let inner = &mut self.inner;
+        inner.stats.enable(|| {
+            let configs = inner
+                .configs
+                .iter()
+                .map(IntegerConfig::to_hybrid_uint_config)
+                .collect();
+            DecoderStats::new(inner.clusters.clone(), configs)
+        });
+    }
+
+    /// Sets the label of statistics, which describes what this decoder is used for.
+    ///
+    /// This has no effect if statistics mode is disabled.
+    pub fn set_label(&mut self, label: &'static str) {
+        if let Some(stats) = self.inner.stats.get_mut() {
+            stats.set_label(label);
+        }
+    }
+
+    /// Returns statistics recorded so far, if statistics mode is enabled.
+    #[inline]
+    pub fn stats(&self) -> Option<&DecoderStats> {
+        self.inner.stats.get()
+    }
+
+    /// Takes statistics recorded so far and resets the counters, if statistics mode is enabled.
+    pub fn take_stats(&mut self) -> Option<DecoderStats> {
+        self.inner.stats.take()
+    }
 }
 
 /// An entropy decoder, in RLE mode.
@@ -198,6 +284,40 @@ impl DecoderRleMode<'_> {
         &mut self,
         bitstream: &mut Bitstream,
         cluster: u8,
+    ) -> Result<RleToken> {
+        if !self.inner.stats.is_enabled() {
+            return self.read_varint_clustered_inner(bitstream, cluster);
+        }
+
+        let start_bits = bitstream.num_read_bits();
+        let ret = self.read_varint_clustered_inner(bitstream, cluster);
+        if let (Ok(token), Some(stats)) = (&ret, self.inner.stats.get_mut()) {
+            let num_bits = (bitstream.num_read_bits() - start_bits) as u64;
+            let delta = match *token {
+                RleToken::Value(_) => SymbolStats {
+                    num_tokens: 1,
+                    num_values: 1,
+                    num_bits,
+                    ..Default::default()
+                },
+                RleToken::Repeat(len) => SymbolStats {
+                    num_tokens: 1,
+                    num_values: len as u64,
+                    num_bits,
+                    lz77_copies: 1,
+                    lz77_copied_values: len as u64,
+                },
+            };
+            stats.record(cluster, &delta);
+        }
+        ret
+    }
+
+    #[inline(always)]
+    fn read_varint_clustered_inner(
+        &mut self,
+        bitstream: &mut Bitstream,
+        cluster: u8,
     ) -> Result<RleToken> {
         self.inner
             .code
@@ -205,12 +325,12 @@ impl DecoderRleMode<'_> {
             .and_then(|token| {
                 let token = token as u32;
                 if let Some(token) = token.checked_sub(self.min_symbol) {
-                    self.inner
-                        .read_uint(bitstream, &self.len_config, token)
+                    self.len_config
+                        .read(bitstream, token)
                         .map(|v| RleToken::Repeat(v + self.min_length))
                 } else {
-                    self.inner
-                        .read_uint(bitstream, &self.inner.configs[cluster as usize], token)
+                    self.inner.configs[cluster as usize]
+                        .read(bitstream, token)
                         .map(RleToken::Value)
                 }
             })
@@ -254,6 +374,21 @@ impl DecoderNoLz77<'_> {
             .read_varint_with_multiplier_clustered(bitstream, cluster)
     }
 
+    /// Reads integers with the given cluster to fill `out`.
+    ///
+    /// This is faster than calling [`read_varint_clustered`][Self::read_varint_clustered]
+    /// repeatedly, as prefix code streams decode multiple short integers, including their raw
+    /// bits, with a single table lookup.
+    #[inline]
+    pub fn read_varints_clustered(
+        &mut self,
+        bitstream: &mut Bitstream,
+        cluster: u8,
+        out: &mut [u32],
+    ) -> Result<()> {
+        self.0.read_varints_clustered(bitstream, cluster, out)
+    }
+
     #[inline]
     pub fn single_token(&self, cluster: u8) -> Option<u32> {
         self.0.single_token(cluster)
@@ -322,7 +457,7 @@ impl Lz77State {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct IntegerConfig {
     split_exponent: u32,
     split: u32,
@@ -356,6 +491,71 @@ impl IntegerConfig {
             lsb_in_token,
         })
     }
+
+    /// Returns the number of raw bits following the token, which is zero if the token is a
+    /// literal value.
+    #[inline]
+    fn num_raw_bits(&self, token: u32) -> u32 {
+        if token < self.split {
+            return 0;
+        }
+        let Self {
+            split_exponent,
+            split,
+            msb_in_token,
+            lsb_in_token,
+        } = *self;
+        split_exponent - (msb_in_token + lsb_in_token)
+            + ((token - split) >> (msb_in_token + lsb_in_token))
+    }
+
+    /// Assembles an integer from the token and `n` raw bits, where `n` is the value returned by
+    /// [`num_raw_bits`][Self::num_raw_bits].
+    #[inline]
+    fn assemble(&self, token: u32, n: u32, rest_bits: u32) -> Result<u32> {
+        if token < self.split {
+            return Ok(token);
+        }
+
+        let Self {
+            msb_in_token,
+            lsb_in_token,
+            ..
+        } = *self;
+        let low_bits = token & ((1 << lsb_in_token) - 1);
+        let token = token >> lsb_in_token;
+        let token = token & ((1 << msb_in_token) - 1);
+        let token = token | (1 << msb_in_token);
+
+        let rest_bits = rest_bits as u64;
+        let token = token as u64;
+        let low_bits = low_bits as u64;
+        let result = (((token << n) | rest_bits) << lsb_in_token) | low_bits;
+        if result >= (1 << 32) {
+            Err(Error::InvalidIntegerConfig)
+        } else {
+            Ok(result as u32)
+        }
+    }
+
+    /// Reads an integer which starts with the given token.
+    #[inline]
+    fn read(&self, bitstream: &mut Bitstream, token: u32) -> Result<u32> {
+        if token < self.split {
+            return Ok(token);
+        }
+
+        let n = self.num_raw_bits(token);
+        if n >= 32 {
+            return Err(Error::InvalidIntegerConfig);
+        }
+        let rest_bits = bitstream.read_bits(n as usize)?;
+        self.assemble(token, n, rest_bits)
+    }
+
+    fn to_hybrid_uint_config(&self) -> HybridUintConfig {
+        HybridUintConfig::new(self.split_exponent, self.msb_in_token, self.lsb_in_token)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -363,6 +563,7 @@ struct DecoderInner {
     clusters: Vec<u8>,           // num_dist, [0, num_clusters)
     configs: Vec<IntegerConfig>, // num_clusters
     code: Coder,
+    stats: stats::StatsSlot,
 }
 
 impl DecoderInner {
@@ -411,6 +612,7 @@ impl DecoderInner {
             clusters,
             configs,
             code,
+            stats: Default::default(),
         })
     }
 
@@ -427,10 +629,51 @@ impl DecoderInner {
         bitstream: &mut Bitstream,
         cluster: u8,
     ) -> Result<u32> {
+        if self.stats.is_enabled() {
+            return self.read_varint_with_stats(bitstream, cluster);
+        }
         let token = self.code.read_symbol(bitstream, cluster)?;
-        self.read_uint(bitstream, &self.configs[cluster as usize], token as u32)
+        self.configs[cluster as usize].read(bitstream, token as u32)
     }
 
+    fn read_varints_clustered(
+        &mut self,
+        bitstream: &mut Bitstream,
+        cluster: u8,
+        out: &mut [u32],
+    ) -> Result<()> {
+        match &self.code {
+            Coder::PrefixCode(dist) if !self.stats.is_enabled() => {
+                let config = &self.configs[cluster as usize];
+                dist[cluster as usize].read_uints(bitstream, config, out)
+            }
+            _ => {
+                for value in out {
+                    *value = self.read_varint_with_multiplier_clustered(bitstream, cluster)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    #[inline(never)]
+    fn read_varint_with_stats(&mut self, bitstream: &mut Bitstream, cluster: u8) -> Result<u32> {
+        let start_bits = bitstream.num_read_bits();
+        let token = self.code.read_symbol(bitstream, cluster)?;
+        let ret = self.configs[cluster as usize].read(bitstream, token as u32)?;
+        if let Some(stats) = self.stats.get_mut() {
+            let delta = SymbolStats {
+                num_tokens: 1,
+                num_values: 1,
+                num_bits: (bitstream.num_read_bits() - start_bits) as u64,
+                ..Default::default()
+            };
+            stats.record(cluster, &delta);
+        }
+        Ok(ret)
+    }
+
+    #[inline]
     fn read_varint_with_multiplier_clustered_lz77(
         &mut self,
         bitstream: &mut Bitstream,
@@ -440,22 +683,67 @@ impl DecoderInner {
         min_symbol: u16,
         min_length: u32,
     ) -> Result<u32> {
-        #[rustfmt::skip]
-        const SPECIAL_DISTANCES: [[i8; 2]; 120] = [
-            [0, 1], [1, 0], [1, 1], [-1, 1], [0, 2], [2, 0], [1, 2], [-1, 2], [2, 1], [-2, 1],
-            [2, 2], [-2, 2], [0, 3], [3, 0], [1, 3], [-1, 3], [3, 1], [-3, 1], [2, 3], [-2, 3],
-            [3, 2], [-3, 2], [0, 4], [4, 0], [1, 4], [-1, 4], [4, 1], [-4, 1], [3, 3], [-3, 3],
-            [2, 4], [-2, 4], [4, 2], [-4, 2], [0, 5], [3, 4], [-3, 4], [4, 3], [-4, 3], [5, 0],
-            [1, 5], [-1, 5], [5, 1], [-5, 1], [2, 5], [-2, 5], [5, 2], [-5, 2], [4, 4], [-4, 4],
-            [3, 5], [-3, 5], [5, 3], [-5, 3], [0, 6], [6, 0], [1, 6], [-1, 6], [6, 1], [-6, 1],
-            [2, 6], [-2, 6], [6, 2], [-6, 2], [4, 5], [-4, 5], [5, 4], [-5, 4], [3, 6], [-3, 6],
-            [6, 3], [-6, 3], [0, 7], [7, 0], [1, 7], [-1, 7], [5, 5], [-5, 5], [7, 1], [-7, 1],
-            [4, 6], [-4, 6], [6, 4], [-6, 4], [2, 7], [-2, 7], [7, 2], [-7, 2], [3, 7], [-3, 7],
-            [7, 3], [-7, 3], [5, 6], [-5, 6], [6, 5], [-6, 5], [8, 0], [4, 7], [-4, 7], [7, 4],
-            [-7, 4], [8, 1], [8, 2], [6, 6], [-6, 6], [8, 3], [5, 7], [-5, 7], [7, 5], [-7, 5],
-            [8, 4], [6, 7], [-6, 7], [7, 6], [-7, 6], [8, 5], [7, 7], [-7, 7], [8, 6], [8, 7],
-        ];
+        if !self.stats.is_enabled() {
+            return self.read_varint_lz77_inner(
+                bitstream,
+                cluster,
+                dist_multiplier,
+                state,
+                min_symbol,
+                min_length,
+            );
+        }
+
+        let start_bits = bitstream.num_read_bits();
+        let was_copying = state.num_to_copy > 0;
+        let ret = self.read_varint_lz77_inner(
+            bitstream,
+            cluster,
+            dist_multiplier,
+            state,
+            min_symbol,
+            min_length,
+        )?;
+        if let Some(stats) = self.stats.get_mut() {
+            // Copies are at least three values long, so a copy has values left right after it
+            // started.
+            let delta = if was_copying {
+                SymbolStats {
+                    num_values: 1,
+                    lz77_copied_values: 1,
+                    ..Default::default()
+                }
+            } else if state.num_to_copy > 0 {
+                SymbolStats {
+                    num_tokens: 2,
+                    num_values: 1,
+                    num_bits: (bitstream.num_read_bits() - start_bits) as u64,
+                    lz77_copies: 1,
+                    lz77_copied_values: 1,
+                }
+            } else {
+                SymbolStats {
+                    num_tokens: 1,
+                    num_values: 1,
+                    num_bits: (bitstream.num_read_bits() - start_bits) as u64,
+                    ..Default::default()
+                }
+            };
+            stats.record(cluster, &delta);
+        }
+        Ok(ret)
+    }
 
+    #[inline(always)]
+    fn read_varint_lz77_inner(
+        &mut self,
+        bitstream: &mut Bitstream,
+        cluster: u8,
+        dist_multiplier: u32,
+        state: &mut Lz77State,
+        min_symbol: u16,
+        min_length: u32,
+    ) -> Result<u32> {
         let r;
         if state.num_to_copy > 0 {
             r = state.window[(state.copy_pos & 0xfffff) as usize];
@@ -466,15 +754,13 @@ impl DecoderInner {
             if token >= min_symbol {
                 let lz_dist_cluster = self.lz_dist_cluster();
 
-                state.num_to_copy =
-                    self.read_uint(bitstream, &state.lz_len_conf, (token - min_symbol) as u32)?
-                        + min_length;
+                state.num_to_copy = state
+                    .lz_len_conf
+                    .read(bitstream, (token - min_symbol) as u32)?
+                    + min_length;
                 let token = self.code.read_symbol(bitstream, lz_dist_cluster)?;
-                let distance = self.read_uint(
-                    bitstream,
-                    &self.configs[lz_dist_cluster as usize],
-                    token as u32,
-                )?;
+                let distance =
+                    self.configs[lz_dist_cluster as usize].read(bitstream, token as u32)?;
                 let distance = if dist_multiplier == 0 {
                     distance + 1
                 } else if distance < 120 {
@@ -492,7 +778,7 @@ impl DecoderInner {
                 state.copy_pos += 1;
                 state.num_to_copy -= 1;
             } else {
-                r = self.read_uint(bitstream, &self.configs[cluster as usize], token as u32)?;
+                r = self.configs[cluster as usize].read(bitstream, token as u32)?;
             }
         }
         state.window[(state.num_decoded & 0xfffff) as usize] = r;
@@ -500,50 +786,6 @@ impl DecoderInner {
         Ok(r)
     }
 
-    #[inline]
-    fn read_uint(
-        &self,
-        bitstream: &mut Bitstream,
-        config: &IntegerConfig,
-        token: u32,
-    ) -> Result<u32> {
-        let &IntegerConfig {
-            split_exponent,
-            split,
-            msb_in_token,
-            lsb_in_token,
-            ..
-        } = config;
-        if token < split {
-            return Ok(token);
-        }
-
-        let n = split_exponent - (msb_in_token + lsb_in_token)
-            + ((token - split) >> (msb_in_token + lsb_in_token));
-        if n >= 32 {
-            return Err(Error::InvalidIntegerConfig);
-        }
-
-        let low_bits = token & ((1 << lsb_in_token) - 1);
-        let token = token >> lsb_in_token;
-        let token = token & ((1 << msb_in_token) - 1);
-        let token = token | (1 << msb_in_token);
-        bitstream
-            .read_bits(n as usize)
-            .map_err(From::from)
-            .and_then(|rest_bits| {
-                let rest_bits = rest_bits as u64;
-                let token = token as u64;
-                let low_bits = low_bits as u64;
-                let result = (((token << n) | rest_bits) << lsb_in_token) | low_bits;
-                if result >= (1 << 32) {
-                    Err(Error::InvalidIntegerConfig)
-                } else {
-                    Ok(result as u32)
-                }
-            })
-    }
-
     #[inline]
     fn lz_dist_cluster(&self) -> u8 {
         *self.clusters.last().unwrap()
@@ -616,6 +858,22 @@ impl Coder {
     }
 }
 
+#[rustfmt::skip]
+const SPECIAL_DISTANCES: [[i8; 2]; 120] = [
+    [0, 1], [1, 0], [1, 1], [-1, 1], [0, 2], [2, 0], [1, 2], [-1, 2], [2, 1], [-2, 1],
+    [2, 2], [-2, 2], [0, 3], [3, 0], [1, 3], [-1, 3], [3, 1], [-3, 1], [2, 3], [-2, 3],
+    [3, 2], [-3, 2], [0, 4], [4, 0], [1, 4], [-1, 4], [4, 1], [-4, 1], [3, 3], [-3, 3],
+    [2, 4], [-2, 4], [4, 2], [-4, 2], [0, 5], [3, 4], [-3, 4], [4, 3], [-4, 3], [5, 0],
+    [1, 5], [-1, 5], [5, 1], [-5, 1], [2, 5], [-2, 5], [5, 2], [-5, 2], [4, 4], [-4, 4],
+    [3, 5], [-3, 5], [5, 3], [-5, 3], [0, 6], [6, 0], [1, 6], [-1, 6], [6, 1], [-6, 1],
+    [2, 6], [-2, 6], [6, 2], [-6, 2], [4, 5], [-4, 5], [5, 4], [-5, 4], [3, 6], [-3, 6],
+    [6, 3], [-6, 3], [0, 7], [7, 0], [1, 7], [-1, 7], [5, 5], [-5, 5], [7, 1], [-7, 1],
+    [4, 6], [-4, 6], [6, 4], [-6, 4], [2, 7], [-2, 7], [7, 2], [-7, 2], [3, 7], [-3, 7],
+    [7, 3], [-7, 3], [5, 6], [-5, 6], [6, 5], [-6, 5], [8, 0], [4, 7], [-4, 7], [7, 4],
+    [-7, 4], [8, 1], [8, 2], [6, 6], [-6, 6], [8, 3], [5, 7], [-5, 7], [7, 5], [-7, 5],
+    [8, 4], [6, 7], [-6, 7], [7, 6], [-7, 6], [8, 5], [7, 7], [-7, 7], [8, 6], [8, 7],
+];
+
 fn add_log2_ceil(x: u32) -> u32 {
     if x >= 0x80000000 {
         32
@@ -643,7 +901,7 @@ pub fn read_clusters(bitstream: &mut Bitstream, num_dist: u32) -> Result<(u32, V
         let mut decoder = if num_dist <= 2 {
             Decoder::parse_assume_no_lz77(bitstream, 1)?
         } else {
-            Decoder::parse(bitstream, 1)?
+            Decoder::parse_inner(bitstream, 1)?
         };
         decoder.begin(bitstream)?;
         let mut ret = (0..num_dist)
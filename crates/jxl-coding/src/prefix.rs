@@ -1,12 +1,56 @@
 //! Prefix code based on Brotli
+use std::sync::OnceLock;
+
 use jxl_bitstream::{read_bits, Bitstream};
 
-use crate::{Error, Result};
+use crate::{Error, IntegerConfig, Result};
+
+/// Number of bits looked up at once.
+pub(crate) const LUT_BITS: usize = 10;
+/// Maximum number of integers decoded with a single lookup of the fused table.
+const MAX_FUSED_VALUES: usize = 4;
+/// Code length of lookup table entries of which the code is longer than the table index.
+const LONG_CODE: u8 = u8::MAX;
 
-#[derive(Debug)]
 pub struct Histogram {
     configs: Vec<u32>,
     symbols: Vec<u16>,
+    lut: Vec<LutEntry>,
+    lut_mask: u32,
+    fused: OnceLock<FusedTable>,
+}
+
+impl std::fmt::Debug for Histogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Histogram")
+            .field("configs", &self.configs)
+            .field("symbols", &self.symbols)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Symbol decoded from the lookup table index, and its code length.
+#[derive(Debug, Copy, Clone)]
+struct LutEntry {
+    symbol: u16,
+    len: u8,
+}
+
+/// Fused lookup table, with the hybrid integer configuration it was built with.
+#[derive(Debug)]
+struct FusedTable {
+    config: IntegerConfig,
+    entries: Box<[FusedEntry]>,
+}
+
+/// Integers decoded from the lookup table index, with both prefix codes and raw bits of hybrid
+/// integers.
+#[derive(Debug, Copy, Clone, Default)]
+pub(crate) struct FusedEntry {
+    values: [u32; MAX_FUSED_VALUES],
+    /// Number of bits consumed after decoding each value.
+    bits: [u8; MAX_FUSED_VALUES],
+    count: u8,
 }
 
 impl Histogram {
@@ -25,26 +69,63 @@ impl Histogram {
         let mut configs = Vec::new();
         let mut symbols = Vec::new();
         let mut current_bits = 0u16;
-        for (idx, syms) in syms_for_length.into_iter().enumerate() {
+        for (idx, syms) in syms_for_length.iter().enumerate() {
             let shifts = 14 - idx;
             let sym_count = syms.len() as u16;
             current_bits += sym_count << shifts;
 
             configs.push(((current_bits as u32) << 16) | (symbols.len() as u32));
-            symbols.extend(syms);
+            symbols.extend_from_slice(syms);
         }
 
-        if current_bits == 1 << 15 {
-            Ok(Self { configs, symbols })
-        } else {
-            Err(Error::InvalidPrefixHistogram)
+        if current_bits != 1 << 15 {
+            return Err(Error::InvalidPrefixHistogram);
+        }
+
+        // Canonical codes are assigned in the order of code length, then symbol. Codes are read
+        // from the least significant bit of the peeked bits.
+        let lut_bits = syms_for_length.len().min(LUT_BITS);
+        let mut lut = vec![
+            LutEntry {
+                symbol: 0,
+                len: LONG_CODE,
+            };
+            1 << lut_bits
+        ];
+        let mut code = 0u32;
+        for (idx, syms) in syms_for_length.iter().enumerate() {
+            let len = idx + 1;
+            for &symbol in syms {
+                if len <= lut_bits {
+                    let reversed = code.reverse_bits() >> (32 - len);
+                    for high in 0..1usize << (lut_bits - len) {
+                        lut[(high << len) | reversed as usize] = LutEntry {
+                            symbol,
+                            len: len as u8,
+                        };
+                    }
+                }
+                code += 1;
+            }
+            code <<= 1;
         }
+
+        Ok(Self {
+            configs,
+            symbols,
+            lut,
+            lut_mask: (1 << lut_bits) - 1,
+            fused: OnceLock::new(),
+        })
     }
 
     fn with_single_symbol(symbol: u16) -> Self {
         Self {
             configs: Vec::new(),
             symbols: vec![symbol],
+            lut: vec![LutEntry { symbol, len: 0 }],
+            lut_mask: 0,
+            fused: OnceLock::new(),
         }
     }
 
@@ -244,8 +325,27 @@ impl Histogram {
 impl Histogram {
     #[inline]
     pub fn read_symbol(&self, bitstream: &mut Bitstream) -> Result<u16> {
-        let Self { configs, symbols } = self;
         let peeked = bitstream.peek_bits(15);
+        let entry = self.lut[(peeked & self.lut_mask) as usize];
+        if entry.len != LONG_CODE {
+            bitstream.consume_bits(entry.len as usize)?;
+            return Ok(entry.symbol);
+        }
+        self.read_long_symbol(bitstream, peeked)
+    }
+
+    /// Reads a symbol by comparing the code with each code length, without the lookup table.
+    #[cfg(test)]
+    fn read_symbol_without_lut(&self, bitstream: &mut Bitstream) -> Result<u16> {
+        let peeked = bitstream.peek_bits(15);
+        self.read_long_symbol(bitstream, peeked)
+    }
+
+    #[inline(never)]
+    fn read_long_symbol(&self, bitstream: &mut Bitstream, peeked: u32) -> Result<u16> {
+        let Self {
+            configs, symbols, ..
+        } = self;
         let bits = (peeked.reverse_bits() >> 1) | 0xffff;
         let mut prev = 0u32;
         for (count, &config) in configs.iter().enumerate() {
@@ -262,6 +362,103 @@ impl Histogram {
         Ok(symbols[0])
     }
 
+    /// Recovers code lengths of symbols.
+    pub fn distribution(&self) -> crate::ClusterDistribution {
+        if let Some(symbol) = self.single_symbol() {
+            return crate::ClusterDistribution::PrefixSingleSymbol(symbol);
+        }
+
+        let num_symbols = self.symbols.iter().max().map(|&sym| sym as usize + 1);
+        let mut code_lengths = vec![0u8; num_symbols.unwrap_or(0)];
+        for (idx, &config) in self.configs.iter().enumerate() {
+            let start = (config & 0xffff) as usize;
+            let end = self
+                .configs
+                .get(idx + 1)
+                .map(|&next| (next & 0xffff) as usize)
+                .unwrap_or(self.symbols.len());
+            for &sym in &self.symbols[start..end] {
+                code_lengths[sym as usize] = idx as u8 + 1;
+            }
+        }
+        crate::ClusterDistribution::Prefix(code_lengths)
+    }
+
+    /// Returns the table which decodes multiple integers at once from [`LUT_BITS`] bits, with the
+    /// given hybrid integer configuration.
+    ///
+    /// The table is built on first use; `config` must be the same for every call, which is
+    /// checked in debug builds.
+    pub(crate) fn fused_table(&self, config: &IntegerConfig) -> &[FusedEntry] {
+        let table = self.fused.get_or_init(|| FusedTable {
+            config: config.clone(),
+            entries: self.build_fused_table(config),
+        });
+        debug_assert_eq!(
+            &table.config, config,
+            "fused table is used with another hybrid integer configuration"
+        );
+        &table.entries
+    }
+
+    fn build_fused_table(&self, config: &IntegerConfig) -> Box<[FusedEntry]> {
+        (0..1u32 << LUT_BITS)
+            .map(|idx| {
+                let mut entry = FusedEntry::default();
+                let mut used = 0usize;
+                while (entry.count as usize) < MAX_FUSED_VALUES {
+                    let available = LUT_BITS - used;
+                    let bits = idx >> used;
+                    // Bits above `available` are unknown, the entry is valid only if the code
+                    // fits in available bits.
+                    let LutEntry { symbol, len } = self.lut[(bits & self.lut_mask) as usize];
+                    let len = len as usize;
+                    let token = symbol as u32;
+                    let n = config.num_raw_bits(token);
+                    if len == LONG_CODE as usize || n >= 32 || len + n as usize > available {
+                        break;
+                    }
+                    let rest_bits = (bits >> len) & ((1 << n) - 1);
+                    let Ok(value) = config.assemble(token, n, rest_bits) else {
+                        break;
+                    };
+
+                    used += len + n as usize;
+                    entry.values[entry.count as usize] = value;
+                    entry.bits[entry.count as usize] = used as u8;
+                    entry.count += 1;
+                }
+                entry
+            })
+            .collect()
+    }
+
+    /// Decodes integers to fill `out`, using the fused table.
+    #[inline]
+    pub(crate) fn read_uints(
+        &self,
+        bitstream: &mut Bitstream,
+        config: &IntegerConfig,
+        mut out: &mut [u32],
+    ) -> Result<()> {
+        let table = self.fused_table(config);
+        while !out.is_empty() {
+            let entry = &table[bitstream.peek_bits(LUT_BITS) as usize];
+            let count = (entry.count as usize).min(out.len());
+            if count == 0 {
+                let token = self.read_symbol(bitstream)? as u32;
+                out[0] = config.read(bitstream, token)?;
+                out = &mut out[1..];
+                continue;
+            }
+
+            bitstream.consume_bits(entry.bits[count - 1] as usize)?;
+            out[..count].copy_from_slice(&entry.values[..count]);
+            out = &mut out[count..];
+        }
+        Ok(())
+    }
+
     #[inline]
     pub fn single_symbol(&self) -> Option<u16> {
         let &[symbol] = &*self.symbols else {
@@ -270,3 +467,118 @@ impl Histogram {
         Some(symbol)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use jxl_bitstream::Bitstream;
+
+    use super::*;
+
+    struct Lcg(u32);
+
+    impl Lcg {
+        fn next(&mut self, bound: u32) -> u32 {
+            self.0 = self.0.wrapping_mul(1103515245).wrapping_add(12345);
+            (self.0 >> 8) % bound
+        }
+    }
+
+    /// Builds a complete prefix code by splitting random leaves, with unused symbols in between.
+    fn random_histogram(rng: &mut Lcg) -> Histogram {
+        let mut lengths = vec![1u8, 1];
+        for _ in 0..rng.next(40) {
+            // Splitting the last leaf often makes codes longer than the lookup table index.
+            let idx = if rng.next(3) == 0 {
+                lengths.len() - 1
+            } else {
+                rng.next(lengths.len() as u32) as usize
+            };
+            if lengths[idx] < 15 {
+                lengths[idx] += 1;
+                lengths.push(lengths[idx]);
+            }
+        }
+
+        let mut code_lengths = Vec::new();
+        while !lengths.is_empty() {
+            if rng.next(4) == 0 {
+                code_lengths.push(0);
+            } else {
+                let idx = rng.next(lengths.len() as u32) as usize;
+                code_lengths.push(lengths.swap_remove(idx));
+            }
+        }
+        Histogram::with_code_lengths(code_lengths).unwrap()
+    }
+
+    /// Reads integers one at a time, stopping at the first error, and returns those with the
+    /// number of bits consumed by them.
+    fn read_uints_reference(
+        histogram: &Histogram,
+        bitstream: &mut Bitstream,
+        config: &IntegerConfig,
+        count: usize,
+    ) -> (Vec<u32>, usize) {
+        let mut out = Vec::with_capacity(count);
+        let mut num_bits = 0;
+        while out.len() < count {
+            let Ok(token) = histogram.read_symbol(bitstream) else {
+                break;
+            };
+            let Ok(value) = config.read(bitstream, token as u32) else {
+                break;
+            };
+            out.push(value);
+            num_bits = bitstream.num_read_bits();
+        }
+        (out, num_bits)
+    }
+
+    #[test]
+    fn lookup_tables_match_reference() {
+        let configs = [
+            (0, 0, 0),
+            (2, 1, 0),
+            (3, 0, 2),
+            (4, 2, 0),
+            (4, 1, 1),
+            (8, 0, 0),
+        ]
+        .map(
+            |(split_exponent, msb_in_token, lsb_in_token)| IntegerConfig {
+                split_exponent,
+                split: 1 << split_exponent,
+                msb_in_token,
+                lsb_in_token,
+            },
+        );
+
+        let mut rng = Lcg(1);
+        let data = (0..8192).map(|_| rng.next(256) as u8).collect::<Vec<_>>();
+        let histograms = (0..64)
+            .map(|_| random_histogram(&mut rng))
+            .chain([Histogram::with_single_symbol(3)]);
+        for (idx, histogram) in histograms.enumerate() {
+            let mut bitstream = Bitstream::new(&data);
+            let mut reference = Bitstream::new(&data);
+            for _ in 0..1000 {
+                let symbol = histogram.read_symbol(&mut bitstream).unwrap();
+                let expected = histogram.read_symbol_without_lut(&mut reference).unwrap();
+                assert_eq!(symbol, expected);
+                assert_eq!(bitstream.num_read_bits(), reference.num_read_bits());
+            }
+
+            let config = &configs[idx % configs.len()];
+            let mut reference = Bitstream::new(&data);
+            let (expected, num_bits) =
+                read_uints_reference(&histogram, &mut reference, config, 1000);
+            let mut bitstream = Bitstream::new(&data);
+            let mut out = vec![0u32; expected.len()];
+            histogram
+                .read_uints(&mut bitstream, config, &mut out)
+                .unwrap();
+            assert_eq!(out, expected);
+            assert_eq!(bitstream.num_read_bits(), num_bits);
+        }
+    }
+}
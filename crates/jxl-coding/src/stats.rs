@@ -0,0 +1,289 @@
+//! Statistics of entropy decoded streams, used to analyze where the bits are spent.
+use std::cell::RefCell;
+
+use crate::HybridUintConfig;
+
+/// Counters of decoded symbols, either of a context or of a cluster.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct SymbolStats {
+    /// Number of tokens read from the entropy coded stream, including LZ77 length and distance
+    /// tokens.
+    pub num_tokens: u64,
+    /// Number of integers decoded, including those copied by LZ77.
+    pub num_values: u64,
+    /// Number of bits consumed, including raw bits of hybrid integers.
+    pub num_bits: u64,
+    /// Number of LZ77 copies started.
+    pub lz77_copies: u64,
+    /// Number of integers produced by LZ77 copies.
+    pub lz77_copied_values: u64,
+}
+
+impl SymbolStats {
+    /// Adds the counters of `other` to `self`.
+    pub fn merge(&mut self, other: &Self) {
+        self.num_tokens += other.num_tokens;
+        self.num_values += other.num_values;
+        self.num_bits += other.num_bits;
+        self.lz77_copies += other.lz77_copies;
+        self.lz77_copied_values += other.lz77_copied_values;
+    }
+
+    fn diff(&self, before: &Self) -> Self {
+        Self {
+            num_tokens: self.num_tokens - before.num_tokens,
+            num_values: self.num_values - before.num_values,
+            num_bits: self.num_bits - before.num_bits,
+            lz77_copies: self.lz77_copies - before.lz77_copies,
+            lz77_copied_values: self.lz77_copied_values - before.lz77_copied_values,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.num_values == 0 && self.num_bits == 0
+    }
+}
+
+/// Symbol distribution of a cluster, as parsed from the bitstream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClusterDistribution {
+    /// ANS distribution, with frequencies summing up to `1 << 12`.
+    Ans(Vec<u16>),
+    /// Code lengths of prefix code, indexed by symbol. Unused symbols have zero length.
+    Prefix(Vec<u8>),
+    /// Prefix code with only one symbol, which is decoded without consuming any bits.
+    PrefixSingleSymbol(u16),
+}
+
+/// Statistics collected by a [`Decoder`](crate::Decoder) in statistics mode.
+///
+/// Per-context counters are recorded only for reads with a context, e.g. with
+/// [`Decoder::read_varint`](crate::Decoder::read_varint); per-cluster counters include every read.
+/// Bits of an LZ77 copy, including its distance token, are attributed to the context and cluster
+/// which started the copy.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecoderStats {
+    label: Option<&'static str>,
+    cluster_map: Vec<u8>,
+    configs: Vec<HybridUintConfig>,
+    contexts: Vec<SymbolStats>,
+    clusters: Vec<SymbolStats>,
+}
+
+impl DecoderStats {
+    pub(crate) fn new(cluster_map: Vec<u8>, configs: Vec<HybridUintConfig>) -> Self {
+        let contexts = vec![SymbolStats::default(); cluster_map.len()];
+        let clusters = vec![SymbolStats::default(); configs.len()];
+        Self {
+            label: None,
+            cluster_map,
+            configs,
+            contexts,
+            clusters,
+        }
+    }
+
+    /// Returns the label of the decoder, set with
+    /// [`Decoder::set_label`](crate::Decoder::set_label).
+    #[inline]
+    pub fn label(&self) -> Option<&'static str> {
+        self.label
+    }
+
+    /// Returns the cluster mapping of contexts.
+    ///
+    /// If LZ77 is enabled, the last context is the one used for LZ77 distances.
+    #[inline]
+    pub fn cluster_map(&self) -> &[u8] {
+        &self.cluster_map
+    }
+
+    /// Returns hybrid integer configurations, indexed by cluster.
+    #[inline]
+    pub fn integer_configs(&self) -> &[HybridUintConfig] {
+        &self.configs
+    }
+
+    /// Returns counters indexed by context.
+    #[inline]
+    pub fn contexts(&self) -> &[SymbolStats] {
+        &self.contexts
+    }
+
+    /// Returns counters indexed by cluster.
+    #[inline]
+    pub fn clusters(&self) -> &[SymbolStats] {
+        &self.clusters
+    }
+
+    /// Returns the sum of counters of all clusters.
+    pub fn total(&self) -> SymbolStats {
+        let mut total = SymbolStats::default();
+        for stats in &self.clusters {
+            total.merge(stats);
+        }
+        total
+    }
+
+    /// Returns whether `other` came from a decoder with the same label and the same
+    /// configuration, so that it can be merged into `self`.
+    pub fn is_compatible(&self, other: &Self) -> bool {
+        self.label == other.label
+            && self.cluster_map == other.cluster_map
+            && self.configs == other.configs
+    }
+
+    /// Adds the counters of `other` to `self`.
+    ///
+    /// # Panics
+    /// Panics if `other` is not [compatible][Self::is_compatible] with `self`.
+    pub fn merge(&mut self, other: &Self) {
+        assert!(self.is_compatible(other));
+        for (acc, stats) in self.contexts.iter_mut().zip(&other.contexts) {
+            acc.merge(stats);
+        }
+        for (acc, stats) in self.clusters.iter_mut().zip(&other.clusters) {
+            acc.merge(stats);
+        }
+    }
+
+    pub(crate) fn set_label(&mut self, label: &'static str) {
+        self.label = Some(label);
+    }
+
+    #[inline]
+    pub(crate) fn cluster(&self, cluster: u8) -> SymbolStats {
+        self.clusters[cluster as usize]
+    }
+
+    #[inline]
+    pub(crate) fn record(&mut self, cluster: u8, stats: &SymbolStats) {
+        self.clusters[cluster as usize].merge(stats);
+    }
+
+    #[inline]
+    pub(crate) fn record_context_since(&mut self, ctx: u32, cluster: u8, before: &SymbolStats) {
+        let delta = self.clusters[cluster as usize].diff(before);
+        self.contexts[ctx as usize].merge(&delta);
+    }
+
+    fn is_empty(&self) -> bool {
+        self.clusters.iter().all(SymbolStats::is_empty)
+    }
+
+    fn cleared(&self) -> Self {
+        Self {
+            label: self.label,
+            cluster_map: self.cluster_map.clone(),
+            configs: self.configs.clone(),
+            contexts: vec![SymbolStats::default(); self.contexts.len()],
+            clusters: vec![SymbolStats::default(); self.clusters.len()],
+        }
+    }
+}
+
+/// Storage of decoder statistics.
+///
+/// Cloning starts with empty counters so that reads are not counted twice, and dropping submits
+/// the counters to the active collector, if any.
+#[derive(Debug, Default)]
+pub(crate) struct StatsSlot(Option<Box<DecoderStats>>);
+
+impl Clone for StatsSlot {
+    fn clone(&self) -> Self {
+        Self(self.0.as_ref().map(|stats| Box::new(stats.cleared())))
+    }
+}
+
+impl Drop for StatsSlot {
+    fn drop(&mut self) {
+        if let Some(stats) = self.0.take() {
+            submit(*stats);
+        }
+    }
+}
+
+impl StatsSlot {
+    #[inline(always)]
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn enable(&mut self, stats: impl FnOnce() -> DecoderStats) {
+        if self.0.is_none() {
+            self.0 = Some(Box::new(stats()));
+        }
+    }
+
+    #[inline]
+    pub fn get(&self) -> Option<&DecoderStats> {
+        self.0.as_deref()
+    }
+
+    #[inline]
+    pub fn get_mut(&mut self) -> Option<&mut DecoderStats> {
+        self.0.as_deref_mut()
+    }
+
+    pub fn take(&mut self) -> Option<DecoderStats> {
+        let stats = self.0.as_mut()?;
+        let cleared = stats.cleared();
+        Some(std::mem::replace(stats, cleared))
+    }
+}
+
+thread_local! {
+    static COLLECTORS: RefCell<Vec<Vec<DecoderStats>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f`, collecting statistics of entropy decoders used on the current thread.
+///
+/// Decoders [parsed][crate::Decoder::parse] while `f` is running have statistics mode enabled.
+/// Counters of a decoder, or of its clones, are collected when it is dropped on the current thread
+/// before `f` returns; decoders dropped in a nested call go to the innermost collector. Statistics
+/// of compatible decoders are merged.
+pub fn collect_stats<R>(f: impl FnOnce() -> R) -> (R, Vec<DecoderStats>) {
+    struct PopGuard;
+
+    impl Drop for PopGuard {
+        fn drop(&mut self) {
+            COLLECTORS.with_borrow_mut(|collectors| collectors.pop());
+        }
+    }
+
+    COLLECTORS.with_borrow_mut(|collectors| collectors.push(Vec::new()));
+    let guard = PopGuard;
+    let ret = f();
+    let stats = COLLECTORS.with_borrow_mut(|collectors| {
+        collectors
+            .last_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    });
+    drop(guard);
+    (ret, stats)
+}
+
+/// Returns whether statistics are being collected on the current thread.
+pub(crate) fn is_collecting() -> bool {
+    COLLECTORS
+        .try_with(|collectors| !collectors.borrow().is_empty())
+        .unwrap_or(false)
+}
+
+fn submit(stats: DecoderStats) {
+    if stats.is_empty() {
+        return;
+    }
+    let _ = COLLECTORS.try_with(|collectors| {
+        let mut collectors = collectors.borrow_mut();
+        let Some(collected) = collectors.last_mut() else {
+            return;
+        };
+        if let Some(acc) = collected.iter_mut().find(|acc| acc.is_compatible(&stats)) {
+            acc.merge(&stats);
+        } else {
+            collected.push(stats);
+        }
+    });
+}
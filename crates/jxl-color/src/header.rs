@@ -1,8 +1,10 @@
 #![allow(clippy::excessive_precision)]
-use jxl_bitstream::{define_bundle, read_bits, Bitstream, Bundle, Error, Result};
+use jxl_bitstream::{
+    define_bundle, read_bits, write_bits, BitWriter, Bitstream, Bundle, BundleWrite, Error, Result,
+};
 
 define_bundle! {
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct ColourEncoding {
         all_default: ty(Bool) default(true),
         pub want_icc: ty(Bool) cond(!all_default) default(false),
@@ -19,7 +21,7 @@ define_bundle! {
         pub y: ty(U32(u(19), 524288 + u(19), 1048576 + u(20), 2097152 + u(21)); UnpackSigned),
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct ToneMapping {
         all_default: ty(Bool) default(true),
         pub intensity_target: ty(F16) cond(!all_default) default(255.0),
@@ -30,6 +32,28 @@ define_bundle! {
 }
 
 impl ColourEncoding {
+    /// Creates a `ColourEncoding` which signals an embedded ICC profile.
+    ///
+    /// `colour_space` should match the colour space of the profile.
+    pub fn with_icc(colour_space: ColourSpace) -> Self {
+        Self {
+            all_default: false,
+            want_icc: true,
+            colour_space,
+            white_point: WhitePoint::D65,
+            primaries: Primaries::Srgb,
+            tf: TransferFunction::Srgb,
+            rendering_intent: RenderingIntent::Relative,
+        }
+    }
+
+    /// Marks the fields as explicitly signalled, so that modified values are written to the
+    /// bitstream.
+    #[inline]
+    pub fn set_explicit(&mut self) {
+        self.all_default = false;
+    }
+
     /// Returns whether this `ColourEncoding` represents the sRGB colorspace.
     #[inline]
     pub fn is_srgb(&self) -> bool {
@@ -55,6 +79,15 @@ impl ColourEncoding {
     }
 }
 
+impl ToneMapping {
+    /// Marks the fields as explicitly signalled, so that modified values are written to the
+    /// bitstream.
+    #[inline]
+    pub fn set_explicit(&mut self) {
+        self.all_default = false;
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum ColourSpace {
@@ -78,7 +111,7 @@ impl TryFrom<u32> for ColourSpace {
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 enum WhitePointDiscriminator {
     D65 = 1,
@@ -128,7 +161,23 @@ impl<Ctx> Bundle<Ctx> for WhitePoint {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Default)]
+impl<Ctx> BundleWrite<Ctx> for WhitePoint {
+    fn write(&self, writer: &mut BitWriter, _ctx: Ctx) -> Result<()> {
+        let d = match self {
+            Self::D65 => WhitePointDiscriminator::D65,
+            Self::Custom(_) => WhitePointDiscriminator::Custom,
+            Self::E => WhitePointDiscriminator::E,
+            Self::Dci => WhitePointDiscriminator::Dci,
+        };
+        write_bits!(writer, Enum(WhitePointDiscriminator), &d)?;
+        if let Self::Custom(white) = self {
+            write_bits!(writer, Bundle(Customxy), white)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
 #[repr(u8)]
 enum PrimariesDiscriminator {
     #[default]
@@ -185,6 +234,24 @@ impl<Ctx> Bundle<Ctx> for Primaries {
     }
 }
 
+impl<Ctx> BundleWrite<Ctx> for Primaries {
+    fn write(&self, writer: &mut BitWriter, _ctx: Ctx) -> Result<()> {
+        let d = match self {
+            Self::Srgb => PrimariesDiscriminator::Srgb,
+            Self::Custom { .. } => PrimariesDiscriminator::Custom,
+            Self::Bt2100 => PrimariesDiscriminator::Bt2100,
+            Self::P3 => PrimariesDiscriminator::P3,
+        };
+        write_bits!(writer, Enum(PrimariesDiscriminator), &d)?;
+        if let Self::Custom { red, green, blue } = self {
+            write_bits!(writer, Bundle(Customxy), red)?;
+            write_bits!(writer, Bundle(Customxy), green)?;
+            write_bits!(writer, Bundle(Customxy), blue)?;
+        }
+        Ok(())
+    }
+}
+
 impl Primaries {
     pub fn cicp(&self) -> Option<u8> {
         match self {
@@ -265,6 +332,26 @@ impl<Ctx> Bundle<Ctx> for TransferFunction {
     }
 }
 
+impl<Ctx> BundleWrite<Ctx> for TransferFunction {
+    fn write(&self, writer: &mut BitWriter, _ctx: Ctx) -> Result<()> {
+        let value = match *self {
+            Self::Gamma(gamma) => {
+                writer.write_bool(true)?;
+                return writer.write_bits(24, gamma);
+            }
+            Self::Bt709 => 1,
+            Self::Unknown => 2,
+            Self::Linear => 8,
+            Self::Srgb => 13,
+            Self::Pq => 16,
+            Self::Dci => 17,
+            Self::Hlg => 18,
+        };
+        writer.write_bool(false)?;
+        write_bits!(writer, U32(0, 1, 2 + u(4), 18 + u(6)), &value)
+    }
+}
+
 impl TransferFunction {
     pub fn cicp(&self) -> Option<u8> {
         match self {
@@ -281,7 +368,7 @@ impl TransferFunction {
 }
 
 define_bundle! {
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct OpsinInverseMatrix {
         all_default: ty(Bool) default(true),
         pub inv_mat: ty(Array[Array[F16]; 3]; 3) cond(!all_default) default([
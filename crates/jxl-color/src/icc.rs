@@ -1,13 +1,14 @@
 //! Functions related to ICC profiles.
 //!
 //! - [`read_icc`] and [`decode_icc`] can be used to read embedded ICC profile from the bitstream.
+//! - [`encode_icc`] and [`write_icc`] can be used to embed an ICC profile into the bitstream.
 //! - [`colour_encoding_to_icc`] can be used to create an ICC profile to embed into the decoded
 //!   image file, or to be used by the color management system for various purposes.
 
 use std::io::prelude::*;
 use std::io::Cursor;
 
-use jxl_bitstream::Bitstream;
+use jxl_bitstream::{BitWriter, Bitstream};
 
 use crate::{
     ciexyz::*, consts::*, tf, ColourEncoding, ColourSpace, Error, Primaries, RenderingIntent,
@@ -27,6 +28,7 @@ pub fn read_icc(bitstream: &mut Bitstream) -> Result<Vec<u8>> {
     }
 
     let mut decoder = jxl_coding::Decoder::parse(bitstream, 41)?;
+    decoder.set_label("ICC profile");
 
     let mut encoded_icc = vec![0u8; enc_size as usize];
     let mut b1 = 0u8;
@@ -46,6 +48,25 @@ pub fn read_icc(bitstream: &mut Bitstream) -> Result<Vec<u8>> {
     Ok(encoded_icc)
 }
 
+/// Writes the encoded ICC profile stream to the given bit writer.
+///
+/// The stream is entropy coded with the same context model as [`read_icc`].
+pub fn write_icc(writer: &mut BitWriter, encoded_icc: &[u8]) -> Result<()> {
+    writer.write_u64(encoded_icc.len() as u64)?;
+
+    let mut encoder = jxl_coding::Encoder::new(41);
+    encoder.begin_stream();
+    let mut b1 = 0u8;
+    let mut b2 = 0u8;
+    for (idx, &b) in encoded_icc.iter().enumerate() {
+        encoder.push(get_icc_ctx(idx, b1, b2), b as u32);
+        b2 = b1;
+        b1 = b;
+    }
+    encoder.write(writer)?;
+    Ok(())
+}
+
 fn get_icc_ctx(idx: usize, b1: u8, b2: u8) -> u32 {
     if idx <= 128 {
         return 0;
@@ -154,6 +175,43 @@ fn shuffle4(bytes: &[u8]) -> Vec<u8> {
     out
 }
 
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Encodes the given ICC profile into an ICC profile stream, which can be decoded with
+/// [`decode_icc`].
+///
+/// Only the header prediction is applied; the rest of the profile is stored as-is.
+pub fn encode_icc(icc: &[u8]) -> Vec<u8> {
+    let output_size = icc.len();
+    let header_size = output_size.min(128);
+
+    let mut commands = Vec::new();
+    if output_size > 128 {
+        // No tag list, followed by a single command copying the remaining bytes.
+        write_varint(&mut commands, 0);
+        commands.push(1);
+        write_varint(&mut commands, (output_size - 128) as u64);
+    }
+
+    let mut out = Vec::with_capacity(output_size + commands.len() + 16);
+    write_varint(&mut out, output_size as u64);
+    write_varint(&mut out, commands.len() as u64);
+    out.extend_from_slice(&commands);
+    let header = &icc[..header_size];
+    for (idx, &b) in header.iter().enumerate() {
+        let p = predict_header(idx, output_size as u32, header);
+        out.push(b.wrapping_sub(p));
+    }
+    out.extend_from_slice(&icc[header_size..]);
+    out
+}
+
 /// Decodes the given ICC profile stream.
 pub fn decode_icc(stream: &[u8]) -> Result<Vec<u8>> {
     const COMMON_TAGS: [&[u8]; 19] = [
@@ -1,7 +1,9 @@
 use jxl_bitstream::{define_bundle, read_bits, Bitstream, Bundle};
 use jxl_grid::AllocTracker;
-use jxl_image::ImageHeader;
-use jxl_modular::{ChannelShift, MaConfig, Modular, ModularChannelParams, ModularParams};
+use jxl_image::{ImageHeader, Level};
+use jxl_modular::{
+    ChannelShift, MaConfig, MaTreeLimits, Modular, ModularChannelParams, ModularParams,
+};
 use jxl_vardct::{HfBlockContext, LfChannelCorrelation, LfChannelDequantization, Quantizer};
 
 use crate::{header::Encoding, FrameHeader, Result};
@@ -24,6 +26,7 @@ pub struct LfGlobalParams<'a, 'b> {
     pub frame_header: &'a FrameHeader,
     pub tracker: Option<&'b AllocTracker>,
     pub allow_partial: bool,
+    pub level: Option<Level>,
 }
 
 impl<'a, 'b> LfGlobalParams<'a, 'b> {
@@ -38,8 +41,14 @@ impl<'a, 'b> LfGlobalParams<'a, 'b> {
             frame_header,
             tracker,
             allow_partial,
+            level: None,
         }
     }
+
+    /// Sets the codestream level to enforce the limits of.
+    pub fn with_level(self, level: Option<Level>) -> Self {
+        Self { level, ..self }
+    }
 }
 
 impl Bundle<LfGlobalParams<'_, '_>> for LfGlobal {
@@ -49,6 +58,7 @@ impl Bundle<LfGlobalParams<'_, '_>> for LfGlobal {
         let LfGlobalParams {
             image_header,
             frame_header: header,
+            level,
             ..
         } = params;
         let patches = header
@@ -88,6 +98,27 @@ impl Bundle<LfGlobalParams<'_, '_>> for LfGlobal {
                 Splines::parse(bitstream, header)
             })
             .transpose()?;
+        if let (Some(level), Some(splines)) = (level, &splines) {
+            let limits = level.limits();
+            let num_splines = splines.quant_splines.len();
+            if num_splines > limits.max_splines {
+                tracing::error!(num_splines, level = level.as_u8(), "Too many splines");
+                return Err(level.error("number of splines").into());
+            }
+            let num_control_points = splines
+                .quant_splines
+                .iter()
+                .map(|spline| spline.quant_points.len())
+                .sum::<usize>();
+            if num_control_points > limits.max_spline_control_points {
+                tracing::error!(
+                    num_control_points,
+                    level = level.as_u8(),
+                    "Too many spline control points"
+                );
+                return Err(level.error("number of spline control points").into());
+            }
+        }
         let noise = header
             .flags
             .noise()
@@ -116,7 +147,7 @@ impl Bundle<LfGlobalParams<'_, '_>> for LfGlobal {
 }
 
 define_bundle! {
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct LfGlobalVarDct error(crate::Error) {
         pub quantizer: ty(Bundle(Quantizer)),
         pub hf_block_ctx: ty(Bundle(HfBlockContext)),
@@ -158,6 +189,7 @@ impl Bundle<LfGlobalParams<'_, '_>> for GlobalModular {
             frame_header: header,
             tracker,
             allow_partial,
+            level,
         } = params;
         let span = tracing::span!(tracing::Level::TRACE, "Decode GlobalModular");
         let _guard = span.enter();
@@ -229,6 +261,10 @@ impl Bundle<LfGlobalParams<'_, '_>> for GlobalModular {
                 )
                 .into());
             }
+
+            if let Some(limits) = ma_tree_limits(level) {
+                limits.check(ma_config)?;
+            }
         }
 
         let group_dim = header.group_dim();
@@ -238,7 +274,8 @@ impl Bundle<LfGlobalParams<'_, '_>> for GlobalModular {
             shifts,
             ma_config.as_ref(),
             tracker,
-        );
+        )
+        .with_ma_tree_limits(ma_tree_limits(level));
         let mut modular = read_bits!(bitstream, Bundle(Modular), modular_params)?;
         if let Some(image) = modular.image_mut() {
             let mut gmodular = image.prepare_gmodular()?;
@@ -252,3 +289,15 @@ impl Bundle<LfGlobalParams<'_, '_>> for GlobalModular {
         })
     }
 }
+
+/// Returns the limits on the size of MA trees of the codestream level, if it's being enforced.
+pub(crate) fn ma_tree_limits(level: Option<Level>) -> Option<MaTreeLimits> {
+    level.map(|level| {
+        let limits = level.limits();
+        MaTreeLimits {
+            level: level.as_u8(),
+            max_nodes: limits.max_ma_tree_nodes,
+            max_depth: limits.max_ma_tree_depth,
+        }
+    })
+}
@@ -1,6 +1,6 @@
 use jxl_bitstream::{Bitstream, Bundle};
 use jxl_grid::AllocTracker;
-use jxl_modular::{image::TransformedModularSubimage, MaConfig};
+use jxl_modular::{image::TransformedModularSubimage, MaConfig, MaTreeLimits};
 use jxl_vardct::{HfMetadata, HfMetadataParams, LfCoeff, LfCoeffParams, Quantizer};
 
 use crate::{filter::EdgePreservingFilter, header::Encoding, FrameHeader, Result};
@@ -10,6 +10,8 @@ pub struct LfGroupParams<'a, 'dest, 'tracker> {
     pub frame_header: &'a FrameHeader,
     pub quantizer: Option<&'a Quantizer>,
     pub global_ma_config: Option<&'a MaConfig>,
+    /// Limits on the size of local MA trees of the LF group.
+    pub ma_tree_limits: Option<MaTreeLimits>,
     pub mlf_group: Option<TransformedModularSubimage<'dest>>,
     pub lf_group_idx: u32,
     pub allow_partial: bool,
@@ -31,6 +33,7 @@ impl Bundle<LfGroupParams<'_, '_, '_>> for LfGroup {
         let LfGroupParams {
             frame_header,
             global_ma_config,
+            ma_tree_limits,
             mlf_group,
             lf_group_idx,
             allow_partial,
@@ -50,6 +53,7 @@ impl Bundle<LfGroupParams<'_, '_, '_>> for LfGroup {
                 jpeg_upsampling: frame_header.jpeg_upsampling,
                 bits_per_sample: frame_header.bit_depth.bits_per_sample(),
                 global_ma_config,
+                ma_tree_limits,
                 allow_partial,
                 tracker,
                 pool,
@@ -90,6 +94,7 @@ impl Bundle<LfGroupParams<'_, '_, '_>> for LfGroup {
                     jpeg_upsampling: frame_header.jpeg_upsampling,
                     bits_per_sample: frame_header.bit_depth.bits_per_sample(),
                     global_ma_config,
+                    ma_tree_limits,
                     epf: match &frame_header.restoration_filter.epf {
                         EdgePreservingFilter::Disabled => None,
                         EdgePreservingFilter::Enabled {
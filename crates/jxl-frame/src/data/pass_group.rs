@@ -2,33 +2,36 @@ use jxl_bitstream::Bitstream;
 use jxl_grid::{AllocTracker, CutGrid};
 use jxl_modular::{image::TransformedModularSubimage, ChannelShift, MaConfig};
 use jxl_threadpool::JxlThreadPool;
-use jxl_vardct::{write_hf_coeff, HfCoeffParams};
+use jxl_vardct::{write_hf_coeff, HfCoeffParams, HfCoeffSample};
 
 use super::{HfGlobal, LfGlobalVarDct, LfGroup};
 use crate::{FrameHeader, Result};
 
 #[derive(Debug)]
-pub struct PassGroupParams<'frame, 'buf, 'g, 'tracker> {
+pub struct PassGroupParams<'frame, 'buf, 'g, 'tracker, S: HfCoeffSample = f32> {
     pub frame_header: &'frame FrameHeader,
     pub lf_group: &'frame LfGroup,
     pub pass_idx: u32,
     pub group_idx: u32,
     pub global_ma_config: Option<&'frame MaConfig>,
     pub modular: Option<TransformedModularSubimage<'g>>,
-    pub vardct: Option<PassGroupParamsVardct<'frame, 'buf, 'g>>,
+    pub vardct: Option<PassGroupParamsVardct<'frame, 'buf, 'g, S>>,
     pub allow_partial: bool,
     pub tracker: Option<&'tracker AllocTracker>,
     pub pool: &'frame JxlThreadPool,
 }
 
 #[derive(Debug)]
-pub struct PassGroupParamsVardct<'frame, 'buf, 'g> {
+pub struct PassGroupParamsVardct<'frame, 'buf, 'g, S: HfCoeffSample = f32> {
     pub lf_vardct: &'frame LfGlobalVarDct,
     pub hf_global: &'frame HfGlobal,
-    pub hf_coeff_output: &'buf mut [CutGrid<'g, f32>; 3],
+    pub hf_coeff_output: &'buf mut [CutGrid<'g, S>; 3],
 }
 
-pub fn decode_pass_group(bitstream: &mut Bitstream, params: PassGroupParams) -> Result<()> {
+pub fn decode_pass_group<S: HfCoeffSample>(
+    bitstream: &mut Bitstream,
+    params: PassGroupParams<S>,
+) -> Result<()> {
     let PassGroupParams {
         frame_header,
         lf_group,
@@ -97,6 +97,7 @@ impl Bundle<(&ImageHeader, &FrameHeader)> for Patches {
             .collect::<Vec<_>>();
 
         let mut decoder = jxl_coding::Decoder::parse(bitstream, 10)?;
+        decoder.set_label("patches");
         decoder.begin(bitstream)?;
 
         let num_patches = decoder.read_varint(bitstream, 0)?;
@@ -18,6 +18,7 @@ impl Bundle<&FrameHeader> for Splines {
 
     fn parse(bitstream: &mut Bitstream, header: &FrameHeader) -> Result<Self> {
         let mut decoder = jxl_coding::Decoder::parse(bitstream, 6)?;
+        decoder.set_label("splines");
         decoder.begin(bitstream)?;
 
         let num_splines = decoder.read_varint(bitstream, 2)? as usize;
@@ -1,5 +1,5 @@
 use crate::Result;
-use jxl_bitstream::{read_bits, Bitstream, Bundle};
+use jxl_bitstream::{read_bits, write_bits, BitWriter, Bitstream, Bundle, BundleWrite};
 
 /// Table of contents of a frame.
 ///
@@ -160,6 +160,71 @@ impl Toc {
 }
 
 impl Toc {
+    /// Creates a non-permuted TOC from sizes of sections in bytes, in the original order.
+    ///
+    /// Offsets of sections are relative to the beginning of the first section.
+    ///
+    /// # Errors
+    /// Returns `Error::ValidationFailed` if the number of sections doesn't match the frame header.
+    pub fn from_sizes(frame_header: &crate::FrameHeader, sizes: &[u32]) -> Result<Self> {
+        if sizes.len() != Self::entry_count(frame_header) as usize {
+            return Err(jxl_bitstream::Error::ValidationFailed("TOC entry count mismatch").into());
+        }
+
+        let mut offset = 0usize;
+        let groups = sizes
+            .iter()
+            .zip(Self::section_kinds(frame_header))
+            .map(|(&size, kind)| {
+                let group = TocGroup { kind, offset, size };
+                offset += size as usize;
+                group
+            })
+            .collect();
+
+        Ok(Self {
+            num_lf_groups: frame_header.num_lf_groups() as usize,
+            num_groups: frame_header.num_groups() as usize,
+            groups,
+            bitstream_to_original: Vec::new(),
+            original_to_bitstream: Vec::new(),
+            total_size: offset,
+        })
+    }
+
+    fn entry_count(frame_header: &crate::FrameHeader) -> u32 {
+        let num_groups = frame_header.num_groups();
+        let num_passes = frame_header.passes.num_passes;
+        if num_groups == 1 && num_passes == 1 {
+            1
+        } else {
+            1 + frame_header.num_lf_groups() + 1 + num_groups * num_passes
+        }
+    }
+
+    fn section_kinds(frame_header: &crate::FrameHeader) -> Vec<TocGroupKind> {
+        let entry_count = Self::entry_count(frame_header);
+        if entry_count == 1 {
+            return vec![TocGroupKind::All];
+        }
+
+        let mut out = Vec::with_capacity(entry_count as usize);
+        out.push(TocGroupKind::LfGlobal);
+        for idx in 0..frame_header.num_lf_groups() {
+            out.push(TocGroupKind::LfGroup(idx));
+        }
+        out.push(TocGroupKind::HfGlobal);
+        for pass_idx in 0..frame_header.passes.num_passes {
+            for group_idx in 0..frame_header.num_groups() {
+                out.push(TocGroupKind::GroupPass {
+                    pass_idx,
+                    group_idx,
+                });
+            }
+        }
+        out
+    }
+
     pub(crate) fn adjust_offsets(&mut self, global_frame_offset: usize) {
         if global_frame_offset == 0 {
             return;
@@ -179,14 +244,7 @@ impl Bundle<&crate::FrameHeader> for Toc {
 
     fn parse(bitstream: &mut Bitstream, ctx: &crate::FrameHeader) -> Result<Self> {
         let num_groups = ctx.num_groups();
-        let num_passes = ctx.passes.num_passes;
-
-        let entry_count = if num_groups == 1 && num_passes == 1 {
-            1
-        } else {
-            1 + ctx.num_lf_groups() + 1 + num_groups * num_passes
-        };
-
+        let entry_count = Self::entry_count(ctx);
         if entry_count > 65536 {
             return Err(jxl_bitstream::Error::ValidationFailed("Too many TOC entries").into());
         }
@@ -194,6 +252,7 @@ impl Bundle<&crate::FrameHeader> for Toc {
         let permutated_toc = bitstream.read_bool()?;
         let permutation = if permutated_toc {
             let mut decoder = jxl_coding::Decoder::parse(bitstream, 8)?;
+            decoder.set_label("TOC permutation");
             decoder.begin(bitstream)?;
             let permutation =
                 jxl_coding::read_permutation(bitstream, &mut decoder, entry_count, 0)?;
@@ -223,25 +282,7 @@ impl Bundle<&crate::FrameHeader> for Toc {
             total_size += size as usize;
         }
 
-        let section_kinds = if entry_count == 1 {
-            vec![TocGroupKind::All]
-        } else {
-            let mut out = Vec::with_capacity(entry_count as usize);
-            out.push(TocGroupKind::LfGlobal);
-            for idx in 0..ctx.num_lf_groups() {
-                out.push(TocGroupKind::LfGroup(idx));
-            }
-            out.push(TocGroupKind::HfGlobal);
-            for pass_idx in 0..num_passes {
-                for group_idx in 0..num_groups {
-                    out.push(TocGroupKind::GroupPass {
-                        pass_idx,
-                        group_idx,
-                    });
-                }
-            }
-            out
-        };
+        let section_kinds = Self::section_kinds(ctx);
 
         let (offsets, sizes, bitstream_to_original, original_to_bitstream) = if permutated_toc {
             let mut bitstream_to_original = vec![0usize; permutation.len()];
@@ -274,3 +315,33 @@ impl Bundle<&crate::FrameHeader> for Toc {
         })
     }
 }
+
+impl BundleWrite<&crate::FrameHeader> for Toc {
+    /// Writes the TOC.
+    ///
+    /// Permuted TOC is not supported yet, and returns `Error::Unrepresentable`.
+    fn write(&self, writer: &mut BitWriter, ctx: &crate::FrameHeader) -> jxl_bitstream::Result<()> {
+        if self.groups.len() != Self::entry_count(ctx) as usize {
+            return Err(jxl_bitstream::Error::Unrepresentable(
+                "TOC entry count doesn't match the frame header",
+            ));
+        }
+        if !self.bitstream_to_original.is_empty() {
+            return Err(jxl_bitstream::Error::Unrepresentable(
+                "writing permuted TOC is not supported",
+            ));
+        }
+
+        writer.write_bool(false)?;
+        writer.zero_pad_to_byte();
+        for group in &self.groups {
+            write_bits!(
+                writer,
+                U32(u(10), 1024 + u(14), 17408 + u(22), 4211712 + u(30)),
+                &group.size
+            )?;
+        }
+        writer.zero_pad_to_byte();
+        Ok(())
+    }
+}
@@ -1,3 +1,7 @@
+use jxl_bitstream::ErrorLocation;
+
+use crate::data::TocGroupKind;
+
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum Error {
@@ -7,7 +11,17 @@ pub enum Error {
     Modular(jxl_modular::Error),
     VarDct(jxl_vardct::Error),
     InvalidTocPermutation,
-    IncompleteFrameData { field: &'static str },
+    IncompleteFrameData {
+        field: &'static str,
+    },
+    /// An error with the TOC group and the location where it occurred.
+    ///
+    /// `location` is `None` if the location is recorded in the inner error.
+    Located {
+        error: Box<Error>,
+        group: Option<TocGroupKind>,
+        location: Option<ErrorLocation>,
+    },
 }
 
 impl From<jxl_bitstream::Error> for Error {
@@ -52,6 +66,19 @@ impl std::fmt::Display for Error {
             Self::IncompleteFrameData { field } => {
                 write!(f, "incomplete frame data: {} is missing", field)
             }
+            Self::Located {
+                error,
+                group,
+                location,
+            } => {
+                write!(f, "{error}")?;
+                match (group, location) {
+                    (Some(group), Some(location)) => write!(f, " (in {group:?}, {location})"),
+                    (Some(group), None) => write!(f, " (in {group:?})"),
+                    (None, Some(location)) => write!(f, " ({location})"),
+                    (None, None) => Ok(()),
+                }
+            }
         }
     }
 }
@@ -64,6 +91,7 @@ impl std::error::Error for Error {
             Self::Buffer(err) => Some(err),
             Self::Modular(err) => Some(err),
             Self::VarDct(err) => Some(err),
+            Self::Located { error, .. } => error.source(),
             _ => None,
         }
     }
@@ -72,7 +100,7 @@ impl std::error::Error for Error {
 impl Error {
     /// Returns whether the error is caused by the unexpected EOF of the bitstream.
     pub fn unexpected_eof(&self) -> bool {
-        let bitstream_err = match self {
+        let bitstream_err = match self.inner() {
             Self::Bitstream(b)
             | Self::Decoder(jxl_coding::Error::Bitstream(b))
             | Self::Modular(jxl_modular::Error::Decoder(jxl_coding::Error::Bitstream(b)))
@@ -85,10 +113,107 @@ impl Error {
             ))) => b,
             _ => return false,
         };
-        if let jxl_bitstream::Error::Io(e) = bitstream_err {
-            e.kind() == std::io::ErrorKind::UnexpectedEof
-        } else {
-            false
+        bitstream_err.unexpected_eof()
+    }
+
+    /// Returns the error without the TOC group and the location.
+    pub fn inner(&self) -> &Error {
+        match self {
+            Self::Located { error, .. } => error.inner(),
+            _ => self,
+        }
+    }
+
+    /// Returns the TOC group where the error occurred, if it's known.
+    pub fn group(&self) -> Option<TocGroupKind> {
+        match self {
+            Self::Located {
+                group: Some(group), ..
+            } => Some(*group),
+            Self::Located { error, .. } => error.group(),
+            _ => None,
+        }
+    }
+
+    /// Returns the location where the error occurred, if it's known.
+    ///
+    /// Bit offset of errors occurred in a TOC group is measured from the beginning of the
+    /// codestream, if the codestream offset of the frame is set with
+    /// [`Frame::set_codestream_offset`][crate::Frame::set_codestream_offset].
+    pub fn location(&self) -> Option<&ErrorLocation> {
+        match self {
+            Self::Bitstream(e) => e.location(),
+            Self::Decoder(e) => e.location(),
+            Self::Modular(e) => e.location(),
+            Self::VarDct(e) => e.location(),
+            Self::Located {
+                location: Some(location),
+                ..
+            } => Some(location),
+            Self::Located { error, .. } => error.location(),
+            _ => None,
+        }
+    }
+
+    /// Returns the mutable reference to the location where the error occurred, if it's known.
+    pub fn location_mut(&mut self) -> Option<&mut ErrorLocation> {
+        match self {
+            Self::Bitstream(e) => e.location_mut(),
+            Self::Decoder(e) => e.location_mut(),
+            Self::Modular(e) => e.location_mut(),
+            Self::VarDct(e) => e.location_mut(),
+            Self::Located {
+                location: Some(location),
+                ..
+            } => Some(location),
+            Self::Located { error, .. } => error.location_mut(),
+            _ => None,
+        }
+    }
+
+    /// Records that the error occurred in the TOC group, at `bit_offset` from the beginning of
+    /// the group.
+    ///
+    /// `group_offset` is the byte offset of the group, which the bit offset is rebased onto.
+    pub(crate) fn in_group(
+        mut self,
+        group: TocGroupKind,
+        group_offset: u64,
+        bit_offset: usize,
+    ) -> Self {
+        if self.group().is_some() {
+            return self;
+        }
+
+        let location = match self.location_mut() {
+            Some(location) => {
+                location.set_bit_offset_if_missing(bit_offset as u64);
+                location.rebase(group_offset * 8);
+                None
+            }
+            None => Some(ErrorLocation::at_bit(group_offset * 8 + bit_offset as u64)),
+        };
+        Self::Located {
+            error: Box::new(self),
+            group: Some(group),
+            location,
+        }
+    }
+}
+
+impl jxl_bitstream::LocateError for Error {
+    fn in_field(mut self, bundle: &'static str, field: &'static str, bit_offset: usize) -> Self {
+        if let Some(location) = self.location_mut() {
+            location.push_field(bundle, field, bit_offset);
+            return self;
+        }
+
+        let mut location = ErrorLocation::default();
+        location.push_field(bundle, field, bit_offset);
+        Self::Located {
+            error: Box::new(self),
+            group: None,
+            location: Some(location),
         }
     }
 }
@@ -1,5 +1,5 @@
 use crate::{header::Encoding, Result};
-use jxl_bitstream::{Bitstream, Bundle};
+use jxl_bitstream::{BitWriter, Bitstream, Bundle, BundleWrite};
 
 #[derive(Debug, Clone)]
 pub enum Gabor {
@@ -43,13 +43,34 @@ impl<Ctx> Bundle<Ctx> for Gabor {
     }
 }
 
+impl<Ctx> BundleWrite<Ctx> for Gabor {
+    fn write(&self, writer: &mut BitWriter, _ctx: Ctx) -> jxl_bitstream::Result<()> {
+        let Self::Enabled(weights) = self else {
+            return writer.write_bool(false);
+        };
+        writer.write_bool(true)?;
+
+        let Self::Enabled(default_weights) = Self::default() else {
+            unreachable!()
+        };
+        let custom = *weights != default_weights;
+        writer.write_bool(custom)?;
+        if custom {
+            for &weight in weights.iter().flatten() {
+                writer.write_f32_as_f16(weight)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Gabor {
     pub fn enabled(&self) -> bool {
         matches!(self, Self::Enabled(_))
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum EdgePreservingFilter {
     Disabled,
     Enabled {
@@ -154,7 +175,62 @@ impl Bundle<Encoding> for EdgePreservingFilter {
     }
 }
 
-#[derive(Debug)]
+impl BundleWrite<Encoding> for EdgePreservingFilter {
+    fn write(&self, writer: &mut BitWriter, encoding: Encoding) -> jxl_bitstream::Result<()> {
+        let Self::Enabled {
+            iters,
+            sharp_lut,
+            channel_scale,
+            sigma,
+            sigma_for_modular,
+        } = self
+        else {
+            return writer.write_bits(2, 0);
+        };
+        if *iters == 0 {
+            return Err(jxl_bitstream::Error::Unrepresentable(
+                "enabled EPF should have at least one iteration",
+            ));
+        }
+        writer.write_bits(2, *iters)?;
+
+        if encoding == Encoding::VarDct {
+            let sharp_custom = *sharp_lut != Self::SHARP_LUT_DEFAULT;
+            writer.write_bool(sharp_custom)?;
+            if sharp_custom {
+                for &v in sharp_lut {
+                    writer.write_f32_as_f16(v)?;
+                }
+            }
+        } else if *sharp_lut != Self::SHARP_LUT_DEFAULT {
+            return Err(jxl_bitstream::Error::Unrepresentable(
+                "custom EPF sharpness LUT is not available in Modular frames",
+            ));
+        }
+
+        let weight_custom = *channel_scale != Self::CHANNEL_SCALE_DEFAULT;
+        writer.write_bool(weight_custom)?;
+        if weight_custom {
+            for &v in channel_scale {
+                writer.write_f32_as_f16(v)?;
+            }
+            writer.write_bits(32, 0)?;
+        }
+
+        let sigma_custom = *sigma != EpfSigma::default();
+        writer.write_bool(sigma_custom)?;
+        if sigma_custom {
+            sigma.write(writer, encoding)?;
+        }
+
+        if encoding == Encoding::Modular {
+            writer.write_f32_as_f16(*sigma_for_modular)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct EpfSigma {
     pub quant_mul: f32,
     pub pass0_sigma_scale: f32,
@@ -189,3 +265,14 @@ impl Bundle<Encoding> for EpfSigma {
         })
     }
 }
+
+impl BundleWrite<Encoding> for EpfSigma {
+    fn write(&self, writer: &mut BitWriter, encoding: Encoding) -> jxl_bitstream::Result<()> {
+        if encoding == Encoding::VarDct {
+            writer.write_f32_as_f16(self.quant_mul)?;
+        }
+        writer.write_f32_as_f16(self.pass0_sigma_scale)?;
+        writer.write_f32_as_f16(self.pass2_sigma_scale)?;
+        writer.write_f32_as_f16(self.border_sad_mul)
+    }
+}
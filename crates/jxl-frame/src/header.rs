@@ -1,10 +1,13 @@
 use crate::Result;
-use jxl_bitstream::{define_bundle, read_bits, Bitstream, Bundle, Name};
+use jxl_bitstream::{
+    define_bundle, read_bits, write_bits, BitWriter, Bitstream, Bundle, BundleDefault, BundleWrite,
+    Name,
+};
 use jxl_image::{BitDepth, Extensions, ImageHeader, SizeHeader};
 
 define_bundle! {
     /// Frame header.
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct FrameHeader ctx(headers: &ImageHeader) error(crate::Error) {
         all_default: ty(Bool) default(true),
         pub frame_type: ty(Bundle(FrameType)) cond(!all_default) default(FrameType::RegularFrame),
@@ -116,7 +119,7 @@ define_bundle! {
         pub bit_depth: ty(Bundle(BitDepth)) cond(false) default(headers.metadata.bit_depth),
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct Passes error(crate::Error) {
         pub num_passes: ty(U32(1, 2, 3, 4 + u(3))) default(1),
         pub num_ds: ty(U32(0, 1, 2, 3 + u(1))) cond(num_passes != 1) default(0),
@@ -125,7 +128,7 @@ define_bundle! {
         pub last_pass: ty(Vec[U32(0, 1, 2, u(3))]; num_ds) cond(num_passes != 1) default(vec![0; num_ds as usize]),
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct BlendingInfo ctx(context: (bool, Option<BlendMode>, bool)) error(crate::Error) {
         pub mode: ty(Bundle(BlendMode)),
         pub alpha_channel:
@@ -142,7 +145,7 @@ define_bundle! {
             default(0),
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct RestorationFilter ctx(encoding: Encoding) error(crate::Error) {
         all_default: ty(Bool) default(true),
         pub gab: ty(Bundle(crate::filter::Gabor)) cond(!all_default),
@@ -152,6 +155,23 @@ define_bundle! {
 }
 
 impl FrameHeader {
+    /// Creates a header of a single, full-image Modular frame without restoration filters, which
+    /// is suitable for lossless images.
+    pub fn lossless_modular(headers: &ImageHeader) -> Self {
+        let mut restoration_filter = RestorationFilter::default_with_context(Encoding::Modular);
+        restoration_filter.all_default = false;
+        restoration_filter.gab = crate::filter::Gabor::Disabled;
+        restoration_filter.epf = crate::filter::EdgePreservingFilter::Disabled;
+
+        let mut header = Self::default_with_context(headers);
+        header.all_default = false;
+        header.encoding = Encoding::Modular;
+        header.ec_blending_info =
+            vec![header.blending_info.clone(); headers.metadata.ec_info.len()];
+        header.restoration_filter = restoration_filter;
+        header
+    }
+
     fn test_full_image(x0: i32, y0: i32, width: u32, height: u32, size: &SizeHeader) -> bool {
         if x0 > 0 || y0 > 0 {
             return false;
@@ -336,6 +356,27 @@ impl FrameHeader {
     }
 }
 
+impl Passes {
+    /// Returns the number of passes needed to render the frame downscaled by `downsample`, as
+    /// signalled by the frame header.
+    ///
+    /// All passes are needed if the frame header doesn't signal such a downsampling factor.
+    pub fn num_passes_for_downsample(&self, downsample: u32) -> u32 {
+        if downsample <= 1 {
+            return self.num_passes;
+        }
+
+        self.downsample
+            .iter()
+            .zip(&self.last_pass)
+            .filter(|&(&ds, _)| ds <= downsample)
+            .map(|(_, &last_pass)| last_pass + 1)
+            .min()
+            .unwrap_or(self.num_passes)
+            .min(self.num_passes)
+    }
+}
+
 #[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
 #[repr(u8)]
 pub enum FrameType {
@@ -370,6 +411,12 @@ impl<Ctx> Bundle<Ctx> for FrameType {
     }
 }
 
+impl<Ctx> BundleWrite<Ctx> for FrameType {
+    fn write(&self, writer: &mut BitWriter, _ctx: Ctx) -> jxl_bitstream::Result<()> {
+        writer.write_bits(2, *self as u32)
+    }
+}
+
 #[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
 #[repr(u8)]
 pub enum Encoding {
@@ -390,6 +437,12 @@ impl<Ctx> Bundle<Ctx> for Encoding {
     }
 }
 
+impl<Ctx> BundleWrite<Ctx> for Encoding {
+    fn write(&self, writer: &mut BitWriter, _ctx: Ctx) -> jxl_bitstream::Result<()> {
+        writer.write_bits(1, *self as u32)
+    }
+}
+
 #[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
 pub struct FrameFlags(u64);
 
@@ -429,6 +482,12 @@ impl<Ctx> Bundle<Ctx> for FrameFlags {
     }
 }
 
+impl<Ctx> BundleWrite<Ctx> for FrameFlags {
+    fn write(&self, writer: &mut BitWriter, _ctx: Ctx) -> jxl_bitstream::Result<()> {
+        writer.write_u64(self.0)
+    }
+}
+
 #[derive(Debug, Default, PartialEq, Eq, Copy, Clone)]
 #[repr(u8)]
 pub enum BlendMode {
@@ -461,6 +520,12 @@ impl<Ctx> Bundle<Ctx> for BlendMode {
     }
 }
 
+impl<Ctx> BundleWrite<Ctx> for BlendMode {
+    fn write(&self, writer: &mut BitWriter, _ctx: Ctx) -> jxl_bitstream::Result<()> {
+        write_bits!(writer, U32(0, 1, 2, 3 + u(2)), &(*self as u32))
+    }
+}
+
 impl BlendMode {
     #[inline]
     pub fn use_alpha(self) -> bool {
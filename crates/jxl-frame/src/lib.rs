@@ -16,13 +16,14 @@
 //! [`num_lf_groups`]: FrameHeader::num_lf_groups
 //! [`num_groups`]: FrameHeader::num_groups
 //! [`num_passes`]: header::Passes::num_passes
+use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, OnceLock};
 
-use jxl_bitstream::{read_bits, Bitstream, Bundle};
-use jxl_grid::AllocTracker;
-use jxl_image::ImageHeader;
+use jxl_bitstream::{read_bits, Bitstream, Bundle, LocateError};
+use jxl_grid::{AllocHandle, AllocTracker};
+use jxl_image::{ImageHeader, Level};
 
 pub mod data;
 mod error;
@@ -44,12 +45,42 @@ pub struct Frame {
     pool: JxlThreadPool,
     tracker: Option<AllocTracker>,
     image_header: Arc<ImageHeader>,
+    level: Option<Level>,
     header: FrameHeader,
     toc: Toc,
     data: Vec<GroupData>,
     all_group_offsets: AllGroupOffsets,
     reading_data_index: usize,
     pass_shifts: BTreeMap<u32, (i32, i32)>,
+    codestream_offset: u64,
+    data_source: Option<DataSourceRef>,
+    entropy_stats: OnceLock<Mutex<BTreeMap<TocGroupKind, Vec<jxl_coding::DecoderStats>>>>,
+    ma_trees: OnceLock<Mutex<BTreeMap<TocGroupKind, Vec<MaConfig>>>>,
+}
+
+/// Source of frame data which is read on demand.
+///
+/// A frame with a data source attached reads each group only when it is decoded, so that a part of
+/// the frame can be decoded without loading the whole frame data. See
+/// [`Frame::set_data_source`].
+pub trait FrameDataSource: Send + Sync {
+    /// Fills `buf` with the bytes starting at `offset`, measured from the beginning of the
+    /// codestream.
+    fn read_exact_at(&self, offset: u64, buf: &mut [u8]) -> std::io::Result<()>;
+}
+
+#[derive(Clone)]
+struct DataSourceRef {
+    source: Arc<dyn FrameDataSource>,
+    frame_offset: u64,
+}
+
+impl std::fmt::Debug for DataSourceRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DataSourceRef")
+            .field("frame_offset", &self.frame_offset)
+            .finish_non_exhaustive()
+    }
 }
 
 #[derive(Debug, Default)]
@@ -67,10 +98,9 @@ struct GroupData {
 
 impl From<TocGroup> for GroupData {
     fn from(value: TocGroup) -> Self {
-        let cap = value.size as usize;
         Self {
             toc_group: value,
-            bytes: Vec::with_capacity(cap),
+            bytes: Vec::new(),
         }
     }
 }
@@ -80,6 +110,8 @@ pub struct FrameContext<'a> {
     pub image_header: Arc<ImageHeader>,
     pub tracker: Option<&'a AllocTracker>,
     pub pool: JxlThreadPool,
+    /// Codestream level to enforce the limits of, if any.
+    pub level: Option<Level>,
 }
 
 impl Bundle<FrameContext<'_>> for Frame {
@@ -90,6 +122,7 @@ impl Bundle<FrameContext<'_>> for Frame {
             image_header,
             tracker,
             pool,
+            level,
         } = ctx;
         let tracker = tracker.cloned();
 
@@ -114,6 +147,9 @@ impl Bundle<FrameContext<'_>> for Frame {
             );
             return Err(jxl_bitstream::Error::ProfileConformance("frame area too large").into());
         }
+        if let Some(level) = level {
+            level.check_frame_size(header.width, header.height)?;
+        }
 
         for blending_info in std::iter::once(&header.blending_info).chain(&header.ec_blending_info)
         {
@@ -158,7 +194,9 @@ impl Bundle<FrameContext<'_>> for Frame {
             .into());
         }
 
-        let mut toc = read_bits!(bitstream, Bundle(Toc), &header)?;
+        let toc_offset = bitstream.num_read_bits();
+        let mut toc = read_bits!(bitstream, Bundle(Toc), &header)
+            .map_err(|e| e.in_field("Frame", "toc", toc_offset))?;
         toc.adjust_offsets(base_offset);
         let data = toc.iter_bitstream_order().map(GroupData::from).collect();
 
@@ -176,12 +214,17 @@ impl Bundle<FrameContext<'_>> for Frame {
             pool,
             tracker,
             image_header,
+            level,
             header,
             toc,
             data,
             all_group_offsets: AllGroupOffsets::default(),
             reading_data_index: 0,
             pass_shifts,
+            codestream_offset: 0,
+            data_source: None,
+            entropy_stats: OnceLock::new(),
+            ma_trees: OnceLock::new(),
         })
     }
 }
@@ -216,9 +259,91 @@ impl Frame {
         &self.pass_shifts
     }
 
-    pub fn data(&self, group: TocGroupKind) -> Option<&[u8]> {
+    /// Returns the data of the group loaded so far.
+    ///
+    /// If a data source is attached, the group is read from the source, and `None` is returned
+    /// if reading fails.
+    pub fn data(&self, group: TocGroupKind) -> Option<Cow<'_, [u8]>> {
         let idx = self.toc.group_index_bitstream_order(group);
-        self.data.get(idx).map(|b| &*b.bytes)
+        match self.group_bytes(idx)? {
+            Ok((bytes, _)) => Some(bytes.bytes),
+            Err(e) => {
+                tracing::error!(?group, %e, "Failed to read group data");
+                None
+            }
+        }
+    }
+
+    /// Returns the bytes of the group at the bitstream order index, and whether the group is
+    /// partially loaded.
+    ///
+    /// If a data source is attached, the group is read into a buffer which is freed when the
+    /// returned bytes are dropped, so that decoding groups one by one doesn't keep the whole frame
+    /// data in memory.
+    fn group_bytes(&self, idx: usize) -> Option<Result<(GroupBytes<'_>, bool)>> {
+        let group = self.data.get(idx)?;
+        let size = group.toc_group.size as usize;
+        let Some(data_source) = &self.data_source else {
+            let bytes = GroupBytes {
+                bytes: Cow::Borrowed(&group.bytes),
+                _handle: None,
+            };
+            return Some(Ok((bytes, group.bytes.len() < size)));
+        };
+
+        let handle = self
+            .tracker
+            .as_ref()
+            .map(|tracker| tracker.alloc::<u8>(size))
+            .transpose();
+        let handle = match handle {
+            Ok(handle) => handle,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let offset = data_source.frame_offset + group.toc_group.offset as u64;
+        let mut bytes = vec![0u8; size];
+        if let Err(e) = data_source.source.read_exact_at(offset, &mut bytes) {
+            let e = Error::from(jxl_bitstream::Error::Io(e));
+            return Some(Err(self.locate_group_error(e, idx, 0)));
+        }
+        tracing::trace!(kind = ?group.toc_group.kind, offset, size, "Read group data");
+        let bytes = GroupBytes {
+            bytes: Cow::Owned(bytes),
+            _handle: handle,
+        };
+        Some(Ok((bytes, false)))
+    }
+
+    /// Records the TOC group at the bitstream order index, and the location in the group where
+    /// the error occurred.
+    fn locate_group_error(&self, error: Error, idx: usize, bit_offset: usize) -> Error {
+        let toc_group = &self.data[idx].toc_group;
+        let group_offset = self.codestream_offset + toc_group.offset as u64;
+        error.in_group(toc_group.kind, group_offset, bit_offset)
+    }
+
+    /// Records the pass group and the location in the group where the error occurred, to the
+    /// error returned while decoding the bitstream from
+    /// [`pass_group_bitstream`][Self::pass_group_bitstream].
+    ///
+    /// `bit_offset` is the position of the bitstream when the error occurred.
+    pub fn locate_pass_group_error(
+        &self,
+        error: Error,
+        pass_idx: u32,
+        group_idx: u32,
+        bit_offset: usize,
+    ) -> Error {
+        let idx = if self.toc.is_single_entry() {
+            0
+        } else {
+            self.toc
+                .group_index_bitstream_order(TocGroupKind::GroupPass {
+                    pass_idx,
+                    group_idx,
+                })
+        };
+        self.locate_group_error(error, idx, bit_offset)
     }
 }
 
@@ -226,6 +351,9 @@ impl Frame {
     pub fn feed_bytes<'buf>(&mut self, mut buf: &'buf [u8]) -> &'buf [u8] {
         while let Some(group_data) = self.data.get_mut(self.reading_data_index) {
             let bytes_left = group_data.toc_group.size as usize - group_data.bytes.len();
+            if group_data.bytes.is_empty() {
+                group_data.bytes.reserve_exact(bytes_left);
+            }
             if buf.len() < bytes_left {
                 group_data.bytes.extend_from_slice(buf);
                 return &[];
@@ -242,13 +370,147 @@ impl Frame {
     pub fn is_loading_done(&self) -> bool {
         self.reading_data_index >= self.data.len()
     }
+
+    /// Sets the codestream offset of the frame header.
+    ///
+    /// The offset is used to report the location of errors occurred in TOC groups, measured from
+    /// the beginning of the codestream.
+    #[inline]
+    pub fn set_codestream_offset(&mut self, offset: u64) {
+        self.codestream_offset = offset;
+    }
+
+    /// Attaches a source of frame data, and marks the frame as fully loaded.
+    ///
+    /// Groups are read from `source` when they are decoded, instead of being fed with
+    /// [`feed_bytes`][Self::feed_bytes]. `frame_offset` is the codestream offset of the frame
+    /// header, which TOC offsets are relative to.
+    pub fn set_data_source(&mut self, source: Arc<dyn FrameDataSource>, frame_offset: u64) {
+        for group in &mut self.data {
+            group.bytes = Vec::new();
+        }
+        self.reading_data_index = self.data.len();
+        self.codestream_offset = frame_offset;
+        self.data_source = Some(DataSourceRef {
+            source,
+            frame_offset,
+        });
+    }
+}
+
+/// Entropy decoder statistics of a TOC group.
+#[derive(Debug, Clone)]
+pub struct GroupEntropyStats {
+    /// Size of the group in bits, if known.
+    ///
+    /// For frames with single-entry TOC, this is the size of the section within the entry.
+    pub num_bits: Option<u64>,
+    /// Statistics of entropy decoders used while decoding the group.
+    pub decoders: Vec<jxl_coding::DecoderStats>,
+}
+
+impl Frame {
+    /// Starts collecting entropy decoder statistics of groups parsed afterwards.
+    ///
+    /// Statistics are collected only for decoders used on the thread which parses the group.
+    /// See [`jxl_coding::collect_stats`].
+    pub fn enable_entropy_stats(&self) {
+        self.entropy_stats.get_or_init(Default::default);
+    }
+
+    /// Returns entropy decoder statistics collected so far, keyed by TOC group.
+    ///
+    /// Returns `None` if [statistics are not enabled][Self::enable_entropy_stats].
+    pub fn entropy_stats(&self) -> Option<BTreeMap<TocGroupKind, GroupEntropyStats>> {
+        let stats = self.entropy_stats.get()?.lock().unwrap();
+        let ret = stats
+            .iter()
+            .map(|(&kind, decoders)| {
+                let stats = GroupEntropyStats {
+                    num_bits: self.group_num_bits(kind),
+                    decoders: decoders.clone(),
+                };
+                (kind, stats)
+            })
+            .collect();
+        Some(ret)
+    }
+
+    /// Starts collecting MA trees read in groups parsed afterwards.
+    ///
+    /// Both the global tree in LfGlobal and local trees of groups are collected.
+    pub fn enable_ma_tree_capture(&self) {
+        self.ma_trees.get_or_init(Default::default);
+    }
+
+    /// Returns MA trees collected so far, keyed by TOC group, in the order they are read.
+    ///
+    /// Returns `None` if [capturing is not enabled][Self::enable_ma_tree_capture].
+    pub fn ma_trees(&self) -> Option<BTreeMap<TocGroupKind, Vec<MaConfig>>> {
+        Some(self.ma_trees.get()?.lock().unwrap().clone())
+    }
+
+    /// Runs `f`, which decodes the given group, recording entropy decoder statistics and MA trees
+    /// of the group if enabled.
+    ///
+    /// Data recorded previously for the group are replaced, so that the latest result is kept when
+    /// the group is decoded again.
+    pub fn record_group_stats<R>(&self, group: TocGroupKind, f: impl FnOnce() -> R) -> R {
+        let Some(ma_trees) = self.ma_trees.get() else {
+            return self.record_entropy_stats(group, f);
+        };
+        let (ret, trees) = jxl_modular::collect_ma_trees(|| self.record_entropy_stats(group, f));
+        if !trees.is_empty() {
+            ma_trees.lock().unwrap().insert(group, trees);
+        }
+        ret
+    }
+
+    fn record_entropy_stats<R>(&self, group: TocGroupKind, f: impl FnOnce() -> R) -> R {
+        let Some(entropy_stats) = self.entropy_stats.get() else {
+            return f();
+        };
+        let (ret, decoders) = jxl_coding::collect_stats(f);
+        if !decoders.is_empty() {
+            entropy_stats.lock().unwrap().insert(group, decoders);
+        }
+        ret
+    }
+
+    fn group_num_bits(&self, group: TocGroupKind) -> Option<u64> {
+        if !self.toc.is_single_entry() {
+            let idx = self.toc.group_index_bitstream_order(group);
+            return Some(self.data.get(idx)?.toc_group.size as u64 * 8);
+        }
+
+        let lf_group = self.all_group_offsets.lf_group.load(Ordering::Relaxed);
+        let hf_global = self.all_group_offsets.hf_global.load(Ordering::Relaxed);
+        let pass_group = self.all_group_offsets.pass_group.load(Ordering::Relaxed);
+        let total = self.data.first()?.toc_group.size as usize * 8;
+        let (start, end) = match group {
+            TocGroupKind::All => (0, total),
+            TocGroupKind::LfGlobal => (0, lf_group),
+            TocGroupKind::LfGroup(_) => (lf_group, hf_global),
+            TocGroupKind::HfGlobal => (hf_global, pass_group),
+            TocGroupKind::GroupPass { .. } => (pass_group, total),
+        };
+        // Offsets are zero until the preceding sections are parsed.
+        (end != 0 && start <= end).then(|| (end - start) as u64)
+    }
 }
 
 impl Frame {
     pub fn try_parse_lf_global(&self) -> Option<Result<LfGlobal>> {
+        self.record_group_stats(TocGroupKind::LfGlobal, || self.parse_lf_global_inner())
+    }
+
+    fn parse_lf_global_inner(&self) -> Option<Result<LfGlobal>> {
         Some(if self.toc.is_single_entry() {
-            let group = self.data.get(0)?;
-            let mut bitstream = Bitstream::new(&group.bytes);
+            let (bytes, _) = match self.group_bytes(0)? {
+                Ok(x) => x,
+                Err(e) => return Some(Err(e)),
+            };
+            let mut bitstream = Bitstream::new(&bytes);
             let lf_global = LfGlobal::parse(
                 &mut bitstream,
                 LfGlobalParams::new(
@@ -256,7 +518,8 @@ impl Frame {
                     &self.header,
                     self.tracker.as_ref(),
                     false,
-                ),
+                )
+                .with_level(self.level),
             );
             if lf_global.is_ok() {
                 tracing::trace!(num_read_bits = bitstream.num_read_bits(), "LfGlobal");
@@ -264,13 +527,15 @@ impl Frame {
                     .lf_group
                     .store(bitstream.num_read_bits(), Ordering::Relaxed);
             }
-            lf_global
+            lf_global.map_err(|e| self.locate_group_error(e, 0, bitstream.num_read_bits()))
         } else {
             let idx = self.toc.group_index_bitstream_order(TocGroupKind::LfGlobal);
-            let group = self.data.get(idx)?;
-            let allow_partial = group.bytes.len() < group.toc_group.size as usize;
+            let (bytes, allow_partial) = match self.group_bytes(idx)? {
+                Ok(x) => x,
+                Err(e) => return Some(Err(e)),
+            };
 
-            let mut bitstream = Bitstream::new(&group.bytes);
+            let mut bitstream = Bitstream::new(&bytes);
             LfGlobal::parse(
                 &mut bitstream,
                 LfGlobalParams::new(
@@ -278,8 +543,10 @@ impl Frame {
                     &self.header,
                     self.tracker.as_ref(),
                     allow_partial,
-                ),
+                )
+                .with_level(self.level),
             )
+            .map_err(|e| self.locate_group_error(e, idx, bitstream.num_read_bits()))
         })
     }
 
@@ -289,14 +556,29 @@ impl Frame {
         global_ma_config: Option<&MaConfig>,
         mlf_group: Option<TransformedModularSubimage>,
         lf_group_idx: u32,
+    ) -> Option<Result<LfGroup>> {
+        self.record_group_stats(TocGroupKind::LfGroup(lf_group_idx), || {
+            self.parse_lf_group_inner(lf_global_vardct, global_ma_config, mlf_group, lf_group_idx)
+        })
+    }
+
+    fn parse_lf_group_inner(
+        &self,
+        lf_global_vardct: Option<&LfGlobalVarDct>,
+        global_ma_config: Option<&MaConfig>,
+        mlf_group: Option<TransformedModularSubimage>,
+        lf_group_idx: u32,
     ) -> Option<Result<LfGroup>> {
         if self.toc.is_single_entry() {
             if lf_group_idx != 0 {
                 return None;
             }
 
-            let group = self.data.get(0)?;
-            let mut bitstream = Bitstream::new(&group.bytes);
+            let (bytes, allow_partial) = match self.group_bytes(0)? {
+                Ok(x) => x,
+                Err(e) => return Some(Err(e)),
+            };
+            let mut bitstream = Bitstream::new(&bytes);
             let offset = self.all_group_offsets.lf_group.load(Ordering::Relaxed);
             if offset == 0 {
                 let lf_global = self.try_parse_lf_global().unwrap();
@@ -307,13 +589,13 @@ impl Frame {
             let offset = self.all_group_offsets.lf_group.load(Ordering::Relaxed);
             bitstream.skip_bits(offset).unwrap();
 
-            let allow_partial = group.bytes.len() < group.toc_group.size as usize;
             let result = LfGroup::parse(
                 &mut bitstream,
                 LfGroupParams {
                     frame_header: &self.header,
                     quantizer: lf_global_vardct.map(|x| &x.quantizer),
                     global_ma_config,
+                    ma_tree_limits: ma_tree_limits(self.level),
                     mlf_group,
                     lf_group_idx,
                     allow_partial,
@@ -328,21 +610,24 @@ impl Frame {
             self.all_group_offsets
                 .hf_global
                 .store(bitstream.num_read_bits(), Ordering::Relaxed);
-            Some(result)
+            Some(result.map_err(|e| self.locate_group_error(e, 0, bitstream.num_read_bits())))
         } else {
             let idx = self
                 .toc
                 .group_index_bitstream_order(TocGroupKind::LfGroup(lf_group_idx));
-            let group = self.data.get(idx)?;
-            let allow_partial = group.bytes.len() < group.toc_group.size as usize;
+            let (bytes, allow_partial) = match self.group_bytes(idx)? {
+                Ok(x) => x,
+                Err(e) => return Some(Err(e)),
+            };
 
-            let mut bitstream = Bitstream::new(&group.bytes);
+            let mut bitstream = Bitstream::new(&bytes);
             let result = LfGroup::parse(
                 &mut bitstream,
                 LfGroupParams {
                     frame_header: &self.header,
                     quantizer: lf_global_vardct.map(|x| &x.quantizer),
                     global_ma_config,
+                    ma_tree_limits: ma_tree_limits(self.level),
                     mlf_group,
                     lf_group_idx,
                     allow_partial,
@@ -353,19 +638,31 @@ impl Frame {
             if allow_partial && result.is_err() {
                 return None;
             }
-            Some(result)
+            Some(result.map_err(|e| self.locate_group_error(e, idx, bitstream.num_read_bits())))
         }
     }
 
     pub fn try_parse_hf_global(
         &self,
         cached_lf_global: Option<&LfGlobal>,
+    ) -> Option<Result<HfGlobal>> {
+        self.record_group_stats(TocGroupKind::HfGlobal, || {
+            self.parse_hf_global_inner(cached_lf_global)
+        })
+    }
+
+    fn parse_hf_global_inner(
+        &self,
+        cached_lf_global: Option<&LfGlobal>,
     ) -> Option<Result<HfGlobal>> {
         let is_modular = self.header.encoding == header::Encoding::Modular;
 
         if self.toc.is_single_entry() {
-            let group = self.data.get(0)?;
-            let mut bitstream = Bitstream::new(&group.bytes);
+            let (bytes, _) = match self.group_bytes(0)? {
+                Ok(x) => x,
+                Err(e) => return Some(Err(e)),
+            };
+            let mut bitstream = Bitstream::new(&bytes);
             let offset = self.all_group_offsets.hf_global.load(Ordering::Relaxed);
             let lf_global = if cached_lf_global.is_none() && (offset == 0 || !is_modular) {
                 match self.try_parse_lf_global()? {
@@ -429,19 +726,22 @@ impl Frame {
             self.all_group_offsets
                 .pass_group
                 .store(bitstream.num_read_bits(), Ordering::Relaxed);
-            Some(result)
+            Some(result.map_err(|e| self.locate_group_error(e, 0, bitstream.num_read_bits())))
         } else {
             if self.header.encoding == header::Encoding::Modular {
                 return None;
             }
 
             let idx = self.toc.group_index_bitstream_order(TocGroupKind::HfGlobal);
-            let group = self.data.get(idx)?;
-            if group.bytes.len() < group.toc_group.size as usize {
+            let (bytes, partial) = match self.group_bytes(idx)? {
+                Ok(x) => x,
+                Err(e) => return Some(Err(e)),
+            };
+            if partial {
                 return None;
             }
 
-            let mut bitstream = Bitstream::new(&group.bytes);
+            let mut bitstream = Bitstream::new(&bytes);
             let lf_global = if cached_lf_global.is_none() {
                 match self.try_parse_lf_global()? {
                     Ok(lf_global) => Some(lf_global),
@@ -458,7 +758,8 @@ impl Frame {
                 self.tracker.as_ref(),
                 &self.pool,
             );
-            Some(HfGlobal::parse(&mut bitstream, params))
+            let result = HfGlobal::parse(&mut bitstream, params);
+            Some(result.map_err(|e| self.locate_group_error(e, idx, bitstream.num_read_bits())))
         }
     }
 
@@ -472,8 +773,10 @@ impl Frame {
                 return None;
             }
 
-            let group = self.data.get(0)?;
-            let mut bitstream = Bitstream::new(&group.bytes);
+            let (bytes, partial) = match self.group_bytes(0)? {
+                Ok(x) => x,
+                Err(e) => return Some(Err(e)),
+            };
             let mut offset = self.all_group_offsets.pass_group.load(Ordering::Relaxed);
             if offset == 0 {
                 let hf_global = self.try_parse_hf_global(None)?;
@@ -482,11 +785,11 @@ impl Frame {
                 }
                 offset = self.all_group_offsets.pass_group.load(Ordering::Relaxed);
             }
-            bitstream.skip_bits(offset).unwrap();
 
             Ok(PassGroupBitstream {
-                bitstream,
-                partial: group.bytes.len() < group.toc_group.size as usize,
+                bytes,
+                start_bits: offset,
+                partial,
             })
         } else {
             let idx = self
@@ -495,23 +798,51 @@ impl Frame {
                     pass_idx,
                     group_idx,
                 });
-            let group = self.data.get(idx)?;
-            let partial = group.bytes.len() < group.toc_group.size as usize;
+            let (bytes, partial) = match self.group_bytes(idx)? {
+                Ok(x) => x,
+                Err(e) => return Some(Err(e)),
+            };
 
             Ok(PassGroupBitstream {
-                bitstream: Bitstream::new(&group.bytes),
+                bytes,
+                start_bits: 0,
                 partial,
             })
         })
     }
 }
 
+/// Bytes of a group, either borrowed from the frame or read from the data source.
+#[derive(Debug)]
+struct GroupBytes<'buf> {
+    bytes: Cow<'buf, [u8]>,
+    _handle: Option<AllocHandle>,
+}
+
+impl std::ops::Deref for GroupBytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
 #[derive(Debug)]
 pub struct PassGroupBitstream<'buf> {
-    pub bitstream: Bitstream<'buf>,
+    bytes: GroupBytes<'buf>,
+    start_bits: usize,
     pub partial: bool,
 }
 
+impl PassGroupBitstream<'_> {
+    /// Returns the bitstream positioned at the beginning of the pass group.
+    pub fn bitstream(&self) -> Bitstream<'_> {
+        let mut bitstream = Bitstream::new(&self.bytes);
+        bitstream.skip_bits(self.start_bits).unwrap();
+        bitstream
+    }
+}
+
 impl Frame {
     /// Adjusts the cropping region of the image to the actual decoding region of the frame.
     ///
@@ -97,4 +97,16 @@ impl AllocHandle {
             inner: Arc::clone(&self.inner),
         }
     }
+
+    /// Moves the record of `count` number of `T` out of this handle into a new handle.
+    ///
+    /// The record is moved without updating the tracker, so this never fails.
+    pub(crate) fn split<T>(&mut self, count: usize) -> AllocHandle {
+        let bytes = (count * std::mem::size_of::<T>()).min(self.bytes);
+        self.bytes -= bytes;
+        AllocHandle {
+            bytes,
+            inner: Arc::clone(&self.inner),
+        }
+    }
 }
@@ -73,6 +73,42 @@ impl<S: Default + Clone> SimpleGrid<S> {
         out.buf_mut().clone_from_slice(self.buf());
         Ok(out)
     }
+
+    /// Splits the buffer at row `y`, and returns rows from `y` in a new buffer.
+    ///
+    /// The buffer keeps rows before `y`, and memory of the split rows is released. Recorded
+    /// allocation of the split rows is moved to the returned buffer, so that splitting a buffer
+    /// doesn't need more memory than the buffer itself.
+    ///
+    /// # Panics
+    /// Panics if `y` is larger than the height.
+    pub fn split_off_rows(&mut self, y: usize) -> Self {
+        assert!(y <= self.height);
+        let len = y * self.width;
+        let mut out = Self::with_alloc_tracker(self.width, self.height - y, None).unwrap();
+        out.buf_mut().clone_from_slice(&self.buf()[len..]);
+        out.handle = self
+            .handle
+            .as_mut()
+            .map(|handle| handle.split::<S>(out.width * out.height));
+
+        let size = std::mem::size_of::<S>();
+        self.buf.truncate(self.offset + len);
+        self.buf.shrink_to(len + (Self::ALIGN - 1) / size);
+        // Shrinking may move the buffer, breaking the alignment.
+        let extra = self.buf.as_ptr() as usize & (Self::ALIGN - 1);
+        let offset = ((Self::ALIGN - extra) % Self::ALIGN) / size;
+        if offset > self.offset {
+            self.buf.resize_with(offset + len, S::default);
+            self.buf[self.offset..].rotate_right(offset - self.offset);
+        } else if offset < self.offset {
+            self.buf[offset..].rotate_left(self.offset - offset);
+            self.buf.truncate(offset + len);
+        }
+        self.offset = offset;
+        self.height = y;
+        out
+    }
 }
 
 impl<S> SimpleGrid<S> {
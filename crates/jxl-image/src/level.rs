@@ -0,0 +1,142 @@
+use jxl_bitstream::{Error, Result};
+
+use crate::ImageHeader;
+
+/// Codestream level, signalled in the `jxll` box.
+///
+/// Level defines the limits that conforming codestreams should stay within. Codestreams without
+/// the `jxll` box are at level 5.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Level {
+    /// Level 5, suitable for most of the images.
+    #[default]
+    Level5,
+    /// Level 10, which allows larger images and more complex codestreams.
+    Level10,
+}
+
+/// Limits of a codestream [level][Level].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct LevelLimits {
+    /// Maximum width and height of the image and frames.
+    pub max_dimension: u64,
+    /// Maximum number of pixels of the image and frames.
+    pub max_pixels: u64,
+    /// Maximum number of extra channels.
+    pub max_extra_channels: usize,
+    /// Maximum size of the ICC profile, in bytes.
+    pub max_icc_size: u64,
+    /// Maximum number of MA tree nodes.
+    pub max_ma_tree_nodes: usize,
+    /// Maximum depth of MA trees.
+    pub max_ma_tree_depth: usize,
+    /// Maximum number of splines in a frame.
+    pub max_splines: usize,
+    /// Maximum number of spline control points in a frame.
+    pub max_spline_control_points: usize,
+}
+
+impl TryFrom<u8> for Level {
+    type Error = Error;
+
+    fn try_from(value: u8) -> Result<Self> {
+        match value {
+            5 => Ok(Self::Level5),
+            10 => Ok(Self::Level10),
+            _ => Err(Error::ValidationFailed("invalid codestream level")),
+        }
+    }
+}
+
+impl Level {
+    /// Returns the numeric value of the level.
+    #[inline]
+    pub fn as_u8(self) -> u8 {
+        match self {
+            Self::Level5 => 5,
+            Self::Level10 => 10,
+        }
+    }
+
+    /// Returns the limits of the level.
+    pub fn limits(self) -> LevelLimits {
+        match self {
+            Self::Level5 => LevelLimits {
+                max_dimension: 1 << 18,
+                max_pixels: 1 << 28,
+                max_extra_channels: 4,
+                max_icc_size: 1 << 22,
+                max_ma_tree_nodes: 1 << 20,
+                max_ma_tree_depth: 64,
+                max_splines: 1 << 16,
+                max_spline_control_points: 1 << 16,
+            },
+            Self::Level10 => LevelLimits {
+                max_dimension: 1 << 30,
+                max_pixels: 1 << 40,
+                max_extra_channels: 256,
+                max_icc_size: 1 << 28,
+                max_ma_tree_nodes: 1 << 22,
+                max_ma_tree_depth: 2048,
+                max_splines: 1 << 24,
+                max_spline_control_points: 1 << 20,
+            },
+        }
+    }
+
+    /// Returns an error saying that `limit` of the level is exceeded.
+    #[inline]
+    pub fn error(self, limit: &'static str) -> Error {
+        Error::LevelLimitExceeded {
+            level: self.as_u8(),
+            limit,
+        }
+    }
+
+    /// Checks if the image header is within the limits of the level.
+    pub fn check_image_header(self, header: &ImageHeader) -> Result<()> {
+        let limits = self.limits();
+        let width = header.size.width as u64;
+        let height = header.size.height as u64;
+        if width > limits.max_dimension || height > limits.max_dimension {
+            tracing::error!(width, height, level = self.as_u8(), "Image too large");
+            return Err(self.error("image dimension"));
+        }
+        if width * height > limits.max_pixels {
+            tracing::error!(width, height, level = self.as_u8(), "Image area too large");
+            return Err(self.error("image area"));
+        }
+
+        let num_extra = header.metadata.ec_info.len();
+        if num_extra > limits.max_extra_channels {
+            tracing::error!(num_extra, level = self.as_u8(), "Too many extra channels");
+            return Err(self.error("number of extra channels"));
+        }
+        Ok(())
+    }
+
+    /// Checks if the frame dimension is within the limits of the level.
+    pub fn check_frame_size(self, width: u32, height: u32) -> Result<()> {
+        let limits = self.limits();
+        let width = width as u64;
+        let height = height as u64;
+        if width > limits.max_dimension || height > limits.max_dimension {
+            tracing::error!(width, height, level = self.as_u8(), "Frame too large");
+            return Err(self.error("frame dimension"));
+        }
+        if width * height > limits.max_pixels {
+            tracing::error!(width, height, level = self.as_u8(), "Frame area too large");
+            return Err(self.error("frame area"));
+        }
+        Ok(())
+    }
+
+    /// Checks if the size of the ICC profile is within the limits of the level.
+    pub fn check_icc_size(self, size: usize) -> Result<()> {
+        if size as u64 > self.limits().max_icc_size {
+            tracing::error!(size, level = self.as_u8(), "ICC profile too large");
+            return Err(self.error("ICC profile size"));
+        }
+        Ok(())
+    }
+}
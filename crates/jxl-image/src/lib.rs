@@ -5,13 +5,20 @@
 //!
 //! Image header is at the beginning of the bitstream. One can parse [`ImageHeader`] from the
 //! bitstream to retrieve information about the image.
-use jxl_bitstream::{define_bundle, read_bits, Bitstream, Bundle, Name, Result};
+use jxl_bitstream::{
+    define_bundle, read_bits, write_bits, BitWriter, Bitstream, Bundle, BundleDefault, BundleWrite,
+    Name, Result,
+};
 use jxl_color::header::*;
 
+mod level;
+
+pub use level::{Level, LevelLimits};
+
 /// JPEG XL image header.
 ///
-/// Use [`Bundle::parse`] to parse the header.
-#[derive(Debug)]
+/// Use [`Bundle::parse`] to parse the header, and [`BundleWrite::write`] to write it back.
+#[derive(Debug, Clone)]
 pub struct ImageHeader {
     /// Image size information.
     pub size: SizeHeader,
@@ -63,7 +70,27 @@ impl<Ctx> Bundle<Ctx> for ImageHeader {
     }
 }
 
+impl<Ctx> BundleWrite<Ctx> for ImageHeader {
+    fn write(&self, writer: &mut BitWriter, _: Ctx) -> Result<()> {
+        writer.write_bits(16, 0xaff)?;
+        self.size.write(writer, ())?;
+        self.metadata.write(writer, ())
+    }
+}
+
 impl ImageHeader {
+    /// Creates an image header of the given size, with default metadata.
+    ///
+    /// # Errors
+    /// Returns `Error::ValidationFailed` if the width or the height is zero, and
+    /// `Error::Unrepresentable` if it's larger than `1 << 30`.
+    pub fn new(width: u32, height: u32) -> Result<Self> {
+        Ok(Self {
+            size: SizeHeader::new(width, height)?,
+            metadata: ImageMetadata::default_with_context(()),
+        })
+    }
+
     /// Returns the image width with orientation applied.
     #[inline]
     pub fn width_with_orientation(&self) -> u32 {
@@ -83,7 +110,7 @@ impl ImageHeader {
 
 define_bundle! {
     /// Image size information.
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct SizeHeader {
         div8: ty(Bool) default(false),
         h_div8: ty(1 + u(5)) cond(div8) default(0),
@@ -101,6 +128,30 @@ define_bundle! {
 }
 
 impl SizeHeader {
+    /// Creates a size header with the given dimension.
+    ///
+    /// # Errors
+    /// Returns `Error::ValidationFailed` if the width or the height is zero, and
+    /// `Error::Unrepresentable` if it's larger than `1 << 30`.
+    pub fn new(width: u32, height: u32) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(jxl_bitstream::Error::ValidationFailed("empty image"));
+        }
+        if width > 1 << 30 || height > 1 << 30 {
+            return Err(jxl_bitstream::Error::Unrepresentable(
+                "image dimension is too large",
+            ));
+        }
+        Ok(Self {
+            div8: false,
+            h_div8: 0,
+            height,
+            ratio: 0,
+            w_div8: 0,
+            width,
+        })
+    }
+
     fn compute_default_width(ratio: u32, w_div8: u32, height: u32) -> u32 {
         let height = height as u64;
         let res = match ratio {
@@ -120,7 +171,7 @@ impl SizeHeader {
 
 define_bundle! {
     /// Image metadata.
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct ImageMetadata {
         all_default: ty(Bool) default(true),
         extra_fields: ty(Bool) cond(!all_default) default(false),
@@ -165,7 +216,7 @@ define_bundle! {
         pub up8_weight: ty(Array[F16]; 210) cond(cw_mask & 4 != 0) default(Self::D_UP8),
     }
 
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct PreviewHeader {
         div8: ty(Bool),
         h_div8: ty(U32(16, 32, 1 + u(5), 33 + u(9))) cond(div8) default(1),
@@ -185,7 +236,7 @@ define_bundle! {
     ///
     /// TPS (ticks per second) is computed as `tps_numerator / tps_denominator`, which means
     /// `tps_denominator / tps_numerator` seconds per tick.
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct AnimationHeader {
         /// TPS numerator.
         pub tps_numerator: ty(U32(100, 1000, 1 + u(10), 1 + u(30))) default(0),
@@ -198,7 +249,7 @@ define_bundle! {
     }
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 #[allow(unused)]
 pub struct Extensions {
     extensions: u64,
@@ -232,6 +283,16 @@ impl<Ctx> Bundle<Ctx> for Extensions {
     }
 }
 
+impl<Ctx> BundleWrite<Ctx> for Extensions {
+    fn write(&self, writer: &mut BitWriter, _: Ctx) -> Result<()> {
+        writer.write_u64(self.extensions)?;
+        for &bits in &self.extension_bits {
+            writer.write_u64(bits)?;
+        }
+        Ok(())
+    }
+}
+
 impl ImageMetadata {
     /// Returns whether the image is grayscale.
     #[inline]
@@ -256,6 +317,137 @@ impl ImageMetadata {
             .position(|info| matches!(info.ty, ExtraChannelType::Alpha { .. }))
     }
 
+    /// Sets the orientation of the image.
+    ///
+    /// # Errors
+    /// Returns `Error::ValidationFailed` if `orientation` is not in range `1..=8`.
+    pub fn set_orientation(&mut self, orientation: u32) -> Result<()> {
+        if !(1..=8).contains(&orientation) {
+            return Err(jxl_bitstream::Error::ValidationFailed(
+                "invalid orientation",
+            ));
+        }
+        self.orientation = orientation;
+        self.all_default = false;
+        self.extra_fields = true;
+        Ok(())
+    }
+
+    /// Sets the intensity target of the tone mapping information, in nits.
+    ///
+    /// # Errors
+    /// Returns `Error::ValidationFailed` if the intensity target is not positive or less than
+    /// `min_nits`, and `Error::Unrepresentable` if it's out of range of `F16`.
+    pub fn set_intensity_target(&mut self, intensity_target: f32) -> Result<()> {
+        if !(intensity_target > 0.0 && intensity_target >= self.tone_mapping.min_nits) {
+            return Err(jxl_bitstream::Error::ValidationFailed(
+                "Invalid intensity target",
+            ));
+        }
+        if intensity_target >= 65520.0 {
+            return Err(jxl_bitstream::Error::Unrepresentable(
+                "value is out of range of F16",
+            ));
+        }
+        self.tone_mapping.intensity_target = intensity_target;
+        self.tone_mapping.set_explicit();
+        self.all_default = false;
+        self.extra_fields = true;
+        Ok(())
+    }
+
+    /// Sets the animation header, where `None` makes the image a still image.
+    ///
+    /// # Errors
+    /// Returns `Error::ValidationFailed` if the tick unit is zero.
+    pub fn set_animation(&mut self, animation: Option<AnimationHeader>) -> Result<()> {
+        if let Some(animation) = &animation {
+            if animation.tps_numerator == 0 || animation.tps_denominator == 0 {
+                return Err(jxl_bitstream::Error::ValidationFailed(
+                    "invalid tick unit of animation",
+                ));
+            }
+            self.all_default = false;
+            self.extra_fields = true;
+        }
+        self.have_animation = animation.is_some();
+        self.animation = animation;
+        Ok(())
+    }
+
+    /// Sets the number of loops of the animation, where 0 means it loops forever.
+    ///
+    /// # Errors
+    /// Returns `Error::ValidationFailed` if the image is not animated.
+    pub fn set_num_loops(&mut self, num_loops: u32) -> Result<()> {
+        let animation = self
+            .animation
+            .as_mut()
+            .ok_or(jxl_bitstream::Error::ValidationFailed(
+                "image is not animated",
+            ))?;
+        animation.num_loops = num_loops;
+        Ok(())
+    }
+
+    /// Sets the colour encoding of the image.
+    ///
+    /// If the image is not XYB encoded, the new colour encoding should have the same number of
+    /// channels, as samples are not converted.
+    ///
+    /// # Errors
+    /// Returns `Error::ValidationFailed` if the number of colour channels changes.
+    pub fn set_colour_encoding(&mut self, mut colour_encoding: ColourEncoding) -> Result<()> {
+        if !self.xyb_encoded
+            && (colour_encoding.colour_space == ColourSpace::Grey) != self.grayscale()
+        {
+            return Err(jxl_bitstream::Error::ValidationFailed(
+                "number of colour channels cannot be changed",
+            ));
+        }
+        colour_encoding.set_explicit();
+        self.colour_encoding = colour_encoding;
+        self.all_default = false;
+        Ok(())
+    }
+
+    /// Sets the bit depth of colour channels.
+    pub fn set_bit_depth(&mut self, bit_depth: BitDepth) {
+        self.bit_depth = bit_depth;
+        self.all_default = false;
+    }
+
+    /// Sets whether samples of the image are XYB encoded.
+    ///
+    /// Colour encoding should be set before this, since the number of colour channels cannot be
+    /// changed after the image is marked as not XYB encoded.
+    pub fn set_xyb_encoded(&mut self, xyb_encoded: bool) {
+        self.xyb_encoded = xyb_encoded;
+        self.all_default = false;
+    }
+
+    /// Sets whether 16-bit buffers are sufficient to decode Modular images.
+    pub fn set_modular_16bit_buffers(&mut self, modular_16bit_buffers: bool) {
+        self.modular_16bit_buffers = modular_16bit_buffers;
+        self.all_default = false;
+    }
+
+    /// Sets information about extra channels.
+    ///
+    /// # Errors
+    /// Returns `Error::ProfileConformance` if there are more than 256 extra channels.
+    pub fn set_extra_channels(&mut self, ec_info: Vec<ExtraChannelInfo>) -> Result<()> {
+        if ec_info.len() > 256 {
+            return Err(jxl_bitstream::Error::ProfileConformance(
+                "num_extra too large",
+            ));
+        }
+        self.num_extra = ec_info.len() as u32;
+        self.ec_info = ec_info;
+        self.all_default = false;
+        Ok(())
+    }
+
     /// Returns where the given coordinate will be placed after the orientation is applied.
     #[inline]
     pub fn apply_orientation(
@@ -345,6 +537,57 @@ impl<Ctx> Bundle<Ctx> for ExtraChannelInfo {
     }
 }
 
+impl<Ctx> BundleWrite<Ctx> for ExtraChannelInfo {
+    fn write(&self, writer: &mut BitWriter, _: Ctx) -> Result<()> {
+        let default_alpha_channel = self.ty == ExtraChannelType::default()
+            && matches!(
+                self.bit_depth,
+                BitDepth::IntegerSample { bits_per_sample: 8 }
+            )
+            && self.dim_shift == 0
+            && self.name.is_empty();
+        writer.write_bool(default_alpha_channel)?;
+        if default_alpha_channel {
+            return Ok(());
+        }
+
+        let ty_id = match self.ty {
+            ExtraChannelType::Alpha { .. } => ExtraChannelTypeRaw::Alpha,
+            ExtraChannelType::Depth => ExtraChannelTypeRaw::Depth,
+            ExtraChannelType::SpotColour { .. } => ExtraChannelTypeRaw::SpotColour,
+            ExtraChannelType::SelectionMask => ExtraChannelTypeRaw::SelectionMask,
+            ExtraChannelType::Black => ExtraChannelTypeRaw::Black,
+            ExtraChannelType::Cfa { .. } => ExtraChannelTypeRaw::Cfa,
+            ExtraChannelType::Thermal => ExtraChannelTypeRaw::Thermal,
+            ExtraChannelType::NonOptional => ExtraChannelTypeRaw::NonOptional,
+            ExtraChannelType::Optional => ExtraChannelTypeRaw::Optional,
+        };
+        write_bits!(writer, Enum(ExtraChannelTypeRaw), &ty_id)?;
+        self.bit_depth.write(writer, ())?;
+        write_bits!(writer, U32(0, 3, 4, 1 + u(3)), &self.dim_shift)?;
+        self.name.write(writer, ())?;
+
+        match self.ty {
+            ExtraChannelType::Alpha { alpha_associated } => writer.write_bool(alpha_associated),
+            ExtraChannelType::SpotColour {
+                red,
+                green,
+                blue,
+                solidity,
+            } => {
+                writer.write_f32_as_f16(red)?;
+                writer.write_f32_as_f16(green)?;
+                writer.write_f32_as_f16(blue)?;
+                writer.write_f32_as_f16(solidity)
+            }
+            ExtraChannelType::Cfa { cfa_channel } => {
+                write_bits!(writer, U32(1, u(2), 3 + u(4), 19 + u(8)), &cfa_channel)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
 impl ExtraChannelInfo {
     /// Returns whether this is an alpha channel.
     #[inline]
@@ -538,6 +781,25 @@ impl<Ctx> Bundle<Ctx> for BitDepth {
     }
 }
 
+impl<Ctx> BundleWrite<Ctx> for BitDepth {
+    fn write(&self, writer: &mut BitWriter, _ctx: Ctx) -> Result<()> {
+        match *self {
+            Self::FloatSample {
+                bits_per_sample,
+                exp_bits,
+            } => {
+                writer.write_bool(true)?;
+                write_bits!(writer, U32(32, 16, 24, 1 + u(6)), &bits_per_sample)?;
+                write_bits!(writer, 1 + u(4), &exp_bits)
+            }
+            Self::IntegerSample { bits_per_sample } => {
+                writer.write_bool(false)?;
+                write_bits!(writer, U32(8, 10, 12, 1 + u(6)), &bits_per_sample)
+            }
+        }
+    }
+}
+
 #[allow(clippy::excessive_precision)]
 #[rustfmt::skip]
 impl ImageMetadata {
@@ -0,0 +1,367 @@
+use jxl_frame::{
+    data::{PassGroupParams, PassGroupParamsVardct},
+    header::Encoding,
+    Frame,
+};
+use jxl_grid::{CutGrid, SimpleGrid};
+use jxl_modular::ChannelShift;
+use jxl_threadpool::JxlThreadPool;
+use jxl_vardct::{BlockInfo, TransformType};
+
+use crate::{Error, Result};
+
+/// Fixed point precision used by integer chroma-from-luma of recompressed JPEG images.
+const CFL_FIXED_POINT_PRECISION: u32 = 11;
+const CFL_DEFAULT_COLOR_FACTOR: i32 = 84;
+
+/// Quantized DCT coefficients of a JPEG component.
+#[derive(Debug)]
+pub(crate) struct ComponentCoeffs {
+    pub(crate) h_samp_factor: u32,
+    pub(crate) v_samp_factor: u32,
+    pub(crate) width_in_blocks: usize,
+    pub(crate) height_in_blocks: usize,
+    /// Coefficients of each block, in the natural order of JPEG.
+    pub(crate) coeffs: Vec<i16>,
+    /// Quantization table of the component, in the natural order of JPEG.
+    pub(crate) quant_table: [i32; 64],
+}
+
+/// Quantized DCT coefficients of a frame, in the layout of JPEG.
+#[derive(Debug)]
+pub(crate) struct FrameCoeffs {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) components: Vec<ComponentCoeffs>,
+}
+
+/// Returns the JPEG component index of each JPEG XL channel.
+fn jpeg_order(is_gray: bool, do_ycbcr: bool) -> [usize; 3] {
+    if is_gray {
+        [0, 0, 0]
+    } else if do_ycbcr {
+        [1, 0, 2]
+    } else {
+        [0, 1, 2]
+    }
+}
+
+pub(crate) fn decode_frame_coeffs(
+    frame: &Frame,
+    is_gray: bool,
+    pool: &JxlThreadPool,
+) -> Result<FrameCoeffs> {
+    let image_header = frame.image_header();
+    let frame_header = frame.header();
+    let tracker = frame.alloc_tracker();
+
+    if image_header.metadata.xyb_encoded {
+        return Err(Error::UnsupportedFrame("image is XYB encoded"));
+    }
+    if frame_header.encoding != Encoding::VarDct {
+        return Err(Error::UnsupportedFrame("frame is not VarDCT encoded"));
+    }
+    if frame_header.flags.use_lf_frame() {
+        return Err(Error::UnsupportedFrame("frame uses LF frame"));
+    }
+    if !frame.is_loading_done() {
+        return Err(Error::IncompleteFrame);
+    }
+
+    let jpeg_upsampling = frame_header.jpeg_upsampling;
+    let shifts: [_; 3] =
+        std::array::from_fn(|idx| ChannelShift::from_jpeg_upsampling(jpeg_upsampling, idx));
+    let subsampled = jpeg_upsampling.into_iter().any(|x| x != 0);
+    let h_upsample = jpeg_upsampling.into_iter().any(|j| j == 1 || j == 2);
+    let v_upsample = jpeg_upsampling.into_iter().any(|j| j == 1 || j == 3);
+    if is_gray && subsampled {
+        return Err(Error::UnsupportedFrame(
+            "grayscale frame is chroma subsampled",
+        ));
+    }
+
+    let channels: &[usize] = if is_gray { &[1] } else { &[1, 0, 2] };
+    let jpeg_c_map = jpeg_order(is_gray, frame_header.do_ycbcr);
+    let do_cfl = !is_gray && !subsampled;
+
+    let width = frame_header.color_sample_width();
+    let height = frame_header.color_sample_height();
+    let mut bw = (width as usize).div_ceil(8);
+    let mut bh = (height as usize).div_ceil(8);
+    if h_upsample {
+        bw = bw.div_ceil(2) * 2;
+    }
+    if v_upsample {
+        bh = bh.div_ceil(2) * 2;
+    }
+
+    let lf_global = frame
+        .try_parse_lf_global()
+        .ok_or(Error::IncompleteFrame)??;
+    let lf_global_vardct = lf_global.vardct.as_ref().unwrap();
+    let hf_global = frame
+        .try_parse_hf_global(Some(&lf_global))
+        .ok_or(Error::IncompleteFrame)??;
+
+    let mut quant_tables = [[0i32; 64]; 3];
+    for &c in channels {
+        let raw = hf_global
+            .dequant_matrices
+            .jpeg_quant_table(c)
+            .ok_or(Error::UnsupportedFrame(
+                "quantization table is not a JPEG quantization table",
+            ))?;
+        if raw.iter().any(|&q| q <= 0) {
+            return Err(Error::UnsupportedFrame("invalid JPEG quantization table"));
+        }
+        quant_tables[c].copy_from_slice(raw);
+    }
+
+    let mut components = Vec::with_capacity(channels.len());
+    for &c in channels {
+        let shift = shifts[c];
+        let hshift = shift.hshift() as u32;
+        let vshift = shift.vshift() as u32;
+        let width_in_blocks = bw >> hshift;
+        let height_in_blocks = bh >> vshift;
+
+        // Quantization tables of JPEG XL are transposed.
+        let mut quant_table = [0i32; 64];
+        for y in 0..8 {
+            for x in 0..8 {
+                quant_table[x * 8 + y] = quant_tables[c][y * 8 + x];
+            }
+        }
+
+        components.push((
+            jpeg_c_map[c],
+            ComponentCoeffs {
+                h_samp_factor: 1 << (h_upsample as u32 - hshift),
+                v_samp_factor: 1 << (v_upsample as u32 - vshift),
+                width_in_blocks,
+                height_in_blocks,
+                coeffs: vec![0i16; width_in_blocks * height_in_blocks * 64],
+                quant_table,
+            },
+        ));
+    }
+    components.sort_by_key(|&(idx, _)| idx);
+    let mut components: Vec<_> = components.into_iter().map(|(_, comp)| comp).collect();
+    let jpeg_component = |c: usize| if is_gray { 0 } else { jpeg_c_map[c] };
+
+    let mut gmodular = lf_global.gmodular.try_clone()?;
+    let groups = gmodular
+        .modular
+        .image_mut()
+        .map(|x| x.prepare_groups(frame.pass_shifts()))
+        .transpose()?;
+    let (mlf_groups, pass_groups) = groups
+        .map(|x| (x.lf_groups, x.pass_groups))
+        .unwrap_or_default();
+    let mut pass_groups: Vec<Vec<_>> = pass_groups
+        .into_iter()
+        .map(|groups| groups.into_iter().map(Some).collect())
+        .collect();
+    let mut mlf_groups = mlf_groups.into_iter();
+    let global_ma_config = gmodular.ma_config.as_ref();
+
+    let group_dim = frame_header.group_dim() as usize;
+    let lf_groups_per_row = frame_header.lf_groups_per_row();
+    let mut lf_groups = Vec::with_capacity(frame_header.num_lf_groups() as usize);
+    for lf_group_idx in 0..frame_header.num_lf_groups() {
+        let lf_group = frame
+            .try_parse_lf_group(
+                Some(lf_global_vardct),
+                global_ma_config,
+                mlf_groups.next(),
+                lf_group_idx,
+            )
+            .ok_or(Error::IncompleteFrame)??;
+        if lf_group.partial {
+            return Err(Error::IncompleteFrame);
+        }
+        let Some(hf_meta) = &lf_group.hf_meta else {
+            return Err(Error::IncompleteFrame);
+        };
+        for block_info in hf_meta.block_info.buf() {
+            match block_info {
+                BlockInfo::Data {
+                    dct_select: TransformType::Dct8,
+                    ..
+                } => {}
+                BlockInfo::Uninit => return Err(Error::IncompleteFrame),
+                _ => {
+                    return Err(Error::UnsupportedFrame(
+                        "frame uses varblocks other than DCT8",
+                    ))
+                }
+            }
+        }
+
+        let lf_coeff = lf_group.lf_coeff.as_ref().unwrap();
+        let lf_quant = lf_coeff.lf_quant.image().unwrap().image_channels();
+        let lf_left = (lf_group_idx % lf_groups_per_row) as usize * group_dim;
+        let lf_top = (lf_group_idx / lf_groups_per_row) as usize * group_dim;
+        for &c in channels {
+            let shift = shifts[c];
+            let comp = &mut components[jpeg_component(c)];
+            let dc_offset = if frame_header.do_ycbcr {
+                0
+            } else {
+                1024 / quant_tables[c][0]
+            };
+
+            let lf = &lf_quant[[1, 0, 2][c]];
+            let left = lf_left >> shift.hshift();
+            let top = lf_top >> shift.vshift();
+            let lf_width = lf.width().min(comp.width_in_blocks.saturating_sub(left));
+            let lf_height = lf.height().min(comp.height_in_blocks.saturating_sub(top));
+            for y in 0..lf_height {
+                let row = &lf.buf()[y * lf.width()..][..lf_width];
+                for (x, &q) in row.iter().enumerate() {
+                    let block_idx = (top + y) * comp.width_in_blocks + left + x;
+                    comp.coeffs[block_idx * 64] = (q - dc_offset).clamp(-2047, 2047) as i16;
+                }
+            }
+        }
+
+        lf_groups.push(lf_group);
+    }
+
+    let num_passes = frame_header.passes.num_passes;
+    let groups_per_row = frame_header.groups_per_row();
+    let group_dim_blocks = group_dim / 8;
+    for group_idx in 0..frame_header.num_groups() {
+        let lf_group_idx = frame_header.lf_group_idx_from_group_idx(group_idx);
+        let lf_group = &lf_groups[lf_group_idx as usize];
+
+        let group_x = (group_idx % groups_per_row) as usize;
+        let group_y = (group_idx / groups_per_row) as usize;
+        let block_left = group_x * group_dim_blocks;
+        let block_top = group_y * group_dim_blocks;
+        let block_width = group_dim_blocks.min(bw - block_left);
+        let block_height = group_dim_blocks.min(bh - block_top);
+
+        let mut grids = Vec::with_capacity(3);
+        for shift in shifts {
+            let (w, h) = shift.shift_size((block_width as u32, block_height as u32));
+            grids.push(SimpleGrid::<i32>::with_alloc_tracker(
+                w as usize * 8,
+                h as usize * 8,
+                tracker,
+            )?);
+        }
+        let [grid_x, grid_y, grid_b] = &mut *grids else {
+            unreachable!()
+        };
+        let mut hf_coeff_output = [
+            CutGrid::from_simple_grid(grid_x),
+            CutGrid::from_simple_grid(grid_y),
+            CutGrid::from_simple_grid(grid_b),
+        ];
+
+        for pass_idx in 0..num_passes {
+            let modular = pass_groups
+                .get_mut(pass_idx as usize)
+                .and_then(|groups| groups.get_mut(group_idx as usize))
+                .and_then(Option::take);
+            let bitstream = frame
+                .pass_group_bitstream(pass_idx, group_idx)
+                .ok_or(Error::IncompleteFrame)??;
+            if bitstream.partial {
+                return Err(Error::IncompleteFrame);
+            }
+            let mut bitstream = bitstream.bitstream();
+
+            jxl_frame::data::decode_pass_group(
+                &mut bitstream,
+                PassGroupParams {
+                    frame_header,
+                    lf_group,
+                    pass_idx,
+                    group_idx,
+                    global_ma_config,
+                    modular,
+                    vardct: Some(PassGroupParamsVardct {
+                        lf_vardct: lf_global_vardct,
+                        hf_global: &hf_global,
+                        hf_coeff_output: &mut hf_coeff_output,
+                    }),
+                    allow_partial: false,
+                    tracker,
+                    pool,
+                },
+            )?;
+        }
+
+        let hf_meta = lf_group.hf_meta.as_ref().unwrap();
+        let left_in_lf = (group_x % 8) * group_dim_blocks;
+        let top_in_lf = (group_y % 8) * group_dim_blocks;
+        for &c in channels {
+            let shift = shifts[c];
+            let hshift = shift.hshift();
+            let vshift = shift.vshift();
+            let grid = &grids[c];
+            let comp = &mut components[jpeg_component(c)];
+            let comp_left = block_left >> hshift;
+            let comp_top = block_top >> vshift;
+            let cfl_map = match c {
+                0 if do_cfl => Some(&hf_meta.x_from_y),
+                2 if do_cfl => Some(&hf_meta.b_from_y),
+                _ => None,
+            };
+
+            for by in 0..grid.height() / 8 {
+                for bx in 0..grid.width() / 8 {
+                    let block_idx = (comp_top + by) * comp.width_in_blocks + comp_left + bx;
+                    let block = &mut comp.coeffs[block_idx * 64..][..64];
+
+                    let cfl = cfl_map.map(|map| {
+                        let factor = *map
+                            .get((left_in_lf + bx) / 8, (top_in_lf + by) / 8)
+                            .unwrap();
+                        factor * (1 << CFL_FIXED_POINT_PRECISION) / CFL_DEFAULT_COLOR_FACTOR
+                    });
+
+                    for y in 0..8 {
+                        for x in 0..8 {
+                            if x == 0 && y == 0 {
+                                continue;
+                            }
+                            let gx = bx * 8 + x;
+                            let gy = by * 8 + y;
+                            let mut coeff = *grid.get(gx, gy).unwrap();
+                            if let Some(ratio) = cfl {
+                                let k = y * 8 + x;
+                                let coeff_y = *grids[1].get(gx, gy).unwrap();
+                                let scaled_qtable = (1i32 << CFL_FIXED_POINT_PRECISION)
+                                    .wrapping_mul(quant_tables[1][k])
+                                    / quant_tables[c][k];
+                                coeff = coeff.wrapping_add(cfl_multiply(
+                                    coeff_y,
+                                    cfl_multiply(scaled_qtable, ratio),
+                                ));
+                            }
+                            // JPEG XL is transposed, JPEG isn't.
+                            block[x * 8 + y] = coeff.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(FrameCoeffs {
+        width,
+        height,
+        components,
+    })
+}
+
+/// Fixed point multiplication used by integer chroma-from-luma.
+#[inline]
+fn cfl_multiply(a: i32, b: i32) -> i32 {
+    a.wrapping_mul(b)
+        .wrapping_add(1 << (CFL_FIXED_POINT_PRECISION - 1))
+        >> CFL_FIXED_POINT_PRECISION
+}
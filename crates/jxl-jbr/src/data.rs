@@ -0,0 +1,509 @@
+use std::io::Read;
+
+use jxl_bitstream::{read_bits, Bitstream};
+use jxl_grid::{AllocHandle, AllocTracker};
+
+use crate::{Error, Result};
+
+pub(crate) const ICC_PROFILE_TAG: &[u8; 12] = b"ICC_PROFILE\0";
+pub(crate) const EXIF_TAG: &[u8; 6] = b"Exif\0\0";
+pub(crate) const XMP_TAG: &[u8; 29] = b"http://ns.adobe.com/xap/1.0/\0";
+
+/// Maximum length of tail data, defined by the specification.
+const MAX_TAIL_DATA_LEN: u32 = 4260096;
+
+/// Type of an APPn marker.
+///
+/// Payload of ICC, Exif and XMP markers are not stored in the `jbrd` box; those are restored from
+/// the codestream and the metadata boxes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AppMarkerType {
+    Unknown,
+    Icc,
+    Exif,
+    Xmp,
+}
+
+impl TryFrom<u32> for AppMarkerType {
+    type Error = ();
+
+    fn try_from(value: u32) -> std::result::Result<Self, ()> {
+        Ok(match value {
+            0 => Self::Unknown,
+            1 => Self::Icc,
+            2 => Self::Exif,
+            3 => Self::Xmp,
+            _ => return Err(()),
+        })
+    }
+}
+
+/// APPn marker, including marker byte and length.
+#[derive(Debug)]
+pub(crate) struct AppMarker {
+    pub(crate) ty: AppMarkerType,
+    pub(crate) data: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub(crate) struct QuantTable {
+    pub(crate) precision: u8,
+    pub(crate) index: u8,
+    pub(crate) is_last: bool,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(crate) enum ComponentType {
+    Gray,
+    YCbCr,
+    Rgb,
+    Custom,
+}
+
+#[derive(Debug)]
+pub(crate) struct Component {
+    pub(crate) id: u8,
+    pub(crate) quant_idx: u8,
+}
+
+/// Huffman code description, in the form of DHT segment.
+///
+/// `counts[0]` is always zero. The last symbol, which is 256, is a placeholder for the all-ones
+/// code, and is not written to the DHT segment.
+#[derive(Debug)]
+pub(crate) struct HuffmanCode {
+    pub(crate) is_ac: bool,
+    pub(crate) id: u8,
+    pub(crate) is_last: bool,
+    pub(crate) counts: [u32; 17],
+    pub(crate) values: Vec<u16>,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct ScanComponent {
+    pub(crate) comp_idx: u8,
+    pub(crate) ac_tbl_idx: u8,
+    pub(crate) dc_tbl_idx: u8,
+}
+
+#[derive(Debug)]
+pub(crate) struct ExtraZeroRun {
+    pub(crate) num_extra_zero_runs: u32,
+    pub(crate) block_idx: u32,
+}
+
+#[derive(Debug)]
+pub(crate) struct ScanInfo {
+    pub(crate) components: Vec<ScanComponent>,
+    pub(crate) ss: u8,
+    pub(crate) se: u8,
+    pub(crate) al: u8,
+    pub(crate) ah: u8,
+    pub(crate) reset_points: Vec<u32>,
+    pub(crate) extra_zero_runs: Vec<ExtraZeroRun>,
+}
+
+/// JPEG bitstream reconstruction data, read from the `jbrd` box.
+///
+/// This holds everything required to restore the original JPEG file except for the DCT
+/// coefficients, which are stored in the frame, and ICC, Exif and XMP payloads, which are stored
+/// in the codestream and metadata boxes.
+#[derive(Debug)]
+pub struct JpegBitstreamData {
+    pub(crate) markers: Vec<u8>,
+    pub(crate) app_markers: Vec<AppMarker>,
+    pub(crate) com_markers: Vec<Vec<u8>>,
+    pub(crate) quant_tables: Vec<QuantTable>,
+    pub(crate) component_type: ComponentType,
+    pub(crate) components: Vec<Component>,
+    pub(crate) huffman_codes: Vec<HuffmanCode>,
+    pub(crate) scans: Vec<ScanInfo>,
+    pub(crate) restart_interval: u32,
+    pub(crate) intermarker_data: Vec<Vec<u8>>,
+    pub(crate) tail_data: Vec<u8>,
+    pub(crate) padding_bits: Option<Vec<bool>>,
+    _handle: Option<AllocHandle>,
+}
+
+impl JpegBitstreamData {
+    /// Parses the content of a `jbrd` box.
+    ///
+    /// Memory used by APPn, COM, intermarker and tail data is recorded to `tracker`, if given,
+    /// for the lifetime of the returned data.
+    pub fn parse(data: &[u8], tracker: Option<&AllocTracker>) -> Result<Self> {
+        let mut bitstream = Bitstream::new(data);
+        let mut jbrd = Self::parse_header(&mut bitstream, tracker)?;
+        bitstream.zero_pad_to_byte()?;
+
+        let compressed = &data[bitstream.num_read_bits() / 8..];
+        jbrd.read_compressed_data(compressed)?;
+        Ok(jbrd)
+    }
+
+    fn parse_header(bitstream: &mut Bitstream, tracker: Option<&AllocTracker>) -> Result<Self> {
+        let is_gray = bitstream.read_bool()?;
+
+        let mut markers = Vec::new();
+        let mut num_app_markers = 0usize;
+        let mut num_com_markers = 0usize;
+        let mut num_scans = 0usize;
+        let mut num_intermarker = 0usize;
+        let mut has_dri = false;
+        loop {
+            let marker = bitstream.read_bits(6)? as u8 + 0xc0;
+            markers.push(marker);
+            match marker {
+                0xe0..=0xef => num_app_markers += 1,
+                0xfe => num_com_markers += 1,
+                0xda => num_scans += 1,
+                0xff => num_intermarker += 1,
+                0xdd => has_dri = true,
+                _ => {}
+            }
+            if marker == 0xd9 {
+                break;
+            }
+        }
+
+        // Buffers are allocated after reading every length, so that the total size is recorded
+        // to the allocation tracker at once.
+        let mut app_markers = Vec::with_capacity(num_app_markers);
+        for _ in 0..num_app_markers {
+            let ty = read_bits!(bitstream, U32(0, 1, 2 + u(1), 4 + u(2)))?;
+            let ty = AppMarkerType::try_from(ty)
+                .map_err(|_| Error::InvalidData("unknown APPn marker type"))?;
+            let len = bitstream.read_bits(16)? as usize + 1;
+            if len < 3 {
+                return Err(Error::InvalidData("APPn marker is too short"));
+            }
+            app_markers.push((ty, len));
+        }
+
+        let mut com_markers = Vec::with_capacity(num_com_markers);
+        for _ in 0..num_com_markers {
+            let len = bitstream.read_bits(16)? as usize + 1;
+            if len < 3 {
+                return Err(Error::InvalidData("COM marker is too short"));
+            }
+            com_markers.push(len);
+        }
+
+        let num_quant_tables = read_bits!(bitstream, U32(1, 2, 3, 4))?;
+        let mut quant_tables = Vec::with_capacity(num_quant_tables as usize);
+        for idx in 0..num_quant_tables {
+            let precision = bitstream.read_bits(1)? as u8;
+            let index = bitstream.read_bits(2)? as u8;
+            let is_last = bitstream.read_bool()?;
+            tracing::trace!(idx, precision, index, is_last, "Quantization table");
+            quant_tables.push(QuantTable {
+                precision,
+                index,
+                is_last,
+            });
+        }
+
+        let component_type = match bitstream.read_bits(2)? {
+            0 => ComponentType::Gray,
+            1 => ComponentType::YCbCr,
+            2 => ComponentType::Rgb,
+            _ => ComponentType::Custom,
+        };
+        let num_components = match component_type {
+            ComponentType::Gray => 1,
+            ComponentType::YCbCr | ComponentType::Rgb => 3,
+            ComponentType::Custom => {
+                let num_components = read_bits!(bitstream, U32(1, 2, 3, 4))?;
+                if num_components != 1 && num_components != 3 {
+                    return Err(Error::InvalidData("invalid number of components"));
+                }
+                num_components as usize
+            }
+        };
+        if (num_components == 1) != is_gray {
+            return Err(Error::InvalidData(
+                "number of components doesn't match grayscale flag",
+            ));
+        }
+
+        let component_ids: Vec<u8> = match component_type {
+            ComponentType::Gray => vec![1],
+            ComponentType::YCbCr => vec![1, 2, 3],
+            ComponentType::Rgb => vec![b'R', b'G', b'B'],
+            ComponentType::Custom => (0..num_components)
+                .map(|_| bitstream.read_bits(8).map(|id| id as u8))
+                .collect::<std::result::Result<_, _>>()?,
+        };
+        let mut components = Vec::with_capacity(num_components);
+        let mut used_tables = 0u32;
+        for id in component_ids {
+            let quant_idx = bitstream.read_bits(2)? as u8;
+            if quant_idx as u32 >= num_quant_tables {
+                return Err(Error::InvalidData("quantization table index out of range"));
+            }
+            used_tables |= 1 << quant_idx;
+            components.push(Component { id, quant_idx });
+        }
+        if used_tables & 1 == 0 {
+            return Err(Error::InvalidData("first quantization table is unused"));
+        }
+
+        let num_huffman_codes = read_bits!(bitstream, U32(4, 2 + u(3), 10 + u(4), 26 + u(6)))?;
+        let mut huffman_codes = Vec::with_capacity(num_huffman_codes as usize);
+        for _ in 0..num_huffman_codes {
+            let is_ac = bitstream.read_bool()?;
+            let id = bitstream.read_bits(2)? as u8;
+            let is_last = bitstream.read_bool()?;
+
+            let mut counts = [0u32; 17];
+            let mut num_symbols = 0u32;
+            for count in &mut counts {
+                *count = read_bits!(bitstream, U32(0, 1, 2 + u(3), u(8)))?;
+                num_symbols += *count;
+            }
+            if num_symbols == 0 {
+                return Err(Error::InvalidData("empty Huffman table"));
+            }
+            if num_symbols > 257 {
+                return Err(Error::InvalidData("too many Huffman symbols"));
+            }
+
+            let mut values = Vec::with_capacity(num_symbols as usize);
+            let mut value_slots = [0u64; 5];
+            for _ in 0..num_symbols {
+                let value = read_bits!(bitstream, U32(u(2), 4 + u(2), 8 + u(4), 1 + u(8)))?;
+                value_slots[value as usize >> 6] |= 1u64 << (value & 0x3f);
+                values.push(value as u16);
+            }
+            if values.last() != Some(&256) {
+                return Err(Error::InvalidData("missing EOI symbol in Huffman table"));
+            }
+            let num_values: u32 = value_slots.iter().map(|slot| slot.count_ones()).sum();
+            if num_values != num_symbols {
+                return Err(Error::InvalidData("duplicate Huffman symbols"));
+            }
+            if !is_ac {
+                let only_dc =
+                    ((value_slots[0] >> 12) | value_slots[1] | value_slots[2] | value_slots[3])
+                        == 0;
+                if !only_dc {
+                    return Err(Error::InvalidData("Huffman symbols out of DC range"));
+                }
+            }
+
+            huffman_codes.push(HuffmanCode {
+                is_ac,
+                id,
+                is_last,
+                counts,
+                values,
+            });
+        }
+
+        let mut scans = Vec::with_capacity(num_scans);
+        for _ in 0..num_scans {
+            let num_components = read_bits!(bitstream, U32(1, 2, 3, 4))?;
+            if num_components >= 4 {
+                return Err(Error::InvalidData("too many components in a scan"));
+            }
+            let ss = bitstream.read_bits(6)? as u8;
+            let se = bitstream.read_bits(6)? as u8;
+            let al = bitstream.read_bits(4)? as u8;
+            let ah = bitstream.read_bits(4)? as u8;
+            let mut scan_components = Vec::with_capacity(num_components as usize);
+            for _ in 0..num_components {
+                let comp_idx = bitstream.read_bits(2)? as u8;
+                if comp_idx as usize >= components.len() {
+                    return Err(Error::InvalidData("component index out of range"));
+                }
+                let ac_tbl_idx = bitstream.read_bits(2)? as u8;
+                let dc_tbl_idx = bitstream.read_bits(2)? as u8;
+                scan_components.push(ScanComponent {
+                    comp_idx,
+                    ac_tbl_idx,
+                    dc_tbl_idx,
+                });
+            }
+            // last_needed_pass is only used for progressive decoding.
+            read_bits!(bitstream, U32(0, 1, 2, 3 + u(3)))?;
+
+            scans.push(ScanInfo {
+                components: scan_components,
+                ss,
+                se,
+                al,
+                ah,
+                reset_points: Vec::new(),
+                extra_zero_runs: Vec::new(),
+            });
+        }
+
+        let restart_interval = if has_dri { bitstream.read_bits(16)? } else { 0 };
+
+        for scan in &mut scans {
+            let num_reset_points = read_bits!(bitstream, U32(0, 1 + u(2), 4 + u(4), 20 + u(16)))?;
+            let mut next_block_idx = 0u32;
+            for _ in 0..num_reset_points {
+                let block_idx = read_bits!(bitstream, U32(0, 1 + u(3), 9 + u(5), 41 + u(28)))?
+                    .checked_add(next_block_idx)
+                    .filter(|&idx| idx < (3 << 26))
+                    .ok_or(Error::InvalidData("reset point out of range"))?;
+                scan.reset_points.push(block_idx);
+                next_block_idx = block_idx + 1;
+            }
+
+            let num_extra_zero_runs =
+                read_bits!(bitstream, U32(0, 1 + u(2), 4 + u(4), 20 + u(16)))?;
+            let mut next_block_idx = 0u32;
+            for _ in 0..num_extra_zero_runs {
+                let num_extra_zero_runs =
+                    read_bits!(bitstream, U32(1, 2 + u(2), 5 + u(4), 20 + u(8)))?;
+                let block_idx = read_bits!(bitstream, U32(0, 1 + u(3), 9 + u(5), 41 + u(28)))?
+                    .checked_add(next_block_idx)
+                    .filter(|&idx| idx <= (3 << 26))
+                    .ok_or(Error::InvalidData("extra zero run out of range"))?;
+                scan.extra_zero_runs.push(ExtraZeroRun {
+                    num_extra_zero_runs,
+                    block_idx,
+                });
+                next_block_idx = block_idx + 1;
+            }
+        }
+
+        let mut intermarker_data = Vec::with_capacity(num_intermarker);
+        for _ in 0..num_intermarker {
+            let len = bitstream.read_bits(16)? as usize;
+            intermarker_data.push(len);
+        }
+
+        let tail_data_len = read_bits!(bitstream, U32(0, 1 + u(8), 257 + u(16), 65793 + u(22)))?;
+        if tail_data_len > MAX_TAIL_DATA_LEN {
+            return Err(Error::InvalidData("tail data is too long"));
+        }
+        let tail_data_len = tail_data_len as usize;
+
+        let has_zero_padding_bit = bitstream.read_bool()?;
+        let padding_bits = if has_zero_padding_bit {
+            let num_bits = bitstream.read_bits(24)? as usize;
+            let mut padding_bits = Vec::with_capacity(num_bits.min(1024));
+            for _ in 0..num_bits {
+                padding_bits.push(bitstream.read_bool()?);
+            }
+            Some(padding_bits)
+        } else {
+            None
+        };
+
+        let total_bytes = app_markers.iter().map(|&(_, len)| len).sum::<usize>()
+            + com_markers.iter().sum::<usize>()
+            + intermarker_data.iter().sum::<usize>()
+            + tail_data_len;
+        let handle = tracker
+            .map(|tracker| tracker.alloc::<u8>(total_bytes))
+            .transpose()?;
+        let app_markers = app_markers
+            .into_iter()
+            .map(|(ty, len)| AppMarker {
+                ty,
+                data: vec![0u8; len],
+            })
+            .collect();
+        let com_markers = com_markers.into_iter().map(|len| vec![0u8; len]).collect();
+        let intermarker_data = intermarker_data
+            .into_iter()
+            .map(|len| vec![0u8; len])
+            .collect();
+
+        Ok(Self {
+            markers,
+            app_markers,
+            com_markers,
+            quant_tables,
+            component_type,
+            components,
+            huffman_codes,
+            scans,
+            restart_interval,
+            intermarker_data,
+            tail_data: vec![0u8; tail_data_len],
+            padding_bits,
+            _handle: handle,
+        })
+    }
+
+    fn read_compressed_data(&mut self, compressed: &[u8]) -> Result<()> {
+        let mut decompressor = brotli_decompressor::Decompressor::new(compressed, 4096);
+        let mut read_exact = |buf: &mut [u8]| {
+            decompressor.read_exact(buf).map_err(|e| {
+                tracing::error!(%e, "Failed to read compressed data of jbrd");
+                Error::Brotli(e)
+            })
+        };
+
+        let mut num_icc = 0u8;
+        for marker in &mut self.app_markers {
+            let data = &mut marker.data;
+            if marker.ty == AppMarkerType::Unknown {
+                read_exact(data)?;
+                if data[1] as usize * 256 + data[2] as usize + 1 != data.len() {
+                    return Err(Error::InvalidData("incorrect APPn marker size"));
+                }
+                continue;
+            }
+
+            let size = data.len() - 1;
+            data[1] = (size >> 8) as u8;
+            data[2] = size as u8;
+            match marker.ty {
+                AppMarkerType::Icc => {
+                    if data.len() < 17 {
+                        return Err(Error::InvalidData("ICC marker is too short"));
+                    }
+                    num_icc = num_icc
+                        .checked_add(1)
+                        .ok_or(Error::InvalidData("too many ICC markers"))?;
+                    data[0] = 0xe2;
+                    data[3..15].copy_from_slice(ICC_PROFILE_TAG);
+                    data[15] = num_icc;
+                }
+                AppMarkerType::Exif => {
+                    if data.len() < 3 + EXIF_TAG.len() {
+                        return Err(Error::InvalidData("Exif marker is too short"));
+                    }
+                    data[0] = 0xe1;
+                    data[3..][..EXIF_TAG.len()].copy_from_slice(EXIF_TAG);
+                }
+                AppMarkerType::Xmp => {
+                    if data.len() < 3 + XMP_TAG.len() {
+                        return Err(Error::InvalidData("XMP marker is too short"));
+                    }
+                    data[0] = 0xe1;
+                    data[3..][..XMP_TAG.len()].copy_from_slice(XMP_TAG);
+                }
+                AppMarkerType::Unknown => unreachable!(),
+            }
+        }
+        for marker in &mut self.app_markers {
+            if marker.ty == AppMarkerType::Icc {
+                marker.data[16] = num_icc;
+            }
+        }
+
+        for marker in &mut self.com_markers {
+            read_exact(marker)?;
+            if marker[1] as usize * 256 + marker[2] as usize + 1 != marker.len() {
+                return Err(Error::InvalidData("incorrect COM marker size"));
+            }
+        }
+        for data in &mut self.intermarker_data {
+            read_exact(data)?;
+        }
+        read_exact(&mut self.tail_data)?;
+
+        let mut excess = [0u8];
+        match decompressor.read(&mut excess) {
+            Ok(0) => Ok(()),
+            Ok(_) => Err(Error::InvalidData("excess data in compressed stream")),
+            Err(e) => Err(Error::Brotli(e)),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    Bitstream(jxl_bitstream::Error),
+    Frame(jxl_frame::Error),
+    Modular(jxl_modular::Error),
+    Buffer(jxl_grid::Error),
+    Io(std::io::Error),
+    Brotli(std::io::Error),
+    IncompleteFrame,
+    UnsupportedFrame(&'static str),
+    InvalidData(&'static str),
+    MetadataMismatch(&'static str),
+}
+
+impl From<jxl_bitstream::Error> for Error {
+    fn from(err: jxl_bitstream::Error) -> Self {
+        Self::Bitstream(err)
+    }
+}
+
+impl From<jxl_frame::Error> for Error {
+    fn from(err: jxl_frame::Error) -> Self {
+        Self::Frame(err)
+    }
+}
+
+impl From<jxl_modular::Error> for Error {
+    fn from(err: jxl_modular::Error) -> Self {
+        Self::Modular(err)
+    }
+}
+
+impl From<jxl_grid::Error> for Error {
+    fn from(err: jxl_grid::Error) -> Self {
+        Self::Buffer(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Error::*;
+
+        match self {
+            Bitstream(err) => write!(f, "bitstream error: {}", err),
+            Frame(err) => write!(f, "error while decoding frame: {}", err),
+            Modular(err) => write!(f, "modular stream error: {}", err),
+            Buffer(err) => write!(f, "{}", err),
+            Io(err) => write!(f, "I/O error: {}", err),
+            Brotli(err) => write!(f, "failed to decompress JPEG bitstream data: {}", err),
+            IncompleteFrame => write!(f, "frame data is incomplete"),
+            UnsupportedFrame(msg) => write!(f, "frame cannot be reconstructed to JPEG: {}", msg),
+            InvalidData(msg) => write!(f, "invalid JPEG bitstream reconstruction data: {}", msg),
+            MetadataMismatch(msg) => write!(f, "metadata does not match JPEG markers: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        use Error::*;
+
+        match self {
+            Bitstream(err) => Some(err),
+            Frame(err) => Some(err),
+            Modular(err) => Some(err),
+            Buffer(err) => Some(err),
+            Io(err) => Some(err),
+            Brotli(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
@@ -0,0 +1,107 @@
+//! This crate provides lossless reconstruction of JPEG bitstreams recompressed into JPEG XL images.
+//!
+//! Recompressed JPEG images store quantized DCT coefficients in a VarDCT frame, and the rest of
+//! the JPEG bitstream (markers, Huffman tables, scan layout, etc.) in the JPEG bitstream
+//! reconstruction data (`jbrd`) box. [`JpegBitstreamData`] parses the `jbrd` box and writes the
+//! original JPEG file using the decoded frame.
+use std::io::Write;
+
+use jxl_frame::Frame;
+use jxl_threadpool::JxlThreadPool;
+
+mod coeff;
+mod data;
+mod error;
+mod writer;
+
+pub use data::{AppMarkerType, JpegBitstreamData};
+pub use error::{Error, Result};
+
+use data::{ComponentType, EXIF_TAG, XMP_TAG};
+
+impl JpegBitstreamData {
+    /// Reconstructs the original JPEG bitstream, and writes it to `writer`.
+    ///
+    /// `frame` should be the first (and only) frame of the image, which is fully loaded. `icc`,
+    /// `exif` and `xmp` are the metadata stored in the image, which are written to APPn markers
+    /// signalled in the reconstruction data. `exif` is the content of the `Exif` box without the
+    /// TIFF header offset.
+    pub fn reconstruct(
+        &self,
+        frame: &Frame,
+        icc: Option<&[u8]>,
+        exif: Option<&[u8]>,
+        xmp: Option<&[u8]>,
+        pool: &JxlThreadPool,
+        mut writer: impl Write,
+    ) -> Result<()> {
+        let is_gray = frame.image_header().metadata.grayscale();
+        let expected_components = if is_gray { 1 } else { 3 };
+        if self.components.len() != expected_components
+            || (self.component_type == ComponentType::Gray && !is_gray)
+        {
+            return Err(Error::InvalidData(
+                "number of components doesn't match the image",
+            ));
+        }
+
+        let app_markers = self.fill_app_markers(icc, exif, xmp)?;
+        let coeffs = coeff::decode_frame_coeffs(frame, is_gray, pool)?;
+        let jpeg = writer::write_jpeg(self, &coeffs, &app_markers)?;
+        writer.write_all(&jpeg)?;
+        Ok(())
+    }
+
+    /// Returns APPn marker data with ICC profile, Exif and XMP payload filled in.
+    fn fill_app_markers(
+        &self,
+        icc: Option<&[u8]>,
+        exif: Option<&[u8]>,
+        xmp: Option<&[u8]>,
+    ) -> Result<Vec<Vec<u8>>> {
+        let mut icc_remaining = icc.unwrap_or(&[]);
+        let mut has_icc_marker = false;
+        let mut app_markers = Vec::with_capacity(self.app_markers.len());
+        for marker in &self.app_markers {
+            let mut data = marker.data.clone();
+            match marker.ty {
+                AppMarkerType::Unknown => {}
+                AppMarkerType::Icc => {
+                    has_icc_marker = true;
+                    let len = data.len() - 17;
+                    if icc_remaining.len() < len {
+                        return Err(Error::MetadataMismatch("ICC profile is too short"));
+                    }
+                    let (chunk, rest) = icc_remaining.split_at(len);
+                    data[17..].copy_from_slice(chunk);
+                    icc_remaining = rest;
+                }
+                AppMarkerType::Exif => {
+                    let exif = exif.ok_or(Error::MetadataMismatch("Exif box not found"))?;
+                    let offset = 3 + EXIF_TAG.len();
+                    if data.len() - offset != exif.len() {
+                        return Err(Error::MetadataMismatch("Exif data size mismatch"));
+                    }
+                    data[offset..].copy_from_slice(exif);
+                }
+                AppMarkerType::Xmp => {
+                    let xmp = xmp.ok_or(Error::MetadataMismatch("XMP box not found"))?;
+                    let offset = 3 + XMP_TAG.len();
+                    if data.len() - offset != xmp.len() {
+                        return Err(Error::MetadataMismatch("XMP data size mismatch"));
+                    }
+                    data[offset..].copy_from_slice(xmp);
+                }
+            }
+            app_markers.push(data);
+        }
+
+        if !icc_remaining.is_empty() {
+            return Err(Error::MetadataMismatch("ICC profile is too long"));
+        }
+        if has_icc_marker && icc.is_none() {
+            return Err(Error::MetadataMismatch("ICC profile not found"));
+        }
+        Ok(app_markers)
+    }
+}
@@ -0,0 +1,849 @@
+use crate::{
+    coeff::{ComponentCoeffs, FrameCoeffs},
+    data::{HuffmanCode, JpegBitstreamData, ScanInfo},
+    Error, Result,
+};
+
+/// Maps zigzag order to the natural order.
+#[rustfmt::skip]
+const JPEG_NATURAL_ORDER: [usize; 64] = [
+    0,  1,  8, 16,  9,  2,  3, 10,
+    17, 24, 32, 25, 18, 11,  4,  5,
+    12, 19, 26, 33, 40, 48, 41, 34,
+    27, 20, 13,  6,  7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46,
+    53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+const MAX_CORRECTION_BITS: usize = 1 << 15;
+
+/// Huffman code table, indexed by symbol.
+#[derive(Debug, Clone)]
+struct HuffmanCodeTable {
+    depth: [u8; 256],
+    code: [u16; 256],
+}
+
+impl Default for HuffmanCodeTable {
+    fn default() -> Self {
+        Self {
+            depth: [0; 256],
+            code: [0; 256],
+        }
+    }
+}
+
+impl HuffmanCodeTable {
+    fn build(huff: &HuffmanCode) -> Result<Self> {
+        let mut sizes = Vec::with_capacity(257);
+        for (len, &count) in huff.counts.iter().enumerate().skip(1) {
+            for _ in 0..count {
+                sizes.push(len as u8);
+            }
+        }
+        if sizes.len() > 257 {
+            return Err(Error::InvalidData("too many Huffman symbols"));
+        }
+
+        let mut table = Self::default();
+        // The last symbol is the reserved all-ones code, which is not assigned to any value.
+        let num_codes = sizes.len().saturating_sub(1);
+        let mut code = 0u32;
+        let mut prev_size = sizes.first().copied().unwrap_or(0);
+        for (&size, &value) in sizes[..num_codes].iter().zip(&huff.values) {
+            code <<= size - prev_size;
+            prev_size = size;
+            if value >= 256 {
+                return Err(Error::InvalidData("invalid Huffman symbol"));
+            }
+            table.depth[value as usize] = size;
+            table.code[value as usize] = code as u16;
+            code += 1;
+        }
+        Ok(table)
+    }
+}
+
+/// Bit writer for entropy-coded segments, which stuffs a zero byte after each `0xff` byte.
+#[derive(Debug)]
+struct JpegBitWriter<'out> {
+    out: &'out mut Vec<u8>,
+    buffer: u64,
+    num_bits: u32,
+    healthy: bool,
+}
+
+impl<'out> JpegBitWriter<'out> {
+    fn new(out: &'out mut Vec<u8>) -> Self {
+        Self {
+            out,
+            buffer: 0,
+            num_bits: 0,
+            healthy: true,
+        }
+    }
+
+    fn emit_byte(&mut self, byte: u8) {
+        self.out.push(byte);
+        if byte == 0xff {
+            self.out.push(0);
+        }
+    }
+
+    fn write_bits(&mut self, nbits: u8, bits: u32) {
+        if nbits == 0 {
+            // Encoding a symbol not present in the Huffman table.
+            self.healthy = false;
+            return;
+        }
+        let nbits = nbits as u32;
+        self.buffer = (self.buffer << nbits) | (bits as u64 & ((1u64 << nbits) - 1));
+        self.num_bits += nbits;
+        while self.num_bits >= 8 {
+            self.num_bits -= 8;
+            self.emit_byte((self.buffer >> self.num_bits) as u8);
+        }
+    }
+
+    fn write_symbol(&mut self, table: &HuffmanCodeTable, symbol: usize) {
+        self.write_bits(table.depth[symbol], table.code[symbol] as u32);
+    }
+
+    /// Fills the partial byte with padding bits.
+    fn jump_to_byte_boundary(&mut self, padding_bits: &mut Option<PaddingBits>) -> Result<()> {
+        if self.num_bits == 0 {
+            return Ok(());
+        }
+
+        let num_pad_bits = 8 - self.num_bits;
+        let pad_pattern = if let Some(padding_bits) = padding_bits {
+            let mut pattern = 0u32;
+            for _ in 0..num_pad_bits {
+                let bit = padding_bits
+                    .next()
+                    .ok_or(Error::InvalidData("not enough padding bits"))?;
+                pattern = (pattern << 1) | bit as u32;
+            }
+            pattern
+        } else {
+            (1u32 << num_pad_bits) - 1
+        };
+
+        let byte = ((self.buffer << num_pad_bits) as u32 | pad_pattern) as u8;
+        self.emit_byte(byte);
+        self.buffer = 0;
+        self.num_bits = 0;
+        Ok(())
+    }
+
+    fn emit_marker(&mut self, marker: u8) {
+        self.out.extend_from_slice(&[0xff, marker]);
+    }
+}
+
+#[derive(Debug)]
+struct PaddingBits<'a> {
+    bits: std::slice::Iter<'a, bool>,
+}
+
+impl PaddingBits<'_> {
+    fn next(&mut self) -> Option<bool> {
+        self.bits.next().copied()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.bits.len() == 0
+    }
+}
+
+/// State of end-of-band runs and refinement bits in progressive scans.
+#[derive(Debug, Default)]
+struct DctCodingState {
+    eob_run: u32,
+    cur_ac_huff: Option<usize>,
+    refinement_bits: Vec<bool>,
+}
+
+impl DctCodingState {
+    fn flush(&mut self, bw: &mut JpegBitWriter, ac_tables: &[HuffmanCodeTable; 4]) {
+        if self.eob_run > 0 {
+            let table = &ac_tables[self.cur_ac_huff.unwrap()];
+            let nbits = 31 - self.eob_run.leading_zeros();
+            bw.write_symbol(table, (nbits << 4) as usize);
+            if nbits > 0 {
+                bw.write_bits(nbits as u8, self.eob_run);
+            }
+            self.eob_run = 0;
+        }
+        for &bit in &self.refinement_bits {
+            bw.write_bits(1, bit as u32);
+        }
+        self.refinement_bits.clear();
+    }
+
+    fn buffer_end_of_band(
+        &mut self,
+        ac_huff: usize,
+        new_bits: Option<&[bool]>,
+        bw: &mut JpegBitWriter,
+        ac_tables: &[HuffmanCodeTable; 4],
+    ) {
+        if self.eob_run == 0 {
+            self.cur_ac_huff = Some(ac_huff);
+        }
+        self.eob_run += 1;
+        if let Some(new_bits) = new_bits {
+            self.refinement_bits.extend_from_slice(new_bits);
+        }
+        if self.eob_run == 0x7fff || self.refinement_bits.len() > MAX_CORRECTION_BITS - 64 + 1 {
+            self.flush(bw, ac_tables);
+        }
+    }
+}
+
+/// Returns the number of bits needed to represent `value`, and the bits to write.
+#[inline]
+fn encode_value(value: i32) -> (u8, u32) {
+    let abs = value.unsigned_abs();
+    let nbits = 32 - abs.leading_zeros();
+    let bits = if value < 0 { !abs } else { abs };
+    (nbits as u8, bits)
+}
+
+struct JpegWriter<'a> {
+    jbrd: &'a JpegBitstreamData,
+    coeffs: &'a FrameCoeffs,
+    app_markers: &'a [Vec<u8>],
+    out: Vec<u8>,
+    is_progressive: bool,
+    seen_dri: bool,
+    dc_tables: [HuffmanCodeTable; 4],
+    ac_tables: [HuffmanCodeTable; 4],
+    padding_bits: Option<PaddingBits<'a>>,
+    dht_index: usize,
+    dqt_index: usize,
+    app_index: usize,
+    com_index: usize,
+    intermarker_index: usize,
+    scan_index: usize,
+}
+
+/// Writes JPEG bitstream using reconstruction data and decoded coefficients.
+///
+/// `app_markers` are APPn markers with the payload filled in.
+pub(crate) fn write_jpeg(
+    jbrd: &JpegBitstreamData,
+    coeffs: &FrameCoeffs,
+    app_markers: &[Vec<u8>],
+) -> Result<Vec<u8>> {
+    if jbrd.components.len() != coeffs.components.len() {
+        return Err(Error::InvalidData(
+            "number of components doesn't match the frame",
+        ));
+    }
+
+    let mut writer = JpegWriter {
+        jbrd,
+        coeffs,
+        app_markers,
+        out: Vec::new(),
+        is_progressive: false,
+        seen_dri: false,
+        dc_tables: Default::default(),
+        ac_tables: Default::default(),
+        padding_bits: jbrd
+            .padding_bits
+            .as_ref()
+            .map(|bits| PaddingBits { bits: bits.iter() }),
+        dht_index: 0,
+        dqt_index: 0,
+        app_index: 0,
+        com_index: 0,
+        intermarker_index: 0,
+        scan_index: 0,
+    };
+
+    writer.out.extend_from_slice(&[0xff, 0xd8]);
+    for &marker in &jbrd.markers {
+        writer.write_marker(marker).map_err(|e| {
+            tracing::error!(marker = format_args!("0x{:02x}", marker), %e, "Failed to write marker");
+            e
+        })?;
+    }
+
+    if !writer
+        .padding_bits
+        .as_ref()
+        .map(|bits| bits.is_empty())
+        .unwrap_or(true)
+    {
+        return Err(Error::InvalidData("excess padding bits"));
+    }
+    Ok(writer.out)
+}
+
+impl JpegWriter<'_> {
+    fn write_marker(&mut self, marker: u8) -> Result<()> {
+        match marker {
+            0xc0..=0xc2 => self.write_sof(marker),
+            0xc4 => self.write_dht(),
+            0xd0..=0xd7 => {
+                self.out.extend_from_slice(&[0xff, marker]);
+                Ok(())
+            }
+            0xd9 => {
+                self.out.extend_from_slice(&[0xff, 0xd9]);
+                self.out.extend_from_slice(&self.jbrd.tail_data);
+                Ok(())
+            }
+            0xda => self.write_scan(),
+            0xdb => self.write_dqt(),
+            0xdd => {
+                self.seen_dri = true;
+                let ri = self.jbrd.restart_interval;
+                self.out
+                    .extend_from_slice(&[0xff, 0xdd, 0, 4, (ri >> 8) as u8, ri as u8]);
+                Ok(())
+            }
+            0xe0..=0xef => {
+                let data = self
+                    .app_markers
+                    .get(self.app_index)
+                    .ok_or(Error::InvalidData("too many APPn markers"))?;
+                self.app_index += 1;
+                self.out.push(0xff);
+                self.out.extend_from_slice(data);
+                Ok(())
+            }
+            0xfe => {
+                let data = self
+                    .jbrd
+                    .com_markers
+                    .get(self.com_index)
+                    .ok_or(Error::InvalidData("too many COM markers"))?;
+                self.com_index += 1;
+                self.out.push(0xff);
+                self.out.extend_from_slice(data);
+                Ok(())
+            }
+            0xff => {
+                let data = self
+                    .jbrd
+                    .intermarker_data
+                    .get(self.intermarker_index)
+                    .ok_or(Error::InvalidData("too many intermarker data"))?;
+                self.intermarker_index += 1;
+                self.out.extend_from_slice(data);
+                Ok(())
+            }
+            _ => Err(Error::InvalidData("unsupported marker")),
+        }
+    }
+
+    fn write_sof(&mut self, marker: u8) -> Result<()> {
+        self.is_progressive = marker == 0xc2;
+
+        let components = &self.jbrd.components;
+        let marker_len = 8 + 3 * components.len();
+        let width = self.coeffs.width;
+        let height = self.coeffs.height;
+        self.out.extend_from_slice(&[
+            0xff,
+            marker,
+            (marker_len >> 8) as u8,
+            marker_len as u8,
+            8,
+            (height >> 8) as u8,
+            height as u8,
+            (width >> 8) as u8,
+            width as u8,
+            components.len() as u8,
+        ]);
+        for (component, coeffs) in components.iter().zip(&self.coeffs.components) {
+            let quant_table = self
+                .jbrd
+                .quant_tables
+                .get(component.quant_idx as usize)
+                .ok_or(Error::InvalidData("quantization table index out of range"))?;
+            self.out.extend_from_slice(&[
+                component.id,
+                ((coeffs.h_samp_factor << 4) | coeffs.v_samp_factor) as u8,
+                quant_table.index,
+            ]);
+        }
+        Ok(())
+    }
+
+    fn write_dht(&mut self) -> Result<()> {
+        let codes = &self.jbrd.huffman_codes;
+
+        let mut marker_len = 2usize;
+        for code in codes.iter().skip(self.dht_index) {
+            marker_len += 16 + code.counts.iter().sum::<u32>() as usize;
+            if code.is_last {
+                break;
+            }
+        }
+        self.out
+            .extend_from_slice(&[0xff, 0xc4, (marker_len >> 8) as u8, marker_len as u8]);
+
+        loop {
+            let code = codes
+                .get(self.dht_index)
+                .ok_or(Error::InvalidData("too many DHT markers"))?;
+            self.dht_index += 1;
+
+            let table = HuffmanCodeTable::build(code)?;
+            let slot_id = ((code.is_ac as u8) << 4) | code.id;
+            if code.is_ac {
+                self.ac_tables[code.id as usize] = table;
+            } else {
+                self.dc_tables[code.id as usize] = table;
+            }
+
+            let max_length = code.counts.iter().rposition(|&c| c != 0).unwrap_or(0);
+            let total_count = code.counts.iter().sum::<u32>() as usize - 1;
+            self.out.push(slot_id);
+            for (len, &count) in code.counts.iter().enumerate().skip(1) {
+                let count = if len == max_length { count - 1 } else { count };
+                self.out.push(count as u8);
+            }
+            self.out
+                .extend(code.values[..total_count].iter().map(|&v| v as u8));
+
+            if code.is_last {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_dqt(&mut self) -> Result<()> {
+        let tables = &self.jbrd.quant_tables;
+
+        let mut marker_len = 2usize;
+        for table in tables.iter().skip(self.dqt_index) {
+            marker_len += 1 + if table.precision != 0 { 128 } else { 64 };
+            if table.is_last {
+                break;
+            }
+        }
+        self.out
+            .extend_from_slice(&[0xff, 0xdb, (marker_len >> 8) as u8, marker_len as u8]);
+
+        loop {
+            let idx = self.dqt_index;
+            let table = tables
+                .get(idx)
+                .ok_or(Error::InvalidData("too many DQT markers"))?;
+            self.dqt_index += 1;
+
+            let values = self.quant_table_values(idx)?;
+            self.out.push((table.precision << 4) | table.index);
+            for &natural_idx in &JPEG_NATURAL_ORDER {
+                let value = values[natural_idx];
+                if table.precision != 0 {
+                    self.out.push((value >> 8) as u8);
+                }
+                self.out.push(value as u8);
+            }
+
+            if table.is_last {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the values of the quantization table, taken from the frame.
+    ///
+    /// Tables not used by any of the components are copies of the previous table.
+    fn quant_table_values(&self, idx: usize) -> Result<[i32; 64]> {
+        for table_idx in (0..=idx).rev() {
+            let component = self
+                .jbrd
+                .components
+                .iter()
+                .zip(&self.coeffs.components)
+                .rfind(|(component, _)| component.quant_idx as usize == table_idx);
+            if let Some((_, coeffs)) = component {
+                return Ok(coeffs.quant_table);
+            }
+        }
+        Err(Error::InvalidData("first quantization table is unused"))
+    }
+
+    fn write_scan(&mut self) -> Result<()> {
+        let scan = self
+            .jbrd
+            .scans
+            .get(self.scan_index)
+            .ok_or(Error::InvalidData("too many SOS markers"))?;
+        self.scan_index += 1;
+
+        let marker_len = 6 + 2 * scan.components.len();
+        self.out.extend_from_slice(&[
+            0xff,
+            0xda,
+            (marker_len >> 8) as u8,
+            marker_len as u8,
+            scan.components.len() as u8,
+        ]);
+        for sc in &scan.components {
+            let component = &self.jbrd.components[sc.comp_idx as usize];
+            self.out
+                .extend_from_slice(&[component.id, (sc.dc_tbl_idx << 4) | sc.ac_tbl_idx]);
+        }
+        self.out
+            .extend_from_slice(&[scan.ss, scan.se, (scan.ah << 4) | scan.al]);
+
+        let (ss, se, ah, al) = if self.is_progressive {
+            (scan.ss, scan.se, scan.ah, scan.al)
+        } else {
+            (0, 63, 0, 0)
+        };
+        let mode = if !self.is_progressive || (ah == 0 && al == 0 && ss == 0 && se == 63) {
+            ScanMode::Sequential
+        } else if ah == 0 {
+            ScanMode::ProgressiveFirst
+        } else {
+            ScanMode::Refinement
+        };
+        self.write_scan_data(scan, mode, ss as usize, se as usize, al as u32)
+    }
+
+    fn write_scan_data(
+        &mut self,
+        scan: &ScanInfo,
+        mode: ScanMode,
+        ss: usize,
+        se: usize,
+        al: u32,
+    ) -> Result<()> {
+        let components = &self.coeffs.components;
+        let restart_interval = if self.seen_dri {
+            self.jbrd.restart_interval
+        } else {
+            0
+        };
+
+        let max_h_samp = components.iter().map(|c| c.h_samp_factor).max().unwrap();
+        let max_v_samp = components.iter().map(|c| c.v_samp_factor).max().unwrap();
+        let is_interleaved = scan.components.len() > 1;
+        let (mcus_per_row, mcu_rows) = if is_interleaved {
+            (
+                self.coeffs.width.div_ceil(8 * max_h_samp),
+                self.coeffs.height.div_ceil(8 * max_v_samp),
+            )
+        } else {
+            let c = &components[scan.components[0].comp_idx as usize];
+            (
+                (self.coeffs.width * c.h_samp_factor).div_ceil(8 * max_h_samp),
+                (self.coeffs.height * c.v_samp_factor).div_ceil(8 * max_v_samp),
+            )
+        };
+
+        let dc_tables = self.dc_tables.clone();
+        let ac_tables = self.ac_tables.clone();
+        let mut padding_bits = self.padding_bits.take();
+        let mut bw = JpegBitWriter::new(&mut self.out);
+        let mut coding_state = DctCodingState::default();
+        let mut last_dc_coeff = [0i32; 4];
+        let mut restarts_to_go = restart_interval;
+        let mut next_restart_marker = 0u8;
+        let mut reset_points = scan.reset_points.iter().copied().peekable();
+        let mut extra_zero_runs = scan.extra_zero_runs.iter().peekable();
+        let mut block_scan_index = 0u32;
+
+        for mcu_y in 0..mcu_rows as usize {
+            for mcu_x in 0..mcus_per_row as usize {
+                if restart_interval > 0 && restarts_to_go == 0 {
+                    coding_state.flush(&mut bw, &ac_tables);
+                    bw.jump_to_byte_boundary(&mut padding_bits)?;
+                    bw.emit_marker(0xd0 + next_restart_marker);
+                    next_restart_marker = (next_restart_marker + 1) & 7;
+                    restarts_to_go = restart_interval;
+                    last_dc_coeff = [0; 4];
+                }
+
+                for sc in &scan.components {
+                    let comp_idx = sc.comp_idx as usize;
+                    let c = &components[comp_idx];
+                    let dc_huff = &dc_tables[sc.dc_tbl_idx as usize];
+                    let ac_huff_idx = sc.ac_tbl_idx as usize;
+                    let (n_blocks_x, n_blocks_y) = if is_interleaved {
+                        (c.h_samp_factor as usize, c.v_samp_factor as usize)
+                    } else {
+                        (1, 1)
+                    };
+
+                    for iy in 0..n_blocks_y {
+                        for ix in 0..n_blocks_x {
+                            let block_y = mcu_y * n_blocks_y + iy;
+                            let block_x = mcu_x * n_blocks_x + ix;
+                            let block = block_of(c, block_x, block_y)?;
+
+                            if reset_points.peek() == Some(&block_scan_index) {
+                                coding_state.flush(&mut bw, &ac_tables);
+                                reset_points.next();
+                            }
+                            let mut num_zero_runs = 0;
+                            if let Some(run) =
+                                extra_zero_runs.next_if(|run| run.block_idx == block_scan_index)
+                            {
+                                num_zero_runs = run.num_extra_zero_runs;
+                            }
+
+                            let ctx = BlockContext {
+                                dc_huff,
+                                ac_huff_idx,
+                                ac_tables: &ac_tables,
+                                num_zero_runs,
+                            };
+                            match mode {
+                                ScanMode::Sequential => encode_block_sequential(
+                                    block,
+                                    &ctx,
+                                    &mut last_dc_coeff[comp_idx],
+                                    &mut bw,
+                                )?,
+                                ScanMode::ProgressiveFirst => encode_block_progressive(
+                                    block,
+                                    &ctx,
+                                    (ss, se, al),
+                                    &mut coding_state,
+                                    &mut last_dc_coeff[comp_idx],
+                                    &mut bw,
+                                ),
+                                ScanMode::Refinement => encode_refinement_bits(
+                                    block,
+                                    &ctx,
+                                    (ss, se, al),
+                                    &mut coding_state,
+                                    &mut bw,
+                                ),
+                            }
+                            block_scan_index += 1;
+                        }
+                    }
+                }
+                restarts_to_go = restarts_to_go.wrapping_sub(1);
+            }
+        }
+
+        coding_state.flush(&mut bw, &ac_tables);
+        bw.jump_to_byte_boundary(&mut padding_bits)?;
+        let healthy = bw.healthy;
+        self.padding_bits = padding_bits;
+        if !healthy {
+            return Err(Error::InvalidData("failed to encode scan"));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum ScanMode {
+    Sequential,
+    ProgressiveFirst,
+    Refinement,
+}
+
+struct BlockContext<'a> {
+    dc_huff: &'a HuffmanCodeTable,
+    ac_huff_idx: usize,
+    ac_tables: &'a [HuffmanCodeTable; 4],
+    num_zero_runs: u32,
+}
+
+impl BlockContext<'_> {
+    #[inline]
+    fn ac_huff(&self) -> &HuffmanCodeTable {
+        &self.ac_tables[self.ac_huff_idx]
+    }
+}
+
+fn block_of(c: &ComponentCoeffs, block_x: usize, block_y: usize) -> Result<&[i16]> {
+    if block_x >= c.width_in_blocks || block_y >= c.height_in_blocks {
+        return Err(Error::InvalidData("block out of range"));
+    }
+    let block_idx = block_y * c.width_in_blocks + block_x;
+    Ok(&c.coeffs[block_idx * 64..][..64])
+}
+
+fn encode_block_sequential(
+    coeffs: &[i16],
+    ctx: &BlockContext,
+    last_dc_coeff: &mut i32,
+    bw: &mut JpegBitWriter,
+) -> Result<()> {
+    let dc = coeffs[0] as i32;
+    let (dc_nbits, dc_bits) = encode_value(dc - *last_dc_coeff);
+    *last_dc_coeff = dc;
+    bw.write_symbol(ctx.dc_huff, dc_nbits as usize);
+    if dc_nbits >= 12 {
+        return Err(Error::InvalidData("DC coefficient out of range"));
+    }
+    if dc_nbits > 0 {
+        bw.write_bits(dc_nbits, dc_bits);
+    }
+
+    let ac_huff = ctx.ac_huff();
+    let mut r = 0isize;
+    for &natural_idx in &JPEG_NATURAL_ORDER[1..] {
+        let coeff = coeffs[natural_idx] as i32;
+        if coeff == 0 {
+            r += 1;
+            continue;
+        }
+        while r > 15 {
+            bw.write_symbol(ac_huff, 0xf0);
+            r -= 16;
+        }
+        let (ac_nbits, ac_bits) = encode_value(coeff);
+        if ac_nbits >= 16 {
+            return Err(Error::InvalidData("AC coefficient out of range"));
+        }
+        bw.write_symbol(ac_huff, ((r as usize) << 4) + ac_nbits as usize);
+        bw.write_bits(ac_nbits, ac_bits);
+        r = 0;
+    }
+    for _ in 0..ctx.num_zero_runs {
+        bw.write_symbol(ac_huff, 0xf0);
+        r -= 16;
+    }
+    if r > 0 {
+        bw.write_symbol(ac_huff, 0);
+    }
+    Ok(())
+}
+
+fn encode_block_progressive(
+    coeffs: &[i16],
+    ctx: &BlockContext,
+    (mut ss, se, al): (usize, usize, u32),
+    coding_state: &mut DctCodingState,
+    last_dc_coeff: &mut i32,
+    bw: &mut JpegBitWriter,
+) {
+    let eob_run_allowed = ss > 0;
+    if ss == 0 {
+        let dc = (coeffs[0] as i32) >> al;
+        let (nbits, bits) = encode_value(dc - *last_dc_coeff);
+        *last_dc_coeff = dc;
+        bw.write_symbol(ctx.dc_huff, nbits as usize);
+        if nbits > 0 {
+            bw.write_bits(nbits, bits);
+        }
+        ss += 1;
+    }
+    if ss > se {
+        return;
+    }
+
+    let ac_huff = ctx.ac_huff();
+    let mut r = 0isize;
+    for &natural_idx in &JPEG_NATURAL_ORDER[ss..=se] {
+        let coeff = coeffs[natural_idx] as i32;
+        if coeff == 0 {
+            r += 1;
+            continue;
+        }
+        let abs = coeff.unsigned_abs() >> al;
+        if abs == 0 {
+            r += 1;
+            continue;
+        }
+        let bits = if coeff < 0 { !abs } else { abs };
+        coding_state.flush(bw, ctx.ac_tables);
+        while r > 15 {
+            bw.write_symbol(ac_huff, 0xf0);
+            r -= 16;
+        }
+        let nbits = (32 - abs.leading_zeros()) as u8;
+        bw.write_symbol(ac_huff, ((r as usize) << 4) + nbits as usize);
+        bw.write_bits(nbits, bits);
+        r = 0;
+    }
+    if ctx.num_zero_runs > 0 {
+        coding_state.flush(bw, ctx.ac_tables);
+        for _ in 0..ctx.num_zero_runs {
+            bw.write_symbol(ac_huff, 0xf0);
+            r -= 16;
+        }
+    }
+    if r > 0 {
+        coding_state.buffer_end_of_band(ctx.ac_huff_idx, None, bw, ctx.ac_tables);
+        if !eob_run_allowed {
+            coding_state.flush(bw, ctx.ac_tables);
+        }
+    }
+}
+
+fn encode_refinement_bits(
+    coeffs: &[i16],
+    ctx: &BlockContext,
+    (mut ss, se, al): (usize, usize, u32),
+    coding_state: &mut DctCodingState,
+    bw: &mut JpegBitWriter,
+) {
+    let eob_run_allowed = ss > 0;
+    if ss == 0 {
+        bw.write_bits(1, ((coeffs[0] as i32) >> al) as u32 & 1);
+        ss += 1;
+    }
+    if ss > se {
+        return;
+    }
+
+    let mut abs_values = [0u32; 64];
+    let mut eob = 0;
+    for k in ss..=se {
+        let abs = (coeffs[JPEG_NATURAL_ORDER[k]] as i32).unsigned_abs() >> al;
+        abs_values[k] = abs;
+        if abs == 1 {
+            eob = k;
+        }
+    }
+
+    let ac_huff = ctx.ac_huff();
+    let mut r = 0usize;
+    let mut refinement_bits = Vec::with_capacity(64);
+    for k in ss..=se {
+        if abs_values[k] == 0 {
+            r += 1;
+            continue;
+        }
+        while r > 15 && k <= eob {
+            coding_state.flush(bw, ctx.ac_tables);
+            bw.write_symbol(ac_huff, 0xf0);
+            r -= 16;
+            for &bit in &refinement_bits {
+                bw.write_bits(1, bit as u32);
+            }
+            refinement_bits.clear();
+        }
+        if abs_values[k] > 1 {
+            refinement_bits.push(abs_values[k] & 1 != 0);
+            continue;
+        }
+        coding_state.flush(bw, ctx.ac_tables);
+        let symbol = (r << 4) + 1;
+        let new_non_zero_bit = coeffs[JPEG_NATURAL_ORDER[k]] >= 0;
+        bw.write_symbol(ac_huff, symbol);
+        bw.write_bits(1, new_non_zero_bit as u32);
+        for &bit in &refinement_bits {
+            bw.write_bits(1, bit as u32);
+        }
+        refinement_bits.clear();
+        r = 0;
+    }
+    if r > 0 || !refinement_bits.is_empty() {
+        coding_state.buffer_end_of_band(ctx.ac_huff_idx, Some(&refinement_bits), bw, ctx.ac_tables);
+        if !eob_run_allowed {
+            coding_state.flush(bw, ctx.ac_tables);
+        }
+    }
+}
@@ -0,0 +1,918 @@
+//! Lossless Modular image encoder.
+//!
+//! The encoder applies either palette or reversible colour transform (RCT) to the channels,
+//! optionally followed by squeeze for progressive decoding, learns a meta-adaptive tree over the
+//! properties the decoder computes, and writes the tree and the residuals of every channel with a
+//! single entropy code shared by every stream.
+use std::collections::{HashMap, VecDeque};
+
+use jxl_bitstream::{pack_signed, BitWriter, BundleDefault, BundleWrite};
+use jxl_coding::{EncodedStreams, Encoder, EncoderOptions, HybridUintConfig};
+use jxl_grid::SimpleGrid;
+
+use crate::predictor::{Predictor, PredictorState, Properties, WpHeader};
+use crate::transform::{Palette, Rct, Squeeze, TransformInfo};
+use crate::{ModularChannelInfo, ModularChannels, ModularHeader, Result};
+
+/// Properties considered when learning the MA tree.
+///
+/// Property 15 requires the self-correcting predictor, which is not used by the encoder.
+const PROPERTIES: [u32; 19] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 16, 17, 18, 19,
+];
+const NUM_PROPERTIES: usize = PROPERTIES.len();
+
+/// Predictors which leaves of the learned MA tree can choose from.
+const PREDICTORS: [Predictor; 7] = [
+    Predictor::Gradient,
+    Predictor::West,
+    Predictor::North,
+    Predictor::Zero,
+    Predictor::Select,
+    Predictor::AvgWestAndNorth,
+    Predictor::AvgAll,
+];
+const NUM_PREDICTORS: usize = PREDICTORS.len();
+
+const MAX_SPLIT_CANDIDATES: usize = 32;
+const MAX_CANDIDATE_SAMPLES: usize = 1024;
+const MAX_SPLIT_SAMPLES: usize = 8192;
+const MIN_LEAF_SAMPLES: usize = 8;
+const MAX_TREE_DEPTH: usize = 64;
+/// Estimated cost of a split in bits, including the tree node and the distribution of a new
+/// context.
+const SPLIT_PENALTY: f64 = 48.0;
+/// Number of pixels sampled when choosing RCT.
+const RCT_ESTIMATE_PIXELS: usize = 1 << 16;
+
+/// Options of the lossless Modular encoder.
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    /// Whether to try palette transform over every channel.
+    pub palette: bool,
+    /// Maximum number of palette colours.
+    pub max_palette_colours: u32,
+    /// Whether to try reversible colour transforms on colour channels, if palette is not used.
+    pub rct: bool,
+    /// Whether to apply squeeze with default parameters, if palette is not used.
+    ///
+    /// Squeezed images can be previewed in lower resolution before every group is loaded.
+    pub squeeze: bool,
+    /// Maximum number of MA tree leaves, in range of `1..=512`. `1` disables tree learning.
+    pub max_tree_leaves: usize,
+    /// Maximum number of samples used to learn the MA tree.
+    pub max_learning_samples: usize,
+    /// Whether to use prefix codes instead of ANS.
+    pub use_prefix_code: bool,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            palette: true,
+            max_palette_colours: 1024,
+            rct: true,
+            squeeze: false,
+            max_tree_leaves: 128,
+            max_learning_samples: 1 << 16,
+            use_prefix_code: false,
+        }
+    }
+}
+
+/// Modular image encoded by [`encode`], which is written to GlobalModular and pass groups of a
+/// frame.
+#[derive(Debug, Clone)]
+pub struct EncodedModular {
+    header: ModularHeader,
+    tree: EncodedStreams,
+    data: EncodedStreams,
+    num_lf_group_streams: usize,
+}
+
+impl EncodedModular {
+    /// Writes GlobalModular section of LfGlobal, which consists of the global MA tree, the
+    /// Modular header and channels small enough to be stored in LfGlobal.
+    pub fn write_global(&self, writer: &mut BitWriter) -> Result<()> {
+        writer.write_bool(true)?;
+        self.tree.write_header(writer)?;
+        self.tree.write_stream(writer, 0)?;
+        self.data.write_header(writer)?;
+        self.header.write(writer, ())?;
+        self.data.write_stream(writer, 0)?;
+        Ok(())
+    }
+
+    /// Returns whether channels are split into LF groups, which happens if squeeze is used.
+    ///
+    /// If this returns `false`, LF groups of the frame should be empty.
+    #[inline]
+    pub fn has_lf_groups(&self) -> bool {
+        self.num_lf_group_streams > 0
+    }
+
+    /// Writes Modular data of the given LF group.
+    ///
+    /// # Panics
+    /// Panics if LF groups are not used, or `lf_group_idx` is out of range.
+    pub fn write_lf_group(&self, writer: &mut BitWriter, lf_group_idx: u32) -> Result<()> {
+        assert!((lf_group_idx as usize) < self.num_lf_group_streams);
+        Self::group_header().write(writer, ())?;
+        self.data.write_stream(writer, 1 + lf_group_idx as usize)?;
+        Ok(())
+    }
+
+    /// Returns whether channels are split into pass groups.
+    ///
+    /// If this returns `false`, pass groups of the frame should be empty.
+    #[inline]
+    pub fn has_pass_groups(&self) -> bool {
+        self.data.num_streams() > 1 + self.num_lf_group_streams
+    }
+
+    /// Writes Modular data of the given pass group.
+    ///
+    /// # Panics
+    /// Panics if pass groups are not used, or `group_idx` is out of range.
+    pub fn write_pass_group(&self, writer: &mut BitWriter, group_idx: u32) -> Result<()> {
+        Self::group_header().write(writer, ())?;
+        let stream_idx = 1 + self.num_lf_group_streams + group_idx as usize;
+        self.data.write_stream(writer, stream_idx)?;
+        Ok(())
+    }
+
+    fn group_header() -> ModularHeader {
+        ModularHeader {
+            use_global_tree: true,
+            wp_params: WpHeader::default_with_context(()),
+            nb_transforms: 0,
+            transform: Vec::new(),
+        }
+    }
+}
+
+/// Encodes channels of an image losslessly.
+///
+/// Every channel should have the same size. The first `num_color_channels` channels are colour
+/// channels, which may be transformed with RCT if there are three of them. `group_dim` should
+/// match the group size of the frame.
+pub fn encode(
+    channels: &[SimpleGrid<i32>],
+    num_color_channels: usize,
+    group_dim: u32,
+    options: &EncodeOptions,
+) -> Result<EncodedModular> {
+    let Some(first) = channels.first() else {
+        return Err(jxl_bitstream::Error::ValidationFailed("no channels to encode").into());
+    };
+    let width = first.width();
+    let height = first.height();
+    if width == 0 || height == 0 {
+        return Err(jxl_bitstream::Error::ValidationFailed("empty channel").into());
+    }
+    if channels
+        .iter()
+        .any(|grid| grid.width() != width || grid.height() != height)
+    {
+        return Err(
+            jxl_bitstream::Error::ValidationFailed("channels should have the same size").into(),
+        );
+    }
+
+    let mut planes = channels
+        .iter()
+        .map(|grid| Plane {
+            info: ModularChannelInfo::new_shifted(width as u32, height as u32, 0, 0),
+            data: grid.buf()[..width * height].to_vec(),
+        })
+        .collect::<Vec<_>>();
+
+    let mut transform = Vec::new();
+    let mut nb_meta_channels = 0usize;
+    let palette = options
+        .palette
+        .then(|| apply_palette(&mut planes, options.max_palette_colours))
+        .flatten();
+    if let Some(palette) = palette {
+        transform.push(TransformInfo::Palette(palette));
+        nb_meta_channels = 1;
+    } else if options.rct && num_color_channels >= 3 && height >= 2 {
+        if let Some(rct) = choose_rct(&planes[..3], width, height) {
+            let [a, b, c, ..] = &mut *planes else {
+                unreachable!()
+            };
+            rct.forward([&mut a.data, &mut b.data, &mut c.data]);
+            transform.push(TransformInfo::Rct(rct));
+        }
+    }
+    if options.squeeze && nb_meta_channels == 0 {
+        let squeeze = Squeeze::new_default();
+        let mut channels = ModularChannels {
+            info: planes.iter().map(|plane| plane.info.clone()).collect(),
+            nb_meta_channels: 0,
+        };
+        let mut data = planes.into_iter().map(|plane| plane.data).collect();
+        squeeze.forward(&mut channels, &mut data)?;
+        planes = channels
+            .info
+            .into_iter()
+            .zip(data)
+            .map(|(info, data)| Plane { info, data })
+            .collect();
+        transform.push(TransformInfo::Squeeze(squeeze));
+    }
+
+    let (streams, num_lf_group_streams) =
+        split_streams(planes, nb_meta_channels, width, height, group_dim);
+
+    let learning_set = LearningSet::collect(&streams, options.max_learning_samples);
+    let max_leaves = options.max_tree_leaves.clamp(1, 512);
+    let tree = learn_tree(&learning_set, max_leaves);
+
+    let mut tree_encoder = Encoder::new(6);
+    let leaf_ctx = write_tree(&tree, &mut tree_encoder);
+    let num_leaves = leaf_ctx.iter().filter(|&&ctx| ctx != u32::MAX).count();
+
+    let mut data_encoder = Encoder::with_options(
+        num_leaves as u32,
+        EncoderOptions {
+            use_prefix_code: options.use_prefix_code,
+            ..Default::default()
+        },
+    );
+    for stream in &streams {
+        data_encoder.begin_stream();
+        for_each_sample(stream, |properties, sample| {
+            let (ctx, predictor) = tree.select(properties, &leaf_ctx);
+            let residual = (sample as i64 - predictor.predict(properties)) as i32;
+            data_encoder.push(ctx, pack_signed(residual));
+        });
+    }
+
+    let header = ModularHeader {
+        use_global_tree: true,
+        wp_params: WpHeader::default_with_context(()),
+        nb_transforms: transform.len() as u32,
+        transform,
+    };
+    Ok(EncodedModular {
+        header,
+        tree: tree_encoder.build()?,
+        data: data_encoder.build()?,
+        num_lf_group_streams,
+    })
+}
+
+/// Channel being encoded, stored in raster order.
+#[derive(Debug, Clone)]
+struct Plane {
+    info: ModularChannelInfo,
+    data: Vec<i32>,
+}
+
+impl Plane {
+    #[inline]
+    fn width(&self) -> usize {
+        self.info.width as usize
+    }
+
+    #[inline]
+    fn height(&self) -> usize {
+        self.info.height as usize
+    }
+
+    #[inline]
+    fn get(&self, x: usize, y: usize) -> i32 {
+        self.data[y * self.width() + x]
+    }
+
+    fn tile(&self, left: usize, top: usize, width: usize, height: usize) -> Self {
+        let width = width.min(self.width() - left);
+        let height = height.min(self.height() - top);
+        let data = (top..top + height)
+            .flat_map(|y| {
+                let offset = y * self.width() + left;
+                self.data[offset..offset + width].iter().copied()
+            })
+            .collect();
+        Self {
+            info: ModularChannelInfo::new_shifted(
+                width as u32,
+                height as u32,
+                self.info.hshift,
+                self.info.vshift,
+            ),
+            data,
+        }
+    }
+
+    fn is_same_kind(&self, other: &Self) -> bool {
+        let a = &self.info;
+        let b = &other.info;
+        a.width == b.width && a.height == b.height && a.hshift == b.hshift && a.vshift == b.vshift
+    }
+}
+
+/// Channels decoded from a single entropy coded stream.
+#[derive(Debug)]
+struct Stream {
+    index: u32,
+    channels: Vec<Plane>,
+}
+
+/// Replaces channels with a palette meta channel and an index channel, if the image has few
+/// distinct colours.
+fn apply_palette(planes: &mut Vec<Plane>, max_colours: u32) -> Option<Palette> {
+    let num_c = planes.len();
+    if num_c > 4 {
+        return None;
+    }
+
+    let num_pixels = planes[0].data.len();
+    let pixel = |idx: usize| {
+        let mut colour = [0i32; 4];
+        for (c, plane) in colour.iter_mut().zip(&*planes) {
+            *c = plane.data[idx];
+        }
+        colour
+    };
+
+    let mut colour_map = HashMap::new();
+    for idx in 0..num_pixels {
+        colour_map.entry(pixel(idx)).or_insert(0u32);
+        if colour_map.len() > max_colours as usize {
+            return None;
+        }
+    }
+    let nb_colours = colour_map.len();
+    if nb_colours * num_c >= num_pixels {
+        return None;
+    }
+
+    // Sort by brightness so that indices of similar colours are close.
+    let mut colours = colour_map.keys().copied().collect::<Vec<_>>();
+    colours.sort_by_key(|colour| (colour.iter().map(|&c| c as i64).sum::<i64>(), *colour));
+    for (idx, colour) in colours.iter().enumerate() {
+        colour_map.insert(*colour, idx as u32);
+    }
+
+    let mut meta = Vec::with_capacity(nb_colours * num_c);
+    for c in 0..num_c {
+        meta.extend(colours.iter().map(|colour| colour[c]));
+    }
+    let indices = (0..num_pixels)
+        .map(|idx| colour_map[&pixel(idx)] as i32)
+        .collect();
+
+    let image_info = planes[0].info.clone();
+    *planes = vec![
+        Plane {
+            info: ModularChannelInfo::new_shifted(nb_colours as u32, num_c as u32, -1, -1),
+            data: meta,
+        },
+        Plane {
+            info: image_info,
+            data: indices,
+        },
+    ];
+    Some(Palette::new(0, num_c as u32, nb_colours as u32))
+}
+
+/// Chooses RCT which minimizes the estimated cost of the first three channels, using pairs of
+/// rows sampled from the image.
+fn choose_rct(planes: &[Plane], width: usize, height: usize) -> Option<Rct> {
+    let row_step = (width * height / RCT_ESTIMATE_PIXELS).max(1) * 2;
+    let rows = (1..height).step_by(row_step).collect::<Vec<_>>();
+    let sampled = planes
+        .iter()
+        .map(|plane| {
+            rows.iter()
+                .flat_map(|&y| plane.data[(y - 1) * width..(y + 1) * width].iter().copied())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let estimate = |channels: &[Vec<i32>]| -> f64 {
+        let config = HybridUintConfig::default();
+        channels
+            .iter()
+            .map(|channel| {
+                let mut histogram = [0u32; 256];
+                let mut raw_bits = 0u64;
+                for pair in channel.chunks_exact(2 * width) {
+                    let (prev, curr) = pair.split_at(width);
+                    for x in 0..width {
+                        let n = prev[x] as i64;
+                        let (w, nw) = if x == 0 {
+                            (n, n)
+                        } else {
+                            (curr[x - 1] as i64, prev[x - 1] as i64)
+                        };
+                        let prediction = (n + w - nw).clamp(w.min(n), w.max(n));
+                        let residual = (curr[x] as i64 - prediction) as i32;
+                        let (token, num_bits, _) = config.encode(pack_signed(residual));
+                        histogram[token as usize] += 1;
+                        raw_bits += num_bits as u64;
+                    }
+                }
+                entropy(&histogram) + raw_bits as f64
+            })
+            .sum()
+    };
+
+    let mut best_cost = estimate(&sampled);
+    let mut best = None;
+    for permutation in 0..6 {
+        for ty in 1..7 {
+            let rct = Rct::new(0, permutation * 7 + ty);
+            let mut channels = sampled.clone();
+            let [a, b, c] = &mut *channels else {
+                unreachable!()
+            };
+            rct.forward([a, b, c]);
+            let cost = estimate(&channels);
+            if cost < best_cost {
+                best_cost = cost;
+                best = Some(rct);
+            }
+        }
+    }
+    best
+}
+
+/// Splits channels into GlobalModular, LF groups and pass groups, in the same way the decoder
+/// does.
+///
+/// Returns the streams in the order of sections, with the number of LF group streams.
+fn split_streams(
+    mut planes: Vec<Plane>,
+    nb_meta_channels: usize,
+    width: usize,
+    height: usize,
+    group_dim: u32,
+) -> (Vec<Stream>, usize) {
+    let num_global = planes
+        .iter()
+        .enumerate()
+        .take_while(|&(i, plane)| {
+            i < nb_meta_channels
+                || (plane.info.width <= group_dim && plane.info.height <= group_dim)
+        })
+        .count();
+    let (lf_planes, pass_planes): (Vec<_>, Vec<_>) = planes
+        .split_off(num_global)
+        .into_iter()
+        .partition(|plane| plane.info.hshift >= 3 && plane.info.vshift >= 3);
+
+    let mut streams = vec![Stream {
+        index: 0,
+        channels: planes,
+    }];
+
+    let group_dim = group_dim as usize;
+    let lf_group_dim = group_dim * 8;
+    let lf_groups_per_row = width.div_ceil(lf_group_dim);
+    let num_lf_groups = lf_groups_per_row * height.div_ceil(lf_group_dim);
+    let groups_per_row = width.div_ceil(group_dim);
+    let num_groups = groups_per_row * height.div_ceil(group_dim);
+
+    let mut push_groups =
+        |planes: &[Plane], groups_per_row: usize, num_groups, index_base, shift| {
+            if planes.is_empty() {
+                return 0;
+            }
+            for group_idx in 0..num_groups {
+                let group_x = group_idx % groups_per_row;
+                let group_y = group_idx / groups_per_row;
+                let channels = planes
+                    .iter()
+                    .map(|plane| {
+                        let group_width = group_dim >> (plane.info.hshift - shift);
+                        let group_height = group_dim >> (plane.info.vshift - shift);
+                        plane.tile(
+                            group_x * group_width,
+                            group_y * group_height,
+                            group_width,
+                            group_height,
+                        )
+                    })
+                    .collect();
+                streams.push(Stream {
+                    index: (index_base + group_idx) as u32,
+                    channels,
+                });
+            }
+            num_groups
+        };
+    let num_lf_group_streams = push_groups(
+        &lf_planes,
+        lf_groups_per_row,
+        num_lf_groups,
+        1 + num_lf_groups,
+        3,
+    );
+    push_groups(
+        &pass_planes,
+        groups_per_row,
+        num_groups,
+        1 + 3 * num_lf_groups + 17,
+        0,
+    );
+    (streams, num_lf_group_streams)
+}
+
+/// Visits samples of the stream in decoding order, with the properties the decoder computes.
+fn for_each_sample(stream: &Stream, mut f: impl FnMut(&Properties<'_, '_>, i32)) {
+    let mut prev = Vec::<&Plane>::new();
+    for (i, plane) in stream.channels.iter().enumerate() {
+        let width = plane.width();
+        let height = plane.height();
+        if width == 0 || height == 0 {
+            continue;
+        }
+
+        let prev_rev = prev
+            .iter()
+            .rev()
+            .filter(|prev_plane| plane.is_same_kind(prev_plane))
+            .copied()
+            .collect::<Vec<_>>();
+        let mut predictor =
+            PredictorState::new(width as u32, i as u32, stream.index, prev_rev.len(), None);
+        let mut prev_samples_rev = vec![0i32; prev_rev.len()];
+        for y in 0..height {
+            for x in 0..width {
+                for (prev_plane, sample) in prev_rev.iter().zip(&mut prev_samples_rev) {
+                    *sample = prev_plane.get(x, y);
+                }
+                let properties = predictor.properties(&prev_samples_rev);
+                let sample = plane.get(x, y);
+                f(&properties, sample);
+                properties.record(sample);
+            }
+        }
+
+        prev.push(plane);
+    }
+}
+
+/// Samples used to learn the MA tree, with tokens of residuals of every candidate predictor.
+#[derive(Debug, Default)]
+struct LearningSet {
+    properties: Vec<[i32; NUM_PROPERTIES]>,
+    tokens: Vec<[u8; NUM_PREDICTORS]>,
+    raw_bits: Vec<[u8; NUM_PREDICTORS]>,
+    alphabet_size: usize,
+    /// `n * log2(n)` for every possible count of samples.
+    nlog2n: Vec<f64>,
+    /// Ratio of the number of all samples to the number of collected samples.
+    scale: f64,
+}
+
+impl LearningSet {
+    fn collect(streams: &[Stream], max_samples: usize) -> Self {
+        let total = streams
+            .iter()
+            .flat_map(|stream| &stream.channels)
+            .map(|plane| plane.data.len())
+            .sum::<usize>();
+        let keep_one_in = total.div_ceil(max_samples.max(1)).max(1) as u64;
+
+        let config = HybridUintConfig::default();
+        let mut set = Self::default();
+        let mut rng = 0x2545f4914f6cdd1du64;
+        for stream in streams {
+            for_each_sample(stream, |properties, sample| {
+                rng = rng
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                if !(rng >> 33).is_multiple_of(keep_one_in) {
+                    return;
+                }
+
+                set.properties
+                    .push(PROPERTIES.map(|property| properties.get(property as usize)));
+                let mut tokens = [0u8; NUM_PREDICTORS];
+                let mut raw_bits = [0u8; NUM_PREDICTORS];
+                for (k, predictor) in PREDICTORS.iter().enumerate() {
+                    let residual = (sample as i64 - predictor.predict(properties)) as i32;
+                    let (token, num_bits, _) = config.encode(pack_signed(residual));
+                    tokens[k] = token as u8;
+                    raw_bits[k] = num_bits as u8;
+                    set.alphabet_size = set.alphabet_size.max(token as usize + 1);
+                }
+                set.tokens.push(tokens);
+                set.raw_bits.push(raw_bits);
+            });
+        }
+
+        set.nlog2n = (0..=set.properties.len())
+            .map(|n| {
+                if n == 0 {
+                    0.0
+                } else {
+                    n as f64 * (n as f64).log2()
+                }
+            })
+            .collect();
+        set.scale = if set.properties.is_empty() {
+            1.0
+        } else {
+            total as f64 / set.properties.len() as f64
+        };
+        set
+    }
+
+    /// Returns the cost of samples in bits and the index of the best predictor for them.
+    fn leaf_cost(&self, indices: &[u32]) -> (f64, usize) {
+        let mut histograms = vec![0u32; NUM_PREDICTORS * self.alphabet_size];
+        let mut raw_bits = [0u64; NUM_PREDICTORS];
+        for &idx in indices {
+            let idx = idx as usize;
+            for k in 0..NUM_PREDICTORS {
+                histograms[k * self.alphabet_size + self.tokens[idx][k] as usize] += 1;
+                raw_bits[k] += self.raw_bits[idx][k] as u64;
+            }
+        }
+        self.best_predictor(&histograms, &raw_bits)
+    }
+
+    /// Returns the cost of the predictor with the smallest cost, and the index of the predictor.
+    fn best_predictor(&self, histograms: &[u32], raw_bits: &[u64; NUM_PREDICTORS]) -> (f64, usize) {
+        if self.alphabet_size == 0 {
+            return (0.0, 0);
+        }
+
+        let mut best = (f64::INFINITY, 0);
+        for (k, histogram) in histograms.chunks_exact(self.alphabet_size).enumerate() {
+            let cost = self.cost(histogram, raw_bits[k]);
+            if cost < best.0 {
+                best = (cost, k);
+            }
+        }
+        best
+    }
+
+    /// Returns the estimated cost in bits of the samples with given token histogram and the
+    /// number of raw bits.
+    fn cost(&self, histogram: &[u32], raw_bits: u64) -> f64 {
+        let mut total = 0usize;
+        let mut sum_nlog2n = 0.0;
+        for &count in histogram {
+            total += count as usize;
+            sum_nlog2n += self.nlog2n[count as usize];
+        }
+        self.nlog2n[total] - sum_nlog2n + raw_bits as f64
+    }
+
+    /// Finds the split of samples which reduces the cost the most, returning the index of the
+    /// property and the split value.
+    ///
+    /// Splits are evaluated with the predictor at index `k`, which is the best predictor of the
+    /// node.
+    fn find_split(&self, indices: &[u32], k: usize) -> Option<(usize, i32)> {
+        // Large nodes are evaluated with a subset of samples.
+        let step = indices.len().div_ceil(MAX_SPLIT_SAMPLES).max(1);
+        let subset;
+        let indices = if step > 1 {
+            subset = indices.iter().step_by(step).copied().collect::<Vec<_>>();
+            &subset[..]
+        } else {
+            indices
+        };
+
+        let alphabet_size = self.alphabet_size;
+        let mut total_histogram = vec![0u32; alphabet_size];
+        let mut total_raw_bits = 0u64;
+        for &idx in indices {
+            total_histogram[self.tokens[idx as usize][k] as usize] += 1;
+            total_raw_bits += self.raw_bits[idx as usize][k] as u64;
+        }
+        let parent_cost = self.cost(&total_histogram, total_raw_bits);
+
+        let mut best: Option<(f64, usize, i32)> = None;
+        let candidate_step = (indices.len() / MAX_CANDIDATE_SAMPLES).max(1);
+        let mut values = Vec::with_capacity(MAX_CANDIDATE_SAMPLES + 1);
+        let mut bucket_histograms = Vec::new();
+        let mut bucket_raw_bits = Vec::new();
+        let mut bucket_counts = Vec::new();
+        let mut right_histogram = vec![0u32; alphabet_size];
+        let mut left_histogram = vec![0u32; alphabet_size];
+        for prop_idx in 0..NUM_PROPERTIES {
+            // Split candidates are computed from a subset of samples, which is good enough for
+            // choosing quantiles.
+            values.clear();
+            values.extend(
+                indices
+                    .iter()
+                    .step_by(candidate_step)
+                    .map(|&idx| self.properties[idx as usize][prop_idx]),
+            );
+            values.sort_unstable();
+            let thresholds = split_candidates(&values);
+            if thresholds.is_empty() {
+                continue;
+            }
+
+            let num_buckets = thresholds.len() + 1;
+            bucket_histograms.clear();
+            bucket_histograms.resize(num_buckets * alphabet_size, 0u32);
+            bucket_raw_bits.clear();
+            bucket_raw_bits.resize(num_buckets, 0u64);
+            bucket_counts.clear();
+            bucket_counts.resize(num_buckets, 0usize);
+            for &idx in indices {
+                let idx = idx as usize;
+                let value = self.properties[idx][prop_idx];
+                let bucket = thresholds.partition_point(|&t| t < value);
+                bucket_histograms[bucket * alphabet_size + self.tokens[idx][k] as usize] += 1;
+                bucket_raw_bits[bucket] += self.raw_bits[idx][k] as u64;
+                bucket_counts[bucket] += 1;
+            }
+
+            // Samples with the property value not greater than the threshold go to the right.
+            right_histogram.fill(0);
+            let mut right_raw_bits = 0u64;
+            let mut right_count = 0usize;
+            for (bucket, &threshold) in thresholds.iter().enumerate() {
+                let histogram = &bucket_histograms[bucket * alphabet_size..][..alphabet_size];
+                for (acc, &count) in right_histogram.iter_mut().zip(histogram) {
+                    *acc += count;
+                }
+                right_raw_bits += bucket_raw_bits[bucket];
+                right_count += bucket_counts[bucket];
+                let left_count = indices.len() - right_count;
+                if right_count < MIN_LEAF_SAMPLES || left_count < MIN_LEAF_SAMPLES {
+                    continue;
+                }
+
+                for ((left, &total), &right) in left_histogram
+                    .iter_mut()
+                    .zip(&total_histogram)
+                    .zip(&right_histogram)
+                {
+                    *left = total - right;
+                }
+                let cost = self.cost(&right_histogram, right_raw_bits)
+                    + self.cost(&left_histogram, total_raw_bits - right_raw_bits);
+                if best
+                    .map(|(best_cost, _, _)| cost < best_cost)
+                    .unwrap_or(true)
+                {
+                    best = Some((cost, prop_idx, threshold));
+                }
+            }
+        }
+
+        let (cost, prop_idx, threshold) = best?;
+        let gain = (parent_cost - cost) * self.scale * step as f64;
+        (gain > SPLIT_PENALTY).then_some((prop_idx, threshold))
+    }
+}
+
+/// Returns split values, where samples with the property value greater than the split value go
+/// to the left. `values` should be sorted.
+fn split_candidates(values: &[i32]) -> Vec<i32> {
+    let Some(&max) = values.last() else {
+        return Vec::new();
+    };
+
+    let mut candidates = values.to_vec();
+    candidates.dedup();
+    if candidates.len() > MAX_SPLIT_CANDIDATES + 1 {
+        candidates = (1..=MAX_SPLIT_CANDIDATES)
+            .map(|j| values[j * values.len() / (MAX_SPLIT_CANDIDATES + 1)])
+            .collect();
+        candidates.dedup();
+    }
+    candidates.retain(|&value| value < max);
+    candidates
+}
+
+fn entropy(histogram: &[u32]) -> f64 {
+    let total = histogram.iter().map(|&c| c as u64).sum::<u64>();
+    if total == 0 {
+        return 0.0;
+    }
+    let total = total as f64;
+    histogram
+        .iter()
+        .filter(|&&c| c != 0)
+        .map(|&c| c as f64 * (total / c as f64).log2())
+        .sum()
+}
+
+/// Node of the learned MA tree.
+#[derive(Debug, Clone)]
+enum TreeNode {
+    Decision {
+        property: u32,
+        value: i32,
+        left: usize,
+        right: usize,
+    },
+    Leaf(Predictor),
+}
+
+#[derive(Debug)]
+struct Tree {
+    nodes: Vec<TreeNode>,
+}
+
+impl Tree {
+    /// Returns the context and the predictor of the leaf which the sample belongs to.
+    fn select(&self, properties: &Properties<'_, '_>, leaf_ctx: &[u32]) -> (u32, Predictor) {
+        let mut idx = 0usize;
+        loop {
+            match self.nodes[idx] {
+                TreeNode::Decision {
+                    property,
+                    value,
+                    left,
+                    right,
+                } => {
+                    idx = if properties.get(property as usize) > value {
+                        left
+                    } else {
+                        right
+                    };
+                }
+                TreeNode::Leaf(predictor) => return (leaf_ctx[idx], predictor),
+            }
+        }
+    }
+}
+
+/// Learns the MA tree greedily, splitting nodes in breadth-first order.
+fn learn_tree(set: &LearningSet, max_leaves: usize) -> Tree {
+    let mut nodes = vec![TreeNode::Leaf(Predictor::Gradient)];
+    let mut num_leaves = 1usize;
+    let all = (0..set.properties.len() as u32).collect::<Vec<_>>();
+    let mut queue = VecDeque::from([(0usize, all, 0usize)]);
+    while let Some((node_idx, indices, depth)) = queue.pop_front() {
+        let (_, k) = set.leaf_cost(&indices);
+        nodes[node_idx] = TreeNode::Leaf(PREDICTORS[k]);
+        if num_leaves >= max_leaves
+            || depth >= MAX_TREE_DEPTH
+            || indices.len() < 2 * MIN_LEAF_SAMPLES
+        {
+            continue;
+        }
+
+        let Some((prop_idx, value)) = set.find_split(&indices, k) else {
+            continue;
+        };
+        let (left_indices, right_indices): (Vec<_>, Vec<_>) = indices
+            .into_iter()
+            .partition(|&idx| set.properties[idx as usize][prop_idx] > value);
+
+        let left = nodes.len();
+        let right = left + 1;
+        nodes.push(TreeNode::Leaf(Predictor::Gradient));
+        nodes.push(TreeNode::Leaf(Predictor::Gradient));
+        nodes[node_idx] = TreeNode::Decision {
+            property: PROPERTIES[prop_idx],
+            value,
+            left,
+            right,
+        };
+        num_leaves += 1;
+        queue.push_back((left, left_indices, depth + 1));
+        queue.push_back((right, right_indices, depth + 1));
+    }
+    Tree { nodes }
+}
+
+/// Pushes the tree to the encoder in the order the decoder reads, and returns the context of
+/// each leaf node, or `u32::MAX` for decision nodes.
+fn write_tree(tree: &Tree, encoder: &mut Encoder) -> Vec<u32> {
+    let mut leaf_ctx = vec![u32::MAX; tree.nodes.len()];
+    let mut next_ctx = 0u32;
+    let mut queue = VecDeque::from([0usize]);
+    while let Some(idx) = queue.pop_front() {
+        match tree.nodes[idx] {
+            TreeNode::Decision {
+                property,
+                value,
+                left,
+                right,
+            } => {
+                encoder.push(1, property + 1);
+                encoder.push(0, pack_signed(value));
+                queue.push_back(left);
+                queue.push_back(right);
+            }
+            TreeNode::Leaf(predictor) => {
+                encoder.push(1, 0);
+                encoder.push(2, predictor as u32);
+                // Offset, log2 of the multiplier, and the multiplier bits.
+                encoder.push(3, 0);
+                encoder.push(4, 0);
+                encoder.push(5, 0);
+                leaf_ctx[idx] = next_ctx;
+                next_ctx += 1;
+            }
+        }
+    }
+    leaf_ctx
+}
@@ -4,8 +4,9 @@ use jxl_grid::{AllocTracker, CutGrid, SimpleGrid};
 
 use crate::{
     ma::{FlatMaTree, MaTreeLeafClustered},
-    predictor::{Predictor, PredictorState},
-    MaConfig, ModularChannelInfo, ModularChannels, ModularHeader, Result,
+    predictor::{Predictor, PredictorState, WpHeader},
+    transform::{PartialInverse, TransformInfo},
+    MaConfig, MaTreeLimits, ModularChannelInfo, ModularChannels, ModularHeader, Result,
 };
 
 #[derive(Debug)]
@@ -94,6 +95,7 @@ impl<'dest> TransformedGrid<'dest> {
 pub struct ModularImageDestination {
     header: ModularHeader,
     ma_ctx: MaConfig,
+    ma_tree_limits: Option<MaTreeLimits>,
     group_dim: u32,
     bit_depth: u32,
     channels: ModularChannels,
@@ -108,6 +110,7 @@ impl ModularImageDestination {
         group_dim: u32,
         bit_depth: u32,
         channels: ModularChannels,
+        ma_tree_limits: Option<MaTreeLimits>,
         tracker: Option<&AllocTracker>,
     ) -> Result<Self> {
         let mut meta_channels = Vec::new();
@@ -126,6 +129,7 @@ impl ModularImageDestination {
         Ok(Self {
             header,
             ma_ctx,
+            ma_tree_limits,
             group_dim,
             bit_depth,
             channels,
@@ -138,6 +142,7 @@ impl ModularImageDestination {
         Ok(Self {
             header: self.header.clone(),
             ma_ctx: self.ma_ctx.clone(),
+            ma_tree_limits: self.ma_tree_limits,
             group_dim: self.group_dim,
             bit_depth: self.bit_depth,
             channels: self.channels.clone(),
@@ -169,6 +174,32 @@ impl ModularImageDestination {
     pub fn has_squeeze(&self) -> bool {
         self.header.transform.iter().any(|tr| tr.is_squeeze())
     }
+
+    /// Returns the palette and the index channel, if the image is coded with a single palette
+    /// transform without any other transforms.
+    ///
+    /// Indices are overwritten by inverse transforms, so this should be called after decoding
+    /// groups but before applying inverse transforms.
+    pub fn single_palette(&self) -> Result<Option<PaletteImage>> {
+        let [TransformInfo::Palette(palette)] = &*self.header.transform else {
+            return Ok(None);
+        };
+
+        // Index channel is stored in the buffer of the first channel covered by the palette.
+        let begin_c = palette.begin_c();
+        let Some(indices) = self.image_channels.get(begin_c as usize) else {
+            return Ok(None);
+        };
+
+        Ok(Some(PaletteImage {
+            begin_c,
+            num_c: palette.num_c(),
+            nb_deltas: palette.nb_deltas(),
+            d_pred: palette.d_pred(),
+            palette: self.meta_channels[0].try_clone()?,
+            indices: indices.try_clone()?,
+        }))
+    }
 }
 
 impl ModularImageDestination {
@@ -227,30 +258,30 @@ impl ModularImageDestination {
                 TransformedGrid::Single(g) => g,
                 TransformedGrid::Merged { leader, .. } => leader,
             };
-            let (groups, grids) = if hshift < 3 || vshift < 3 {
-                let shift = hshift.min(vshift); // shift < 3
-                let pass_idx = *pass_shifts
-                    .iter()
-                    .find(|(_, &(minshift, maxshift))| (minshift..maxshift).contains(&shift))
-                    .unwrap()
-                    .0;
-                let pass_idx = pass_idx as usize;
-
-                let group_width = group_dim >> hshift;
-                let group_height = group_dim >> vshift;
-                let grids = grid.into_groups(group_width as usize, group_height as usize);
-                (&mut pass_groups[pass_idx], grids)
-            } else {
-                // hshift >= 3 && vshift >= 3
-                let lf_group_width = group_dim >> (hshift - 3);
-                let lf_group_height = group_dim >> (vshift - 3);
-                let grids = grid.into_groups(lf_group_width as usize, lf_group_height as usize);
-                (&mut lf_groups, grids)
-            };
+            let (groups, grids) =
+                if let ChannelSection::PassGroups(pass_idx) = channel_section(&info, pass_shifts) {
+                    let pass_idx = pass_idx as usize;
+
+                    let group_width = group_dim >> hshift;
+                    let group_height = group_dim >> vshift;
+                    let grids = grid.into_groups(group_width as usize, group_height as usize);
+                    (&mut pass_groups[pass_idx], grids)
+                } else {
+                    // hshift >= 3 && vshift >= 3
+                    let lf_group_width = group_dim >> (hshift - 3);
+                    let lf_group_height = group_dim >> (vshift - 3);
+                    let grids = grid.into_groups(lf_group_width as usize, lf_group_height as usize);
+                    (&mut lf_groups, grids)
+                };
 
             if groups.is_empty() {
                 groups.resize_with(grids.len(), || {
-                    TransformedModularSubimage::empty(&subimage.header, &subimage.ma_ctx, bit_depth)
+                    TransformedModularSubimage::empty(
+                        &subimage.header,
+                        &subimage.ma_ctx,
+                        subimage.ma_tree_limits,
+                        bit_depth,
+                    )
                 });
             } else if groups.len() != grids.len() {
                 panic!();
@@ -277,6 +308,85 @@ impl ModularImageDestination {
         })
     }
 
+    /// Applies inverse transforms to a partially loaded image, returning a preview in reduced
+    /// resolution.
+    ///
+    /// `is_loaded` should return whether every group of the given section is loaded. Squeeze
+    /// steps of which residual channels are not loaded are skipped, along with every step after
+    /// those. Image channels are then upsampled to the full resolution with nearest neighbor, and
+    /// samples in the reduced resolution are returned as a [`ModularPreview`].
+    pub fn finish_preview(
+        &mut self,
+        pass_shifts: &std::collections::BTreeMap<u32, (i32, i32)>,
+        is_loaded: impl Fn(ChannelSection) -> bool,
+        tracker: Option<&AllocTracker>,
+        pool: &jxl_threadpool::JxlThreadPool,
+    ) -> Result<ModularPreview> {
+        self.finish_preview_downscaled(pass_shifts, is_loaded, 0, tracker, pool)
+    }
+
+    /// Applies inverse transforms up to the resolution downscaled by `1 << target_shift`,
+    /// returning a preview in reduced resolution.
+    ///
+    /// This works like [`finish_preview`][Self::finish_preview], but the last `target_shift`
+    /// in-place squeeze steps in each direction are skipped even if their residual channels are
+    /// loaded. Images without squeeze are not downscaled.
+    pub fn finish_preview_downscaled(
+        &mut self,
+        pass_shifts: &std::collections::BTreeMap<u32, (i32, i32)>,
+        is_loaded: impl Fn(ChannelSection) -> bool,
+        target_shift: u32,
+        tracker: Option<&AllocTracker>,
+        pool: &jxl_threadpool::JxlThreadPool,
+    ) -> Result<ModularPreview> {
+        let group_dim = self.group_dim;
+        let subimage = self.prepare_subimage()?;
+
+        let mut is_global = true;
+        let loaded = subimage
+            .channel_info
+            .iter()
+            .enumerate()
+            .map(|(i, info)| {
+                is_global = is_global
+                    && (i < subimage.nb_meta_channels
+                        || (info.width <= group_dim && info.height <= group_dim));
+                let section = if is_global {
+                    ChannelSection::Global
+                } else {
+                    channel_section(info, pass_shifts)
+                };
+                is_loaded(section)
+            })
+            .collect();
+        let state = subimage.finish_partial(loaded, target_shift, pool);
+
+        let hshift = state.skipped_h;
+        let vshift = state.skipped_v;
+        let complete = state.loaded.iter().all(|&loaded| loaded);
+        let channels = self
+            .image_channels
+            .iter()
+            .map(|g| {
+                let width = (g.width() + (1 << hshift) - 1) >> hshift;
+                let height = (g.height() + (1 << vshift) - 1) >> vshift;
+                let mut out = SimpleGrid::with_alloc_tracker(width, height, tracker)?;
+                for y in 0..height {
+                    for x in 0..width {
+                        *out.get_mut(x, y).unwrap() = *g.get(x << hshift, y << vshift).unwrap();
+                    }
+                }
+                Ok(out)
+            })
+            .collect::<Result<_>>()?;
+        Ok(ModularPreview {
+            hshift,
+            vshift,
+            complete,
+            channels,
+        })
+    }
+
     pub fn prepare_subimage(&mut self) -> Result<TransformedModularSubimage> {
         let mut channels = self.channels.clone();
         let mut meta_channel_grids = self
@@ -306,6 +416,7 @@ impl ModularImageDestination {
         Ok(TransformedModularSubimage {
             header: self.header.clone(),
             ma_ctx: self.ma_ctx.clone(),
+            ma_tree_limits: self.ma_tree_limits,
             bit_depth: self.bit_depth,
             nb_meta_channels: channels.nb_meta_channels as usize,
             channel_info,
@@ -326,6 +437,7 @@ pub struct TransformedGlobalModular<'dest> {
 pub struct TransformedModularSubimage<'dest> {
     header: ModularHeader,
     ma_ctx: MaConfig,
+    ma_tree_limits: Option<MaTreeLimits>,
     bit_depth: u32,
     nb_meta_channels: usize,
     channel_info: Vec<ModularChannelInfo>,
@@ -335,10 +447,16 @@ pub struct TransformedModularSubimage<'dest> {
 }
 
 impl<'dest> TransformedModularSubimage<'dest> {
-    fn empty(header: &ModularHeader, ma_ctx: &MaConfig, bit_depth: u32) -> Self {
+    fn empty(
+        header: &ModularHeader,
+        ma_ctx: &MaConfig,
+        ma_tree_limits: Option<MaTreeLimits>,
+        bit_depth: u32,
+    ) -> Self {
         Self {
             header: header.clone(),
             ma_ctx: ma_ctx.clone(),
+            ma_tree_limits,
             bit_depth,
             nb_meta_channels: 0,
             channel_info: Vec::new(),
@@ -362,12 +480,17 @@ impl<'dest> TransformedModularSubimage<'dest> {
                 .ok_or(crate::Error::GlobalMaTreeNotAvailable)?
                 .clone()
         } else {
-            bitstream.read_bundle::<crate::MaConfig>()?
+            let ma_ctx = bitstream.read_bundle::<crate::MaConfig>()?;
+            if let Some(limits) = &self.ma_tree_limits {
+                limits.check(&ma_ctx)?;
+            }
+            ma_ctx
         };
 
         let mut image = RecursiveModularImage {
             header,
             ma_ctx,
+            ma_tree_limits: self.ma_tree_limits,
             bit_depth: self.bit_depth,
             channels: ModularChannels {
                 info: self.channel_info,
@@ -388,6 +511,183 @@ impl<'dest> TransformedModularSubimage<'dest> {
         }
         !self.partial
     }
+
+    fn finish_partial(
+        mut self,
+        loaded: Vec<bool>,
+        target_shift: u32,
+        pool: &jxl_threadpool::JxlThreadPool,
+    ) -> PartialInverse {
+        let mut state = PartialInverse::new(loaded, target_shift);
+        for tr in self.header.transform.iter().rev() {
+            tr.inverse_partial(&mut self.grid, &mut state, self.bit_depth, pool);
+        }
+        state
+    }
+}
+
+/// Section of a frame where Modular channels are stored.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum ChannelSection {
+    /// GlobalModular in LfGlobal.
+    Global,
+    /// ModularLfGroup of LF groups.
+    LfGroups,
+    /// Modular data of pass groups of the given pass.
+    PassGroups(u32),
+}
+
+/// Returns the section of a channel which is not stored in GlobalModular.
+fn channel_section(
+    info: &ModularChannelInfo,
+    pass_shifts: &std::collections::BTreeMap<u32, (i32, i32)>,
+) -> ChannelSection {
+    let &ModularChannelInfo { hshift, vshift, .. } = info;
+    if hshift >= 3 && vshift >= 3 {
+        return ChannelSection::LfGroups;
+    }
+
+    let shift = hshift.min(vshift); // shift < 3
+    let pass_idx = *pass_shifts
+        .iter()
+        .find(|(_, &(minshift, maxshift))| (minshift..maxshift).contains(&shift))
+        .unwrap()
+        .0;
+    ChannelSection::PassGroups(pass_idx)
+}
+
+/// Preview of a partially loaded Modular image in reduced resolution, returned by
+/// [`ModularImageDestination::finish_preview`] and
+/// [`ModularImageDestination::finish_preview_downscaled`].
+#[derive(Debug)]
+pub struct ModularPreview {
+    hshift: u32,
+    vshift: u32,
+    complete: bool,
+    channels: Vec<SimpleGrid<i32>>,
+}
+
+impl ModularPreview {
+    /// Returns the horizontal downsampling factor, in log2.
+    #[inline]
+    pub fn hshift(&self) -> u32 {
+        self.hshift
+    }
+
+    /// Returns the vertical downsampling factor, in log2.
+    #[inline]
+    pub fn vshift(&self) -> u32 {
+        self.vshift
+    }
+
+    /// Returns whether the preview is in the full resolution.
+    #[inline]
+    pub fn is_full_resolution(&self) -> bool {
+        self.hshift == 0 && self.vshift == 0
+    }
+
+    /// Returns whether every channel is reconstructed from loaded data.
+    ///
+    /// Channels which are not loaded, and cannot be reconstructed in lower resolution with
+    /// squeeze, are left as zero.
+    #[inline]
+    pub fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// Returns the image channels in reduced resolution.
+    #[inline]
+    pub fn channels(&self) -> &[SimpleGrid<i32>] {
+        &self.channels
+    }
+
+    /// Consumes the preview, returning the image channels in reduced resolution.
+    #[inline]
+    pub fn into_channels(self) -> Vec<SimpleGrid<i32>> {
+        self.channels
+    }
+}
+
+/// Palette and index channel of an image coded with a single palette transform, returned by
+/// [`ModularImageDestination::single_palette`].
+///
+/// Each index refers to one of the following:
+/// - `0..num_deltas`: delta entry, which is added to the sample predicted by
+///   [`delta_predictor`][Self::delta_predictor] from already decoded samples of the channel.
+/// - `num_deltas..num_colours`: explicit colour entry.
+/// - `num_colours..`, or negative: implicit colour or delta entry defined by the specification.
+#[derive(Debug)]
+pub struct PaletteImage {
+    begin_c: u32,
+    num_c: u32,
+    nb_deltas: u32,
+    d_pred: Predictor,
+    palette: SimpleGrid<i32>,
+    indices: SimpleGrid<i32>,
+}
+
+impl PaletteImage {
+    /// Returns the index of the first channel covered by the palette.
+    #[inline]
+    pub fn first_channel(&self) -> u32 {
+        self.begin_c
+    }
+
+    /// Returns the number of channels covered by the palette.
+    #[inline]
+    pub fn num_channels(&self) -> u32 {
+        self.num_c
+    }
+
+    /// Returns the number of entries in the palette, including delta entries.
+    #[inline]
+    pub fn num_colours(&self) -> u32 {
+        self.palette.width() as u32
+    }
+
+    /// Returns the number of delta entries at the beginning of the palette.
+    #[inline]
+    pub fn num_deltas(&self) -> u32 {
+        self.nb_deltas
+    }
+
+    /// Returns the predictor used with delta entries.
+    #[inline]
+    pub fn delta_predictor(&self) -> Predictor {
+        self.d_pred
+    }
+
+    /// Returns the palette, with one column per entry and one row per channel.
+    #[inline]
+    pub fn palette(&self) -> &SimpleGrid<i32> {
+        &self.palette
+    }
+
+    /// Returns the entry of the palette at the given index, if the index refers to an entry.
+    pub fn entry(&self, index: i32) -> Option<Vec<i32>> {
+        let index = usize::try_from(index).ok()?;
+        if index >= self.palette.width() {
+            return None;
+        }
+        Some(
+            (0..self.palette.height())
+                .map(|c| *self.palette.get(index, c).unwrap())
+                .collect(),
+        )
+    }
+
+    /// Returns the index channel.
+    #[inline]
+    pub fn indices(&self) -> &SimpleGrid<i32> {
+        &self.indices
+    }
+
+    /// Returns whether every index refers to an explicit colour entry, so that each sample can be
+    /// reconstructed by looking up the palette.
+    pub fn is_plain(&self) -> bool {
+        let range = self.nb_deltas as i32..self.palette.width() as i32;
+        self.indices.buf().iter().all(|index| range.contains(index))
+    }
 }
 
 impl<'dest> TransformedModularSubimage<'dest> {
@@ -399,7 +699,7 @@ impl<'dest> TransformedModularSubimage<'dest> {
             &mut CutGrid<i32>,
             &[&CutGrid<i32>],
             FlatMaTree,
-            &crate::predictor::WpHeader,
+            &WpHeader,
         ) -> Result<()>,
     ) -> Result<()> {
         let wp_header = &self.header.wp_params;
@@ -422,6 +722,8 @@ impl<'dest> TransformedModularSubimage<'dest> {
             filtered_prev.reverse();
 
             let ma_tree = self.ma_ctx.make_flat_tree(i as u32, stream_index);
+            // Channels not referenced by the tree don't need to be tracked.
+            filtered_prev.truncate(ma_tree.num_prev_channels());
             loop_fn(i, grid.grid_mut(), &filtered_prev, ma_tree, wp_header)?;
 
             prev.push((info, grid.grid()));
@@ -494,10 +796,15 @@ impl<'dest> TransformedModularSubimage<'dest> {
                         }
                     } else {
                         tracing::trace!("Fast path");
+                        // The cluster doesn't change, so tokens of a row can be read at once.
+                        let mut tokens = vec![0u32; width];
                         for y in 0..height {
-                            for x in 0..width {
-                                let token =
-                                    no_lz77_decoder.read_varint_clustered(bitstream, cluster)?;
+                            no_lz77_decoder.read_varints_clustered(
+                                bitstream,
+                                cluster,
+                                &mut tokens,
+                            )?;
+                            for (x, &token) in tokens.iter().enumerate() {
                                 let value = unpack_signed(token) * multiplier as i32 + offset;
                                 *grid.get_mut(x, y) = value;
                             }
@@ -505,19 +812,20 @@ impl<'dest> TransformedModularSubimage<'dest> {
                     }
                     return Ok(());
                 }
-                if predictor == Predictor::Gradient && offset == 0 && multiplier == 1 {
+                if predictor == Predictor::Gradient {
                     tracing::trace!("Quite fast path");
                     let mut prev_row = vec![0i32; width];
+                    let mut tokens = vec![0u32; width];
                     for y in 0..height {
+                        no_lz77_decoder.read_varints_clustered(bitstream, cluster, &mut tokens)?;
                         let mut w = prev_row[0] as i64;
                         let mut nw = w;
-                        for (x, prev) in prev_row.iter_mut().enumerate() {
+                        for (x, (prev, &token)) in prev_row.iter_mut().zip(&tokens).enumerate() {
                             let n = if y == 0 { w } else { *prev as i64 };
                             let pred = (n + w - nw).clamp(w.min(n), w.max(n));
 
-                            let token =
-                                no_lz77_decoder.read_varint_clustered(bitstream, cluster)?;
-                            let value = ((unpack_signed(token) as i64) + pred) as i32;
+                            let diff = unpack_signed(token) * multiplier as i32 + offset;
+                            let value = (diff as i64 + pred) as i32;
                             *grid.get_mut(x, y) = value;
                             *prev = value;
                             nw = n;
@@ -528,19 +836,19 @@ impl<'dest> TransformedModularSubimage<'dest> {
                 }
             }
 
-            let wp_header = ma_tree.need_self_correcting().then_some(wp_header);
-            let mut predictor = PredictorState::new(
-                width as u32,
-                i as u32,
-                stream_index,
-                prev_rev.len(),
-                wp_header,
-            );
             let mut next = |cluster: u8| -> Result<i32> {
                 let token = no_lz77_decoder.read_varint_clustered(bitstream, cluster)?;
                 Ok(unpack_signed(token))
             };
-            decode_channel_slow(&mut next, &ma_tree, &mut predictor, grid, prev_rev)
+            decode_channel(
+                &mut next,
+                &ma_tree,
+                i,
+                stream_index,
+                wp_header,
+                grid,
+                prev_rev,
+            )
         })?;
 
         decoder.finalize()?;
@@ -562,66 +870,6 @@ impl<'dest> TransformedModularSubimage<'dest> {
             .unwrap_or(0);
 
         self.decode_channel_loop(stream_index, |i, grid, prev_rev, ma_tree, wp_header| {
-            let width = grid.width();
-            let height = grid.height();
-
-            if let Some(&MaTreeLeafClustered {
-                cluster,
-                predictor,
-                offset,
-                multiplier,
-            }) = ma_tree.single_node()
-            {
-                tracing::trace!(cluster, ?predictor, "Single MA tree node");
-                if predictor == Predictor::Zero {
-                    tracing::trace!("Fast path");
-                    for y in 0..height {
-                        for x in 0..width {
-                            let token = decoder.read_varint_with_multiplier_clustered(
-                                bitstream,
-                                cluster,
-                                dist_multiplier,
-                            )?;
-                            let value = unpack_signed(token) * multiplier as i32 + offset;
-                            *grid.get_mut(x, y) = value;
-                        }
-                    }
-                    return Ok(());
-                }
-                if predictor == Predictor::Gradient && offset == 0 && multiplier == 1 {
-                    tracing::trace!("Quite fast path");
-                    let mut prev_row = vec![0i32; width];
-                    for y in 0..height {
-                        let mut w = prev_row[0] as i64;
-                        let mut nw = w;
-                        for (x, prev) in prev_row.iter_mut().enumerate() {
-                            let n = if y == 0 { w } else { *prev as i64 };
-                            let pred = (n + w - nw).clamp(w.min(n), w.max(n));
-
-                            let token = decoder.read_varint_with_multiplier_clustered(
-                                bitstream,
-                                cluster,
-                                dist_multiplier,
-                            )?;
-                            let value = ((unpack_signed(token) as i64) + pred) as i32;
-                            *grid.get_mut(x, y) = value;
-                            *prev = value;
-                            nw = n;
-                            w = value as i64;
-                        }
-                    }
-                    return Ok(());
-                }
-            }
-
-            let wp_header = ma_tree.need_self_correcting().then_some(wp_header);
-            let mut predictor = PredictorState::new(
-                width as u32,
-                i as u32,
-                stream_index,
-                prev_rev.len(),
-                wp_header,
-            );
             let mut next = |cluster: u8| -> Result<i32> {
                 let token = decoder.read_varint_with_multiplier_clustered(
                     bitstream,
@@ -630,7 +878,15 @@ impl<'dest> TransformedModularSubimage<'dest> {
                 )?;
                 Ok(unpack_signed(token))
             };
-            decode_channel_slow(&mut next, &ma_tree, &mut predictor, grid, prev_rev)
+            decode_channel(
+                &mut next,
+                &ma_tree,
+                i,
+                stream_index,
+                wp_header,
+                grid,
+                prev_rev,
+            )
         })
     }
 
@@ -662,63 +918,140 @@ impl<'dest> TransformedModularSubimage<'dest> {
         };
 
         self.decode_channel_loop(stream_index, |i, grid, prev_rev, ma_tree, wp_header| {
-            let width = grid.width();
-            let height = grid.height();
-
-            if let Some(&MaTreeLeafClustered {
-                cluster,
-                predictor,
-                offset,
-                multiplier,
-            }) = ma_tree.single_node()
-            {
-                tracing::trace!(cluster, ?predictor, "Single MA tree node");
-                if predictor == Predictor::Zero {
-                    tracing::trace!("Quite fast path");
-                    for y in 0..height {
-                        for x in 0..width {
-                            let token = next(cluster)?;
-                            let value = (token * multiplier as i32) + offset;
-                            *grid.get_mut(x, y) = value;
-                        }
-                    }
-                    return Ok(());
-                }
-                if predictor == Predictor::Gradient && offset == 0 && multiplier == 1 {
-                    tracing::trace!("libjxl fast-lossless: quite fast path");
-                    let mut prev_row = vec![0i32; width];
-                    for y in 0..height {
-                        let mut w = prev_row[0] as i64;
-                        let mut nw = w;
-                        for (x, prev) in prev_row.iter_mut().enumerate() {
-                            let n = if y == 0 { w } else { *prev as i64 };
-                            let pred = (n + w - nw).clamp(w.min(n), w.max(n));
-
-                            let token = next(cluster)?;
-                            let value = ((token as i64) + pred) as i32;
-                            *grid.get_mut(x, y) = value;
-                            *prev = value;
-                            nw = n;
-                            w = value as i64;
-                        }
-                    }
-                    return Ok(());
-                }
-            }
-
-            let wp_header = ma_tree.need_self_correcting().then_some(wp_header);
-            let mut predictor = PredictorState::new(
-                width as u32,
-                i as u32,
+            decode_channel(
+                &mut next,
+                &ma_tree,
+                i,
                 stream_index,
-                prev_rev.len(),
                 wp_header,
-            );
-            decode_channel_slow(&mut next, &ma_tree, &mut predictor, grid, prev_rev)
+                grid,
+                prev_rev,
+            )
         })
     }
 }
 
+/// Decodes a channel, dispatching to a specialized loop depending on the shape of the MA tree.
+fn decode_channel(
+    next: &mut impl FnMut(u8) -> Result<i32>,
+    ma_tree: &FlatMaTree,
+    channel_index: usize,
+    stream_index: u32,
+    wp_header: &WpHeader,
+    grid: &mut CutGrid<i32>,
+    prev_rev: &[&CutGrid<i32>],
+) -> Result<()> {
+    let width = grid.width();
+    let wp_header = ma_tree.need_self_correcting().then_some(wp_header);
+
+    if let Some(leaf) = ma_tree.single_node() {
+        tracing::trace!(cluster = leaf.cluster, predictor = ?leaf.predictor, "Single MA tree node");
+        return match leaf.predictor {
+            Predictor::Zero => decode_channel_zero(next, leaf, grid),
+            Predictor::Gradient => decode_channel_gradient(next, leaf, grid),
+            _ => {
+                // Properties are not used by the tree, so previous channels are not tracked.
+                let mut predictor = PredictorState::new(
+                    width as u32,
+                    channel_index as u32,
+                    stream_index,
+                    0,
+                    wp_header,
+                );
+                decode_channel_single_leaf(next, leaf, &mut predictor, grid)
+            }
+        };
+    }
+
+    let mut predictor = PredictorState::new(
+        width as u32,
+        channel_index as u32,
+        stream_index,
+        prev_rev.len(),
+        wp_header,
+    );
+    decode_channel_slow(next, ma_tree, &mut predictor, grid, prev_rev)
+}
+
+fn decode_channel_zero(
+    next: &mut impl FnMut(u8) -> Result<i32>,
+    leaf: &MaTreeLeafClustered,
+    grid: &mut CutGrid<i32>,
+) -> Result<()> {
+    let &MaTreeLeafClustered {
+        cluster,
+        offset,
+        multiplier,
+        ..
+    } = leaf;
+    for y in 0..grid.height() {
+        for x in 0..grid.width() {
+            let token = next(cluster)?;
+            *grid.get_mut(x, y) = token * multiplier as i32 + offset;
+        }
+    }
+    Ok(())
+}
+
+fn decode_channel_gradient(
+    next: &mut impl FnMut(u8) -> Result<i32>,
+    leaf: &MaTreeLeafClustered,
+    grid: &mut CutGrid<i32>,
+) -> Result<()> {
+    let &MaTreeLeafClustered {
+        cluster,
+        offset,
+        multiplier,
+        ..
+    } = leaf;
+    let mut prev_row = vec![0i32; grid.width()];
+    for y in 0..grid.height() {
+        let mut w = prev_row[0] as i64;
+        let mut nw = w;
+        for (x, prev) in prev_row.iter_mut().enumerate() {
+            let n = if y == 0 { w } else { *prev as i64 };
+            let pred = (n + w - nw).clamp(w.min(n), w.max(n));
+
+            let diff = next(cluster)? * multiplier as i32 + offset;
+            let value = (diff as i64 + pred) as i32;
+            *grid.get_mut(x, y) = value;
+            *prev = value;
+            nw = n;
+            w = value as i64;
+        }
+    }
+    Ok(())
+}
+
+/// Decodes a channel with a single leaf, skipping tree traversal.
+///
+/// This is mainly for the self-correcting predictor, which needs predictor state updated for
+/// every sample.
+fn decode_channel_single_leaf(
+    next: &mut impl FnMut(u8) -> Result<i32>,
+    leaf: &MaTreeLeafClustered,
+    predictor: &mut PredictorState,
+    grid: &mut CutGrid<i32>,
+) -> Result<()> {
+    let &MaTreeLeafClustered {
+        cluster,
+        predictor: leaf_predictor,
+        offset,
+        multiplier,
+    } = leaf;
+    for y in 0..grid.height() {
+        for x in 0..grid.width() {
+            let properties = predictor.properties(&[]);
+            let diff = next(cluster)? * multiplier as i32 + offset;
+            let sample_prediction = leaf_predictor.predict(&properties);
+            let true_value = (diff as i64 + sample_prediction) as i32;
+            *grid.get_mut(x, y) = true_value;
+            properties.record(true_value);
+        }
+    }
+    Ok(())
+}
+
 fn decode_channel_slow(
     next: &mut impl FnMut(u8) -> Result<i32>,
     ma_tree: &FlatMaTree,
@@ -753,6 +1086,7 @@ fn decode_channel_slow(
 pub struct RecursiveModularImage<'dest> {
     header: ModularHeader,
     ma_ctx: MaConfig,
+    ma_tree_limits: Option<MaTreeLimits>,
     bit_depth: u32,
     channels: ModularChannels,
     meta_channels: Vec<SimpleGrid<i32>>,
@@ -785,6 +1119,7 @@ impl<'dest> RecursiveModularImage<'dest> {
         Ok(TransformedModularSubimage {
             header: self.header.clone(),
             ma_ctx: self.ma_ctx.clone(),
+            ma_tree_limits: self.ma_tree_limits,
             bit_depth: self.bit_depth,
             nb_meta_channels: channels.nb_meta_channels as usize,
             channel_info,
@@ -794,3 +1129,79 @@ impl<'dest> RecursiveModularImage<'dest> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use jxl_bitstream::BundleDefault;
+
+    use super::*;
+
+    /// Returns a source of pseudo-random residuals in range `-8..8`.
+    fn residuals(seed: u32) -> impl FnMut(u8) -> Result<i32> {
+        let mut state = seed;
+        move |cluster| {
+            assert_eq!(cluster, 1);
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            Ok(((state >> 16) % 16) as i32 - 8)
+        }
+    }
+
+    /// Decodes a channel with a single leaf tree, using either the specialized loop for the leaf
+    /// or the generic one.
+    fn decode(
+        width: usize,
+        height: usize,
+        leaf: &MaTreeLeafClustered,
+        specialized: bool,
+    ) -> Vec<i32> {
+        let tree = FlatMaTree::single_leaf(leaf.clone());
+        let wp_header = WpHeader::default_with_context(());
+        let wp_header = tree.need_self_correcting().then_some(&wp_header);
+        let mut predictor = PredictorState::new(width as u32, 0, 0, 0, wp_header);
+
+        let mut buf = vec![0i32; width * height];
+        let mut grid = CutGrid::from_buf(&mut buf, width, height, width);
+        let mut next = residuals((width * 31 + height) as u32);
+        let result = if !specialized {
+            decode_channel_slow(&mut next, &tree, &mut predictor, &mut grid, &[])
+        } else {
+            match leaf.predictor {
+                Predictor::Zero => decode_channel_zero(&mut next, leaf, &mut grid),
+                Predictor::Gradient => decode_channel_gradient(&mut next, leaf, &mut grid),
+                _ => decode_channel_single_leaf(&mut next, leaf, &mut predictor, &mut grid),
+            }
+        };
+        result.unwrap();
+        buf
+    }
+
+    #[test]
+    fn specialized_decode_matches_slow_path() {
+        let predictors = [
+            Predictor::Zero,
+            Predictor::Gradient,
+            Predictor::West,
+            Predictor::North,
+            Predictor::Select,
+            Predictor::SelfCorrecting,
+            Predictor::NorthEast,
+            Predictor::WestWest,
+            Predictor::AvgAll,
+        ];
+        for predictor in predictors {
+            for (width, height) in [(1, 1), (1, 19), (19, 1), (2, 2), (13, 9)] {
+                let leaf = MaTreeLeafClustered {
+                    cluster: 1,
+                    predictor,
+                    offset: -3,
+                    multiplier: 2,
+                };
+                assert_eq!(
+                    decode(width, height, &leaf, true),
+                    decode(width, height, &leaf, false),
+                    "{predictor:?}, {width}x{height}",
+                );
+            }
+        }
+    }
+}
@@ -3,8 +3,12 @@
 //! A Modular image represents a set of grids (two-dimensional arrays) of integer values. Modular
 //! images are used mainly for lossless images, but lossy VarDCT images also use them to store
 //! various information, such as quantized LF images and varblock configurations.
+//!
+//! The [`encode`] module provides a lossless Modular encoder, which produces images decodable with
+//! this crate.
 use jxl_bitstream::{define_bundle, read_bits, Bitstream, Bundle};
 
+pub mod encode;
 mod error;
 pub mod image;
 mod ma;
@@ -12,8 +16,9 @@ mod param;
 mod predictor;
 mod transform;
 pub use error::{Error, Result};
-pub use ma::MaConfig;
+pub use ma::{collect_ma_trees, MaConfig, MaNode, MaTreeLimits};
 pub use param::*;
+pub use predictor::Predictor;
 
 /// A Modular encoded image.
 ///
@@ -114,7 +119,11 @@ impl Bundle<ModularParams<'_, '_>> for ModularData {
                 .ok_or(crate::Error::GlobalMaTreeNotAvailable)?
                 .clone()
         } else {
-            read_bits!(bitstream, Bundle(ma::MaConfig))?
+            let ma_ctx = read_bits!(bitstream, Bundle(ma::MaConfig))?;
+            if let Some(limits) = &params.ma_tree_limits {
+                limits.check(&ma_ctx)?;
+            }
+            ma_ctx
         };
         if ma_ctx.tree_depth() > 2048 {
             tracing::error!(
@@ -168,6 +177,7 @@ impl Bundle<ModularParams<'_, '_>> for ModularData {
                 params.group_dim,
                 params.bit_depth,
                 channels,
+                params.ma_tree_limits,
                 params.tracker,
             )?,
         })
@@ -232,3 +242,123 @@ impl ModularChannelInfo {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use jxl_bitstream::{pack_signed, BitWriter, BundleDefault, BundleWrite};
+    use jxl_coding::Encoder;
+
+    use super::*;
+
+    /// Writes an MA tree which is a chain of `depth` decision nodes, followed by the entropy code
+    /// of its leaves.
+    fn write_chain_tree(writer: &mut BitWriter, depth: u32) {
+        let mut tree = Encoder::new(6);
+        let mut decision_idx = 0;
+        let mut push_decision = |tree: &mut Encoder| {
+            tree.push(1, 1);
+            tree.push(0, pack_signed(decision_idx));
+            decision_idx += 1;
+        };
+        let push_leaf = |tree: &mut Encoder| {
+            tree.push(1, 0);
+            for ctx in 2..6 {
+                tree.push(ctx, 0);
+            }
+        };
+
+        // Nodes in breadth-first order; right child of each decision node is the next one.
+        if depth > 0 {
+            push_decision(&mut tree);
+            for _ in 1..depth {
+                push_leaf(&mut tree);
+                push_decision(&mut tree);
+            }
+            push_leaf(&mut tree);
+        }
+        push_leaf(&mut tree);
+
+        let tree = tree.build().unwrap();
+        tree.write_header(writer).unwrap();
+        tree.write_stream(writer, 0).unwrap();
+        let data = Encoder::new(depth + 1).build().unwrap();
+        data.write_header(writer).unwrap();
+    }
+
+    fn group_header(use_global_tree: bool) -> ModularHeader {
+        ModularHeader {
+            use_global_tree,
+            wp_params: predictor::WpHeader::default_with_context(()),
+            nb_transforms: 0,
+            transform: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn local_ma_tree_limits() {
+        let limits = MaTreeLimits {
+            level: 5,
+            max_nodes: 1 << 20,
+            max_depth: 64,
+        };
+
+        let mut writer = BitWriter::new();
+        write_chain_tree(&mut writer, 0);
+        let global_tree = writer.finish();
+        let global_ma_config = MaConfig::parse(&mut Bitstream::new(&global_tree), ()).unwrap();
+
+        let mut writer = BitWriter::new();
+        group_header(true).write(&mut writer, ()).unwrap();
+        let global = writer.finish();
+
+        for (depth, result) in [(64, true), (65, false)] {
+            let mut writer = BitWriter::new();
+            group_header(false).write(&mut writer, ()).unwrap();
+            write_chain_tree(&mut writer, depth);
+            let local = writer.finish();
+
+            // Local tree in GlobalModular.
+            let params = ModularParams::new(
+                16,
+                16,
+                128,
+                8,
+                vec![ChannelShift::from_shift(0)],
+                None,
+                None,
+            )
+            .with_ma_tree_limits(Some(limits));
+            let modular = Modular::parse(&mut Bitstream::new(&local), params);
+            assert_eq!(modular.is_ok(), result);
+
+            // Local tree in a pass group.
+            let params = ModularParams::new(
+                256,
+                128,
+                128,
+                8,
+                vec![ChannelShift::from_shift(0)],
+                Some(&global_ma_config),
+                None,
+            )
+            .with_ma_tree_limits(Some(limits));
+            let mut modular = Modular::parse(&mut Bitstream::new(&global), params).unwrap();
+            let pass_shifts = [(0, (0, 3))].into();
+            let mut groups = modular
+                .image_mut()
+                .unwrap()
+                .prepare_groups(&pass_shifts)
+                .unwrap();
+            let subimage = groups.pass_groups[0].pop().unwrap();
+            let recursive = subimage.recursive(&mut Bitstream::new(&local), None, None);
+            match recursive {
+                Ok(_) => assert!(result),
+                Err(crate::Error::Bitstream(jxl_bitstream::Error::LevelLimitExceeded {
+                    level: 5,
+                    ..
+                })) => assert!(!result),
+                Err(e) => panic!("unexpected error: {e}"),
+            }
+        }
+    }
+}
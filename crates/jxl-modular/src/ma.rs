@@ -1,4 +1,6 @@
+use std::cell::RefCell;
 use std::collections::VecDeque;
+use std::fmt::Write;
 use std::sync::Arc;
 
 use jxl_bitstream::{unpack_signed, Bitstream, Bundle};
@@ -17,9 +19,118 @@ pub struct MaConfig {
     num_tree_nodes: usize,
     tree_depth: usize,
     tree: Arc<MaTreeNode>,
+    nodes: Arc<[MaNode]>,
     decoder: Decoder,
 }
 
+/// Node of an MA tree, as stored in the bitstream.
+///
+/// Nodes are stored in breadth-first order, and the root node is at index 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MaNode {
+    /// Decision node, which takes the left child if the property value is greater than `value`.
+    Decision {
+        /// Index of the property.
+        property: u32,
+        /// Split value.
+        value: i32,
+        /// Index of the left child node.
+        left: usize,
+        /// Index of the right child node.
+        right: usize,
+    },
+    /// Leaf node, which determines how the sample is decoded.
+    Leaf {
+        /// Context of the entropy decoder.
+        ctx: u32,
+        /// Cluster the context is mapped to.
+        cluster: u8,
+        /// Predictor of the sample.
+        predictor: Predictor,
+        /// Offset added to the decoded residual.
+        offset: i32,
+        /// Multiplier of the decoded residual.
+        multiplier: u32,
+    },
+}
+
+/// Limits on the size of MA trees, usually derived from the codestream level being enforced.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct MaTreeLimits {
+    /// Codestream level the limits are derived from, used in the error.
+    pub level: u8,
+    /// Maximum number of MA tree nodes.
+    pub max_nodes: usize,
+    /// Maximum depth of MA trees.
+    pub max_depth: usize,
+}
+
+impl MaTreeLimits {
+    /// Checks if the MA tree is within the limits.
+    ///
+    /// # Errors
+    /// Returns [`jxl_bitstream::Error::LevelLimitExceeded`] if the tree exceeds the limits.
+    pub fn check(&self, config: &MaConfig) -> Result<()> {
+        let level = self.level;
+        let num_nodes = config.num_tree_nodes();
+        if num_nodes > self.max_nodes {
+            tracing::error!(num_nodes, level, "Too many MA tree nodes");
+            return Err(jxl_bitstream::Error::LevelLimitExceeded {
+                level,
+                limit: "number of MA tree nodes",
+            }
+            .into());
+        }
+        let tree_depth = config.tree_depth();
+        if tree_depth > self.max_depth {
+            tracing::error!(tree_depth, level, "MA tree too deep");
+            return Err(jxl_bitstream::Error::LevelLimitExceeded {
+                level,
+                limit: "MA tree depth",
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+thread_local! {
+    static TREE_COLLECTORS: RefCell<Vec<Vec<MaConfig>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Runs `f`, collecting MA trees parsed on the current thread while it runs.
+///
+/// Trees parsed in a nested call go to the innermost collector.
+pub fn collect_ma_trees<R>(f: impl FnOnce() -> R) -> (R, Vec<MaConfig>) {
+    struct PopGuard;
+
+    impl Drop for PopGuard {
+        fn drop(&mut self) {
+            TREE_COLLECTORS.with_borrow_mut(|collectors| collectors.pop());
+        }
+    }
+
+    TREE_COLLECTORS.with_borrow_mut(|collectors| collectors.push(Vec::new()));
+    let guard = PopGuard;
+    let ret = f();
+    let trees = TREE_COLLECTORS.with_borrow_mut(|collectors| {
+        collectors
+            .last_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    });
+    drop(guard);
+    (ret, trees)
+}
+
+fn submit_tree(config: &MaConfig) {
+    let _ = TREE_COLLECTORS.try_with(|collectors| {
+        if let Some(collected) = collectors.borrow_mut().last_mut() {
+            collected.push(config.clone());
+        }
+    });
+}
+
 impl MaConfig {
     /// Returns the entropy decoder.
     ///
@@ -51,6 +162,127 @@ impl MaConfig {
     pub fn tree_depth(&self) -> usize {
         self.tree_depth
     }
+
+    /// Returns the MA tree nodes in breadth-first order, starting from the root node.
+    #[inline]
+    pub fn nodes(&self) -> impl ExactSizeIterator<Item = &MaNode> + '_ {
+        self.nodes.iter()
+    }
+
+    /// Serializes the MA tree to Graphviz DOT format.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph MaTree {\n  node [shape=box];\n");
+        for (idx, node) in self.nodes.iter().enumerate() {
+            match *node {
+                MaNode::Decision {
+                    property,
+                    value,
+                    left,
+                    right,
+                } => {
+                    let name = property_name(property);
+                    writeln!(out, "  n{idx} [label=\"{name} > {value}\"];").unwrap();
+                    writeln!(out, "  n{idx} -> n{left} [label=\"yes\"];").unwrap();
+                    writeln!(out, "  n{idx} -> n{right} [label=\"no\"];").unwrap();
+                }
+                MaNode::Leaf {
+                    ctx,
+                    cluster,
+                    predictor,
+                    offset,
+                    multiplier,
+                } => {
+                    writeln!(
+                        out,
+                        "  n{idx} [shape=ellipse, label=\"ctx {ctx} (cluster {cluster})\\n\
+                         {predictor:?}\\noffset {offset}, multiplier {multiplier}\"];",
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// Serializes the MA tree to JSON.
+    ///
+    /// The output is an object with `depth` and `nodes`, where `nodes` is an array of nodes in
+    /// breadth-first order. Child nodes are referenced by their index in the array.
+    pub fn to_json(&self) -> String {
+        let mut out = format!("{{\"depth\":{},\"nodes\":[", self.tree_depth);
+        for (idx, node) in self.nodes.iter().enumerate() {
+            if idx != 0 {
+                out.push(',');
+            }
+            match *node {
+                MaNode::Decision {
+                    property,
+                    value,
+                    left,
+                    right,
+                } => {
+                    write!(
+                        out,
+                        "{{\"type\":\"decision\",\"property\":{property},\"value\":{value},\
+                         \"left\":{left},\"right\":{right}}}",
+                    )
+                    .unwrap();
+                }
+                MaNode::Leaf {
+                    ctx,
+                    cluster,
+                    predictor,
+                    offset,
+                    multiplier,
+                } => {
+                    write!(
+                        out,
+                        "{{\"type\":\"leaf\",\"ctx\":{ctx},\"cluster\":{cluster},\
+                         \"predictor\":\"{predictor:?}\",\"offset\":{offset},\
+                         \"multiplier\":{multiplier}}}",
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        out.push_str("]}");
+        out
+    }
+}
+
+/// Returns a human-readable name of the property.
+fn property_name(property: u32) -> String {
+    const NAMES: [&str; 16] = [
+        "channel",
+        "stream",
+        "y",
+        "x",
+        "|N|",
+        "|W|",
+        "N",
+        "W",
+        "W-prev_grad",
+        "W+N-NW",
+        "W-NW",
+        "NW-N",
+        "N-NE",
+        "N-NN",
+        "W-WW",
+        "max_error",
+    ];
+    if let Some(name) = NAMES.get(property as usize) {
+        return name.to_string();
+    }
+
+    let prev_channel = (property - 16) / 4;
+    let name = match (property - 16) % 4 {
+        0 => "|c|",
+        1 => "c",
+        2 => "|c-g|",
+        _ => "c-g",
+    };
+    format!("prev{prev_channel} {name}")
 }
 
 impl<Ctx> Bundle<Ctx> for MaConfig {
@@ -70,9 +302,11 @@ impl<Ctx> Bundle<Ctx> for MaConfig {
         }
 
         let mut tree_decoder = Decoder::parse(bitstream, 6)?;
+        tree_decoder.set_label("MA tree");
         let mut ctx = 0u32;
         let mut nodes_left = 1usize;
         let mut nodes = Vec::new();
+        let mut node_info = Vec::new();
 
         tree_decoder.begin(bitstream)?;
         while nodes_left > 0 {
@@ -85,6 +319,13 @@ impl<Ctx> Bundle<Ctx> for MaConfig {
             let node = if let Some(property) = property.checked_sub(1) {
                 let value = unpack_signed(tree_decoder.read_varint(bitstream, 0)?);
                 let node = FoldingTree::Decision(property, value);
+                let left = nodes.len() + nodes_left + 1;
+                node_info.push(MaNode::Decision {
+                    property,
+                    value,
+                    left,
+                    right: left + 1,
+                });
                 nodes_left += 2;
                 node
             } else {
@@ -100,6 +341,13 @@ impl<Ctx> Bundle<Ctx> for MaConfig {
                     return Err(crate::Error::InvalidMaTree);
                 }
                 let multiplier = (mul_bits + 1) << mul_log;
+                node_info.push(MaNode::Leaf {
+                    ctx,
+                    cluster: 0,
+                    predictor,
+                    offset,
+                    multiplier,
+                });
                 let node = FoldingTree::Leaf(FoldingTreeLeaf {
                     ctx,
                     predictor,
@@ -113,8 +361,14 @@ impl<Ctx> Bundle<Ctx> for MaConfig {
         }
         tree_decoder.finalize()?;
         let num_tree_nodes = nodes.len();
-        let decoder = Decoder::parse(bitstream, ctx)?;
+        let mut decoder = Decoder::parse(bitstream, ctx)?;
+        decoder.set_label("modular");
         let cluster_map = decoder.cluster_map();
+        for node in &mut node_info {
+            if let MaNode::Leaf { ctx, cluster, .. } = node {
+                *cluster = cluster_map[*ctx as usize];
+            }
+        }
 
         let mut tmp = VecDeque::<(_, usize)>::new();
         for node in nodes.into_iter().rev() {
@@ -152,12 +406,15 @@ impl<Ctx> Bundle<Ctx> for MaConfig {
         assert_eq!(tmp.len(), 1);
         let (tree, tree_depth) = tmp.pop_front().unwrap();
 
-        Ok(Self {
+        let config = Self {
             num_tree_nodes,
             tree_depth,
             tree: Arc::new(tree),
+            nodes: node_info.into(),
             decoder,
-        })
+        };
+        submit_tree(&config);
+        Ok(config)
     }
 }
 
@@ -168,6 +425,7 @@ impl<Ctx> Bundle<Ctx> for MaConfig {
 pub struct FlatMaTree {
     nodes: Vec<FlatMaTreeNode>,
     need_self_correcting: bool,
+    num_prev_channels: usize,
 }
 
 #[derive(Debug)]
@@ -204,9 +462,26 @@ impl FlatMaTree {
             }
         });
 
+        // Properties 16 and above refer to previous channels, four properties per channel.
+        let num_prev_channels = nodes
+            .iter()
+            .filter_map(|node| match *node {
+                FlatMaTreeNode::FusedDecision {
+                    prop_level0: p,
+                    props_level1: (pl, pr),
+                    ..
+                } => Some(p.max(pl).max(pr)),
+                FlatMaTreeNode::Leaf(_) => None,
+            })
+            .filter_map(|p| p.checked_sub(16))
+            .map(|p| p as usize / 4 + 1)
+            .max()
+            .unwrap_or(0);
+
         Self {
             nodes,
             need_self_correcting,
+            num_prev_channels,
         }
     }
 
@@ -246,6 +521,14 @@ impl FlatMaTree {
         self.need_self_correcting
     }
 
+    /// Returns the number of previous channels referenced by the tree.
+    ///
+    /// Samples of channels beyond this count don't affect decoding, so those don't need to be
+    /// tracked.
+    pub fn num_prev_channels(&self) -> usize {
+        self.num_prev_channels
+    }
+
     /// Decode a sample with the given state.
     pub fn decode_sample(
         &self,
@@ -276,6 +559,12 @@ impl FlatMaTree {
         Ok((diff, leaf.predictor))
     }
 
+    /// Creates a tree with a single leaf node.
+    #[cfg(test)]
+    pub(crate) fn single_leaf(leaf: MaTreeLeafClustered) -> Self {
+        Self::new(vec![FlatMaTreeNode::Leaf(leaf)])
+    }
+
     #[inline]
     pub(crate) fn single_node(&self) -> Option<&MaTreeLeafClustered> {
         match self.nodes.get(0) {
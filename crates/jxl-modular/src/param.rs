@@ -1,6 +1,6 @@
 use jxl_grid::AllocTracker;
 
-use super::MaConfig;
+use super::{MaConfig, MaTreeLimits};
 
 #[derive(Debug, Clone)]
 pub struct ModularParams<'a, 'b> {
@@ -8,6 +8,8 @@ pub struct ModularParams<'a, 'b> {
     pub bit_depth: u32,
     pub channels: Vec<ModularChannelParams>,
     pub ma_config: Option<&'a MaConfig>,
+    /// Limits on the size of local MA trees, which are also applied to trees of subimages.
+    pub ma_tree_limits: Option<MaTreeLimits>,
     pub tracker: Option<&'b AllocTracker>,
 }
 
@@ -44,9 +46,18 @@ impl<'a, 'b> ModularParams<'a, 'b> {
             bit_depth,
             channels,
             ma_config,
+            ma_tree_limits: None,
             tracker,
         }
     }
+
+    /// Sets the limits on the size of local MA trees.
+    pub fn with_ma_tree_limits(self, ma_tree_limits: Option<MaTreeLimits>) -> Self {
+        Self {
+            ma_tree_limits,
+            ..self
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -18,6 +18,7 @@ define_bundle! {
     }
 }
 
+/// Predictor of Modular samples, selected by MA tree leaf nodes.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Default)]
 #[repr(u8)]
 pub enum Predictor {
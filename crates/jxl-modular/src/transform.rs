@@ -1,6 +1,8 @@
 use std::num::Wrapping;
 
-use jxl_bitstream::{define_bundle, read_bits, Bitstream, Bundle};
+use jxl_bitstream::{
+    define_bundle, read_bits, write_bits, BitWriter, Bitstream, Bundle, BundleWrite,
+};
 use jxl_grid::{AllocTracker, CutGrid, SimpleGrid};
 
 use super::{
@@ -79,6 +81,32 @@ impl TransformInfo {
         }
     }
 
+    /// Applies inverse transform to partially loaded channels.
+    ///
+    /// Squeeze steps of which residual channels are not loaded are skipped, along with every step
+    /// after those, and the squeezed channels are upsampled with nearest neighbor instead.
+    pub(super) fn inverse_partial(
+        &self,
+        grids: &mut Vec<TransformedGrid<'_>>,
+        state: &mut PartialInverse,
+        bit_depth: u32,
+        pool: &jxl_threadpool::JxlThreadPool,
+    ) {
+        match self {
+            Self::Rct(rct) => rct.inverse(grids),
+            Self::Palette(pal) => {
+                pal.inverse(grids, bit_depth);
+                let loaded = &mut state.loaded;
+                loaded.remove(0);
+                let begin_c = pal.begin_c as usize;
+                for _ in 1..pal.num_c {
+                    loaded.insert(begin_c + 1, loaded[begin_c]);
+                }
+            }
+            Self::Squeeze(sq) => sq.inverse_partial(grids, state, pool),
+        }
+    }
+
     pub fn is_palette(&self) -> bool {
         matches!(self, Self::Palette(_))
     }
@@ -105,6 +133,25 @@ impl Bundle<&WpHeader> for TransformInfo {
     }
 }
 
+impl BundleWrite<&WpHeader> for TransformInfo {
+    fn write(&self, writer: &mut BitWriter, wp_header: &WpHeader) -> jxl_bitstream::Result<()> {
+        match self {
+            Self::Rct(rct) => {
+                writer.write_bits(2, 0)?;
+                write_bits!(writer, Bundle(Rct), rct)
+            }
+            Self::Palette(pal) => {
+                writer.write_bits(2, 1)?;
+                write_bits!(writer, Bundle(Palette), pal, wp_header)
+            }
+            Self::Squeeze(sq) => {
+                writer.write_bits(2, 2)?;
+                write_bits!(writer, Bundle(Squeeze), sq)
+            }
+        }
+    }
+}
+
 define_bundle! {
     #[derive(Debug, Clone)]
     pub struct Rct error(crate::Error) {
@@ -164,7 +211,68 @@ impl Bundle<&WpHeader> for Palette {
     }
 }
 
+impl BundleWrite<&WpHeader> for Palette {
+    fn write(&self, writer: &mut BitWriter, _: &WpHeader) -> jxl_bitstream::Result<()> {
+        write_bits!(
+            writer,
+            U32(u(3), 8 + u(6), 72 + u(10), 1096 + u(13)),
+            &self.begin_c
+        )?;
+        write_bits!(writer, U32(1, 3, 4, 1 + u(13)), &self.num_c)?;
+        write_bits!(
+            writer,
+            U32(u(8), 256 + u(10), 1280 + u(12), 5376 + u(16)),
+            &self.nb_colours
+        )?;
+        write_bits!(
+            writer,
+            U32(0, 1 + u(8), 257 + u(10), 1281 + u(16)),
+            &self.nb_deltas
+        )?;
+        writer.write_bits(4, self.d_pred as u32)
+    }
+}
+
 impl Rct {
+    pub(crate) fn new(begin_c: u32, rct_type: u32) -> Self {
+        Self { begin_c, rct_type }
+    }
+
+    /// Applies the forward transform to samples of three channels, which is undone by the
+    /// inverse transform.
+    pub(crate) fn forward(&self, [a, b, c]: [&mut [i32]; 3]) {
+        let permutation = (self.rct_type / 7) as usize;
+        let ty = self.rct_type % 7;
+
+        for ((a, b), c) in a.iter_mut().zip(b.iter_mut()).zip(c.iter_mut()) {
+            let samples = [*a, *b, *c];
+            let d = Wrapping(samples[permutation % 3]);
+            let e = Wrapping(samples[(permutation + 1 + (permutation / 3)) % 3]);
+            let f = Wrapping(samples[(permutation + 2 - (permutation / 3)) % 3]);
+
+            let (out_a, out_b, out_c);
+            if ty == 6 {
+                out_b = d - f;
+                let tmp = f + (out_b >> 1);
+                out_c = e - tmp;
+                out_a = tmp + (out_c >> 1);
+            } else {
+                out_a = d;
+                out_c = if ty & 1 != 0 { f - d } else { f };
+                out_b = if (ty >> 1) == 1 {
+                    e - d
+                } else if (ty >> 1) == 2 {
+                    e - ((d + f) >> 1)
+                } else {
+                    e
+                };
+            }
+            *a = out_a.0;
+            *b = out_b.0;
+            *c = out_c.0;
+        }
+    }
+
     fn transform_channel_info(&self, channels: &mut super::ModularChannels) -> Result<()> {
         let begin_c = self.begin_c;
         let end_c = self.begin_c + 3;
@@ -250,6 +358,38 @@ impl Palette {
         [45, -45, 24], [24, 45, -45], [64, 64, -64], [128, 128, 0], [0, 0, -128], [-24, 45, -45],
     ];
 
+    /// Creates a palette transform without delta entries.
+    pub(crate) fn new(begin_c: u32, num_c: u32, nb_colours: u32) -> Self {
+        Self {
+            begin_c,
+            num_c,
+            nb_colours,
+            nb_deltas: 0,
+            d_pred: Predictor::Zero,
+            wp_header: None,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn begin_c(&self) -> u32 {
+        self.begin_c
+    }
+
+    #[inline]
+    pub(crate) fn num_c(&self) -> u32 {
+        self.num_c
+    }
+
+    #[inline]
+    pub(crate) fn nb_deltas(&self) -> u32 {
+        self.nb_deltas
+    }
+
+    #[inline]
+    pub(crate) fn d_pred(&self) -> Predictor {
+        self.d_pred
+    }
+
     fn transform_channel_info<'dest>(
         &self,
         channels: &mut super::ModularChannels,
@@ -413,6 +553,58 @@ impl Palette {
 }
 
 impl Squeeze {
+    /// Creates a squeeze transform with default parameters.
+    pub(crate) fn new_default() -> Self {
+        Self {
+            num_sq: 0,
+            sp: Vec::new(),
+        }
+    }
+
+    /// Applies forward transform to channels stored in raster order.
+    ///
+    /// `planes` should have samples of each channel in `channels`, and is updated along with
+    /// `channels` in the same way the decoder transforms channel list.
+    pub(crate) fn forward(
+        &self,
+        channels: &mut super::ModularChannels,
+        planes: &mut Vec<Vec<i32>>,
+    ) -> Result<()> {
+        let mut sq = self.clone();
+        sq.set_default_params(channels);
+        for sp in &sq.sp {
+            let begin = sp.begin_c as usize;
+            let end = begin + sp.num_c as usize;
+            let info_before = channels.info.get(begin..end).map(|info| info.to_vec());
+            let step = Squeeze {
+                num_sq: 1,
+                sp: vec![sp.clone()],
+            };
+            step.transform_channel_info(channels, None)?;
+            let info_before = info_before.ok_or(Error::InvalidSqueezeParams)?;
+
+            let mut residus = Vec::with_capacity(sp.num_c as usize);
+            for (plane, info) in planes[begin..end].iter_mut().zip(info_before) {
+                let (avgs, residu) = squeeze::forward(
+                    plane,
+                    info.width as usize,
+                    info.height as usize,
+                    sp.horizontal,
+                );
+                *plane = avgs;
+                residus.push(residu);
+            }
+            if sp.in_place {
+                let rest = planes.split_off(end);
+                planes.extend(residus);
+                planes.extend(rest);
+            } else {
+                planes.extend(residus);
+            }
+        }
+        Ok(())
+    }
+
     fn set_default_params(&mut self, channels: &super::ModularChannels) {
         if !self.sp.is_empty() {
             return;
@@ -578,7 +770,129 @@ impl Squeeze {
     }
 }
 
+/// State of inverse transforms applied to partially loaded channels.
+#[derive(Debug)]
+pub(crate) struct PartialInverse {
+    /// Whether each channel is loaded, kept in sync with the list of channels.
+    pub(crate) loaded: Vec<bool>,
+    /// Whether a squeeze step has been skipped.
+    skipping: bool,
+    /// Number of skipped in-place horizontal squeeze steps.
+    pub(crate) skipped_h: u32,
+    /// Number of skipped in-place vertical squeeze steps.
+    pub(crate) skipped_v: u32,
+    /// Number of last in-place squeeze steps in each direction to skip regardless of whether
+    /// residual channels are loaded.
+    target_shift: u32,
+}
+
+impl PartialInverse {
+    pub(crate) fn new(loaded: Vec<bool>, target_shift: u32) -> Self {
+        Self {
+            loaded,
+            skipping: false,
+            skipped_h: 0,
+            skipped_v: 0,
+            target_shift,
+        }
+    }
+}
+
+impl Squeeze {
+    fn inverse_partial(
+        &self,
+        grids: &mut Vec<TransformedGrid<'_>>,
+        state: &mut PartialInverse,
+        pool: &jxl_threadpool::JxlThreadPool,
+    ) {
+        // Number of remaining in-place steps in each direction, including the current one
+        let mut remaining_h = self
+            .sp
+            .iter()
+            .filter(|sp| sp.in_place && sp.horizontal)
+            .count() as u32;
+        let mut remaining_v = self
+            .sp
+            .iter()
+            .filter(|sp| sp.in_place && !sp.horizontal)
+            .count() as u32;
+
+        for sp in self.sp.iter().rev() {
+            let begin = sp.begin_c as usize;
+            let channel_count = sp.num_c as usize;
+            let end = begin + channel_count;
+            let residual_range = if sp.in_place {
+                end..(end + channel_count)
+            } else {
+                (grids.len() - channel_count)..grids.len()
+            };
+            let residual_channels: Vec<_> = grids.drain(residual_range.clone()).collect();
+            let loaded = state.loaded.drain(residual_range).all(|loaded| loaded);
+
+            if !loaded && !state.skipping {
+                tracing::debug!(?sp, "Residual channels not loaded, skipping squeeze");
+                state.skipping = true;
+            }
+            if sp.in_place {
+                let remaining = if sp.horizontal {
+                    &mut remaining_h
+                } else {
+                    &mut remaining_v
+                };
+                if *remaining <= state.target_shift && !state.skipping {
+                    tracing::debug!(?sp, "Target resolution reached, skipping squeeze");
+                    state.skipping = true;
+                }
+                *remaining -= 1;
+            }
+            if state.skipping && sp.in_place {
+                if sp.horizontal {
+                    state.skipped_h += 1;
+                } else {
+                    state.skipped_v += 1;
+                }
+            }
+
+            for (ch, residu) in grids[begin..end].iter_mut().zip(residual_channels) {
+                if state.skipping {
+                    sp.upsample_nearest(ch, residu);
+                } else {
+                    sp.inverse(ch, residu, pool);
+                }
+            }
+        }
+    }
+}
+
 impl SqueezeParams {
+    fn upsample_nearest<'dest>(&self, i0: &mut TransformedGrid<'dest>, i1: TransformedGrid<'dest>) {
+        let i0 = i0.grid_mut();
+        let TransformedGrid::Single(i1) = i1 else {
+            panic!("residual channel should be Single channel")
+        };
+        let width = i0.width();
+        let height = i0.height();
+        if self.horizontal {
+            if !i0.merge_interleaved_horizontal(i1) {
+                panic!("two grids are not from same squeeze transform");
+            }
+            for y in 0..height {
+                for x in 0..(i0.width() - width) {
+                    *i0.get_mut(2 * x + 1, y) = i0.get(2 * x, y);
+                }
+            }
+        } else {
+            if !i0.merge_interleaved_vertical(i1) {
+                panic!("two grids are not from same squeeze transform");
+            }
+            for y in 0..(i0.height() - height) {
+                for x in 0..width {
+                    *i0.get_mut(x, 2 * y + 1) = i0.get(x, 2 * y);
+                }
+            }
+        }
+    }
+
     fn inverse<'dest>(
         &self,
         i0: &mut TransformedGrid<'dest>,
@@ -199,6 +199,69 @@ unsafe fn inverse_v_avx2<'g>(merged: &'g mut CutGrid<'_, i32>) -> CutGrid<'g, i3
     merged.split_horizontal(w8 * 8).1
 }
 
+/// Applies forward squeeze to a plane stored in raster order, returning the plane of averages and
+/// the plane of residuals.
+pub(crate) fn forward(
+    data: &[i32],
+    width: usize,
+    height: usize,
+    horizontal: bool,
+) -> (Vec<i32>, Vec<i32>) {
+    let (len, num_lines) = if horizontal {
+        (width, height)
+    } else {
+        (height, width)
+    };
+    let avg_len = len.div_ceil(2);
+    let residu_len = len / 2;
+    let mut avgs = vec![0i32; avg_len * num_lines];
+    let mut residus = vec![0i32; residu_len * num_lines];
+
+    // Index of the `i`-th sample of the line, where `line_stride` is the distance between lines.
+    let index = |line: usize, i: usize, line_stride: usize| {
+        if horizontal {
+            line * line_stride + i
+        } else {
+            i * line_stride + line
+        }
+    };
+    let (in_stride, avg_stride, residu_stride) = if horizontal {
+        (width, avg_len, residu_len)
+    } else {
+        (width, width, width)
+    };
+
+    let mut line_buf = vec![0i32; len];
+    let average = |a: i32, b: i32| (a + b + (a > b) as i32) >> 1;
+    for line in 0..num_lines {
+        for (i, v) in line_buf.iter_mut().enumerate() {
+            *v = data[index(line, i, in_stride)];
+        }
+
+        for x2 in 0..residu_len {
+            let x = x2 * 2;
+            let a = line_buf[x];
+            let b = line_buf[x + 1];
+            let avg = average(a, b);
+            let next_avg = if x + 3 < len {
+                average(line_buf[x + 2], line_buf[x + 3])
+            } else if x + 2 < len {
+                line_buf[x + 2]
+            } else {
+                avg
+            };
+            let left = if x > 0 { line_buf[x - 1] } else { avg };
+            avgs[index(line, x2, avg_stride)] = avg;
+            residus[index(line, x2, residu_stride)] = a - b - tendency(left, avg, next_avg);
+        }
+        if len % 2 == 1 {
+            avgs[index(line, avg_len - 1, avg_stride)] = line_buf[len - 1];
+        }
+    }
+
+    (avgs, residus)
+}
+
 fn tendency(a: i32, b: i32, c: i32) -> i32 {
     if a >= b && b >= c {
         let mut x = (4 * a - 3 * c - b + 6) / 12;
@@ -2,7 +2,7 @@ use std::{io::prelude::*, path::PathBuf};
 
 use clap::Parser;
 use jxl_oxide::{
-    AllocTracker, CropInfo, FrameBuffer, JxlImage, JxlThreadPool, PixelFormat, Render,
+    AllocTracker, CropInfo, DebugView, FrameBuffer, JxlImage, JxlThreadPool, PixelFormat, Render,
 };
 use lcms2::Profile;
 
@@ -47,6 +47,9 @@ struct Args {
     /// Format to output
     #[arg(value_enum, short = 'f', long, default_value_t = OutputFormat::Png)]
     output_format: OutputFormat,
+    /// (unstable) Render false-colour visualisation of the first keyframe instead of pixels
+    #[arg(value_enum, long)]
+    debug_view: Option<DebugViewArg>,
     /// Print debug information
     #[arg(short, long)]
     verbose: bool,
@@ -68,6 +71,32 @@ enum OutputFormat {
     Npy,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum DebugViewArg {
+    /// Transform type of each varblock.
+    TransformType,
+    /// Quantization multiplier of each varblock.
+    HfMul,
+    /// Sigma of the edge-preserving filter.
+    EpfSigma,
+    /// Chroma-from-luma multipliers.
+    Cfl,
+    /// Group and LF group boundaries.
+    Groups,
+}
+
+impl From<DebugViewArg> for DebugView {
+    fn from(value: DebugViewArg) -> Self {
+        match value {
+            DebugViewArg::TransformType => DebugView::TransformType,
+            DebugViewArg::HfMul => DebugView::HfMul,
+            DebugViewArg::EpfSigma => DebugView::EpfSigma,
+            DebugViewArg::Cfl => DebugView::ChromaFromLuma,
+            DebugViewArg::Groups => DebugView::GroupBoundaries,
+        }
+    }
+}
+
 fn parse_crop_info(s: &str) -> Result<CropInfo, std::num::ParseIntError> {
     let s = s.trim();
     let mut it = s.split_whitespace().map(|s| s.parse::<u32>());
@@ -157,6 +186,26 @@ fn main() {
         std::fs::write(icc_path, image.rendered_icc()).expect("Failed to write ICC profile");
     }
 
+    if let Some(view) = args.debug_view {
+        if args.crop.is_some() {
+            tracing::warn!("--crop is ignored when rendering debug view");
+        }
+        if image.num_loaded_keyframes() > 1 {
+            tracing::warn!("Rendering debug view of the first keyframe only");
+        }
+
+        let fb = image
+            .render_frame_debug(0, view.into())
+            .expect("rendering debug view failed");
+        if let Some(output) = &args.output {
+            let output = std::fs::File::create(output).expect("failed to open output file");
+            write_debug_png(output, &fb);
+        } else {
+            tracing::info!("No output path specified, skipping output encoding");
+        }
+        return;
+    }
+
     let crop = args.crop.and_then(|crop| {
         if crop.width == 0 && crop.height == 0 {
             None
@@ -474,6 +523,24 @@ fn write_png<W: Write>(
     writer.finish().expect("failed to finish writing png");
 }
 
+fn write_debug_png<W: Write>(output: W, fb: &FrameBuffer) {
+    let mut encoder = png::Encoder::new(output, fb.width() as u32, fb.height() as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_srgb(png::SrgbRenderingIntent::Perceptual);
+
+    let mut writer = encoder.write_header().expect("failed to write header");
+    let buf = fb
+        .buf()
+        .iter()
+        .map(|s| (*s * 255.0 + 0.5).clamp(0.0, 255.0) as u8)
+        .collect::<Vec<_>>();
+    writer
+        .write_image_data(&buf)
+        .expect("failed to write frame");
+    writer.finish().expect("failed to finish writing png");
+}
+
 fn write_npy<W: Write>(output: W, image: &JxlImage, keyframes: &[Render], width: u32, height: u32) {
     let metadata = &image.image_header().metadata;
     let (width, height, _, _) = metadata.apply_orientation(width, height, 0, 0, false);
@@ -0,0 +1,75 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+use jxl_oxide::HeaderEditor;
+
+/// Edits image header of JPEG XL image without re-encoding.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Args {
+    /// Input file
+    input: PathBuf,
+    /// Output file
+    #[arg(short, long)]
+    output: PathBuf,
+    /// Orientation of the image, in range 1 to 8 as in Exif
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..=8))]
+    orientation: Option<u32>,
+    /// Intensity target of the image, in nits
+    #[arg(long)]
+    intensity_target: Option<f32>,
+    /// Number of loops of the animation, 0 loops forever
+    #[arg(long)]
+    num_loops: Option<u32>,
+    /// ICC profile to embed, which removes JPEG bitstream reconstruction data
+    #[arg(long)]
+    icc: Option<PathBuf>,
+    /// Print debug information
+    #[arg(short, long)]
+    verbose: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let filter = if args.verbose {
+        tracing::level_filters::LevelFilter::DEBUG
+    } else {
+        tracing::level_filters::LevelFilter::INFO
+    };
+    let env_filter = tracing_subscriber::EnvFilter::builder()
+        .with_default_directive(filter.into())
+        .from_env_lossy();
+    tracing_subscriber::fmt().with_env_filter(env_filter).init();
+
+    let span = tracing::span!(tracing::Level::TRACE, "jxl-edit");
+    let _guard = span.enter();
+
+    let mut editor = HeaderEditor::open(&args.input).expect("Failed to open file");
+    if let Some(orientation) = args.orientation {
+        editor
+            .set_orientation(orientation)
+            .expect("Failed to set orientation");
+    }
+    if let Some(intensity_target) = args.intensity_target {
+        editor
+            .set_intensity_target(intensity_target)
+            .expect("Failed to set intensity target");
+    }
+    if let Some(num_loops) = args.num_loops {
+        editor
+            .set_num_loops(num_loops)
+            .expect("Failed to set number of loops");
+    }
+    if let Some(icc_path) = &args.icc {
+        let icc = std::fs::read(icc_path).expect("Failed to read ICC profile");
+        editor
+            .set_icc_profile(icc)
+            .expect("Failed to set ICC profile");
+    }
+
+    let output = std::fs::File::create(&args.output).expect("Failed to open output file");
+    let output = std::io::BufWriter::new(output);
+    editor.write(output).expect("Failed to write image");
+    tracing::info!("Image written to {}", args.output.display());
+}
@@ -1,6 +1,6 @@
 use std::path::PathBuf;
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use jxl_oxide::JxlImage;
 
 /// Prints information about JPEG XL image.
@@ -15,11 +15,22 @@ struct Args {
     /// Output group sizes and offsets
     #[arg(long)]
     with_offset: bool,
+    /// Decode keyframes and dump the global and per-group MA trees in the given format
+    #[arg(long, value_name = "FORMAT")]
+    ma_trees: Option<MaTreeFormat>,
     /// Print debug information
     #[arg(short, long)]
     verbose: bool,
 }
 
+#[derive(Debug, Copy, Clone, ValueEnum)]
+enum MaTreeFormat {
+    /// Graphviz DOT
+    Dot,
+    /// JSON
+    Json,
+}
+
 fn main() {
     let args = Args::parse();
 
@@ -47,6 +58,7 @@ fn main() {
     let image_reader = image.reader();
 
     println!("JPEG XL image ({:?})", image_reader.kind());
+    println!("  Codestream level: {}", image.level().as_u8());
 
     println!("  Image dimension: {}x{}", image.width(), image.height());
     if image_meta.orientation != 1 {
@@ -141,6 +153,37 @@ fn main() {
         );
     }
 
+    match image.raw_exif_data() {
+        Ok(Some(exif)) => println!("  Exif metadata: {} bytes", exif.payload().len()),
+        Ok(None) => {}
+        Err(e) => println!("  Invalid Exif metadata: {e}"),
+    }
+    match image.xmp() {
+        Ok(Some(xmp)) => println!("  XMP metadata: {} bytes", xmp.len()),
+        Ok(None) => {}
+        Err(e) => println!("  Invalid XMP metadata: {e}"),
+    }
+    for jumbf in image.jumbf_boxes() {
+        match jumbf {
+            Ok(jumbf) => println!("  JUMBF box: {} bytes", jumbf.len()),
+            Err(e) => println!("  Invalid JUMBF box: {e}"),
+        }
+    }
+
+    if args.ma_trees.is_some() {
+        for idx in 0..image.num_loaded_frames() {
+            let frame = image.frame(idx).unwrap();
+            frame.enable_ma_tree_capture();
+            // LF global might have been parsed while loading the image.
+            let _ = frame.try_parse_lf_global();
+        }
+        for keyframe_idx in 0..image.num_loaded_keyframes() {
+            if let Err(e) = image.render_frame(keyframe_idx) {
+                println!("Failed to decode keyframe #{keyframe_idx}: {e}");
+            }
+        }
+    }
+
     let animated = image_meta.animation.is_some();
     for idx in 0..image.num_loaded_frames() + 1 {
         let Some(frame) = image.frame(idx) else {
@@ -236,6 +279,27 @@ fn main() {
                 );
             }
         }
+
+        if let Some(format) = args.ma_trees {
+            let trees = frame.ma_trees().unwrap_or_default();
+            if trees.is_empty() {
+                println!("  No MA trees decoded");
+            }
+            for (group, trees) in trees {
+                for (tree_idx, tree) in trees.iter().enumerate() {
+                    println!(
+                        "  MA tree #{tree_idx} in {group:?}: {} node{}, depth {}",
+                        tree.num_tree_nodes(),
+                        if tree.num_tree_nodes() == 1 { "" } else { "s" },
+                        tree.tree_depth(),
+                    );
+                    match format {
+                        MaTreeFormat::Dot => print!("{}", tree.to_dot()),
+                        MaTreeFormat::Json => println!("{}", tree.to_json()),
+                    }
+                }
+            }
+        }
     }
 
     if !image.is_loading_done() {
@@ -0,0 +1,70 @@
+use std::future::poll_fn;
+use std::ops::ControlFlow;
+use std::task::{Context, Poll};
+
+use crate::{JxlImage, JxlImageBuilder, ReadState, Result};
+
+impl JxlImageBuilder {
+    /// Consumes the builder, and creates a JPEG XL image decoder by reading image from the
+    /// [`futures_io::AsyncRead`] reader.
+    ///
+    /// The returned future doesn't block on the reader; it's pending while waiting for more data.
+    #[cfg(feature = "futures")]
+    pub async fn read_async(self, reader: impl futures_io::AsyncRead) -> Result<JxlImage> {
+        let mut reader = std::pin::pin!(reader);
+        self.read_with_poll(|cx, buf| reader.as_mut().poll_read(cx, buf))
+            .await
+    }
+
+    /// Consumes the builder, and creates a JPEG XL image decoder by reading image from the
+    /// [`tokio::io::AsyncRead`] reader.
+    ///
+    /// The returned future doesn't block on the reader; it's pending while waiting for more data.
+    #[cfg(feature = "tokio")]
+    pub async fn read_async_tokio(self, reader: impl tokio::io::AsyncRead) -> Result<JxlImage> {
+        let mut reader = std::pin::pin!(reader);
+        self.read_with_poll(|cx, buf| {
+            let mut read_buf = tokio::io::ReadBuf::new(buf);
+            match reader.as_mut().poll_read(cx, &mut read_buf) {
+                Poll::Ready(Ok(())) => Poll::Ready(Ok(read_buf.filled().len())),
+                Poll::Ready(Err(e)) => Poll::Ready(Err(e)),
+                Poll::Pending => Poll::Pending,
+            }
+        })
+        .await
+    }
+
+    /// Reads image using `poll_read`, which behaves like `AsyncRead::poll_read`.
+    ///
+    /// The future yields between chunks, as feeding a chunk may take a while.
+    async fn read_with_poll(
+        self,
+        mut poll_read: impl FnMut(&mut Context<'_>, &mut [u8]) -> Poll<std::io::Result<usize>>,
+    ) -> Result<JxlImage> {
+        let mut state = ReadState::new(self.build_uninit());
+        let mut buf = vec![0u8; 4096];
+        loop {
+            let count = poll_fn(|cx| poll_read(cx, &mut buf)).await?;
+            match state.feed(&buf[..count])? {
+                ControlFlow::Continue(next) => state = next,
+                ControlFlow::Break(image) => return Ok(image),
+            }
+            yield_now().await;
+        }
+    }
+}
+
+/// Returns a future which is pending once, waking the task immediately, so that other tasks can
+/// make progress.
+async fn yield_now() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
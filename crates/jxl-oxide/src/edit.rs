@@ -0,0 +1,219 @@
+use std::io::{Read, Write};
+use std::ops::Range;
+
+use jxl_bitstream::{
+    BitWriter, Bitstream, BitstreamKind, Bundle, BundleWrite, ContainerBoxType,
+    ContainerDetectingReader, ContainerWriter, FrameIndex,
+};
+use jxl_color::header::{ColourEncoding, ColourSpace};
+use jxl_image::ImageHeader;
+
+use crate::Result;
+
+/// Lossless editor of the image header.
+///
+/// `HeaderEditor` rewrites fields of [`ImageHeader`], such as orientation and intensity target,
+/// without decoding frames. Frame data is copied verbatim after the rewritten header.
+///
+/// If the input is a container, auxiliary boxes are kept, and the codestream is written in a
+/// single `jxlc` box. The frame index (`jxli` box) is updated if the header size changes. JPEG
+/// bitstream reconstruction data (`jbrd` box) is removed if the ICC profile or the colour encoding
+/// is replaced, since the original JPEG cannot be reconstructed from the edited image.
+///
+/// # Examples
+/// ```no_run
+/// # use jxl_oxide::HeaderEditor;
+/// # fn main() -> jxl_oxide::Result<()> {
+/// let mut editor = HeaderEditor::open("input.jxl")?;
+/// editor.set_orientation(6)?;
+/// editor.write(std::fs::File::create("output.jxl")?)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug)]
+pub struct HeaderEditor {
+    reader: ContainerDetectingReader,
+    codestream: Vec<u8>,
+    image_header: ImageHeader,
+    icc: Option<Vec<u8>>,
+    original_icc_bits: Option<Range<usize>>,
+    colour_encoding_changed: bool,
+    frames_offset: usize,
+}
+
+impl HeaderEditor {
+    /// Creates an editor by reading the whole image from the reader.
+    pub fn read(mut reader: impl Read) -> Result<Self> {
+        let mut container_reader = ContainerDetectingReader::new();
+        let mut codestream = Vec::new();
+        let mut buf = vec![0u8; 4096];
+        loop {
+            let count = reader.read(&mut buf)?;
+            if count == 0 {
+                break;
+            }
+            container_reader.feed_bytes(&buf[..count])?;
+            codestream.extend(container_reader.take_bytes());
+        }
+        container_reader.finish()?;
+
+        let mut bitstream = Bitstream::new(&codestream);
+        let image_header = ImageHeader::parse(&mut bitstream, ())?;
+        let header_end = bitstream.num_read_bits();
+        let (icc, original_icc_bits) = if image_header.metadata.colour_encoding.want_icc {
+            let icc = jxl_color::icc::read_icc(&mut bitstream)?;
+            let icc = jxl_color::icc::decode_icc(&icc)?;
+            (Some(icc), Some(header_end..bitstream.num_read_bits()))
+        } else {
+            (None, None)
+        };
+        bitstream.zero_pad_to_byte()?;
+        let frames_offset = bitstream.num_read_bits() / 8;
+
+        Ok(Self {
+            reader: container_reader,
+            codestream,
+            image_header,
+            icc,
+            original_icc_bits,
+            colour_encoding_changed: false,
+            frames_offset,
+        })
+    }
+
+    /// Creates an editor by reading the whole image from the file.
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self> {
+        let file = std::fs::File::open(path)?;
+        Self::read(file)
+    }
+
+    /// Returns the image header to be written.
+    #[inline]
+    pub fn image_header(&self) -> &ImageHeader {
+        &self.image_header
+    }
+
+    /// Returns the ICC profile to be embedded, if there's any.
+    #[inline]
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.icc.as_deref()
+    }
+
+    /// Sets the orientation of the image, in range `1..=8`.
+    pub fn set_orientation(&mut self, orientation: u32) -> Result<&mut Self> {
+        self.image_header.metadata.set_orientation(orientation)?;
+        Ok(self)
+    }
+
+    /// Sets the intensity target of the image, in nits.
+    pub fn set_intensity_target(&mut self, intensity_target: f32) -> Result<&mut Self> {
+        self.image_header
+            .metadata
+            .set_intensity_target(intensity_target)?;
+        Ok(self)
+    }
+
+    /// Sets the number of loops of the animation, where 0 means it loops forever.
+    pub fn set_num_loops(&mut self, num_loops: u32) -> Result<&mut Self> {
+        self.image_header.metadata.set_num_loops(num_loops)?;
+        Ok(self)
+    }
+
+    /// Replaces the embedded ICC profile, or embeds one if the image doesn't have it.
+    ///
+    /// The colour space is taken from the header of the profile.
+    pub fn set_icc_profile(&mut self, icc: Vec<u8>) -> Result<&mut Self> {
+        let colour_space = match icc.get(16..20) {
+            Some(b"RGB ") => ColourSpace::Rgb,
+            Some(b"GRAY") => ColourSpace::Grey,
+            Some(_) => ColourSpace::Unknown,
+            None => {
+                return Err(
+                    jxl_bitstream::Error::ValidationFailed("ICC profile is too short").into(),
+                )
+            }
+        };
+        self.image_header
+            .metadata
+            .set_colour_encoding(ColourEncoding::with_icc(colour_space))?;
+        self.icc = Some(icc);
+        self.original_icc_bits = None;
+        self.colour_encoding_changed = true;
+        Ok(self)
+    }
+
+    /// Sets the colour encoding of the image, removing the embedded ICC profile.
+    ///
+    /// Use [`set_icc_profile`][Self::set_icc_profile] to embed an ICC profile.
+    pub fn set_colour_encoding(&mut self, colour_encoding: ColourEncoding) -> Result<&mut Self> {
+        if colour_encoding.want_icc {
+            return Err(jxl_bitstream::Error::ValidationFailed(
+                "ICC profile should be given with set_icc_profile",
+            )
+            .into());
+        }
+        self.image_header
+            .metadata
+            .set_colour_encoding(colour_encoding)?;
+        self.icc = None;
+        self.original_icc_bits = None;
+        self.colour_encoding_changed = true;
+        Ok(self)
+    }
+
+    /// Writes the edited codestream.
+    pub fn codestream(&self) -> Result<Vec<u8>> {
+        let mut writer = BitWriter::new();
+        self.image_header.write(&mut writer, ())?;
+        if let Some(icc) = &self.icc {
+            match &self.original_icc_bits {
+                Some(range) => copy_bits(&mut writer, &self.codestream, range.clone())?,
+                None => {
+                    let encoded = jxl_color::icc::encode_icc(icc);
+                    jxl_color::icc::write_icc(&mut writer, &encoded)?;
+                }
+            }
+        }
+
+        let mut out = writer.finish();
+        out.extend_from_slice(&self.codestream[self.frames_offset..]);
+        Ok(out)
+    }
+
+    /// Writes the edited image to `writer`, in the same format as the input.
+    pub fn write(&self, mut writer: impl Write) -> Result<()> {
+        let codestream = self.codestream()?;
+        if self.reader.kind() != BitstreamKind::Container {
+            writer.write_all(&codestream)?;
+            return Ok(());
+        }
+
+        let delta = codestream.len() as i64 - self.codestream.len() as i64;
+        let mut container = ContainerWriter::from_reader(&self.reader, codestream);
+        if let Some(aux_box) = self.reader.find_aux_box(ContainerBoxType::FRAME_INDEX) {
+            if delta != 0 {
+                let mut frame_index = FrameIndex::parse(aux_box.data()?)?;
+                frame_index.shift_codestream_offsets(delta)?;
+                container.set_frame_index(Some(&frame_index));
+            }
+        }
+        if self.colour_encoding_changed {
+            container.set_jpeg_reconstruction(None);
+        }
+        container.write(writer)?;
+        Ok(())
+    }
+}
+
+/// Copies bits in `range` of `bytes` to the writer.
+fn copy_bits(writer: &mut BitWriter, bytes: &[u8], range: Range<usize>) -> Result<()> {
+    let mut bitstream = Bitstream::new(bytes);
+    bitstream.skip_bits(range.start)?;
+    let mut remaining = range.len();
+    while remaining > 0 {
+        let n = remaining.min(16);
+        writer.write_bits(n, bitstream.read_bits(n)?)?;
+        remaining -= n;
+    }
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use jxl_bitstream::{BitWriter, BundleDefault, BundleWrite};
+use jxl_color::header::{ColourEncoding, ColourSpace};
+use jxl_frame::data::Toc;
+use jxl_frame::FrameHeader;
+use jxl_grid::SimpleGrid;
+use jxl_image::{BitDepth, ExtraChannelInfo, ImageHeader};
+use jxl_modular::encode::EncodeOptions;
+
+use crate::{PixelFormat, Result};
+
+/// Lossless encoder of integer images.
+///
+/// `LosslessEncoder` writes a bare codestream with a single Modular frame, which decodes
+/// bit-exactly with [`JxlImage`][crate::JxlImage]. Grayscale and RGB images, with or without alpha,
+/// are supported.
+///
+/// # Examples
+/// ```
+/// # use jxl_oxide::{LosslessEncoder, PixelFormat};
+/// # fn main() -> jxl_oxide::Result<()> {
+/// let pixels = vec![0u8; 64 * 64 * 3];
+/// let jxl = LosslessEncoder::new(64, 64, PixelFormat::Rgb).encode(&pixels)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct LosslessEncoder {
+    width: u32,
+    height: u32,
+    pixel_format: PixelFormat,
+    bits_per_sample: u32,
+    group_size_shift: u32,
+    options: EncodeOptions,
+}
+
+impl LosslessEncoder {
+    /// Creates an encoder of 8-bit images with the given size and pixel format.
+    pub fn new(width: u32, height: u32, pixel_format: PixelFormat) -> Self {
+        Self {
+            width,
+            height,
+            pixel_format,
+            bits_per_sample: 8,
+            group_size_shift: 1,
+            options: EncodeOptions::default(),
+        }
+    }
+
+    /// Sets the bit depth of samples, in range `1..=16`.
+    pub fn bits_per_sample(mut self, bits_per_sample: u32) -> Self {
+        self.bits_per_sample = bits_per_sample;
+        self
+    }
+
+    /// Sets the group size to `128 << group_size_shift`, where `group_size_shift` is in range
+    /// `0..=3`.
+    pub fn group_size_shift(mut self, group_size_shift: u32) -> Self {
+        self.group_size_shift = group_size_shift;
+        self
+    }
+
+    /// Sets options of the Modular encoder.
+    pub fn options(mut self, options: EncodeOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Encodes interleaved samples in the raster order, returning the codestream.
+    ///
+    /// # Errors
+    /// Returns an error if the pixel format is not supported, the number of samples doesn't match
+    /// the image size, or some samples are out of range of the bit depth.
+    pub fn encode<T: Copy + Into<i32>>(&self, samples: &[T]) -> Result<Vec<u8>> {
+        let &Self {
+            width,
+            height,
+            pixel_format,
+            bits_per_sample,
+            group_size_shift,
+            ref options,
+        } = self;
+
+        if pixel_format.has_black() {
+            return Err(jxl_bitstream::Error::ValidationFailed(
+                "CMYK images are not supported by the encoder",
+            )
+            .into());
+        }
+        if !(1..=16).contains(&bits_per_sample) {
+            return Err(jxl_bitstream::Error::ValidationFailed("invalid bit depth").into());
+        }
+        if group_size_shift > 3 {
+            return Err(jxl_bitstream::Error::ValidationFailed("invalid group size shift").into());
+        }
+
+        let num_channels = pixel_format.channels();
+        let num_color_channels = num_channels - pixel_format.has_alpha() as usize;
+        if samples.len() as u64 != width as u64 * height as u64 * num_channels as u64 {
+            return Err(jxl_bitstream::Error::ValidationFailed(
+                "sample count doesn't match image size",
+            )
+            .into());
+        }
+
+        let mut image_header = ImageHeader::new(width, height)?;
+        let bit_depth = BitDepth::IntegerSample { bits_per_sample };
+        let metadata = &mut image_header.metadata;
+        if num_color_channels == 1 {
+            let mut colour_encoding = ColourEncoding::default_with_context(());
+            colour_encoding.colour_space = ColourSpace::Grey;
+            metadata.set_colour_encoding(colour_encoding)?;
+        }
+        metadata.set_xyb_encoded(false);
+        metadata.set_bit_depth(bit_depth);
+        metadata.set_modular_16bit_buffers(bits_per_sample <= 12);
+        if pixel_format.has_alpha() {
+            metadata.set_extra_channels(vec![ExtraChannelInfo {
+                bit_depth,
+                ..Default::default()
+            }])?;
+        }
+
+        let mut frame_header = FrameHeader::lossless_modular(&image_header);
+        frame_header.group_size_shift = group_size_shift;
+
+        let width = width as usize;
+        let height = height as usize;
+        let max_sample = (1i32 << bits_per_sample) - 1;
+        let mut channels = Vec::with_capacity(num_channels);
+        for c in 0..num_channels {
+            let mut grid = SimpleGrid::with_alloc_tracker(width, height, None)?;
+            for (out, &sample) in grid
+                .buf_mut()
+                .iter_mut()
+                .zip(samples[c..].iter().step_by(num_channels))
+            {
+                let sample = sample.into();
+                if !(0..=max_sample).contains(&sample) {
+                    return Err(jxl_bitstream::Error::ValidationFailed(
+                        "sample out of range of the bit depth",
+                    )
+                    .into());
+                }
+                *out = sample;
+            }
+            channels.push(grid);
+        }
+
+        let modular = jxl_modular::encode::encode(
+            &channels,
+            num_color_channels,
+            frame_header.group_dim(),
+            options,
+        )?;
+
+        let mut sections = Vec::new();
+        let mut writer = BitWriter::new();
+        // All-default LF channel dequantization.
+        writer.write_bool(true)?;
+        modular.write_global(&mut writer)?;
+        sections.push(writer.finish());
+        if frame_header.num_groups() > 1 {
+            for lf_group_idx in 0..frame_header.num_lf_groups() {
+                if modular.has_lf_groups() {
+                    let mut writer = BitWriter::new();
+                    modular.write_lf_group(&mut writer, lf_group_idx)?;
+                    sections.push(writer.finish());
+                } else {
+                    sections.push(Vec::new());
+                }
+            }
+            // HfGlobal
+            sections.push(Vec::new());
+            for group_idx in 0..frame_header.num_groups() {
+                if modular.has_pass_groups() {
+                    let mut writer = BitWriter::new();
+                    modular.write_pass_group(&mut writer, group_idx)?;
+                    sections.push(writer.finish());
+                } else {
+                    sections.push(Vec::new());
+                }
+            }
+        }
+
+        let sizes = sections
+            .iter()
+            .map(|section| section.len() as u32)
+            .collect::<Vec<_>>();
+        let toc = Toc::from_sizes(&frame_header, &sizes)?;
+
+        let mut writer = BitWriter::new();
+        image_header.write(&mut writer, ())?;
+        writer.zero_pad_to_byte();
+        frame_header.write(&mut writer, &image_header)?;
+        toc.write(&mut writer, &frame_header)?;
+        let mut out = writer.finish();
+        for section in sections {
+            out.extend(section);
+        }
+        Ok(out)
+    }
+}
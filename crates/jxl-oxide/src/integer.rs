@@ -0,0 +1,249 @@
+use jxl_bitstream::Name;
+use jxl_grid::SimpleGrid;
+use jxl_image::{ExtraChannelInfo, ExtraChannelType};
+use jxl_modular::image::PaletteImage;
+
+use crate::{JxlImage, Result};
+
+/// The result of decoding a lossless Modular keyframe into integer samples.
+///
+/// Samples are stored as decoded, in the original bit depth of each channel, without being
+/// converted to floating point samples.
+#[derive(Debug)]
+pub struct IntegerRender {
+    keyframe_index: usize,
+    orientation: u32,
+    bits_per_sample: u32,
+    color_channels: Vec<SimpleGrid<i32>>,
+    extra_channels: Vec<IntegerExtraChannel>,
+}
+
+impl IntegerRender {
+    /// Returns the keyframe index.
+    #[inline]
+    pub fn keyframe_index(&self) -> usize {
+        self.keyframe_index
+    }
+
+    /// Returns the orientation of the image.
+    ///
+    /// Orientation is not applied to the channels.
+    #[inline]
+    pub fn orientation(&self) -> u32 {
+        self.orientation
+    }
+
+    /// Returns the bit depth of color channels.
+    #[inline]
+    pub fn bits_per_sample(&self) -> u32 {
+        self.bits_per_sample
+    }
+
+    /// Returns the color channels, one for grayscale images and three otherwise.
+    ///
+    /// Orientation is not applied.
+    #[inline]
+    pub fn color_channels(&self) -> &[SimpleGrid<i32>] {
+        &self.color_channels
+    }
+
+    /// Returns the extra channels, potentially including alpha and black channels.
+    ///
+    /// Orientation is not applied.
+    #[inline]
+    pub fn extra_channels(&self) -> &[IntegerExtraChannel] {
+        &self.extra_channels
+    }
+
+    /// Consumes the render, returning color channels followed by extra channels.
+    pub fn into_channels(self) -> Vec<SimpleGrid<i32>> {
+        let mut channels = self.color_channels;
+        channels.extend(self.extra_channels.into_iter().map(|ec| ec.grid));
+        channels
+    }
+}
+
+/// Extra channel of [`IntegerRender`].
+#[derive(Debug)]
+pub struct IntegerExtraChannel {
+    ty: ExtraChannelType,
+    name: Name,
+    bits_per_sample: u32,
+    grid: SimpleGrid<i32>,
+}
+
+impl IntegerExtraChannel {
+    /// Returns the type of the extra channel.
+    #[inline]
+    pub fn ty(&self) -> ExtraChannelType {
+        self.ty
+    }
+
+    /// Returns the name of the channel.
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the bit depth of the channel.
+    #[inline]
+    pub fn bits_per_sample(&self) -> u32 {
+        self.bits_per_sample
+    }
+
+    /// Returns the sample grid of the channel.
+    #[inline]
+    pub fn grid(&self) -> &SimpleGrid<i32> {
+        &self.grid
+    }
+}
+
+/// The result of decoding a palette-coded lossless Modular keyframe into the palette and the index
+/// channel.
+///
+/// The palette covers every color channel, and optionally some of the extra channels following
+/// those. Remaining extra channels are decoded into integer samples.
+#[derive(Debug)]
+pub struct IndexedRender {
+    keyframe_index: usize,
+    orientation: u32,
+    bits_per_sample: u32,
+    num_color_channels: usize,
+    palette: PaletteImage,
+    extra_channels: Vec<IntegerExtraChannel>,
+}
+
+impl IndexedRender {
+    /// Returns the keyframe index.
+    #[inline]
+    pub fn keyframe_index(&self) -> usize {
+        self.keyframe_index
+    }
+
+    /// Returns the orientation of the image.
+    ///
+    /// Orientation is not applied to the index channel.
+    #[inline]
+    pub fn orientation(&self) -> u32 {
+        self.orientation
+    }
+
+    /// Returns the bit depth of color channels.
+    #[inline]
+    pub fn bits_per_sample(&self) -> u32 {
+        self.bits_per_sample
+    }
+
+    /// Returns the number of color channels covered by the palette, one for grayscale images and
+    /// three otherwise.
+    #[inline]
+    pub fn num_color_channels(&self) -> usize {
+        self.num_color_channels
+    }
+
+    /// Returns the number of extra channels covered by the palette, following color channels.
+    #[inline]
+    pub fn num_palette_extra_channels(&self) -> usize {
+        self.palette.num_channels() as usize - self.num_color_channels
+    }
+
+    /// Returns the palette and the index channel.
+    ///
+    /// Channels of palette entries are color channels followed by
+    /// [`num_palette_extra_channels`][Self::num_palette_extra_channels] extra channels.
+    #[inline]
+    pub fn palette(&self) -> &PaletteImage {
+        &self.palette
+    }
+
+    /// Returns whether the image can be written as an indexed image losslessly, i.e. every sample
+    /// refers to an explicit palette entry, without delta or implicit entries.
+    #[inline]
+    pub fn is_plain_indexed(&self) -> bool {
+        self.palette.is_plain()
+    }
+
+    /// Returns the extra channels not covered by the palette.
+    ///
+    /// Orientation is not applied.
+    #[inline]
+    pub fn extra_channels(&self) -> &[IntegerExtraChannel] {
+        &self.extra_channels
+    }
+}
+
+impl IntegerExtraChannel {
+    fn new(ec_info: &ExtraChannelInfo, grid: SimpleGrid<i32>) -> Self {
+        Self {
+            ty: ec_info.ty,
+            name: ec_info.name.clone(),
+            bits_per_sample: ec_info.bit_depth.bits_per_sample(),
+            grid,
+        }
+    }
+}
+
+impl JxlImage {
+    /// Decodes the given keyframe into integer samples, without conversion to floating point
+    /// samples.
+    ///
+    /// This is useful for lossless images where exact sample values matter, such as 16-bit
+    /// images or extra channels with more than 24 bits per sample.
+    ///
+    /// # Errors
+    /// Returns an error if the keyframe cannot be represented exactly with integers: the frame
+    /// should be a non-XYB Modular frame covering the entire image by itself, without upsampling,
+    /// restoration filters or image features, and samples should be integers.
+    pub fn render_frame_integer(&self, keyframe_index: usize) -> Result<IntegerRender> {
+        let mut channels = self.ctx.render_keyframe_integer(keyframe_index)?;
+        let metadata = &self.image_header.metadata;
+
+        let num_color_channels = metadata.encoded_color_channels();
+        let extra_channels = channels
+            .drain(num_color_channels..)
+            .zip(&metadata.ec_info)
+            .map(|(grid, ec_info)| IntegerExtraChannel::new(ec_info, grid))
+            .collect();
+
+        Ok(IntegerRender {
+            keyframe_index,
+            orientation: metadata.orientation,
+            bits_per_sample: metadata.bit_depth.bits_per_sample(),
+            color_channels: channels,
+            extra_channels,
+        })
+    }
+
+    /// Decodes the given keyframe into the palette and the index channel, for images coded with
+    /// a single palette over color channels.
+    ///
+    /// This is useful for writing indexed images, such as pixel art or UI assets, losslessly.
+    /// Check [`IndexedRender::is_plain_indexed`] before writing the index channel as is, as
+    /// samples may refer to delta or implicit palette entries.
+    ///
+    /// # Errors
+    /// Returns an error if the keyframe is not coded with a single palette transform covering
+    /// color channels, in addition to the requirements of
+    /// [`render_frame_integer`][Self::render_frame_integer].
+    pub fn render_frame_indexed(&self, keyframe_index: usize) -> Result<IndexedRender> {
+        let (palette, channels) = self.ctx.render_keyframe_indexed(keyframe_index)?;
+        let metadata = &self.image_header.metadata;
+
+        let num_color_channels = metadata.encoded_color_channels();
+        let num_palette_extra_channels = palette.num_channels() as usize - num_color_channels;
+        let extra_channels = channels
+            .into_iter()
+            .zip(&metadata.ec_info[num_palette_extra_channels..])
+            .map(|(grid, ec_info)| IntegerExtraChannel::new(ec_info, grid))
+            .collect();
+
+        Ok(IndexedRender {
+            keyframe_index,
+            orientation: metadata.orientation,
+            bits_per_sample: metadata.bit_depth.bits_per_sample(),
+            num_color_channels,
+            palette,
+            extra_channels,
+        })
+    }
+}
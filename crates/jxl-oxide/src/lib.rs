@@ -23,9 +23,24 @@
 //! println!("{:?}", image.image_header()); // Prints the image header
 //! ```
 //!
-//! In async context, you'll probably want to feed byte buffers directly. In this case, create an
-//! image struct with *uninitialized state* using [`build_uninit`][JxlImageBuilder::build_uninit],
-//! and call [`feed_bytes`][UninitializedJxlImage::feed_bytes] and
+//! In async context, enable the `futures` or `tokio` feature and use `read_async` or
+//! `read_async_tokio`, which read from `futures::AsyncRead` and `tokio::io::AsyncRead`
+//! respectively:
+//!
+//! ```
+//! # #[cfg(feature = "futures")]
+//! # async fn run(reader: impl futures_io::AsyncRead) -> jxl_oxide::Result<()> {
+//! # use jxl_oxide::JxlImage;
+//! let image = JxlImage::builder().read_async(reader).await?;
+//! println!("{:?}", image.image_header()); // Prints the image header
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! If you're using other async I/O interface, you'll want to feed byte buffers directly. In this
+//! case, create an image struct with *uninitialized state* using
+//! [`build_uninit`][JxlImageBuilder::build_uninit], and call
+//! [`feed_bytes`][UninitializedJxlImage::feed_bytes] and
 //! [`try_init`][UninitializedJxlImage::try_init]:
 //!
 //! ```no_run
@@ -88,24 +103,43 @@
 //! ```
 //!
 //! You might need to use [`JxlImage::rendered_icc`] to do color management correctly.
+use std::ops::ControlFlow;
 use std::sync::Arc;
 
+#[cfg(any(feature = "futures", feature = "tokio"))]
+mod async_read;
+mod edit;
+mod encode;
 mod fb;
+mod integer;
+mod seek;
 
-use jxl_bitstream::ContainerDetectingReader;
+use jxl_bitstream::{BitstreamKind, ContainerDetectingReader};
 pub use jxl_color::header as color;
 pub use jxl_frame::header as frame;
 use jxl_frame::FrameContext;
 pub use jxl_image as image;
+pub use jxl_jbr::JpegBitstreamData;
+pub use jxl_modular::encode::EncodeOptions;
+pub use jxl_modular::{image::PaletteImage, MaConfig, MaNode, Predictor};
 
 use jxl_bitstream::Name;
+pub use jxl_bitstream::{
+    AuxBox, BitWriter, BundleWrite, ContainerBoxType, ContainerWriter, FrameIndex, FrameIndexEntry,
+    RawExif,
+};
 use jxl_bitstream::{Bitstream, Bundle};
 pub use jxl_frame::{Frame, FrameHeader};
 pub use jxl_grid::{AllocTracker, SimpleGrid};
-pub use jxl_image::{ExtraChannelType, ImageHeader};
+pub use jxl_image::{ExtraChannelType, ImageHeader, Level, LevelLimits};
+pub use jxl_render::{DebugView, VarDctCoefficients, Varblock};
 use jxl_render::{IndexedFrame, RenderContext};
+pub use jxl_vardct::{LfChannelCorrelation, Quantizer, TransformType};
 
+pub use edit::HeaderEditor;
+pub use encode::LosslessEncoder;
 pub use fb::FrameBuffer;
+pub use integer::{IndexedRender, IntegerExtraChannel, IntegerRender};
 pub use jxl_threadpool::JxlThreadPool;
 
 pub type Result<T> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync + 'static>>;
@@ -125,6 +159,7 @@ fn default_pool() -> JxlThreadPool {
 pub struct JxlImageBuilder {
     pool: Option<JxlThreadPool>,
     tracker: Option<AllocTracker>,
+    enforce_level: bool,
 }
 
 impl JxlImageBuilder {
@@ -140,22 +175,71 @@ impl JxlImageBuilder {
         self
     }
 
+    /// Sets whether to reject codestreams exceeding the limits of the codestream level.
+    ///
+    /// The level is signalled in the `jxll` box, and defaults to level 5 if the box doesn't
+    /// exist. If enabled, images and frames exceeding the limits of the level are rejected with
+    /// [`jxl_bitstream::Error::LevelLimitExceeded`], and so are invalid `jxll` boxes. Otherwise
+    /// invalid `jxll` boxes are ignored.
+    pub fn enforce_level(mut self, enforce: bool) -> Self {
+        self.enforce_level = enforce;
+        self
+    }
+
     /// Consumes the builder, and creates an empty, uninitialized JPEG XL image decoder.
     pub fn build_uninit(self) -> UninitializedJxlImage {
         UninitializedJxlImage {
             pool: self.pool.unwrap_or_else(default_pool),
+            reader: ContainerDetectingReader::with_alloc_tracker(self.tracker.clone()),
             tracker: self.tracker,
-            reader: ContainerDetectingReader::new(),
+            enforce_level: self.enforce_level,
             buffer: Vec::new(),
+            data_source: None,
         }
     }
 
     /// Consumes the builder, and creates a JPEG XL image decoder by reading image from the reader.
     pub fn read(self, mut reader: impl std::io::Read) -> Result<JxlImage> {
+        let mut state = ReadState::new(self.build_uninit());
+        let mut buf = vec![0u8; 4096];
+        loop {
+            let count = reader.read(&mut buf)?;
+            match state.feed(&buf[..count])? {
+                ControlFlow::Continue(next) => state = next,
+                ControlFlow::Break(image) => return Ok(image),
+            }
+        }
+    }
+
+    /// Consumes the builder, and creates a JPEG XL image decoder by reading image from the file.
+    pub fn open(self, path: impl AsRef<std::path::Path>) -> Result<JxlImage> {
+        let file = std::fs::File::open(path)?;
+        self.read(file)
+    }
+
+    /// Consumes the builder, and creates a JPEG XL image decoder by reading image from the
+    /// seekable reader, loading frames until the given keyframe is loaded.
+    ///
+    /// If the image has a frame index (`jxli` box), the decoder jumps directly to the nearest
+    /// indexed keyframe at or before `keyframe_index`, without reading frames before it. Those
+    /// skipped keyframes are not available in the returned image, and frame indices (not keyframe
+    /// indices) are counted from the indexed keyframe.
+    ///
+    /// Metadata boxes are read regardless of their position in the container.
+    pub fn read_at_keyframe(
+        self,
+        mut reader: impl std::io::Read + std::io::Seek,
+        keyframe_index: usize,
+    ) -> Result<JxlImage> {
+        use std::io::Read;
+
         let mut uninit = self.build_uninit();
+        let layout = seek::ContainerLayout::scan(&mut reader, &mut uninit.reader)?;
+        let mut codestream = layout.codestream(&mut reader)?;
+
         let mut buf = vec![0u8; 4096];
         let mut image = loop {
-            let count = reader.read(&mut buf)?;
+            let count = codestream.read(&mut buf)?;
             if count == 0 {
                 return Err(std::io::Error::new(
                     std::io::ErrorKind::UnexpectedEof,
@@ -163,8 +247,7 @@ impl JxlImageBuilder {
                 )
                 .into());
             }
-            let buf = &buf[..count];
-            uninit.feed_bytes(buf)?;
+            uninit.buffer.extend_from_slice(&buf[..count]);
 
             match uninit.try_init()? {
                 InitializeResult::NeedMoreData(x) => {
@@ -176,22 +259,162 @@ impl JxlImageBuilder {
             }
         };
 
-        while !image.end_of_image {
-            let count = reader.read(&mut buf)?;
+        if let Some(entry) = image.indexed_keyframe_before(keyframe_index)? {
+            let target_keyframe = entry.keyframe_offset() as usize;
+            if target_keyframe > image.num_loaded_keyframes() {
+                let offset = entry.codestream_offset();
+                tracing::debug!(target_keyframe, offset, "Seeking to indexed keyframe");
+                codestream.seek_to(offset)?;
+                image.reset_to_keyframe(offset as usize, target_keyframe);
+            }
+        }
+
+        while !image.end_of_image && image.num_loaded_keyframes() <= keyframe_index {
+            let count = codestream.read(&mut buf)?;
             if count == 0 {
                 break;
             }
-            let buf = &buf[..count];
-            image.feed_bytes(buf)?;
+            image.feed_bytes_inner(&buf[..count])?;
         }
 
         Ok(image)
     }
 
-    /// Consumes the builder, and creates a JPEG XL image decoder by reading image from the file.
-    pub fn open(self, path: impl AsRef<std::path::Path>) -> Result<JxlImage> {
+    /// Consumes the builder, and creates a JPEG XL image decoder by reading image from the file,
+    /// loading frames until the given keyframe is loaded.
+    ///
+    /// See [`read_at_keyframe`][Self::read_at_keyframe] for details.
+    pub fn open_at_keyframe(
+        self,
+        path: impl AsRef<std::path::Path>,
+        keyframe_index: usize,
+    ) -> Result<JxlImage> {
         let file = std::fs::File::open(path)?;
-        self.read(file)
+        self.read_at_keyframe(std::io::BufReader::new(file), keyframe_index)
+    }
+
+    /// Consumes the builder, and creates a JPEG XL image decoder which reads frame data from the
+    /// seekable reader on demand.
+    ///
+    /// Only the headers and TOCs of frames are read upfront, and frame data is skipped by seeking.
+    /// Groups are read from the reader when they're decoded, so that
+    /// [`render_frame_cropped`][JxlImage::render_frame_cropped] reads only the groups which
+    /// intersect the cropping region. This is useful for decoding a part of a very large image.
+    /// Group data is freed after each decode, so it's read again if the group is decoded again.
+    ///
+    /// Metadata boxes are read regardless of their position in the container.
+    pub fn read_seekable(
+        self,
+        mut reader: impl std::io::Read + std::io::Seek + Send + 'static,
+    ) -> Result<JxlImage> {
+        let mut uninit = self.build_uninit();
+        let layout = seek::ContainerLayout::scan(&mut reader, &mut uninit.reader)?;
+        let codestream = Arc::new(seek::SeekableCodestream::new(layout, reader));
+        uninit.data_source = Some(seek::SharedDataSource(codestream.clone()));
+
+        let mut buf = vec![0u8; 4096];
+        let mut offset = 0u64;
+        let mut image = loop {
+            let count = codestream.read_at(offset, &mut buf)?;
+            if count == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "reader ended before parsing image header",
+                )
+                .into());
+            }
+            offset += count as u64;
+            uninit.buffer.extend_from_slice(&buf[..count]);
+
+            match uninit.try_init()? {
+                InitializeResult::NeedMoreData(x) => {
+                    uninit = x;
+                }
+                InitializeResult::Initialized(x) => {
+                    break x;
+                }
+            }
+        };
+
+        loop {
+            offset += image.take_skipped_bytes() as u64;
+            if image.end_of_image {
+                break;
+            }
+
+            let count = codestream.read_at(offset, &mut buf)?;
+            if count == 0 {
+                break;
+            }
+            offset += count as u64;
+            image.feed_bytes_inner(&buf[..count])?;
+        }
+
+        Ok(image)
+    }
+
+    /// Consumes the builder, and creates a JPEG XL image decoder which reads frame data from the
+    /// file on demand.
+    ///
+    /// See [`read_seekable`][Self::read_seekable] for details.
+    pub fn open_seekable(self, path: impl AsRef<std::path::Path>) -> Result<JxlImage> {
+        let file = std::fs::File::open(path)?;
+        self.read_seekable(std::io::BufReader::new(file))
+    }
+}
+
+/// State of reading an image chunk by chunk, shared by synchronous and asynchronous readers.
+#[allow(clippy::large_enum_variant)]
+enum ReadState {
+    Uninit(UninitializedJxlImage),
+    Init(JxlImage),
+}
+
+impl ReadState {
+    fn new(uninit: UninitializedJxlImage) -> Self {
+        Self::Uninit(uninit)
+    }
+
+    /// Feeds a chunk read from the reader, where an empty chunk means the end of the stream.
+    ///
+    /// Returns the image if reading is done. Container boxes after the codestream are read as
+    /// well, as metadata boxes may come after it.
+    fn feed(self, buf: &[u8]) -> Result<ControlFlow<JxlImage, Self>> {
+        let image = match self {
+            Self::Uninit(mut uninit) => {
+                if buf.is_empty() {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "reader ended before parsing image header",
+                    )
+                    .into());
+                }
+                uninit.feed_bytes(buf)?;
+
+                match uninit.try_init()? {
+                    InitializeResult::NeedMoreData(uninit) => {
+                        return Ok(ControlFlow::Continue(Self::Uninit(uninit)));
+                    }
+                    InitializeResult::Initialized(image) => image,
+                }
+            }
+            Self::Init(mut image) => {
+                if buf.is_empty() {
+                    image.reader.finish()?;
+                    return Ok(ControlFlow::Break(image));
+                }
+                image.feed_bytes(buf)?;
+                image
+            }
+        };
+
+        Ok(
+            if image.end_of_image && image.reader.kind() != BitstreamKind::Container {
+                ControlFlow::Break(image)
+            } else {
+                ControlFlow::Continue(Self::Init(image))
+            },
+        )
     }
 }
 
@@ -222,8 +445,10 @@ impl JxlImageBuilder {
 pub struct UninitializedJxlImage {
     pool: JxlThreadPool,
     tracker: Option<AllocTracker>,
+    enforce_level: bool,
     reader: ContainerDetectingReader,
     buffer: Vec<u8>,
+    data_source: Option<seek::SharedDataSource>,
 }
 
 impl UninitializedJxlImage {
@@ -259,6 +484,30 @@ impl UninitializedJxlImage {
             }
         };
 
+        let level = match self.reader.find_aux_box(ContainerBoxType::JXL_LEVEL) {
+            Some(aux_box) => {
+                let level = aux_box.data().and_then(|data| match data {
+                    &[level, ..] => Level::try_from(level),
+                    [] => Err(jxl_bitstream::Error::ValidationFailed("jxll box is empty")),
+                });
+                match level {
+                    Ok(level) => level,
+                    Err(e) if self.enforce_level => return Err(e.into()),
+                    Err(e) => {
+                        // Level is only used to enforce limits.
+                        tracing::warn!(%e, "Invalid jxll box, assuming level 5");
+                        Level::Level5
+                    }
+                }
+            }
+            None => Level::Level5,
+        };
+        tracing::debug!(level = level.as_u8(), "Codestream level");
+        let enforced_level = self.enforce_level.then_some(level);
+        if let Some(level) = enforced_level {
+            level.check_image_header(&image_header)?;
+        }
+
         let embedded_icc = if image_header.metadata.colour_encoding.want_icc {
             tracing::debug!("Image has an embedded ICC profile");
             let icc = match jxl_color::icc::read_icc(&mut bitstream) {
@@ -271,6 +520,9 @@ impl UninitializedJxlImage {
                 }
             };
             let icc = jxl_color::icc::decode_icc(&icc)?;
+            if let Some(level) = enforced_level {
+                level.check_icc_size(icc.len())?;
+            }
             Some(icc)
         } else {
             None
@@ -285,6 +537,7 @@ impl UninitializedJxlImage {
                     image_header: image_header.clone(),
                     tracker: self.tracker.as_ref(),
                     pool: self.pool.clone(),
+                    level: enforced_level,
                 },
             ) {
                 Ok(x) => x,
@@ -316,12 +569,16 @@ impl UninitializedJxlImage {
         if let Some(tracker) = self.tracker {
             builder = builder.alloc_tracker(tracker);
         }
+        if let Some(level) = enforced_level {
+            builder = builder.level(level);
+        }
         let ctx = builder.build(image_header.clone());
 
         let mut image = JxlImage {
             pool: self.pool.clone(),
             reader: self.reader,
             image_header,
+            level,
             original_icc: embedded_icc,
             ctx,
             render_spot_colour,
@@ -329,6 +586,8 @@ impl UninitializedJxlImage {
             buffer: Vec::new(),
             buffer_offset: bytes_read,
             frame_offsets: Vec::new(),
+            data_source: self.data_source,
+            skip_bytes: 0,
         };
         image.feed_bytes_inner(&self.buffer)?;
 
@@ -350,6 +609,7 @@ pub struct JxlImage {
     pool: JxlThreadPool,
     reader: ContainerDetectingReader,
     image_header: Arc<ImageHeader>,
+    level: Level,
     original_icc: Option<Vec<u8>>,
     ctx: RenderContext,
     render_spot_colour: bool,
@@ -357,6 +617,8 @@ pub struct JxlImage {
     buffer: Vec<u8>,
     buffer_offset: usize,
     frame_offsets: Vec<usize>,
+    data_source: Option<seek::SharedDataSource>,
+    skip_bytes: usize,
 }
 
 impl JxlImage {
@@ -374,6 +636,13 @@ impl JxlImage {
     }
 
     fn feed_bytes_inner(&mut self, mut buf: &[u8]) -> Result<()> {
+        if self.skip_bytes > 0 {
+            let count = self.skip_bytes.min(buf.len());
+            buf = &buf[count..];
+            self.skip_bytes -= count;
+            self.buffer_offset += count;
+        }
+
         if buf.is_empty() {
             return Ok(());
         }
@@ -414,19 +683,35 @@ impl JxlImage {
                     self.buffer = buf.to_vec();
                     return Ok(());
                 }
-                Err(e) => {
+                Err(mut e) => {
+                    // Make the location relative to the beginning of the codestream.
+                    if let Some(location) = e.location_mut() {
+                        location.rebase(self.buffer_offset as u64 * 8);
+                    }
                     return Err(e.into());
                 }
             };
+            frame.set_codestream_offset(self.buffer_offset as u64);
             let frame_index = frame.index();
             assert_eq!(self.frame_offsets.len(), frame_index);
             self.frame_offsets.push(self.buffer_offset);
 
             let read_bytes = bitstream.num_read_bits() / 8;
             buf = &buf[read_bytes..];
-            let len = buf.len();
-            buf = frame.feed_bytes(buf);
-            let read_bytes = read_bytes + (len - buf.len());
+            let read_bytes = if let Some(data_source) = &self.data_source {
+                // Frame data is read on demand; skip it.
+                let frame_offset = self.buffer_offset as u64;
+                frame.set_data_source(data_source.0.clone(), frame_offset);
+                let data_size = frame.toc().total_byte_size();
+                let count = data_size.min(buf.len());
+                self.skip_bytes = data_size - count;
+                buf = &buf[count..];
+                read_bytes + count
+            } else {
+                let len = buf.len();
+                buf = frame.feed_bytes(buf);
+                read_bytes + (len - buf.len())
+            };
             self.buffer_offset += read_bytes;
 
             if frame.is_loading_done() {
@@ -443,6 +728,56 @@ impl JxlImage {
         self.buffer.clear();
         Ok(())
     }
+
+    /// Returns the entry of the frame index for the nearest indexed keyframe at or before
+    /// `keyframe_index`.
+    ///
+    /// Returns `None` if the image doesn't have a frame index, or the frame index doesn't start
+    /// from the first frame of the image.
+    fn indexed_keyframe_before(&self, keyframe_index: usize) -> Result<Option<FrameIndexEntry>> {
+        let Some(frame_index) = self.frame_index()? else {
+            return Ok(None);
+        };
+
+        let first_frame_offset = self
+            .frame_offsets
+            .first()
+            .copied()
+            .unwrap_or(self.buffer_offset);
+        let first_entry_offset = frame_index
+            .entries()
+            .first()
+            .map(|entry| entry.codestream_offset());
+        if first_entry_offset != Some(first_frame_offset as u64) {
+            tracing::warn!(
+                first_frame_offset,
+                ?first_entry_offset,
+                "Frame index doesn't start from the first frame, ignoring"
+            );
+            return Ok(None);
+        }
+
+        Ok(frame_index.nearest_entry(keyframe_index as u64).copied())
+    }
+
+    /// Discards loaded frames, and prepares to load frames from the indexed keyframe at the
+    /// given codestream offset.
+    fn reset_to_keyframe(&mut self, codestream_offset: usize, keyframe_index: usize) {
+        self.ctx.reset_to_keyframe(keyframe_index);
+        self.end_of_image = false;
+        self.buffer.clear();
+        self.buffer_offset = codestream_offset;
+        self.frame_offsets.clear();
+        self.skip_bytes = 0;
+    }
+
+    /// Skips the rest of frame data which is read on demand, returning the number of skipped
+    /// bytes.
+    fn take_skipped_bytes(&mut self) -> usize {
+        let count = std::mem::take(&mut self.skip_bytes);
+        self.buffer_offset += count;
+        count
+    }
 }
 
 impl JxlImage {
@@ -452,6 +787,13 @@ impl JxlImage {
         &self.image_header
     }
 
+    /// Returns the codestream level signalled in the `jxll` box, or level 5 if the box doesn't
+    /// exist or is invalid.
+    #[inline]
+    pub fn level(&self) -> Level {
+        self.level
+    }
+
     /// Returns the image width with orientation applied.
     #[inline]
     pub fn width(&self) -> u32 {
@@ -525,6 +867,110 @@ impl JxlImage {
     }
 }
 
+impl JxlImage {
+    /// Returns the raw Exif metadata stored in the `Exif` box, if any.
+    ///
+    /// Metadata boxes are available only if the image is in the container format. Boxes that are
+    /// not fully loaded yet are not returned. Brotli-compressed (`brob`) boxes are returned in
+    /// decompressed form.
+    ///
+    /// # Errors
+    /// Returns an error if the box cannot be decompressed, or the content of the box is invalid.
+    pub fn raw_exif_data(&self) -> Result<Option<RawExif<'_>>> {
+        let Some(aux_box) = self.reader.find_aux_box(ContainerBoxType::EXIF) else {
+            return Ok(None);
+        };
+        Ok(Some(RawExif::new(aux_box.data()?)?))
+    }
+
+    /// Returns the XMP packet stored in the `xml ` box, if any.
+    ///
+    /// Metadata boxes are available only if the image is in the container format. Boxes that are
+    /// not fully loaded yet are not returned. Brotli-compressed (`brob`) boxes are returned in
+    /// decompressed form.
+    ///
+    /// # Errors
+    /// Returns an error if the box cannot be decompressed.
+    pub fn xmp(&self) -> Result<Option<&[u8]>> {
+        let Some(aux_box) = self.reader.find_aux_box(ContainerBoxType::XML) else {
+            return Ok(None);
+        };
+        Ok(Some(aux_box.data()?))
+    }
+
+    /// Returns the frame index stored in the `jxli` box, if any.
+    ///
+    /// Metadata boxes are available only if the image is in the container format. Boxes that are
+    /// not fully loaded yet are not returned.
+    ///
+    /// # Errors
+    /// Returns an error if the content of the box is invalid.
+    pub fn frame_index(&self) -> Result<Option<FrameIndex>> {
+        let Some(aux_box) = self.reader.find_aux_box(ContainerBoxType::FRAME_INDEX) else {
+            return Ok(None);
+        };
+        Ok(Some(FrameIndex::parse(aux_box.data()?)?))
+    }
+
+    /// Returns an iterator over the contents of JUMBF (`jumb`) boxes.
+    ///
+    /// Metadata boxes are available only if the image is in the container format. Boxes that are
+    /// not fully loaded yet are not returned. Brotli-compressed (`brob`) boxes are returned in
+    /// decompressed form, and an error is returned for boxes that cannot be decompressed.
+    pub fn jumbf_boxes(&self) -> impl Iterator<Item = Result<&[u8]>> + '_ {
+        self.reader
+            .aux_boxes()
+            .iter()
+            .filter(|aux_box| aux_box.box_type() == ContainerBoxType::JUMBF)
+            .map(|aux_box| Ok(aux_box.data()?))
+    }
+}
+
+impl JxlImage {
+    /// Returns whether the image contains JPEG bitstream reconstruction data.
+    #[inline]
+    pub fn has_jpeg_reconstruction_data(&self) -> bool {
+        self.reader
+            .find_aux_box(ContainerBoxType::JPEG_RECONSTRUCTION)
+            .is_some()
+    }
+
+    /// Reconstructs the original JPEG bitstream, and writes it to `writer`.
+    ///
+    /// The image should be a losslessly recompressed JPEG image with the JPEG bitstream
+    /// reconstruction data (`jbrd` box), and should be fully loaded.
+    ///
+    /// # Errors
+    /// Returns an error if the image doesn't have reconstruction data, the image is not fully
+    /// loaded or has more than one keyframe, the frame cannot be represented as JPEG, or metadata
+    /// required by the reconstruction data is missing.
+    pub fn reconstruct_jpeg(&self, writer: impl std::io::Write) -> Result<()> {
+        let aux_box = self
+            .reader
+            .find_aux_box(ContainerBoxType::JPEG_RECONSTRUCTION)
+            .ok_or("JPEG bitstream reconstruction data not found")?;
+        if !self.is_loading_done() {
+            return Err("image is not fully loaded yet".into());
+        }
+        if self.num_loaded_keyframes() != 1 {
+            return Err("JPEG bitstream reconstruction requires exactly one keyframe".into());
+        }
+        let jbrd = JpegBitstreamData::parse(aux_box.data()?, self.ctx.alloc_tracker())?;
+
+        let frame = self.frame_by_keyframe(0).ok_or("frame is not loaded yet")?;
+        let exif = self.raw_exif_data()?;
+        jbrd.reconstruct(
+            frame,
+            self.original_icc(),
+            exif.as_ref().map(|exif| exif.raw_payload()),
+            self.xmp()?,
+            &self.pool,
+            writer,
+        )?;
+        Ok(())
+    }
+}
+
 impl JxlImage {
     /// Returns the number of currently loaded keyframes.
     #[inline]
@@ -606,6 +1052,166 @@ impl JxlImage {
         Ok(result)
     }
 
+    /// Renders the given keyframe in bands of rows, passing each band to `sink` as soon as it's
+    /// rendered.
+    ///
+    /// `sink` receives the range of rows of the band, and samples of color channels followed by
+    /// extra channels, each in row-major order with the width of the image. Channels are in the
+    /// same order as [`Render`], orientation is not applied, and the range of rows is in the
+    /// image before orientation. Bands are passed in top-to-bottom order, and each band has the
+    /// height of a group except for the last one.
+    ///
+    /// Keyframes covering the entire image by themselves are rendered band by band, so that peak
+    /// memory usage is proportional to the width of the image times the group size instead of
+    /// the size of the image. Other keyframes, such as ones blended onto previous frames, ones
+    /// referencing other frames, and Modular frames with global palette or squeeze, fall back to
+    /// rendering in full before being split into bands, which needs as much memory as
+    /// [`render_frame`][Self::render_frame].
+    ///
+    /// # Errors
+    /// Returns an error if the keyframe is not loaded enough.
+    pub fn render_frame_streaming(
+        &self,
+        keyframe_index: usize,
+        mut sink: impl FnMut(std::ops::Range<u32>, &[&[f32]]),
+    ) -> Result<()> {
+        self.ctx
+            .render_keyframe_streaming(keyframe_index, |mut band| -> Result<()> {
+                let region = band.region();
+                let rows = region.top as u32..region.bottom() as u32;
+                let (color_channels, extra_channels) = self.process_render(band.take_buffer())?;
+                let channels = color_channels
+                    .iter()
+                    .chain(extra_channels.iter().map(|ec| &ec.grid))
+                    .map(|g| g.buf())
+                    .collect::<Vec<_>>();
+                sink(rows, &channels);
+                Ok(())
+            })
+    }
+
+    /// Renders the given keyframe in reduced resolution, downscaled by `factor`.
+    ///
+    /// `factor` should be one of 1, 2, 4 and 8, which is useful for generating thumbnails or
+    /// zooming out of large images. VarDCT keyframes covering the entire image are rendered in
+    /// 1:8 scale from the LF image if possible, reading only LfGlobal and LF groups of the frame,
+    /// and in 1:2 and 1:4 scale with reduced-size inverse transforms. Modular keyframes with
+    /// squeeze are rendered in 1:2 and 1:4 scale from the squeezed image in reduced resolution.
+    /// Image features and restoration filters are skipped in those cases. Other keyframes are
+    /// rendered in full resolution, then downscaled.
+    ///
+    /// Returned render has the size returned by [`downscaled_size`][Self::downscaled_size].
+    /// Colour conversion is done, and orientation and extra channels are handled in the same way
+    /// as [`render_frame`][Self::render_frame].
+    ///
+    /// # Errors
+    /// Returns an error if `factor` is not supported, or if the keyframe is not loaded enough.
+    pub fn render_frame_downscaled(&self, keyframe_index: usize, factor: u32) -> Result<Render> {
+        self.render_frame_downscaled_cropped(keyframe_index, factor, None)
+    }
+
+    /// Renders the given keyframe in reduced resolution, downscaled by `factor`, within the
+    /// cropping region.
+    ///
+    /// `image_region` is the region of the downscaled image, with orientation applied. The
+    /// region of the image in full resolution that it covers can be computed with
+    /// [`full_resolution_crop`][Self::full_resolution_crop]. See
+    /// [`render_frame_downscaled`][Self::render_frame_downscaled] for how the keyframe is
+    /// downscaled.
+    ///
+    /// # Errors
+    /// Returns an error if `factor` is not supported, or if the keyframe is not loaded enough.
+    pub fn render_frame_downscaled_cropped(
+        &self,
+        keyframe_index: usize,
+        factor: u32,
+        image_region: Option<CropInfo>,
+    ) -> Result<Render> {
+        let mut grids = self.ctx.render_keyframe_downscaled(
+            keyframe_index,
+            factor,
+            image_region.map(From::from),
+        )?;
+        let grids = grids.take_buffer();
+        let (color_channels, extra_channels) = self.process_render(grids)?;
+
+        let frame = self.ctx.keyframe(keyframe_index).unwrap();
+        let frame_header = frame.header();
+        let result = Render {
+            keyframe_index,
+            name: frame_header.name.clone(),
+            duration: frame_header.duration,
+            orientation: self.image_header.metadata.orientation,
+            color_channels,
+            extra_channels,
+        };
+        Ok(result)
+    }
+
+    /// Returns the size of the image downscaled by `factor`, rounded up, with orientation
+    /// applied.
+    ///
+    /// # Errors
+    /// Returns an error if `factor` is not supported.
+    pub fn downscaled_size(&self, factor: u32) -> Result<(u32, u32)> {
+        Ok(self.ctx.downscaled_size(factor)?)
+    }
+
+    /// Returns the cropping region of the image in full resolution which corresponds to
+    /// `image_region` of the image downscaled by `factor`.
+    ///
+    /// Returned region is clipped to the image, and it has zero size if `image_region` is
+    /// outside of the downscaled image.
+    ///
+    /// # Errors
+    /// Returns an error if `factor` is not supported.
+    pub fn full_resolution_crop(&self, factor: u32, image_region: CropInfo) -> Result<CropInfo> {
+        let region = self
+            .ctx
+            .full_resolution_region(factor, image_region.into())?;
+        Ok(CropInfo {
+            width: region.width,
+            height: region.height,
+            left: region.left.max(0) as u32,
+            top: region.top.max(0) as u32,
+        })
+    }
+
+    /// Decodes quantized coefficients and block structure of the given VarDCT keyframe, without
+    /// rendering it.
+    ///
+    /// Returned data includes position, transform type and quantization multiplier of each
+    /// varblock, quantized LF image, quantized and dequantized HF coefficients, and
+    /// chroma-from-luma factors.
+    ///
+    /// # Errors
+    /// Returns an error if the keyframe is not a VarDCT frame, or is not loaded completely.
+    pub fn vardct_coefficients(&self, keyframe_index: usize) -> Result<VarDctCoefficients> {
+        Ok(self.ctx.keyframe_vardct_coefficients(keyframe_index)?)
+    }
+
+    /// Renders a false-colour visualisation of the given keyframe in RGB, such as transform types
+    /// or group boundaries, instead of pixels.
+    ///
+    /// The visualisation has the size of color channels of the frame, before upsampling, and
+    /// orientation is applied.
+    ///
+    /// # Errors
+    /// Returns an error if the view requires a VarDCT frame and the keyframe is not a VarDCT
+    /// frame, or if the keyframe is not loaded completely.
+    pub fn render_frame_debug(
+        &self,
+        keyframe_index: usize,
+        view: DebugView,
+    ) -> Result<FrameBuffer> {
+        let grids = self.ctx.render_keyframe_debug(keyframe_index, view)?;
+        let grids = grids.each_ref();
+        Ok(FrameBuffer::from_grids(
+            &grids,
+            self.image_header.metadata.orientation,
+        ))
+    }
+
     /// Renders the currently loading keyframe.
     pub fn render_loading_frame(&mut self) -> Result<Render> {
         self.render_loading_frame_cropped(None)
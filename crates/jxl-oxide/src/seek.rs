@@ -0,0 +1,277 @@
+use std::io::{Read, Seek, SeekFrom};
+use std::sync::{Arc, Mutex};
+
+use jxl_bitstream::{
+    BitstreamKind, ContainerBoxHeader, ContainerBoxType, ContainerDetectingReader,
+    HeaderParseResult,
+};
+
+/// Location of a part of the codestream in the input.
+#[derive(Debug, Copy, Clone)]
+struct CodestreamChunk {
+    file_offset: u64,
+    codestream_offset: u64,
+    len: Option<u64>,
+}
+
+/// Layout of codestream boxes in the input, read by seeking over the box headers.
+#[derive(Debug)]
+pub(crate) struct ContainerLayout {
+    chunks: Vec<CodestreamChunk>,
+}
+
+impl ContainerLayout {
+    /// Scans the box structure of the input, starting from the current position.
+    ///
+    /// Auxiliary boxes are fed into `container_reader`, while codestream boxes are skipped so
+    /// that the codestream can be read on demand.
+    pub(crate) fn scan(
+        reader: &mut (impl Read + Seek),
+        container_reader: &mut ContainerDetectingReader,
+    ) -> std::io::Result<Self> {
+        let base = reader.stream_position()?;
+        let mut signature = [0u8; 12];
+        let len = read_fully(reader, &mut signature)?;
+        container_reader.feed_bytes(&signature[..len])?;
+        match container_reader.kind() {
+            BitstreamKind::BareCodestream => {
+                container_reader.take_bytes();
+                return Ok(Self {
+                    chunks: vec![CodestreamChunk {
+                        file_offset: base,
+                        codestream_offset: 0,
+                        len: None,
+                    }],
+                });
+            }
+            BitstreamKind::Container => {}
+            BitstreamKind::Unknown => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "reader ended before reading signature",
+                ));
+            }
+            BitstreamKind::Invalid => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    "invalid signature",
+                ));
+            }
+        }
+
+        let mut chunks = Vec::new();
+        let mut codestream_offset = 0u64;
+        let mut pos = base + len as u64;
+        loop {
+            reader.seek(SeekFrom::Start(pos))?;
+            let mut header_buf = [0u8; 16];
+            let len = read_fully(reader, &mut header_buf)?;
+            if len == 0 {
+                break;
+            }
+            let (header, header_size) = match ContainerBoxHeader::parse(&header_buf[..len])? {
+                HeaderParseResult::Done { header, size } => (header, size as u64),
+                HeaderParseResult::NeedMoreData => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "truncated box header",
+                    ));
+                }
+            };
+
+            let ty = header.box_type();
+            let data_offset = pos + header_size;
+            if ty == ContainerBoxType::CODESTREAM || ty == ContainerBoxType::PARTIAL_CODESTREAM {
+                // Skip jxlp box index.
+                let skip = if ty == ContainerBoxType::PARTIAL_CODESTREAM {
+                    4
+                } else {
+                    0
+                };
+                let len = header
+                    .size()
+                    .map(|size| {
+                        size.checked_sub(skip).ok_or(std::io::Error::new(
+                            std::io::ErrorKind::InvalidData,
+                            "jxlp box is too short",
+                        ))
+                    })
+                    .transpose()?;
+
+                tracing::trace!(file_offset = data_offset + skip, codestream_offset, ?len);
+                chunks.push(CodestreamChunk {
+                    file_offset: data_offset + skip,
+                    codestream_offset,
+                    len,
+                });
+                if let Some(len) = len {
+                    codestream_offset += len;
+                }
+            } else {
+                reader.seek(SeekFrom::Start(pos))?;
+                let box_reader = reader.by_ref();
+                let mut box_reader: Box<dyn Read> = match header.size() {
+                    Some(size) => Box::new(box_reader.take(header_size + size)),
+                    None => Box::new(box_reader),
+                };
+                let mut buf = vec![0u8; 4096];
+                loop {
+                    let count = box_reader.read(&mut buf)?;
+                    if count == 0 {
+                        break;
+                    }
+                    container_reader.feed_bytes(&buf[..count])?;
+                }
+            }
+
+            match header.size() {
+                Some(size) => pos = data_offset + size,
+                None => break,
+            }
+        }
+
+        container_reader.finish()?;
+        Ok(Self { chunks })
+    }
+
+    /// Creates a reader that reads the codestream from the input.
+    pub(crate) fn codestream<'a, R: Read + Seek>(
+        &'a self,
+        reader: &'a mut R,
+    ) -> std::io::Result<CodestreamReader<'a, R>> {
+        let mut codestream = CodestreamReader {
+            reader,
+            chunks: &self.chunks,
+            chunk_idx: 0,
+            remaining: None,
+        };
+        codestream.seek_to(0)?;
+        Ok(codestream)
+    }
+}
+
+/// Reader of the codestream which supports seeking by codestream offset.
+pub(crate) struct CodestreamReader<'a, R> {
+    reader: &'a mut R,
+    chunks: &'a [CodestreamChunk],
+    chunk_idx: usize,
+    remaining: Option<u64>,
+}
+
+impl<R: Read + Seek> CodestreamReader<'_, R> {
+    /// Moves to the given offset of the codestream.
+    pub(crate) fn seek_to(&mut self, codestream_offset: u64) -> std::io::Result<()> {
+        let idx = self
+            .chunks
+            .partition_point(|chunk| chunk.codestream_offset <= codestream_offset);
+        let Some(idx) = idx.checked_sub(1) else {
+            self.chunk_idx = self.chunks.len();
+            return Ok(());
+        };
+
+        let chunk = self.chunks[idx];
+        let skip = codestream_offset - chunk.codestream_offset;
+        self.chunk_idx = idx;
+        self.remaining = chunk.len.map(|len| len.saturating_sub(skip));
+        self.reader
+            .seek(SeekFrom::Start(chunk.file_offset + skip))?;
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for CodestreamReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if self.chunk_idx >= self.chunks.len() {
+                return Ok(0);
+            }
+
+            if self.remaining == Some(0) {
+                self.chunk_idx += 1;
+                if let Some(chunk) = self.chunks.get(self.chunk_idx) {
+                    self.remaining = chunk.len;
+                    self.reader.seek(SeekFrom::Start(chunk.file_offset))?;
+                }
+                continue;
+            }
+
+            let max_len = match self.remaining {
+                Some(remaining) => remaining.min(buf.len() as u64) as usize,
+                None => buf.len(),
+            };
+            let count = self.reader.read(&mut buf[..max_len])?;
+            if let Some(remaining) = &mut self.remaining {
+                if count == 0 && max_len != 0 {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "reader ended before the end of codestream box",
+                    ));
+                }
+                *remaining -= count as u64;
+            }
+            return Ok(count);
+        }
+    }
+}
+
+/// Source of frame data attached to frames loaded in seekable mode.
+#[derive(Clone)]
+pub(crate) struct SharedDataSource(pub(crate) Arc<dyn jxl_frame::FrameDataSource>);
+
+impl std::fmt::Debug for SharedDataSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SharedDataSource").finish_non_exhaustive()
+    }
+}
+
+/// Codestream of the seekable input, shared by frames to read group data on demand.
+pub(crate) struct SeekableCodestream<R> {
+    layout: ContainerLayout,
+    reader: Mutex<R>,
+}
+
+impl<R: Read + Seek> SeekableCodestream<R> {
+    pub(crate) fn new(layout: ContainerLayout, reader: R) -> Self {
+        Self {
+            layout,
+            reader: Mutex::new(reader),
+        }
+    }
+
+    /// Reads the codestream starting at `offset` into `buf`, returning the number of bytes read.
+    pub(crate) fn read_at(&self, offset: u64, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.with_codestream_at(offset, |codestream| codestream.read(buf))
+    }
+
+    fn with_codestream_at<T>(
+        &self,
+        offset: u64,
+        f: impl FnOnce(&mut CodestreamReader<'_, R>) -> std::io::Result<T>,
+    ) -> std::io::Result<T> {
+        let mut reader = self
+            .reader
+            .lock()
+            .map_err(|_| std::io::Error::other("reader lock is poisoned"))?;
+        let mut codestream = self.layout.codestream(&mut *reader)?;
+        codestream.seek_to(offset)?;
+        f(&mut codestream)
+    }
+}
+
+impl<R: Read + Seek + Send> jxl_frame::FrameDataSource for SeekableCodestream<R> {
+    fn read_exact_at(&self, offset: u64, buf: &mut [u8]) -> std::io::Result<()> {
+        self.with_codestream_at(offset, |codestream| codestream.read_exact(buf))
+    }
+}
+
+fn read_fully(reader: &mut impl Read, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        let count = reader.read(&mut buf[len..])?;
+        if count == 0 {
+            break;
+        }
+        len += count;
+    }
+    Ok(len)
+}
@@ -0,0 +1,124 @@
+#![cfg(any(feature = "futures", feature = "tokio"))]
+
+use jxl_oxide::JxlImage;
+
+// Image from the crate-level documentation.
+const CODESTREAM: &[u8] = &[
+    0xff, 0x0a, 0x30, 0x54, 0x10, 0x09, 0x08, 0x06, 0x01, 0x00, 0x78, 0x00, 0x4b, 0x38, 0x41, 0x3c,
+    0xb6, 0x3a, 0x51, 0xfe, 0x00, 0x47, 0x1e, 0xa0, 0x85, 0xb8, 0x27, 0x1a, 0x48, 0x45, 0x84, 0x1b,
+    0x71, 0x4f, 0xa8, 0x3e, 0x8e, 0x30, 0x03, 0x92, 0x84, 0x01,
+];
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+}
+
+fn check_image(image: &JxlImage) {
+    let expected = JxlImage::builder()
+        .read(std::io::Cursor::new(CODESTREAM))
+        .unwrap();
+    assert_eq!(image.width(), expected.width());
+    assert_eq!(image.height(), expected.height());
+    assert_eq!(image.num_loaded_keyframes(), 1);
+    let render = image.render_frame(0).unwrap();
+    let expected = expected.render_frame(0).unwrap();
+    assert_eq!(render.image().buf(), expected.image().buf());
+}
+
+/// Reader which yields a few bytes at a time, returning `Pending` in between.
+#[cfg(feature = "futures")]
+struct TrickleReader {
+    data: &'static [u8],
+    ready: bool,
+}
+
+#[cfg(feature = "futures")]
+impl futures_io::AsyncRead for TrickleReader {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        if !self.ready {
+            self.ready = true;
+            cx.waker().wake_by_ref();
+            return std::task::Poll::Pending;
+        }
+
+        self.ready = false;
+        let count = buf.len().min(self.data.len()).min(5);
+        buf[..count].copy_from_slice(&self.data[..count]);
+        self.data = &self.data[count..];
+        std::task::Poll::Ready(Ok(count))
+    }
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn read_async_futures() {
+    let reader = TrickleReader {
+        data: CODESTREAM,
+        ready: false,
+    };
+    let image = runtime()
+        .block_on(JxlImage::builder().read_async(reader))
+        .unwrap();
+    check_image(&image);
+}
+
+/// Reader which is always ready, yielding a few bytes at a time.
+#[cfg(feature = "futures")]
+struct ReadyReader(&'static [u8]);
+
+#[cfg(feature = "futures")]
+impl futures_io::AsyncRead for ReadyReader {
+    fn poll_read(
+        mut self: std::pin::Pin<&mut Self>,
+        _cx: &mut std::task::Context<'_>,
+        buf: &mut [u8],
+    ) -> std::task::Poll<std::io::Result<usize>> {
+        let count = buf.len().min(self.0.len()).min(5);
+        buf[..count].copy_from_slice(&self.0[..count]);
+        self.0 = &self.0[count..];
+        std::task::Poll::Ready(Ok(count))
+    }
+}
+
+#[cfg(feature = "futures")]
+#[test]
+fn read_async_yields_between_chunks() {
+    use std::future::Future;
+
+    let mut future = std::pin::pin!(JxlImage::builder().read_async(ReadyReader(CODESTREAM)));
+    let mut cx = std::task::Context::from_waker(std::task::Waker::noop());
+    let mut num_pending = 0;
+    let image = loop {
+        match future.as_mut().poll(&mut cx) {
+            std::task::Poll::Ready(image) => break image.unwrap(),
+            std::task::Poll::Pending => num_pending += 1,
+        }
+    };
+    check_image(&image);
+    assert!(num_pending >= CODESTREAM.len() / 5);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn read_async_tokio_pipe() {
+    use tokio::io::AsyncWriteExt;
+
+    let image = runtime()
+        .block_on(async {
+            let (mut tx, rx) = tokio::io::duplex(7);
+            let writer = tokio::spawn(async move {
+                tx.write_all(CODESTREAM).await.unwrap();
+            });
+            let image = JxlImage::builder().read_async_tokio(rx).await;
+            writer.await.unwrap();
+            image
+        })
+        .unwrap();
+    check_image(&image);
+}
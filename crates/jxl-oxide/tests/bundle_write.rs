@@ -0,0 +1,22 @@
+use jxl_oxide::{BitWriter, BundleWrite};
+
+mod util;
+
+use util::container::CODESTREAM;
+
+#[test]
+fn header_round_trip() {
+    let image = util::read_image(CODESTREAM);
+    let image_header = image.image_header();
+    let frame = image.frame_by_keyframe(0).unwrap();
+    let frame_header = frame.header();
+
+    let mut writer = BitWriter::new();
+    image_header.write(&mut writer, ()).unwrap();
+    writer.zero_pad_to_byte();
+    frame_header.write(&mut writer, image_header).unwrap();
+    frame.toc().write(&mut writer, frame_header).unwrap();
+    let written = writer.finish();
+
+    assert_eq!(written, &CODESTREAM[..written.len()]);
+}
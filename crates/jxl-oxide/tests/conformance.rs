@@ -375,3 +375,53 @@ conformance_test! {
         0.000976562,
     ),
 }
+
+/// Returns the string value of `key` in a flat JSON object, without unescaping.
+fn json_string_value<'a>(json: &'a str, key: &str) -> Option<&'a str> {
+    let key = format!("\"{key}\"");
+    let rest = json[json.find(&key)? + key.len()..].trim_start();
+    let rest = rest.strip_prefix(':')?.trim_start().strip_prefix('"')?;
+    Some(&rest[..rest.find('"')?])
+}
+
+/// Reconstructs JPEG files from every testcase with a reconstructed JPEG reference, and compares
+/// those with the reference byte by byte.
+#[test]
+fn reconstruct_jpeg() {
+    let mut testcases = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    testcases.push("tests/conformance/testcases");
+
+    let mut num_tested = 0;
+    for entry in std::fs::read_dir(testcases).expect("conformance testcases not found") {
+        let dir = entry.unwrap().path();
+        let Ok(test_json) = std::fs::read_to_string(dir.join("test.json")) else {
+            continue;
+        };
+        let Some(reference) = json_string_value(&test_json, "reconstructed_jpeg") else {
+            continue;
+        };
+
+        let is_hash = reference.len() == 64 && reference.bytes().all(|b| b.is_ascii_hexdigit());
+        let expected = if is_hash {
+            download_object_with_cache(reference, "jpg")
+        } else {
+            std::fs::read(dir.join(reference)).expect("failed to read reference JPEG")
+        };
+
+        let name = dir.file_name().unwrap().to_string_lossy().into_owned();
+        eprintln!("Testing {name}");
+        let image = JxlImage::builder()
+            .open(util::conformance_path(&name))
+            .expect("Failed to open file");
+        let mut reconstructed = Vec::new();
+        image
+            .reconstruct_jpeg(&mut reconstructed)
+            .expect("failed to reconstruct JPEG");
+        assert!(
+            reconstructed == expected,
+            "reconstructed JPEG of {name} differs"
+        );
+        num_tested += 1;
+    }
+    assert!(num_tested > 0);
+}
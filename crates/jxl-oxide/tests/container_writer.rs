@@ -0,0 +1,45 @@
+use jxl_oxide::{ContainerBoxType, ContainerWriter, Level};
+
+mod util;
+
+use util::container::{check_metadata, CODESTREAM, EXIF_TIFF, XMP};
+
+#[test]
+fn container_writer_rewrap() {
+    let mut exif = vec![0, 0, 0, 6];
+    exif.extend_from_slice(b"Exif\x00\x00");
+    exif.extend_from_slice(EXIF_TIFF);
+
+    let mut writer = ContainerWriter::new(CODESTREAM.to_vec());
+    writer.set_level(Some(5)).unwrap();
+    writer.set_exif(Some(exif)).unwrap();
+    writer.set_xmp(Some(XMP.to_vec())).unwrap();
+    writer
+        .add_jumbf(b"first".to_vec())
+        .add_jumbf(b"second".to_vec());
+    writer.split_codestream(Some(3));
+    for ty in [b"brob", b"jbrd", b"jxli", b"jxll", b"jxlc", b"jxlp"] {
+        let ty = ContainerBoxType(*ty);
+        assert!(writer.add_box(ty, Vec::new()).is_err());
+        assert!(writer.replace_box(ty, Vec::new()).is_err());
+    }
+    let mut data = Vec::new();
+    writer.write(&mut data).unwrap();
+
+    let image = util::read_image(&data);
+    check_metadata(&image);
+    assert_eq!(image.level(), Level::Level5);
+    image.render_frame(0).unwrap();
+
+    let mut writer = ContainerWriter::from_reader(image.reader(), CODESTREAM.to_vec());
+    writer.strip_metadata().split_codestream(None);
+    let mut stripped = Vec::new();
+    writer.write(&mut stripped).unwrap();
+
+    let image = util::read_image(stripped);
+    assert!(image.raw_exif_data().unwrap().is_none());
+    assert!(image.xmp().unwrap().is_none());
+    assert_eq!(image.jumbf_boxes().count(), 0);
+    assert_eq!(image.level(), Level::Level5);
+    image.render_frame(0).unwrap();
+}
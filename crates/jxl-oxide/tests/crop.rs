@@ -181,6 +181,65 @@ testcase_with_crop! {
     ],
 }
 
+/// Reader which counts the number of bytes read.
+struct CountingReader<R> {
+    inner: R,
+    count: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+impl<R: std::io::Read> std::io::Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let count = self.inner.read(buf)?;
+        self.count
+            .fetch_add(count, std::sync::atomic::Ordering::Relaxed);
+        Ok(count)
+    }
+}
+
+impl<R: std::io::Seek> std::io::Seek for CountingReader<R> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[test]
+fn crop_seekable_progressive() {
+    let path = util::conformance_path("progressive");
+    let buf = std::fs::read(path).expect("Failed to open file");
+    let image = JxlImage::builder()
+        .read(Cursor::new(&buf))
+        .expect("Failed to open file");
+
+    let count = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let reader = CountingReader {
+        inner: Cursor::new(buf.clone()),
+        count: count.clone(),
+    };
+    let tester_image = JxlImage::builder()
+        .read_seekable(reader)
+        .expect("Failed to open file");
+
+    let crop = CropInfo {
+        width: 315,
+        height: 571,
+        left: 1711,
+        top: 800,
+    };
+    test_crop_region(
+        &image,
+        &tester_image,
+        crop,
+        "crop_seekable_progressive",
+        false,
+    );
+    let read_bytes = count.load(std::sync::atomic::Ordering::Relaxed);
+    assert!(
+        read_bytes < buf.len() / 2,
+        "read {read_bytes} of {} bytes",
+        buf.len()
+    );
+}
+
 fn write_npy(render: &Render, path: impl AsRef<std::path::Path>) {
     use std::io::prelude::*;
 
@@ -0,0 +1,126 @@
+use jxl_oxide::{DebugView, LosslessEncoder, PixelFormat};
+
+mod util;
+
+#[test]
+fn debug_view() {
+    let (width, height) = (300u32, 200u32);
+    let samples = (0..width * height)
+        .map(|i| ((i % width) ^ (i / width)) as u16 & 0xff)
+        .collect::<Vec<_>>();
+    let image = util::encode_image(
+        &LosslessEncoder::new(width, height, PixelFormat::Gray).group_size_shift(0),
+        &samples,
+    );
+
+    let fb = image
+        .render_frame_debug(0, DebugView::GroupBoundaries)
+        .unwrap();
+    assert_eq!((fb.width(), fb.height(), fb.channels()), (300, 200, 3));
+    let pixels = fb.buf_grouped::<3>();
+    // LF group boundary at the top-left corner, group boundaries every 128 pixels
+    assert_eq!(pixels[0], [1.0, 0.0, 0.0]);
+    assert_eq!(pixels[5 * 300 + 128], [0.8; 3]);
+    assert_eq!(pixels[128 * 300 + 5], [0.8; 3]);
+    assert_ne!(pixels[5 * 300 + 5], [0.8; 3]);
+
+    // Other views require VarDCT frames.
+    assert!(image
+        .render_frame_debug(0, DebugView::TransformType)
+        .is_err());
+}
+
+#[test]
+fn debug_view_vardct() {
+    let mut fixture = util::vardct::VarDctImage::sample(300, 200);
+    fixture.epf_iters = 1;
+    let image = util::read_image(fixture.encode());
+    let pixel =
+        |fb: &jxl_oxide::FrameBuffer, x: usize, y: usize| fb.buf_grouped::<3>()[y * 300 + x];
+
+    let mut hf_mul = vec![0i32; 38 * 25];
+    for block in &fixture.blocks {
+        let (w, h) = block.transform_type.dct_select_size();
+        for y in block.top..block.top + h {
+            for x in block.left..block.left + w {
+                hf_mul[y as usize * 38 + x as usize] = block.hf_mul;
+            }
+        }
+    }
+
+    // Varblocks are filled with the colour of the transform type, with darker top and left edges.
+    let fb = image
+        .render_frame_debug(0, DebugView::TransformType)
+        .unwrap();
+    assert_eq!((fb.width(), fb.height(), fb.channels()), (300, 200, 3));
+    let mut colours = std::collections::HashMap::new();
+    for block in &fixture.blocks {
+        let (x, y) = (block.left as usize * 8, block.top as usize * 8);
+        let inner = pixel(&fb, x + 1, y + 1);
+        assert_eq!(pixel(&fb, x, y), inner.map(|v| v * 0.5));
+        // Boundaries of 8x8 blocks inside a varblock are not drawn.
+        if block.transform_type.dct_select_size().0 > 1 {
+            assert_eq!(pixel(&fb, x + 8, y + 1), inner);
+        }
+        assert_eq!(*colours.entry(block.transform_type).or_insert(inner), inner);
+    }
+    assert_eq!(colours.len(), 18);
+    let distinct = colours
+        .values()
+        .map(|colour| colour.map(f32::to_bits))
+        .collect::<std::collections::HashSet<_>>();
+    assert_eq!(distinct.len(), colours.len());
+
+    // Quant multipliers of 1 to 4 are mapped to black, red, yellow and white.
+    let fb = image.render_frame_debug(0, DebugView::HfMul).unwrap();
+    assert_eq!((fb.width(), fb.height(), fb.channels()), (300, 200, 3));
+    let expected = [
+        [0.0, 0.0, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 0.0],
+        [1.0, 1.0, 1.0],
+    ];
+    for block in &fixture.blocks {
+        let (x, y) = (block.left as usize * 8, block.top as usize * 8);
+        assert_eq!(
+            pixel(&fb, x + 2, y + 2),
+            expected[block.hf_mul as usize - 1]
+        );
+    }
+
+    // EPF sigma is proportional to the sharpness LUT value and the inverse of the quant
+    // multiplier, and the largest sigma in the frame is white.
+    let fb = image.render_frame_debug(0, DebugView::EpfSigma).unwrap();
+    assert_eq!((fb.width(), fb.height(), fb.channels()), (300, 200, 3));
+    for by in 0..25 {
+        for bx in 0..38 {
+            let sharpness = *fixture.sharpness.get(bx, by).unwrap() as f32;
+            let t = sharpness / 7.0 / hf_mul[by * 38 + bx] as f32;
+            let expected = [
+                (t * 3.0).clamp(0.0, 1.0),
+                (t * 3.0 - 1.0).clamp(0.0, 1.0),
+                (t * 3.0 - 2.0).clamp(0.0, 1.0),
+            ];
+            let actual = pixel(&fb, bx * 8 + 2, by * 8 + 2);
+            for (actual, expected) in actual.into_iter().zip(expected) {
+                assert!((actual - expected).abs() < 1e-3);
+            }
+        }
+    }
+
+    // X multipliers are mapped to red with zero at mid-grey, and B multipliers are mapped to
+    // blue with one at mid-grey.
+    let fb = image
+        .render_frame_debug(0, DebugView::ChromaFromLuma)
+        .unwrap();
+    assert_eq!((fb.width(), fb.height(), fb.channels()), (300, 200, 3));
+    for ty in 0..4 {
+        for tx in 0..5 {
+            let x_factor = *fixture.x_from_y.get(tx, ty).unwrap() as f32 / 84.0;
+            let b_factor = 1.0 + *fixture.b_from_y.get(tx, ty).unwrap() as f32 / 84.0;
+            let expected = [(0.5 + x_factor).clamp(0.0, 1.0), 0.5, b_factor * 0.5];
+            assert_eq!(pixel(&fb, tx * 64 + 2, ty * 64 + 2), expected);
+            assert_eq!(pixel(&fb, tx * 64, ty * 64), expected.map(|v| v * 0.5));
+        }
+    }
+}
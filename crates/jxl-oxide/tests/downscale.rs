@@ -0,0 +1,330 @@
+use std::io::{Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+
+use jxl_frame::data::TocGroupKind;
+use jxl_oxide::{CropInfo, EncodeOptions, JxlImage, LosslessEncoder, PixelFormat};
+
+mod util;
+
+#[test]
+fn downscaled_render() {
+    let (width, height) = (300u32, 200u32);
+    let samples = (0..width * height)
+        .map(|i| ((i % width) * 3 + (i / width)) as u16 & 0xff)
+        .collect::<Vec<_>>();
+    let image = util::encode_image(
+        &LosslessEncoder::new(width, height, PixelFormat::Gray),
+        &samples,
+    );
+
+    let full = image.render_frame(0).unwrap();
+    let full = &full.color_channels()[0];
+    let render = image.render_frame_downscaled(0, 8).unwrap();
+    let grid = &render.color_channels()[0];
+    assert_eq!((grid.width(), grid.height()), (38, 25));
+
+    // Modular frames are downscaled with box filter, including partial blocks at the edges.
+    for (x, y) in [(0, 0), (10, 7), (37, 24)] {
+        let x_range = x * 8..((x + 1) * 8).min(300);
+        let y_range = y * 8..((y + 1) * 8).min(200);
+        let count = x_range.len() * y_range.len();
+        let sum = y_range
+            .flat_map(|sy| x_range.clone().map(move |sx| (sx, sy)))
+            .map(|(sx, sy)| *full.get(sx, sy).unwrap())
+            .sum::<f32>();
+        let actual = *grid.get(x, y).unwrap();
+        assert!((actual - sum / count as f32).abs() < 1e-5);
+    }
+
+    assert_eq!(image.downscaled_size(2).unwrap(), (150, 100));
+    assert_eq!(image.downscaled_size(4).unwrap(), (75, 50));
+    let quarter = image.render_frame_downscaled(0, 4).unwrap();
+    let quarter = &quarter.color_channels()[0];
+    assert_eq!((quarter.width(), quarter.height()), (75, 50));
+
+    // Crops are specified in the downscaled image.
+    let crop = CropInfo {
+        width: 20,
+        height: 10,
+        left: 10,
+        top: 5,
+    };
+    let full_crop = image.full_resolution_crop(4, crop).unwrap();
+    assert_eq!(
+        (
+            full_crop.left,
+            full_crop.top,
+            full_crop.width,
+            full_crop.height
+        ),
+        (40, 20, 80, 40)
+    );
+    let cropped = image
+        .render_frame_downscaled_cropped(0, 4, Some(crop))
+        .unwrap();
+    let cropped = &cropped.color_channels()[0];
+    assert_eq!((cropped.width(), cropped.height()), (20, 10));
+    for y in 0..10 {
+        for x in 0..20 {
+            let expected = *quarter.get(x + 10, y + 5).unwrap();
+            assert!((*cropped.get(x, y).unwrap() - expected).abs() < 1e-5);
+        }
+    }
+
+    // Crops at the edges are clipped to the image.
+    let edge_crop = CropInfo {
+        width: 10,
+        height: 10,
+        left: 70,
+        top: 45,
+    };
+    let full_crop = image.full_resolution_crop(4, edge_crop).unwrap();
+    assert_eq!(
+        (
+            full_crop.left,
+            full_crop.top,
+            full_crop.width,
+            full_crop.height
+        ),
+        (280, 180, 20, 20)
+    );
+
+    assert!(image.render_frame_downscaled(0, 3).is_err());
+    assert!(image.downscaled_size(16).is_err());
+}
+
+#[test]
+fn downscaled_render_squeeze() {
+    let (width, height) = (300u32, 260u32);
+    let samples = (0..width * height)
+        .map(|idx| {
+            let (x, y) = (idx % width, idx / width);
+            ((x + y) * 255 / (width + height)) as u8
+        })
+        .collect::<Vec<_>>();
+    let options = EncodeOptions {
+        palette: false,
+        squeeze: true,
+        ..Default::default()
+    };
+    let image = util::encode_image(
+        &LosslessEncoder::new(width, height, PixelFormat::Gray).options(options),
+        &samples,
+    );
+
+    // Squeezed images are rendered from the squeezed channels in reduced resolution, which are
+    // the averages of the full resolution samples up to rounding in each squeeze step.
+    for factor in [2u32, 4] {
+        let render = image.render_frame_downscaled(0, factor).unwrap();
+        let grid = &render.color_channels()[0];
+        let (reduced_width, reduced_height) = image.downscaled_size(factor).unwrap();
+        assert_eq!(
+            (grid.width(), grid.height()),
+            (reduced_width as usize, reduced_height as usize)
+        );
+
+        let factor = factor as usize;
+        for y in 0..grid.height() {
+            for x in 0..grid.width() {
+                let x_range = x * factor..((x + 1) * factor).min(width as usize);
+                let y_range = y * factor..((y + 1) * factor).min(height as usize);
+                let count = x_range.len() * y_range.len();
+                let sum = y_range
+                    .flat_map(|sy| x_range.clone().map(move |sx| sy * width as usize + sx))
+                    .map(|idx| samples[idx] as f32)
+                    .sum::<f32>();
+                let actual = *grid.get(x, y).unwrap() * 255.0;
+                assert!((actual - sum / count as f32).abs() < 2.0);
+            }
+        }
+    }
+}
+
+/// Maximum difference between the reduced render and the box-filtered full render, over every
+/// color channel.
+fn max_box_filter_error(
+    full: &jxl_oxide::Render,
+    reduced: &jxl_oxide::Render,
+    factor: usize,
+) -> f32 {
+    let mut max_error = 0f32;
+    for (full, reduced) in full.color_channels().iter().zip(reduced.color_channels()) {
+        let (width, height) = (full.width(), full.height());
+        assert_eq!(reduced.width(), width.div_ceil(factor));
+        assert_eq!(reduced.height(), height.div_ceil(factor));
+        for y in 0..reduced.height() {
+            for x in 0..reduced.width() {
+                let x_range = x * factor..((x + 1) * factor).min(width);
+                let y_range = y * factor..((y + 1) * factor).min(height);
+                let count = x_range.len() * y_range.len();
+                let sum = y_range
+                    .flat_map(|sy| x_range.clone().map(move |sx| (sx, sy)))
+                    .map(|(sx, sy)| *full.get(sx, sy).unwrap())
+                    .sum::<f32>();
+                let error = (*reduced.get(x, y).unwrap() - sum / count as f32).abs();
+                max_error = max_error.max(error);
+            }
+        }
+    }
+    max_error
+}
+
+#[test]
+fn downscaled_render_vardct_lf() {
+    let image = util::read_image(util::vardct::VarDctImage::sample(300, 200).encode());
+    let full = image.render_frame(0).unwrap();
+
+    // 1:8 rendering uses the LF image only, which is the average of each 8x8 block up to the
+    // nonlinear color conversion.
+    let render = image.render_frame_downscaled(0, 8).unwrap();
+    let max_error = max_box_filter_error(&full, &render, 8);
+    assert!(max_error < 0.01, "max error {max_error}");
+}
+
+/// Reader which records the byte ranges read.
+struct RecordingReader<R> {
+    inner: R,
+    reads: Arc<Mutex<Vec<Range<u64>>>>,
+}
+
+impl<R: Read + Seek> Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let start = self.inner.stream_position()?;
+        let count = self.inner.read(buf)?;
+        if count > 0 {
+            self.reads.lock().unwrap().push(start..start + count as u64);
+        }
+        Ok(count)
+    }
+}
+
+impl<R: Seek> Seek for RecordingReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+/// Returns the byte ranges of the sections of the first frame, assuming the image is a bare
+/// codestream with a single frame.
+fn section_ranges(image: &JxlImage, data_len: usize) -> Vec<(TocGroupKind, Range<u64>)> {
+    let toc = image.frame(0).unwrap().toc();
+    let frame_offset = (data_len - toc.total_byte_size()) as u64;
+    toc.iter_bitstream_order()
+        .map(|group| {
+            let start = frame_offset + group.offset as u64;
+            (group.kind, start..start + group.size as u64)
+        })
+        .collect()
+}
+
+fn overlaps(a: &Range<u64>, b: &Range<u64>) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+#[test]
+fn downscaled_render_vardct_lf_seekable() {
+    let data = util::vardct::VarDctImage::sample(1024, 512).encode();
+    let expected = util::read_image(&data)
+        .render_frame_downscaled(0, 8)
+        .unwrap();
+
+    let reads = Arc::new(Mutex::new(Vec::new()));
+    let reader = RecordingReader {
+        inner: Cursor::new(data.clone()),
+        reads: reads.clone(),
+    };
+    let image = JxlImage::builder().read_seekable(reader).unwrap();
+    reads.lock().unwrap().clear();
+
+    let render = image.render_frame_downscaled(0, 8).unwrap();
+    for (actual, expected) in render
+        .color_channels()
+        .iter()
+        .zip(expected.color_channels())
+    {
+        assert_eq!(actual.buf(), expected.buf());
+    }
+
+    let sections = section_ranges(&image, data.len());
+    let reads = reads.lock().unwrap();
+    for (kind, range) in &sections {
+        let is_read = reads.iter().any(|read| overlaps(read, range));
+        match kind {
+            TocGroupKind::LfGlobal | TocGroupKind::LfGroup(_) => {
+                assert!(is_read, "{kind:?} is not read");
+            }
+            _ => assert!(!is_read, "{kind:?} is read"),
+        }
+    }
+    drop(reads);
+
+    // Files are read in the same way.
+    let path = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("downscaled_vardct.jxl");
+    std::fs::write(&path, &data).unwrap();
+    let image = JxlImage::builder().open_seekable(&path).unwrap();
+    let render = image.render_frame_downscaled(0, 8).unwrap();
+    for (actual, expected) in render
+        .color_channels()
+        .iter()
+        .zip(expected.color_channels())
+    {
+        assert_eq!(actual.buf(), expected.buf());
+    }
+}
+
+#[test]
+fn downscaled_render_vardct_reduced() {
+    let fixture = util::vardct::VarDctImage::sample(300, 200);
+    let image = util::read_image(fixture.encode());
+    let full = image.render_frame(0).unwrap();
+
+    // Reduced IDCT drops the coefficients in the higher half of each varblock, while box filtering
+    // aliases some of them into the lower frequencies. Error is bounded by the energy of those
+    // coefficients, which is smaller for 1:4 rendering since the dropped frequencies are
+    // attenuated more.
+    for (factor, tolerance) in [(2u32, 0.03f32), (4, 0.01)] {
+        let render = image.render_frame_downscaled(0, factor).unwrap();
+        let max_error = max_box_filter_error(&full, &render, factor as usize);
+        assert!(max_error < tolerance, "max error {max_error} at 1:{factor}");
+    }
+}
+
+#[test]
+fn downscaled_render_vardct_two_passes() {
+    let mut fixture = util::vardct::VarDctImage::sample(1024, 512);
+    let single_pass = util::read_image(fixture.encode());
+    fixture.two_passes = true;
+    let data = fixture.encode();
+
+    let reads = Arc::new(Mutex::new(Vec::new()));
+    let reader = RecordingReader {
+        inner: Cursor::new(data.clone()),
+        reads: reads.clone(),
+    };
+    let image = JxlImage::builder().read_seekable(reader).unwrap();
+    reads.lock().unwrap().clear();
+
+    // The first pass has every coefficient used by 1:2 rendering, so the second pass is skipped.
+    let expected = single_pass.render_frame_downscaled(0, 2).unwrap();
+    let render = image.render_frame_downscaled(0, 2).unwrap();
+    for (actual, expected) in render
+        .color_channels()
+        .iter()
+        .zip(expected.color_channels())
+    {
+        assert_eq!(actual.buf(), expected.buf());
+    }
+
+    let sections = section_ranges(&image, data.len());
+    let reads = reads.lock().unwrap();
+    for (kind, range) in &sections {
+        let is_read = reads.iter().any(|read| overlaps(read, range));
+        match kind {
+            TocGroupKind::GroupPass { pass_idx: 1, .. } => {
+                assert!(!is_read, "{kind:?} is read");
+            }
+            _ => assert!(is_read, "{kind:?} is not read"),
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use jxl_oxide::{EncodeOptions, LosslessEncoder, PixelFormat};
+use rand::{Rng, SeedableRng};
+
+mod util;
+
+fn check_lossless_round_trip(encoder: LosslessEncoder, samples: &[u16]) {
+    let image = util::encode_image(&encoder, samples);
+    let render = image.render_frame(0).unwrap();
+    let fb = render.image();
+    assert_eq!(fb.buf().len(), samples.len());
+
+    let max = ((1u32 << image.image_header().metadata.bit_depth.bits_per_sample()) - 1) as f32;
+    for (idx, (&actual, &expected)) in fb.buf().iter().zip(samples).enumerate() {
+        let actual = (actual * max).round() as u16;
+        assert_eq!(actual, expected, "sample {idx} differs");
+    }
+}
+
+#[test]
+fn lossless_encode_round_trip() {
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(0x6a786c);
+
+    // Smooth gradient with noise, spanning multiple groups.
+    let (width, height) = (160u32, 140u32);
+    let mut samples = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let base = (x + 2 * y) / 2;
+            samples.push(((base + rng.gen_range(0..4)) % 256) as u16);
+            samples.push(((base + x / 4 + rng.gen_range(0..4)) % 256) as u16);
+            samples.push((y % 256) as u16);
+            samples.push(if x < 60 { 255 } else { rng.gen_range(0..256) });
+        }
+    }
+    let encoder = LosslessEncoder::new(width, height, PixelFormat::Rgba).group_size_shift(0);
+    check_lossless_round_trip(encoder.clone(), &samples);
+    let options = EncodeOptions {
+        use_prefix_code: true,
+        ..Default::default()
+    };
+    check_lossless_round_trip(encoder.clone().options(options), &samples);
+    let options = EncodeOptions {
+        squeeze: true,
+        ..Default::default()
+    };
+    check_lossless_round_trip(encoder.options(options), &samples);
+
+    // Few colours, which are encoded with palette.
+    let colours = [[0u16, 0, 0], [255, 0, 0], [0, 128, 255], [255, 255, 255]];
+    let samples = (0..96 * 64)
+        .flat_map(|idx| colours[(idx / 7 + idx / 96) % 4])
+        .collect::<Vec<_>>();
+    check_lossless_round_trip(LosslessEncoder::new(96, 64, PixelFormat::Rgb), &samples);
+
+    // High bit depth grayscale.
+    let samples = (0..100 * 80)
+        .map(|idx| ((idx % 100) * 600 + (idx / 100) * 17 + rng.gen_range(0..64)) as u16)
+        .collect::<Vec<_>>();
+    let encoder = LosslessEncoder::new(100, 80, PixelFormat::Gray).bits_per_sample(16);
+    check_lossless_round_trip(encoder, &samples);
+}
@@ -0,0 +1,30 @@
+mod util;
+
+use util::container::CODESTREAM;
+
+#[test]
+fn entropy_stats() {
+    let image = util::read_image(CODESTREAM);
+    let frame = image.frame(0).unwrap();
+    assert!(frame.entropy_stats().is_none());
+    frame.enable_entropy_stats();
+    image.render_frame(0).unwrap();
+
+    let stats = frame.entropy_stats().unwrap();
+    assert!(!stats.is_empty());
+    let num_values = stats
+        .values()
+        .flat_map(|group| &group.decoders)
+        .map(|decoder| decoder.total().num_values)
+        .sum::<u64>();
+    assert!(num_values > 0);
+    for group in stats.values() {
+        let num_bits = group.num_bits.unwrap();
+        let decoded_bits = group
+            .decoders
+            .iter()
+            .map(|decoder| decoder.total().num_bits)
+            .sum::<u64>();
+        assert!(decoded_bits <= num_bits);
+    }
+}
@@ -0,0 +1,54 @@
+use jxl_frame::data::TocGroupKind;
+use jxl_oxide::JxlImage;
+
+mod util;
+
+/// Overwrites the data of the TOC group with garbage, and returns the codestream bit range of
+/// the group.
+fn corrupt_group(data: &mut [u8], kind: TocGroupKind) -> std::ops::Range<u64> {
+    let image = util::read_image(&*data);
+    let frame_offset = image.frame_offset(0).unwrap();
+    let group = image
+        .frame(0)
+        .unwrap()
+        .toc()
+        .iter_bitstream_order()
+        .find(|group| group.kind == kind)
+        .unwrap();
+
+    let start = frame_offset + group.offset;
+    let end = start + group.size as usize;
+    for (idx, byte) in data[start..end].iter_mut().enumerate() {
+        *byte = (idx as u8).wrapping_mul(0x9d) ^ 0x5a;
+    }
+    start as u64 * 8..end as u64 * 8
+}
+
+fn render_error(data: Vec<u8>) -> jxl_render::Error {
+    let image = JxlImage::builder()
+        .read(std::io::Cursor::new(data))
+        .unwrap();
+    let err = image.render_frame(0).unwrap_err();
+    *err.downcast::<jxl_render::Error>().unwrap()
+}
+
+#[test]
+fn corrupted_group_location() {
+    let data = util::vardct::VarDctImage::sample(512, 512).encode();
+
+    for kind in [
+        TocGroupKind::GroupPass {
+            pass_idx: 0,
+            group_idx: 1,
+        },
+        TocGroupKind::LfGroup(0),
+    ] {
+        let mut data = data.clone();
+        let range = corrupt_group(&mut data, kind);
+
+        let err = render_error(data);
+        assert_eq!(err.group(), Some(kind), "{err}");
+        let bit_offset = err.location().unwrap().bit_offset().unwrap();
+        assert!(range.contains(&bit_offset), "{bit_offset} not in {range:?}");
+    }
+}
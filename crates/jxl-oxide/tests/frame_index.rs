@@ -0,0 +1,165 @@
+use jxl_frame::data::Toc;
+use jxl_oxide::image::AnimationHeader;
+use jxl_oxide::{BitWriter, BundleWrite, FrameHeader, JxlImage, LosslessEncoder, PixelFormat};
+
+mod util;
+
+use util::container::{container_header, push_box, CODESTREAM};
+
+#[test]
+fn frame_index_seeking() {
+    let first_frame_offset = util::read_image(CODESTREAM).frame_offset(0).unwrap();
+
+    let mut jxli = vec![1];
+    jxli.extend_from_slice(&1u32.to_be_bytes());
+    jxli.extend_from_slice(&1000u32.to_be_bytes());
+    jxli.extend_from_slice(&[first_frame_offset as u8, 0, 1]);
+
+    let mut data = container_header();
+    push_box(&mut data, b"jxli", &jxli);
+    let (first, second) = CODESTREAM.split_at(first_frame_offset + 3);
+    push_box(&mut data, b"jxlp", &[&[0, 0, 0, 0][..], first].concat());
+    push_box(&mut data, b"jxlp", &[&[0x80, 0, 0, 1][..], second].concat());
+
+    let expected = util::read_image(CODESTREAM).render_frame(0).unwrap();
+    let image = JxlImage::builder()
+        .read_at_keyframe(std::io::Cursor::new(data), 0)
+        .unwrap();
+
+    let frame_index = image.frame_index().unwrap().unwrap();
+    assert_eq!(frame_index.tden(), 1000);
+    assert_eq!(frame_index.entries().len(), 1);
+    assert_eq!(
+        frame_index.entries()[0].codestream_offset(),
+        first_frame_offset as u64
+    );
+
+    let render = image.render_frame(0).unwrap();
+    assert_eq!(
+        render.image_all_channels().buf(),
+        expected.image_all_channels().buf()
+    );
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+/// Writes an animation of keyframes with the given RGB samples, each lasting a tick.
+fn encode_animation(width: u32, height: u32, frames: &[Vec<u8>]) -> Vec<u8> {
+    let encoder = LosslessEncoder::new(width, height, PixelFormat::Rgb);
+    let mut image_header = None;
+    let mut out = Vec::new();
+    for (idx, samples) in frames.iter().enumerate() {
+        let codestream = encoder.encode(samples).unwrap();
+        let image = util::read_image(&codestream);
+        let image_header = image_header.get_or_insert_with(|| {
+            let mut image_header = image.image_header().clone();
+            image_header
+                .metadata
+                .set_animation(Some(AnimationHeader {
+                    tps_numerator: 1000,
+                    tps_denominator: 1,
+                    num_loops: 0,
+                    have_timecodes: false,
+                }))
+                .unwrap();
+            let mut writer = BitWriter::new();
+            image_header.write(&mut writer, ()).unwrap();
+            writer.zero_pad_to_byte();
+            out = writer.finish();
+            image_header
+        });
+
+        // Frame data of the single-group image is reused, with a new frame header and TOC.
+        let frame_size = image.frame(0).unwrap().toc().total_byte_size();
+        let mut frame_header = FrameHeader::lossless_modular(image_header);
+        frame_header.duration = 1;
+        frame_header.is_last = idx == frames.len() - 1;
+        let toc = Toc::from_sizes(&frame_header, &[frame_size as u32]).unwrap();
+        let mut writer = BitWriter::new();
+        frame_header.write(&mut writer, image_header).unwrap();
+        toc.write(&mut writer, &frame_header).unwrap();
+        out.extend(writer.finish());
+        out.extend_from_slice(&codestream[codestream.len() - frame_size..]);
+    }
+    out
+}
+
+#[test]
+fn frame_index_seeking_animation() {
+    const NUM_FRAMES: usize = 7;
+    const INDEXED: [usize; 3] = [0, 3, 5];
+
+    let frames = (0..NUM_FRAMES)
+        .map(|idx| {
+            // Noisy samples, so that each frame is larger than a single read from the reader.
+            (0..64 * 64 * 3u64)
+                .map(|i| {
+                    ((i + idx as u64 * 0x10000).wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 56) as u8
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let codestream = encode_animation(64, 64, &frames);
+    let full = util::read_image(&codestream);
+    assert_eq!(full.num_loaded_keyframes(), NUM_FRAMES);
+
+    let mut jxli = Vec::new();
+    write_varint(&mut jxli, INDEXED.len() as u64);
+    jxli.extend_from_slice(&1u32.to_be_bytes());
+    jxli.extend_from_slice(&1000u32.to_be_bytes());
+    let mut prev_offset = 0;
+    for (idx, &keyframe_idx) in INDEXED.iter().enumerate() {
+        let offset = full.frame_offset(keyframe_idx).unwrap();
+        let next_keyframe_idx = INDEXED.get(idx + 1).copied().unwrap_or(NUM_FRAMES);
+        let num_frames = (next_keyframe_idx - keyframe_idx) as u64;
+        write_varint(&mut jxli, (offset - prev_offset) as u64);
+        write_varint(&mut jxli, num_frames);
+        write_varint(&mut jxli, num_frames);
+        prev_offset = offset;
+    }
+
+    let mut data = container_header();
+    push_box(&mut data, b"jxli", &jxli);
+    push_box(&mut data, b"jxlc", &codestream);
+
+    for keyframe_idx in 0..NUM_FRAMES {
+        let image = JxlImage::builder()
+            .read_at_keyframe(std::io::Cursor::new(&data), keyframe_idx)
+            .unwrap();
+
+        let frame_index = image.frame_index().unwrap().unwrap();
+        assert_eq!(frame_index.entries().len(), INDEXED.len());
+        for (entry, &indexed) in frame_index.entries().iter().zip(&INDEXED) {
+            assert_eq!(
+                entry.codestream_offset(),
+                full.frame_offset(indexed).unwrap() as u64
+            );
+            assert_eq!(entry.keyframe_offset(), indexed as u64);
+            assert_eq!(entry.start_ticks(), indexed as u64);
+        }
+
+        // Loading starts from the nearest indexed keyframe, and frame indices count from there.
+        let nearest = INDEXED
+            .into_iter()
+            .rfind(|&indexed| indexed <= keyframe_idx)
+            .unwrap();
+        assert_eq!(image.frame_offset(0), full.frame_offset(nearest));
+        if nearest > 0 {
+            assert!(image.frame_by_keyframe(nearest - 1).is_none());
+        }
+
+        let render = image.render_frame(keyframe_idx).unwrap();
+        let expected = full.render_frame(keyframe_idx).unwrap();
+        assert_eq!(render.keyframe_index(), keyframe_idx);
+        assert_eq!(
+            render.image_all_channels().buf(),
+            expected.image_all_channels().buf()
+        );
+    }
+}
@@ -0,0 +1,70 @@
+use jxl_oxide::HeaderEditor;
+
+mod util;
+
+use util::container::CODESTREAM;
+use util::jpeg::JpegImage;
+
+#[test]
+fn header_edit() {
+    let image = util::read_image(CODESTREAM);
+    let expected = image.render_frame(0).unwrap();
+    let icc = image.rendered_icc();
+
+    let mut editor = HeaderEditor::read(std::io::Cursor::new(CODESTREAM)).unwrap();
+    editor
+        .set_orientation(6)
+        .unwrap()
+        .set_intensity_target(1000.0)
+        .unwrap()
+        .set_icc_profile(icc.clone())
+        .unwrap();
+    assert!(editor.set_num_loops(1).is_err());
+    let mut edited = Vec::new();
+    editor.write(&mut edited).unwrap();
+
+    let image = util::read_image(&edited);
+    let metadata = &image.image_header().metadata;
+    assert_eq!(metadata.orientation, 6);
+    assert_eq!(metadata.tone_mapping.intensity_target, 1000.0);
+    assert_eq!(image.original_icc(), Some(&*icc));
+    let render = image.render_frame(0).unwrap();
+    for (actual, expected) in render
+        .color_channels()
+        .iter()
+        .zip(expected.color_channels())
+    {
+        assert_eq!(actual.buf(), expected.buf());
+    }
+
+    // Embedded ICC profile is copied as-is.
+    let editor = HeaderEditor::read(std::io::Cursor::new(&edited)).unwrap();
+    assert_eq!(editor.codestream().unwrap(), edited);
+}
+
+#[test]
+fn header_edit_jpeg_reconstruction() {
+    let jpeg = JpegImage::sample(64, 64, false).with_sample_metadata();
+    let jxl = jpeg.encode_jxl();
+
+    // Reconstruction data is kept if the colour encoding is not changed.
+    let mut editor = HeaderEditor::read(std::io::Cursor::new(&jxl)).unwrap();
+    editor.set_orientation(6).unwrap();
+    let mut edited = Vec::new();
+    editor.write(&mut edited).unwrap();
+    let mut reconstructed = Vec::new();
+    util::read_image(&edited)
+        .reconstruct_jpeg(&mut reconstructed)
+        .unwrap();
+    assert!(reconstructed == jpeg.encode_jpeg());
+
+    // The original JPEG cannot be reconstructed with another ICC profile.
+    let icc = util::read_image(CODESTREAM).rendered_icc();
+    let mut editor = HeaderEditor::read(std::io::Cursor::new(&jxl)).unwrap();
+    editor.set_icc_profile(icc).unwrap();
+    let mut edited = Vec::new();
+    editor.write(&mut edited).unwrap();
+    let image = util::read_image(&edited);
+    assert!(!image.has_jpeg_reconstruction_data());
+    image.render_frame(0).unwrap();
+}
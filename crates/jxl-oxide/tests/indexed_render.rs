@@ -0,0 +1,41 @@
+use jxl_oxide::{EncodeOptions, LosslessEncoder, PixelFormat};
+
+mod util;
+
+#[test]
+fn indexed_render() {
+    let colours = [
+        [0u8, 0, 0, 255],
+        [255, 0, 0, 255],
+        [0, 128, 255, 128],
+        [255, 255, 255, 0],
+    ];
+    let (width, height) = (96u32, 64u32);
+    let samples = (0..(width * height) as usize)
+        .flat_map(|idx| colours[(idx / 7 + idx / 96) % 4])
+        .collect::<Vec<_>>();
+    let encoder = LosslessEncoder::new(width, height, PixelFormat::Rgba);
+    let image = util::encode_image(&encoder, &samples);
+    let render = image.render_frame_indexed(0).unwrap();
+    assert_eq!(render.num_color_channels(), 3);
+    assert_eq!(render.num_palette_extra_channels(), 1);
+    assert!(render.extra_channels().is_empty());
+    assert!(render.is_plain_indexed());
+
+    let palette = render.palette();
+    assert_eq!(palette.num_colours(), 4);
+    let indices = palette.indices();
+    assert_eq!((indices.width(), indices.height()), (96, 64));
+    for (&index, expected) in indices.buf().iter().zip(samples.chunks_exact(4)) {
+        let entry = palette.entry(index).unwrap();
+        assert!(entry.iter().zip(expected).all(|(&a, &b)| a == b as i32));
+    }
+
+    // Images without palette are rejected.
+    let options = EncodeOptions {
+        palette: false,
+        ..Default::default()
+    };
+    let image = util::encode_image(&encoder.options(options), &samples);
+    assert!(image.render_frame_indexed(0).is_err());
+}
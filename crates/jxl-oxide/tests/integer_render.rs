@@ -0,0 +1,32 @@
+use jxl_oxide::{LosslessEncoder, PixelFormat};
+use rand::{Rng, SeedableRng};
+
+mod util;
+
+#[test]
+fn integer_render() {
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(0x696e74);
+
+    let (width, height) = (150u32, 90u32);
+    let samples = (0..width * height * 2)
+        .map(|_| rng.gen_range(0..=u16::MAX))
+        .collect::<Vec<_>>();
+    let image = util::encode_image(
+        &LosslessEncoder::new(width, height, PixelFormat::Graya)
+            .bits_per_sample(16)
+            .group_size_shift(0),
+        &samples,
+    );
+    let render = image.render_frame_integer(0).unwrap();
+    assert_eq!(render.bits_per_sample(), 16);
+    assert_eq!(render.color_channels().len(), 1);
+    assert_eq!(render.extra_channels().len(), 1);
+    assert_eq!(render.extra_channels()[0].bits_per_sample(), 16);
+
+    let channels = render.into_channels();
+    for (c, channel) in channels.iter().enumerate() {
+        assert_eq!((channel.width(), channel.height()), (150, 90));
+        let expected = samples[c..].iter().step_by(2).map(|&s| s as i32);
+        assert!(channel.buf().iter().copied().eq(expected));
+    }
+}
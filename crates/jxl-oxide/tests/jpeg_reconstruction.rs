@@ -0,0 +1,122 @@
+use jxl_frame::data::Toc;
+use jxl_oxide::image::AnimationHeader;
+use jxl_oxide::{
+    AllocTracker, BitWriter, BundleWrite, ContainerWriter, JpegBitstreamData, JxlImage,
+};
+
+mod util;
+
+use util::jpeg::JpegImage;
+
+fn reconstruct(jxl: &[u8]) -> Vec<u8> {
+    let image = util::read_image(jxl);
+    let mut out = Vec::new();
+    image.reconstruct_jpeg(&mut out).unwrap();
+    out
+}
+
+#[test]
+fn reconstruct_jpeg_420_with_metadata() {
+    let mut jpeg = JpegImage::sample(490, 300, true).with_sample_metadata();
+    jpeg.restart_interval = 7;
+    let expected = jpeg.encode_jpeg();
+
+    let reconstructed = reconstruct(&jpeg.encode_jxl());
+    assert_eq!(reconstructed.len(), expected.len());
+    assert!(reconstructed == expected, "reconstructed JPEG differs");
+}
+
+#[test]
+fn reconstruct_jpeg_444() {
+    let jpeg = JpegImage::sample(100, 75, false);
+    let expected = jpeg.encode_jpeg();
+
+    let reconstructed = reconstruct(&jpeg.encode_jxl());
+    assert_eq!(reconstructed.len(), expected.len());
+    assert!(reconstructed == expected, "reconstructed JPEG differs");
+}
+
+/// Rewrites the single-frame codestream into an animation which repeats the frame.
+fn repeat_frame(codestream: &[u8], num_frames: usize) -> Vec<u8> {
+    let image = util::read_image(codestream);
+    let mut image_header = image.image_header().clone();
+    image_header
+        .metadata
+        .set_animation(Some(AnimationHeader {
+            tps_numerator: 1000,
+            tps_denominator: 1,
+            num_loops: 0,
+            have_timecodes: false,
+        }))
+        .unwrap();
+    let mut writer = BitWriter::new();
+    image_header.write(&mut writer, ()).unwrap();
+    writer.zero_pad_to_byte();
+    let mut out = writer.finish();
+
+    // The TOC of the frame is not permuted.
+    let frame = image.frame(0).unwrap();
+    let sizes = frame
+        .toc()
+        .iter_bitstream_order()
+        .map(|group| group.size)
+        .collect::<Vec<_>>();
+    let frame_size = frame.toc().total_byte_size();
+    for idx in 0..num_frames {
+        let mut frame_header = frame.header().clone();
+        frame_header.duration = 1;
+        frame_header.is_last = idx == num_frames - 1;
+        let toc = Toc::from_sizes(&frame_header, &sizes).unwrap();
+        let mut writer = BitWriter::new();
+        frame_header.write(&mut writer, &image_header).unwrap();
+        toc.write(&mut writer, &frame_header).unwrap();
+        out.extend(writer.finish());
+        out.extend_from_slice(&codestream[codestream.len() - frame_size..]);
+    }
+    out
+}
+
+#[test]
+fn reconstruct_jpeg_requires_single_keyframe() {
+    let jpeg = JpegImage::sample(100, 75, false);
+    let codestream = repeat_frame(&jpeg.encode_codestream(), 2);
+    let mut container = ContainerWriter::new(codestream);
+    container.set_jpeg_reconstruction(Some(jpeg.jbrd()));
+    let mut jxl = Vec::new();
+    container.write(&mut jxl).unwrap();
+
+    let image = util::read_image(&jxl);
+    assert_eq!(image.num_loaded_keyframes(), 2);
+    image.render_frame(1).unwrap();
+    assert!(image.reconstruct_jpeg(Vec::new()).is_err());
+}
+
+#[test]
+fn jbrd_buffers_are_tracked() {
+    let jpeg = JpegImage::sample(64, 64, false).with_sample_metadata();
+    let jbrd = jpeg.jbrd();
+    let size = jpeg.jbrd_buffer_size();
+
+    let tracker = AllocTracker::with_limit(size - 1);
+    assert!(JpegBitstreamData::parse(&jbrd, Some(&tracker)).is_err());
+
+    let tracker = AllocTracker::with_limit(size);
+    let data = JpegBitstreamData::parse(&jbrd, Some(&tracker)).unwrap();
+    assert!(tracker.alloc::<u8>(1).is_err());
+    drop(data);
+    assert!(tracker.alloc::<u8>(size).is_ok());
+}
+
+#[test]
+fn reconstruct_jpeg_with_tracker() {
+    let jpeg = JpegImage::sample(64, 64, false).with_sample_metadata();
+    let jxl = jpeg.encode_jxl();
+
+    let image = JxlImage::builder()
+        .alloc_tracker(AllocTracker::with_limit(1 << 20))
+        .read(std::io::Cursor::new(&jxl))
+        .unwrap();
+    let mut out = Vec::new();
+    image.reconstruct_jpeg(&mut out).unwrap();
+    assert!(out == jpeg.encode_jpeg());
+}
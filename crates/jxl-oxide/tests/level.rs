@@ -0,0 +1,34 @@
+use jxl_oxide::{JxlImage, Level};
+
+mod util;
+
+use util::container::{container_header, push_box, CODESTREAM};
+
+fn build_container_with_level(level: u8) -> Vec<u8> {
+    let mut data = container_header();
+    push_box(&mut data, b"jxll", &[level]);
+    push_box(&mut data, b"jxlc", CODESTREAM);
+    data
+}
+
+#[test]
+fn codestream_level() {
+    let image = util::read_image(CODESTREAM);
+    assert_eq!(image.level(), Level::Level5);
+
+    let image = JxlImage::builder()
+        .enforce_level(true)
+        .read(std::io::Cursor::new(build_container_with_level(10)))
+        .unwrap();
+    assert_eq!(image.level(), Level::Level10);
+    image.render_frame(0).unwrap();
+
+    let image = util::read_image(build_container_with_level(7));
+    assert_eq!(image.level(), Level::Level5);
+    image.render_frame(0).unwrap();
+
+    let result = JxlImage::builder()
+        .enforce_level(true)
+        .read(std::io::Cursor::new(build_container_with_level(7)));
+    assert!(result.is_err());
+}
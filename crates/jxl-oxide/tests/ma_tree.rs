@@ -0,0 +1,35 @@
+use jxl_oxide::{LosslessEncoder, MaNode, PixelFormat};
+
+mod util;
+
+#[test]
+fn ma_tree_capture() {
+    let samples = (0..64 * 48 * 3)
+        .map(|idx| ((idx / 3 % 64) * 3 + (idx / 192) * 2 + idx % 3 * 40) as u8)
+        .collect::<Vec<_>>();
+    let image = util::encode_image(&LosslessEncoder::new(64, 48, PixelFormat::Rgb), &samples);
+    let frame = image.frame(0).unwrap();
+    assert!(frame.ma_trees().is_none());
+    frame.enable_ma_tree_capture();
+    image.render_frame(0).unwrap();
+
+    let trees = frame.ma_trees().unwrap();
+    assert_eq!(trees.len(), 1);
+    let tree = &trees.values().next().unwrap()[0];
+    let nodes = tree.nodes().collect::<Vec<_>>();
+    assert_eq!(nodes.len(), tree.num_tree_nodes());
+    let mut num_leaves = 0;
+    for (idx, node) in nodes.iter().enumerate() {
+        match **node {
+            MaNode::Decision { left, right, .. } => {
+                assert!(left > idx && right == left + 1 && right < nodes.len());
+            }
+            MaNode::Leaf { ctx, .. } => {
+                assert_eq!(ctx, num_leaves);
+                num_leaves += 1;
+            }
+        }
+    }
+    assert!(tree.to_dot().starts_with("digraph MaTree {"));
+    assert!(tree.to_json().contains("\"type\":\"leaf\""));
+}
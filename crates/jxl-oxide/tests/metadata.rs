@@ -0,0 +1,76 @@
+use jxl_oxide::{AllocTracker, JxlImage};
+
+mod util;
+
+use util::container::{
+    build_container, check_metadata, container_header, push_box, push_brob, CODESTREAM,
+};
+
+#[test]
+fn metadata_before_codestream() {
+    let data = build_container(false, false);
+    let image = util::read_image(data);
+    check_metadata(&image);
+}
+
+#[test]
+fn metadata_after_codestream() {
+    let data = build_container(true, false);
+    let image = util::read_image(data);
+    check_metadata(&image);
+}
+
+#[test]
+fn bare_codestream_has_no_metadata() {
+    let image = util::read_image(CODESTREAM);
+    assert!(image.raw_exif_data().unwrap().is_none());
+    assert!(image.xmp().unwrap().is_none());
+    assert_eq!(image.jumbf_boxes().count(), 0);
+}
+
+#[test]
+fn brotli_compressed_metadata() {
+    let data = build_container(false, true);
+    let image = util::read_image(data);
+    check_metadata(&image);
+
+    let aux_boxes = image.reader().aux_boxes();
+    assert!(aux_boxes[1].is_brotli_compressed());
+    assert!(!aux_boxes[3].is_brotli_compressed());
+}
+
+#[test]
+fn brotli_bomb() {
+    let mut data = container_header();
+    push_brob(&mut data, b"xml ", &vec![b' '; 16 * 1024 * 1024]);
+    push_box(&mut data, b"jxlc", CODESTREAM);
+
+    // Boxes are decompressed on access, so only the accessor fails.
+    let image = JxlImage::builder()
+        .alloc_tracker(AllocTracker::with_limit(4 * 1024 * 1024))
+        .read(std::io::Cursor::new(data))
+        .unwrap();
+    image.render_frame(0).unwrap();
+    assert!(image.xmp().is_err());
+}
+
+#[test]
+fn brotli_compressed_jpeg_reconstruction() {
+    let mut data = container_header();
+    push_brob(&mut data, b"jbrd", &[0]);
+    // Corrupt Brotli stream.
+    push_box(&mut data, b"brob", b"Exif\xff\xff\xff\xff");
+    push_box(&mut data, b"jxlc", CODESTREAM);
+
+    let image = util::read_image(data);
+    let expected = util::read_image(CODESTREAM).render_frame(0).unwrap();
+    let render = image.render_frame(0).unwrap();
+    assert_eq!(
+        render.image_all_channels().buf(),
+        expected.image_all_channels().buf()
+    );
+
+    assert!(image.has_jpeg_reconstruction_data());
+    assert!(image.reconstruct_jpeg(Vec::new()).is_err());
+    assert!(image.raw_exif_data().is_err());
+}
@@ -0,0 +1,60 @@
+use jxl_oxide::{EncodeOptions, InitializeResult, JxlImage, LosslessEncoder, PixelFormat};
+use rand::{Rng, SeedableRng};
+
+mod util;
+
+#[test]
+fn squeeze_preview() {
+    let mut rng = rand::rngs::SmallRng::seed_from_u64(0x737173);
+
+    let (width, height) = (300u32, 260u32);
+    let samples = (0..width * height)
+        .map(|idx| {
+            let (x, y) = (idx % width, idx / width);
+            ((x + y) * 255 / (width + height) + rng.gen_range(0..2)) as u8
+        })
+        .collect::<Vec<_>>();
+    let options = EncodeOptions {
+        palette: false,
+        squeeze: true,
+        ..Default::default()
+    };
+    let data = LosslessEncoder::new(width, height, PixelFormat::Gray)
+        .group_size_shift(0)
+        .options(options)
+        .encode(&samples)
+        .unwrap();
+
+    // Cut the codestream before the last pass group.
+    let image = util::read_image(&data);
+    let frame_offset = image.frame_offset(0).unwrap();
+    let last_group = image.frame(0).unwrap().toc().iter_bitstream_order().last();
+    let len = frame_offset + last_group.unwrap().offset;
+
+    let mut uninit = JxlImage::builder().build_uninit();
+    uninit.feed_bytes(&data[..len]).unwrap();
+    let InitializeResult::Initialized(mut image) = uninit.try_init().unwrap() else {
+        panic!("image header should be loaded");
+    };
+    let render = image.render_loading_frame().unwrap();
+    let fb = render.image();
+    assert_eq!((fb.width(), fb.height()), (300, 260));
+
+    // Residuals of the finest squeeze steps are missing, so samples are upsampled with nearest
+    // neighbor.
+    let buf = fb.buf();
+    let width = width as usize;
+    for y in (0..260).step_by(2) {
+        for x in (0..300).step_by(2) {
+            let base = buf[y * width + x];
+            assert_eq!(buf[y * width + x + 1], base);
+            assert_eq!(buf[(y + 1) * width + x], base);
+        }
+    }
+    let total_error = buf
+        .iter()
+        .zip(&samples)
+        .map(|(&actual, &expected)| (actual * 255.0 - expected as f32).abs())
+        .sum::<f32>();
+    assert!(total_error / (buf.len() as f32) < 2.0);
+}
@@ -0,0 +1,95 @@
+use std::io::Cursor;
+
+use jxl_oxide::{AllocTracker, CropInfo, EncodeOptions, JxlImage, LosslessEncoder, PixelFormat};
+
+mod util;
+
+use util::container::{build_container, check_metadata, CODESTREAM};
+
+/// Returns the number of bytes allocated through `tracker`, which has the limit of `limit`.
+fn allocated_bytes(tracker: &AllocTracker, limit: usize) -> usize {
+    let (mut lo, mut hi) = (0usize, limit);
+    while lo < hi {
+        let mid = lo + (hi - lo).div_ceil(2);
+        if tracker.alloc::<u8>(mid).is_ok() {
+            lo = mid;
+        } else {
+            hi = mid - 1;
+        }
+    }
+    limit - lo
+}
+
+#[test]
+fn seekable_memory_is_bounded() {
+    const WIDTH: u32 = 1024;
+    const HEIGHT: u32 = 512;
+    const LIMIT: usize = 256 << 20;
+
+    let mut state = 1u32;
+    let samples = (0..WIDTH * HEIGHT * 3)
+        .map(|_| {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            (state >> 24) as u8
+        })
+        .collect::<Vec<_>>();
+    let options = EncodeOptions {
+        max_tree_leaves: 1,
+        ..Default::default()
+    };
+    let data = LosslessEncoder::new(WIDTH, HEIGHT, PixelFormat::Rgb)
+        .options(options)
+        .encode(&samples)
+        .unwrap();
+
+    let tracker = AllocTracker::with_limit(LIMIT);
+    let image = JxlImage::builder()
+        .alloc_tracker(tracker.clone())
+        .read_seekable(Cursor::new(data.clone()))
+        .unwrap();
+    assert_eq!(allocated_bytes(&tracker, LIMIT), 0);
+
+    // Group data is read for each decode and freed afterwards, so that only rendered regions,
+    // which are cached by the renderer, stay in memory while panning across the image.
+    let render_bytes = 256 * 256 * 3 * std::mem::size_of::<f32>();
+    let mut num_renders = 0;
+    for top in (0..HEIGHT).step_by(256) {
+        for left in (0..WIDTH).step_by(256) {
+            let crop = CropInfo {
+                width: 256,
+                height: 256,
+                left,
+                top,
+            };
+            image.render_frame_cropped(0, Some(crop)).unwrap();
+            num_renders += 1;
+
+            let allocated = allocated_bytes(&tracker, LIMIT);
+            assert!(
+                allocated < num_renders * (render_bytes + 1024),
+                "{allocated} bytes allocated after {num_renders} renders"
+            );
+        }
+    }
+}
+
+#[test]
+fn seekable_read() {
+    let image = util::read_image(CODESTREAM);
+    let expected = image.render_frame(0).unwrap();
+
+    let data = build_container(true, false);
+    let image = JxlImage::builder()
+        .read_seekable(Cursor::new(data))
+        .unwrap();
+    check_metadata(&image);
+    assert!(image.is_loading_done());
+    let render = image.render_frame(0).unwrap();
+    for (actual, expected) in render
+        .color_channels()
+        .iter()
+        .zip(expected.color_channels())
+    {
+        assert_eq!(actual.buf(), expected.buf());
+    }
+}
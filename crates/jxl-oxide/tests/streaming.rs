@@ -0,0 +1,94 @@
+use jxl_oxide::{EncodeOptions, LosslessEncoder, PixelFormat};
+
+mod util;
+
+#[test]
+fn streaming_render() {
+    let (width, height) = (300u32, 200u32);
+    let samples = (0..width * height * 2)
+        .map(|i| {
+            let (x, y) = ((i / 2) % width, (i / 2) / width);
+            if i % 2 == 0 {
+                ((x * 5 + y * 3) & 0xff) as u16
+            } else {
+                ((x + y) & 0xff) as u16
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Squeezed images are rendered in full, then split into bands.
+    for squeeze in [false, true] {
+        let options = EncodeOptions {
+            palette: false,
+            squeeze,
+            ..Default::default()
+        };
+        let image = util::encode_image(
+            &LosslessEncoder::new(width, height, PixelFormat::Graya)
+                .group_size_shift(0)
+                .options(options),
+            &samples,
+        );
+        let render = image.render_frame(0).unwrap();
+        let gray = render.color_channels()[0].buf();
+        let alpha = render.extra_channels()[0].grid().buf();
+
+        // Bands have the height of a group, 128 pixels.
+        let mut next_row = 0u32;
+        image
+            .render_frame_streaming(0, |rows, channels| {
+                assert_eq!(rows.start, next_row);
+                assert_eq!(rows.end, (rows.start + 128).min(height));
+                assert_eq!(channels.len(), 2);
+
+                let range =
+                    rows.start as usize * width as usize..rows.end as usize * width as usize;
+                assert_eq!(channels[0], &gray[range.clone()]);
+                assert_eq!(channels[1], &alpha[range]);
+                next_row = rows.end;
+            })
+            .unwrap();
+        assert_eq!(next_row, height);
+    }
+}
+
+#[test]
+fn streaming_render_vardct() {
+    let (width, height) = (300u32, 600u32);
+
+    // Restoration filters read rows across band boundaries, and orientation is applied by the
+    // consumer.
+    for (gabor, epf_iters, orientation) in [(true, 3, 1), (false, 1, 1), (true, 2, 6)] {
+        let mut fixture = util::vardct::VarDctImage::sample(width, height);
+        fixture.gabor = gabor;
+        fixture.epf_iters = epf_iters;
+        fixture.orientation = orientation;
+        let image = util::read_image(fixture.encode());
+        assert_eq!(image.image_header().metadata.orientation, orientation);
+
+        let render = image.render_frame(0).unwrap();
+        let color_channels = render.color_channels();
+        assert_eq!(color_channels.len(), 3);
+        for grid in color_channels {
+            assert_eq!((grid.width(), grid.height()), (300, 600));
+        }
+
+        // Bands have the height of a VarDCT group, 256 pixels.
+        let mut next_row = 0u32;
+        image
+            .render_frame_streaming(0, |rows, channels| {
+                assert_eq!(rows.start, next_row);
+                assert_eq!(rows.end, (rows.start + 256).min(height));
+                assert_eq!(channels.len(), 3);
+
+                let range =
+                    rows.start as usize * width as usize..rows.end as usize * width as usize;
+                for (band, grid) in channels.iter().zip(color_channels) {
+                    assert_eq!(*band, &grid.buf()[range.clone()]);
+                }
+                next_row = rows.end;
+            })
+            .unwrap();
+        assert_eq!(next_row, height);
+    }
+}
@@ -0,0 +1,80 @@
+//! Sample codestream and helpers for writing container boxes around it.
+
+use std::io::Write;
+
+use jxl_oxide::JxlImage;
+
+/// 8x8 image from the crate-level documentation.
+pub const CODESTREAM: &[u8] = &[
+    0xff, 0x0a, 0x30, 0x54, 0x10, 0x09, 0x08, 0x06, 0x01, 0x00, 0x78, 0x00, 0x4b, 0x38, 0x41, 0x3c,
+    0xb6, 0x3a, 0x51, 0xfe, 0x00, 0x47, 0x1e, 0xa0, 0x85, 0xb8, 0x27, 0x1a, 0x48, 0x45, 0x84, 0x1b,
+    0x71, 0x4f, 0xa8, 0x3e, 0x8e, 0x30, 0x03, 0x92, 0x84, 0x01,
+];
+
+pub const EXIF_TIFF: &[u8] = b"MM\x00\x2a\x00\x00\x00\x08\x00\x00";
+pub const XMP: &[u8] = b"<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"></x:xmpmeta>";
+
+/// Returns the signature box and the `ftyp` box, which start a container.
+pub fn container_header() -> Vec<u8> {
+    let mut out = vec![0, 0, 0, 0xc, b'J', b'X', b'L', b' ', 0xd, 0xa, 0x87, 0xa];
+    push_box(&mut out, b"ftyp", b"jxl \x00\x00\x00\x00jxl ");
+    out
+}
+
+/// Appends a box of type `ty`.
+pub fn push_box(out: &mut Vec<u8>, ty: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32 + 8).to_be_bytes());
+    out.extend_from_slice(ty);
+    out.extend_from_slice(data);
+}
+
+/// Appends a `brob` box compressing the content of a box of type `ty`.
+pub fn push_brob(out: &mut Vec<u8>, ty: &[u8; 4], data: &[u8]) {
+    let mut content = ty.to_vec();
+    let mut writer = brotli::CompressorWriter::new(&mut content, 4096, 5, 22);
+    writer.write_all(data).unwrap();
+    drop(writer);
+    push_box(out, b"brob", &content);
+}
+
+/// Builds a container with Exif, XMP and two JUMBF boxes around [`CODESTREAM`].
+pub fn build_container(metadata_after_codestream: bool, compress: bool) -> Vec<u8> {
+    let mut out = container_header();
+
+    let mut exif = vec![0, 0, 0, 6];
+    exif.extend_from_slice(b"Exif\x00\x00");
+    exif.extend_from_slice(EXIF_TIFF);
+
+    let mut metadata = Vec::new();
+    if compress {
+        push_brob(&mut metadata, b"Exif", &exif);
+        push_brob(&mut metadata, b"xml ", XMP);
+    } else {
+        push_box(&mut metadata, b"Exif", &exif);
+        push_box(&mut metadata, b"xml ", XMP);
+    }
+    push_box(&mut metadata, b"jumb", b"first");
+    push_box(&mut metadata, b"jumb", b"second");
+
+    if metadata_after_codestream {
+        push_box(&mut out, b"jxlc", CODESTREAM);
+        out.extend(metadata);
+    } else {
+        out.extend(metadata);
+        push_box(&mut out, b"jxlc", CODESTREAM);
+    }
+    out
+}
+
+/// Checks that `image` has the metadata written by [`build_container`].
+pub fn check_metadata(image: &JxlImage) {
+    let exif = image.raw_exif_data().unwrap().unwrap();
+    assert_eq!(exif.tiff_header_offset(), 6);
+    assert_eq!(exif.payload(), EXIF_TIFF);
+    assert_eq!(&exif.raw_payload()[..6], b"Exif\x00\x00");
+
+    assert_eq!(image.xmp().unwrap(), Some(XMP));
+
+    let jumbf = image.jumbf_boxes().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(jumbf, [&b"first"[..], &b"second"[..]]);
+}
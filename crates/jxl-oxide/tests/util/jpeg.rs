@@ -0,0 +1,826 @@
+//! Writer of baseline JPEG images, and of JPEG XL images recompressing them losslessly.
+//!
+//! JPEG images have three components with a single interleaved scan and optimized Huffman tables.
+//! The recompressed image stores the quantized coefficients in a VarDCT frame of DCT8 varblocks,
+//! written in the same way as [`VarDctImage`][super::vardct::VarDctImage], and everything else in
+//! the `jbrd` box.
+
+use std::io::Write;
+
+use jxl_bitstream::{BitWriter, BundleDefault, BundleWrite, ContainerWriter, U32Distribution};
+use jxl_coding::Encoder;
+use jxl_color::header::{ColourEncoding, ColourSpace};
+use jxl_frame::header::Encoding;
+use jxl_frame::FrameHeader;
+use jxl_image::ImageHeader;
+use jxl_oxide::{Quantizer, TransformType};
+
+use super::vardct::{
+    encode_hf_coeffs, new_grid, push_channel, single_leaf_tree, write_frame, write_group_header,
+    write_hf_passes, Block, Lcg,
+};
+
+/// Maps zigzag order to the natural order.
+#[rustfmt::skip]
+const ZIGZAG: [usize; 64] = [
+    0,  1,  8, 16,  9,  2,  3, 10,
+    17, 24, 32, 25, 18, 11,  4,  5,
+    12, 19, 26, 33, 40, 48, 41, 34,
+    27, 20, 13,  6,  7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46,
+    53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// Example quantization tables of the JPEG specification, in the natural order.
+#[rustfmt::skip]
+const QUANT_TABLES: [[u16; 64]; 2] = [
+    [
+        16, 11, 10, 16, 24, 40, 51, 61,
+        12, 12, 14, 19, 26, 58, 60, 55,
+        14, 13, 16, 24, 40, 57, 69, 56,
+        14, 17, 22, 29, 51, 87, 80, 62,
+        18, 22, 37, 56, 68, 109, 103, 77,
+        24, 35, 55, 64, 81, 104, 113, 92,
+        49, 64, 78, 87, 103, 121, 120, 101,
+        72, 92, 95, 98, 112, 100, 103, 99,
+    ],
+    [
+        17, 18, 24, 47, 99, 99, 99, 99,
+        18, 21, 26, 66, 99, 99, 99, 99,
+        24, 26, 56, 99, 99, 99, 99, 99,
+        47, 66, 99, 99, 99, 99, 99, 99,
+        99, 99, 99, 99, 99, 99, 99, 99,
+        99, 99, 99, 99, 99, 99, 99, 99,
+        99, 99, 99, 99, 99, 99, 99, 99,
+        99, 99, 99, 99, 99, 99, 99, 99,
+    ],
+];
+
+/// Baseline YCbCr JPEG image in quantized form.
+#[derive(Debug)]
+pub struct JpegImage {
+    pub width: u32,
+    pub height: u32,
+    /// Whether chroma components are subsampled in both directions (4:2:0).
+    pub subsampled: bool,
+    /// Quantized coefficients of Y, Cb and Cr, with blocks in raster order and coefficients in
+    /// the natural order.
+    pub blocks: [Vec<[i16; 64]>; 3],
+    /// Restart interval in MCUs, `0` disables restart markers.
+    pub restart_interval: u16,
+    pub icc: Option<Vec<u8>>,
+    /// Exif payload, without the TIFF header offset of the `Exif` box.
+    pub exif: Option<Vec<u8>>,
+    pub xmp: Option<Vec<u8>>,
+    /// APPn markers other than ICC, Exif and XMP, starting from the marker byte.
+    pub app_markers: Vec<Vec<u8>>,
+    /// COM markers, starting from the marker byte.
+    pub com_markers: Vec<Vec<u8>>,
+    /// Data following the EOI marker.
+    pub tail_data: Vec<u8>,
+}
+
+impl JpegImage {
+    /// Creates an image with smooth DC coefficients and sparse AC coefficients, including long
+    /// zero runs and blocks ending with a nonzero coefficient, without metadata.
+    ///
+    /// Subsampled images should have even number of 8x8 blocks in both directions.
+    pub fn sample(width: u32, height: u32, subsampled: bool) -> Self {
+        if subsampled {
+            assert!(width.div_ceil(8).is_multiple_of(2) && height.div_ceil(8).is_multiple_of(2));
+        }
+
+        let mut image = Self {
+            width,
+            height,
+            subsampled,
+            blocks: Default::default(),
+            restart_interval: 0,
+            icc: None,
+            exif: None,
+            xmp: None,
+            app_markers: Vec::new(),
+            com_markers: Vec::new(),
+            tail_data: Vec::new(),
+        };
+
+        let mut rng = Lcg(0x2468_ace0);
+        for c in 0..3 {
+            let (bw, bh) = image.component_size(c);
+            let max_amplitude = if c == 0 { 64 } else { 16 };
+            for y in 0..bh {
+                for x in 0..bw {
+                    let mut block = [0i16; 64];
+                    let dc = if c == 0 {
+                        (x * 7 + y * 5) % 200
+                    } else {
+                        (x * 3 + y * 11 + c * 17) % 60
+                    };
+                    block[0] = dc as i16 - (rng.next() % 9) as i16;
+                    for (k, &idx) in ZIGZAG.iter().enumerate().skip(1) {
+                        let r = rng.next();
+                        if r % (4 * k as u32 + 4) >= 8 {
+                            continue;
+                        }
+                        let amplitude = max_amplitude / k as i32 + 1;
+                        let mut coeff = (r >> 8) as i32 % (2 * amplitude + 1) - amplitude;
+                        if (r >> 4).is_multiple_of(97) {
+                            // Coefficient with many magnitude bits.
+                            coeff = coeff.signum() * (300 + (r >> 16) as i32 % 700);
+                        }
+                        block[idx] = coeff as i16;
+                    }
+                    if (x + y).is_multiple_of(7) {
+                        // Zero run longer than 16, followed by the last coefficient.
+                        for &idx in &ZIGZAG[20..] {
+                            block[idx] = 0;
+                        }
+                        block[63] = if x.is_multiple_of(2) { 1 } else { -1 };
+                    }
+                    image.blocks[c].push(block);
+                }
+            }
+        }
+        image
+    }
+
+    /// Adds an ICC profile, Exif and XMP metadata, APP0 and COM markers, and tail data.
+    pub fn with_sample_metadata(mut self) -> Self {
+        let srgb = ColourEncoding::default_with_context(());
+        self.icc = Some(jxl_color::icc::colour_encoding_to_icc(&srgb));
+        self.exif = Some(b"II*\0\x08\0\0\0\0\0\0\0\0\0".to_vec());
+        self.xmp = Some(br#"<x:xmpmeta xmlns:x="adobe:ns:meta/"><rdf:RDF/></x:xmpmeta>"#.to_vec());
+        self.app_markers = vec![b"\xe0\0\x10JFIF\0\x01\x01\0\0\x01\0\x01\0\0".to_vec()];
+        self.com_markers = [&b"jxl-oxide"[..], b"JPEG reconstruction test"]
+            .into_iter()
+            .map(|text| {
+                let mut marker = vec![0xfe];
+                marker.extend_from_slice(&(text.len() as u16 + 2).to_be_bytes());
+                marker.extend_from_slice(text);
+                marker
+            })
+            .collect();
+        self.tail_data = b"\xff\xd8tail\xff".to_vec();
+        self
+    }
+
+    /// Writes the JPEG file.
+    pub fn encode_jpeg(&self) -> Vec<u8> {
+        let tables = self.huffman_tables();
+        let app_markers = self.all_app_markers();
+        let mut app_markers = app_markers.iter();
+        let mut com_markers = self.com_markers.iter();
+
+        let mut out = vec![0xff, 0xd8];
+        for marker in self.markers() {
+            match marker {
+                0xe0..=0xef => {
+                    out.push(0xff);
+                    out.extend_from_slice(&app_markers.next().unwrap().1);
+                }
+                0xfe => {
+                    out.push(0xff);
+                    out.extend_from_slice(com_markers.next().unwrap());
+                }
+                0xdb => {
+                    out.extend_from_slice(&[0xff, 0xdb, 0, 2 + 2 * 65]);
+                    for (idx, table) in QUANT_TABLES.iter().enumerate() {
+                        out.push(idx as u8);
+                        out.extend(ZIGZAG.iter().map(|&natural| table[natural] as u8));
+                    }
+                }
+                0xc0 => {
+                    let luma_samp = if self.subsampled { 0x22 } else { 0x11 };
+                    out.extend_from_slice(&[0xff, 0xc0, 0, 17, 8]);
+                    out.extend_from_slice(&(self.height as u16).to_be_bytes());
+                    out.extend_from_slice(&(self.width as u16).to_be_bytes());
+                    out.extend_from_slice(&[3, 1, luma_samp, 0, 2, 0x11, 1, 3, 0x11, 1]);
+                }
+                0xc4 => {
+                    let len: usize = tables.iter().map(|t| 17 + t.values.len()).sum();
+                    out.extend_from_slice(&[0xff, 0xc4]);
+                    out.extend_from_slice(&(len as u16 + 2).to_be_bytes());
+                    for (slot, table) in tables.iter().enumerate() {
+                        out.push((((slot & 1) << 4) | (slot >> 1)) as u8);
+                        out.extend_from_slice(&table.counts[1..]);
+                        out.extend_from_slice(&table.values);
+                    }
+                }
+                0xdd => {
+                    out.extend_from_slice(&[0xff, 0xdd, 0, 4]);
+                    out.extend_from_slice(&self.restart_interval.to_be_bytes());
+                }
+                0xda => {
+                    out.extend_from_slice(&[0xff, 0xda, 0, 12, 3, 1, 0x00, 2, 0x11, 3, 0x11]);
+                    out.extend_from_slice(&[0, 63, 0]);
+                    let mut writer = JpegBitWriter {
+                        out: &mut out,
+                        acc: 0,
+                        num_bits: 0,
+                    };
+                    let mut next_restart = 0u8;
+                    self.for_each_scan_symbol(|symbol| match symbol {
+                        Some(ScanSymbol {
+                            slot,
+                            symbol,
+                            nbits,
+                            bits,
+                        }) => {
+                            let (len, code) = tables[slot].codes[symbol as usize];
+                            assert!(len > 0);
+                            writer.write(len, code as u32);
+                            writer.write(nbits, bits);
+                        }
+                        None => {
+                            writer.pad();
+                            writer.out.extend_from_slice(&[0xff, 0xd0 + next_restart]);
+                            next_restart = (next_restart + 1) % 8;
+                        }
+                    });
+                    writer.pad();
+                }
+                0xd9 => {
+                    out.extend_from_slice(&[0xff, 0xd9]);
+                    out.extend_from_slice(&self.tail_data);
+                }
+                _ => unreachable!(),
+            }
+        }
+        out
+    }
+
+    /// Writes the recompressed image in a container, with `Exif`, `xml ` and `jbrd` boxes.
+    pub fn encode_jxl(&self) -> Vec<u8> {
+        let mut container = ContainerWriter::new(self.encode_codestream());
+        if let Some(exif) = &self.exif {
+            let mut exif_box = vec![0u8; 4];
+            exif_box.extend_from_slice(exif);
+            container.set_exif(Some(exif_box)).unwrap();
+        }
+        container.set_xmp(self.xmp.clone()).unwrap();
+        container.set_jpeg_reconstruction(Some(self.jbrd()));
+        let mut out = Vec::new();
+        container.write(&mut out).unwrap();
+        out
+    }
+
+    /// Returns the total size of APPn, COM and tail data, which is held by the parsed `jbrd` box.
+    pub fn jbrd_buffer_size(&self) -> usize {
+        let app_size: usize = self.all_app_markers().iter().map(|(_, m)| m.len()).sum();
+        let com_size: usize = self.com_markers.iter().map(Vec::len).sum();
+        app_size + com_size + self.tail_data.len()
+    }
+
+    /// Writes the content of the `jbrd` box.
+    pub fn jbrd(&self) -> Vec<u8> {
+        let app_markers = self.all_app_markers();
+        let tables = self.huffman_tables();
+
+        let mut writer = BitWriter::new();
+        // Not grayscale.
+        writer.write_bool(false).unwrap();
+        for marker in self.markers() {
+            writer.write_bits(6, (marker - 0xc0) as u32).unwrap();
+        }
+        for (ty, data) in &app_markers {
+            write_u32(&mut writer, *ty, [(0, 0), (0, 1), (1, 2), (2, 4)]);
+            writer.write_bits(16, data.len() as u32 - 1).unwrap();
+        }
+        for data in &self.com_markers {
+            writer.write_bits(16, data.len() as u32 - 1).unwrap();
+        }
+
+        // Luma and chroma quantization tables, in a single DQT marker.
+        write_u32(&mut writer, 2, [(0, 1), (0, 2), (0, 3), (0, 4)]);
+        for idx in 0..2 {
+            writer.write_bits(1, 0).unwrap();
+            writer.write_bits(2, idx).unwrap();
+            writer.write_bool(idx == 1).unwrap();
+        }
+        // YCbCr components.
+        writer.write_bits(2, 1).unwrap();
+        for quant_idx in [0, 1, 1] {
+            writer.write_bits(2, quant_idx).unwrap();
+        }
+
+        // Four Huffman codes in a single DHT marker.
+        write_u32(&mut writer, 4, [(0, 4), (3, 2), (4, 10), (6, 26)]);
+        for (slot, table) in tables.iter().enumerate() {
+            writer.write_bool(slot & 1 != 0).unwrap();
+            writer.write_bits(2, slot as u32 >> 1).unwrap();
+            writer.write_bool(slot == 3).unwrap();
+            // Counts and values include the reserved all-ones code.
+            let mut counts = table.counts.map(u32::from);
+            let max_len = counts.iter().rposition(|&count| count != 0).unwrap();
+            counts[max_len] += 1;
+            for count in counts {
+                write_u32(&mut writer, count, [(0, 0), (0, 1), (3, 2), (8, 0)]);
+            }
+            for value in table.values.iter().map(|&v| v as u32).chain([256]) {
+                write_u32(&mut writer, value, [(2, 0), (2, 4), (4, 8), (8, 1)]);
+            }
+        }
+
+        // A single sequential scan.
+        write_u32(&mut writer, 3, [(0, 1), (0, 2), (0, 3), (0, 4)]);
+        writer.write_bits(6, 0).unwrap();
+        writer.write_bits(6, 63).unwrap();
+        writer.write_bits(4, 0).unwrap();
+        writer.write_bits(4, 0).unwrap();
+        for (comp_idx, tbl_idx) in [(0, 0), (1, 1), (2, 1)] {
+            writer.write_bits(2, comp_idx).unwrap();
+            writer.write_bits(2, tbl_idx).unwrap();
+            writer.write_bits(2, tbl_idx).unwrap();
+        }
+        write_u32(&mut writer, 0, [(0, 0), (0, 1), (0, 2), (3, 3)]);
+
+        if self.restart_interval > 0 {
+            writer.write_bits(16, self.restart_interval as u32).unwrap();
+        }
+        // No reset points and extra zero runs.
+        for _ in 0..2 {
+            write_u32(&mut writer, 0, [(0, 0), (2, 1), (4, 4), (16, 20)]);
+        }
+        write_u32(
+            &mut writer,
+            self.tail_data.len() as u32,
+            [(0, 0), (8, 1), (16, 257), (22, 65793)],
+        );
+        // Padding bits are all ones.
+        writer.write_bool(false).unwrap();
+        writer.zero_pad_to_byte();
+
+        let mut out = writer.finish();
+        let mut compressor = brotli::CompressorWriter::new(&mut out, 4096, 5, 22);
+        for (ty, data) in &app_markers {
+            if *ty == 0 {
+                compressor.write_all(data).unwrap();
+            }
+        }
+        for data in &self.com_markers {
+            compressor.write_all(data).unwrap();
+        }
+        compressor.write_all(&self.tail_data).unwrap();
+        drop(compressor);
+        out
+    }
+
+    /// Writes the recompressed image as a bare codestream, without the `jbrd` box.
+    pub fn encode_codestream(&self) -> Vec<u8> {
+        let mut image_header = ImageHeader::new(self.width, self.height).unwrap();
+        let metadata = &mut image_header.metadata;
+        metadata.set_xyb_encoded(false);
+        if self.icc.is_some() {
+            metadata
+                .set_colour_encoding(ColourEncoding::with_icc(ColourSpace::Rgb))
+                .unwrap();
+        }
+
+        let mut frame_header = FrameHeader::lossless_modular(&image_header);
+        frame_header.encoding = Encoding::VarDct;
+        frame_header.do_ycbcr = true;
+        if self.subsampled {
+            frame_header.jpeg_upsampling = [0, 1, 0];
+        }
+        assert_eq!(frame_header.num_lf_groups(), 1);
+
+        let bw = self.width.div_ceil(8) as usize;
+        let bh = self.height.div_ceil(8) as usize;
+        let tree = single_leaf_tree();
+
+        let mut modular = Encoder::new(1);
+        // LF coefficients, which are DC coefficients of Y, Cb and Cr.
+        modular.begin_stream();
+        for c in 0..3 {
+            let (cw, ch) = self.component_size(c);
+            let mut lf = new_grid(cw, ch);
+            for (sample, block) in lf.buf_mut().iter_mut().zip(&self.blocks[c]) {
+                *sample = block[0] as i32;
+            }
+            push_channel(&mut modular, &lf);
+        }
+        // HfMeta with zero chroma-from-luma factors and DCT8 varblocks.
+        modular.begin_stream();
+        let tw = self.width.div_ceil(64) as usize;
+        let th = self.height.div_ceil(64) as usize;
+        push_channel(&mut modular, &new_grid(tw, th));
+        push_channel(&mut modular, &new_grid(tw, th));
+        for _ in 0..2 * bw * bh {
+            modular.push(0, 0);
+        }
+        push_channel(&mut modular, &new_grid(bw, bh));
+        // Raw DCT8 dequantization matrix in X, Y, B order, which is the transposed JPEG table.
+        modular.begin_stream();
+        for table in [&QUANT_TABLES[1], &QUANT_TABLES[0], &QUANT_TABLES[1]] {
+            let mut grid = new_grid(8, 8);
+            for y in 0..8 {
+                for x in 0..8 {
+                    *grid.get_mut(x, y).unwrap() = table[x * 8 + y] as i32;
+                }
+            }
+            push_channel(&mut modular, &grid);
+        }
+        let modular = modular.build().unwrap();
+
+        let mut block_map = new_grid::<Option<Block>>(bw, bh);
+        for y in 0..bh {
+            for x in 0..bw {
+                *block_map.get_mut(x, y).unwrap() = Some(Block {
+                    left: x as u32,
+                    top: y as u32,
+                    transform_type: TransformType::Dct8,
+                    hf_mul: 1,
+                });
+            }
+        }
+        let shift = self.subsampled as usize;
+        let hf_stream = encode_hf_coeffs(&frame_header, &block_map, |c, block, dx, dy| {
+            // Channels are in X, Y, B order, and coefficients are transposed.
+            let component = [1, 0, 2][c];
+            let shift = if component == 0 { 0 } else { shift };
+            let (cw, _) = self.component_size(component);
+            let x = block.left as usize >> shift;
+            let y = block.top as usize >> shift;
+            self.blocks[component][y * cw + x][dx * 8 + dy] as i32
+        });
+
+        let mut sections = Vec::new();
+
+        // LfGlobal
+        let mut writer = BitWriter::new();
+        writer.write_bool(true).unwrap();
+        let quantizer = Quantizer {
+            global_scale: 32768,
+            quant_lf: 64,
+        };
+        quantizer.write(&mut writer, ()).unwrap();
+        writer.write_bool(true).unwrap();
+        writer.write_bool(true).unwrap();
+        writer.write_bool(true).unwrap();
+        tree.write_header(&mut writer).unwrap();
+        tree.write_stream(&mut writer, 0).unwrap();
+        modular.write_header(&mut writer).unwrap();
+        sections.push(writer);
+
+        // LfGroup
+        let mut writer = BitWriter::new();
+        writer.write_bits(2, 0).unwrap();
+        write_group_header(&mut writer);
+        modular.write_stream(&mut writer, 0).unwrap();
+        let nb_blocks_bits = (bw * bh).next_power_of_two().trailing_zeros();
+        writer
+            .write_bits(nb_blocks_bits as usize, (bw * bh) as u32 - 1)
+            .unwrap();
+        write_group_header(&mut writer);
+        modular.write_stream(&mut writer, 1).unwrap();
+        sections.push(writer);
+
+        // HfGlobal, with the raw DCT8 matrix and default matrices for the other transforms.
+        let mut writer = BitWriter::new();
+        writer.write_bool(false).unwrap();
+        writer.write_bits(3, 7).unwrap();
+        writer.write_f32_as_f16(1.0 / (8.0 * 255.0)).unwrap();
+        write_group_header(&mut writer);
+        modular.write_stream(&mut writer, 2).unwrap();
+        for _ in 1..17 {
+            writer.write_bits(3, 0).unwrap();
+        }
+        write_hf_passes(&mut writer, &mut sections, &frame_header, &[hf_stream]);
+
+        let mut writer = BitWriter::new();
+        image_header.write(&mut writer, ()).unwrap();
+        if let Some(icc) = &self.icc {
+            let encoded = jxl_color::icc::encode_icc(icc);
+            jxl_color::icc::write_icc(&mut writer, &encoded).unwrap();
+        }
+        writer.zero_pad_to_byte();
+        let mut out = writer.finish();
+        write_frame(&mut out, &image_header, &frame_header, sections);
+        out
+    }
+
+    /// Returns the number of MCUs in each direction.
+    fn mcu_size(&self) -> (usize, usize) {
+        let mcu_dim = if self.subsampled { 16 } else { 8 };
+        (
+            self.width.div_ceil(mcu_dim) as usize,
+            self.height.div_ceil(mcu_dim) as usize,
+        )
+    }
+
+    /// Returns the size of the component in 8x8 blocks.
+    fn component_size(&self, c: usize) -> (usize, usize) {
+        let (mcu_cols, mcu_rows) = self.mcu_size();
+        let samp = if c == 0 && self.subsampled { 2 } else { 1 };
+        (mcu_cols * samp, mcu_rows * samp)
+    }
+
+    /// Returns every APPn marker with its type in the `jbrd` box, in the order of the file.
+    fn all_app_markers(&self) -> Vec<(u32, Vec<u8>)> {
+        let mut out: Vec<_> = self.app_markers.iter().map(|m| (0, m.clone())).collect();
+        if let Some(icc) = &self.icc {
+            // Split into two markers, to test multi-marker ICC profiles.
+            let chunks = icc.chunks(icc.len().div_ceil(2)).collect::<Vec<_>>();
+            for (idx, chunk) in chunks.iter().enumerate() {
+                out.push((
+                    1,
+                    app_marker(0xe2, b"ICC_PROFILE\0", |data| {
+                        data.extend_from_slice(&[idx as u8 + 1, chunks.len() as u8]);
+                        data.extend_from_slice(chunk);
+                    }),
+                ));
+            }
+        }
+        if let Some(exif) = &self.exif {
+            out.push((
+                2,
+                app_marker(0xe1, b"Exif\0\0", |data| data.extend_from_slice(exif)),
+            ));
+        }
+        if let Some(xmp) = &self.xmp {
+            out.push((
+                3,
+                app_marker(0xe1, b"http://ns.adobe.com/xap/1.0/\0", |data| {
+                    data.extend_from_slice(xmp)
+                }),
+            ));
+        }
+        out
+    }
+
+    /// Returns the markers of the file except SOI and RSTn, in the form of the `jbrd` box.
+    fn markers(&self) -> Vec<u8> {
+        let mut markers: Vec<u8> = self.all_app_markers().iter().map(|m| m.1[0]).collect();
+        markers.extend(self.com_markers.iter().map(|_| 0xfe));
+        markers.extend([0xdb, 0xc0, 0xc4]);
+        if self.restart_interval > 0 {
+            markers.push(0xdd);
+        }
+        markers.extend([0xda, 0xd9]);
+        markers
+    }
+
+    /// Builds optimized Huffman tables of luma DC, luma AC, chroma DC and chroma AC, in the order
+    /// of slots.
+    fn huffman_tables(&self) -> [HuffmanTable; 4] {
+        let mut freqs = [[0u32; 256]; 4];
+        self.for_each_scan_symbol(|symbol| {
+            if let Some(ScanSymbol { slot, symbol, .. }) = symbol {
+                freqs[slot][symbol as usize] += 1;
+            }
+        });
+        freqs.map(|freq| HuffmanTable::optimal(&freq))
+    }
+
+    /// Calls `f` with each Huffman-coded symbol of the scan, and `None` at each restart marker.
+    fn for_each_scan_symbol(&self, mut f: impl FnMut(Option<ScanSymbol>)) {
+        let (mcu_cols, mcu_rows) = self.mcu_size();
+        let restart_interval = self.restart_interval as usize;
+        let mut restarts_to_go = restart_interval;
+        let mut prev_dc = [0i32; 3];
+        for mcu_y in 0..mcu_rows {
+            for mcu_x in 0..mcu_cols {
+                if restart_interval > 0 {
+                    if restarts_to_go == 0 {
+                        f(None);
+                        prev_dc = [0; 3];
+                        restarts_to_go = restart_interval;
+                    }
+                    restarts_to_go -= 1;
+                }
+
+                for (c, prev_dc) in prev_dc.iter_mut().enumerate() {
+                    let (cw, _) = self.component_size(c);
+                    let samp = if c == 0 && self.subsampled { 2 } else { 1 };
+                    let dc_slot = if c == 0 { 0 } else { 2 };
+                    let ac_slot = dc_slot + 1;
+                    for iy in 0..samp {
+                        for ix in 0..samp {
+                            let x = mcu_x * samp + ix;
+                            let y = mcu_y * samp + iy;
+                            let block = &self.blocks[c][y * cw + x];
+
+                            let dc = block[0] as i32;
+                            let (nbits, bits) = magnitude(dc - *prev_dc);
+                            *prev_dc = dc;
+                            f(Some(ScanSymbol::new(dc_slot, nbits, nbits, bits)));
+
+                            let mut run = 0u8;
+                            for &idx in &ZIGZAG[1..] {
+                                let coeff = block[idx] as i32;
+                                if coeff == 0 {
+                                    run += 1;
+                                    continue;
+                                }
+                                while run >= 16 {
+                                    f(Some(ScanSymbol::new(ac_slot, 0xf0, 0, 0)));
+                                    run -= 16;
+                                }
+                                let (nbits, bits) = magnitude(coeff);
+                                f(Some(ScanSymbol::new(
+                                    ac_slot,
+                                    (run << 4) | nbits,
+                                    nbits,
+                                    bits,
+                                )));
+                                run = 0;
+                            }
+                            if run > 0 {
+                                f(Some(ScanSymbol::new(ac_slot, 0, 0, 0)));
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Huffman-coded symbol of a scan, followed by `nbits` extra bits.
+#[derive(Debug, Copy, Clone)]
+struct ScanSymbol {
+    slot: usize,
+    symbol: u8,
+    nbits: u8,
+    bits: u32,
+}
+
+impl ScanSymbol {
+    fn new(slot: usize, symbol: u8, nbits: u8, bits: u32) -> Self {
+        Self {
+            slot,
+            symbol,
+            nbits,
+            bits,
+        }
+    }
+}
+
+/// Huffman code in the form of DHT segment.
+#[derive(Debug)]
+struct HuffmanTable {
+    /// Number of codes of each length, where `counts[0]` is always zero.
+    counts: [u8; 17],
+    values: Vec<u8>,
+    /// Length and code of each symbol.
+    codes: [(u8, u16); 256],
+}
+
+impl HuffmanTable {
+    /// Builds a Huffman code limited to 16 bits, in the same way as libjpeg.
+    ///
+    /// An all-ones code is reserved with a pseudo-symbol, which takes one of the longest codes.
+    fn optimal(freq: &[u32; 256]) -> Self {
+        let mut freq = freq.map(u64::from).to_vec();
+        freq.push(1);
+        let mut code_size = [0usize; 257];
+        let mut others = [None::<usize>; 257];
+        loop {
+            let mut c1 = None;
+            let mut c2 = None;
+            let mut v1 = u64::MAX;
+            let mut v2 = u64::MAX;
+            for (idx, &f) in freq.iter().enumerate() {
+                if f != 0 && f <= v1 {
+                    v1 = f;
+                    c1 = Some(idx);
+                }
+            }
+            for (idx, &f) in freq.iter().enumerate() {
+                if f != 0 && f <= v2 && Some(idx) != c1 {
+                    v2 = f;
+                    c2 = Some(idx);
+                }
+            }
+            let (Some(mut c1), Some(mut c2)) = (c1, c2) else {
+                break;
+            };
+
+            freq[c1] += freq[c2];
+            freq[c2] = 0;
+            code_size[c1] += 1;
+            while let Some(next) = others[c1] {
+                c1 = next;
+                code_size[c1] += 1;
+            }
+            others[c1] = Some(c2);
+            code_size[c2] += 1;
+            while let Some(next) = others[c2] {
+                c2 = next;
+                code_size[c2] += 1;
+            }
+        }
+
+        let mut bits = [0u32; 258];
+        for &size in &code_size {
+            if size > 0 {
+                bits[size] += 1;
+            }
+        }
+        for len in (17..bits.len()).rev() {
+            while bits[len] > 0 {
+                let mut shorter = len - 2;
+                while bits[shorter] == 0 {
+                    shorter -= 1;
+                }
+                bits[len] -= 2;
+                bits[len - 1] += 1;
+                bits[shorter + 1] += 2;
+                bits[shorter] -= 1;
+            }
+        }
+        // Remove the reserved code, which is one of the longest codes.
+        let max_len = bits.iter().rposition(|&count| count != 0).unwrap();
+        bits[max_len] -= 1;
+
+        let mut values = Vec::new();
+        for len in 1..code_size.len() {
+            for (symbol, &size) in code_size[..256].iter().enumerate() {
+                if size == len {
+                    values.push(symbol as u8);
+                }
+            }
+        }
+
+        let mut counts = [0u8; 17];
+        let mut codes = [(0u8, 0u16); 256];
+        let mut code = 0u16;
+        let mut values_iter = values.iter();
+        for len in 1..17 {
+            counts[len] = bits[len] as u8;
+            for _ in 0..bits[len] {
+                let &value = values_iter.next().unwrap();
+                codes[value as usize] = (len as u8, code);
+                code += 1;
+            }
+            code <<= 1;
+        }
+        Self {
+            counts,
+            values,
+            codes,
+        }
+    }
+}
+
+/// Bit writer of entropy-coded segments, which stuffs a zero byte after each `0xff` byte.
+struct JpegBitWriter<'out> {
+    out: &'out mut Vec<u8>,
+    acc: u8,
+    num_bits: u8,
+}
+
+impl JpegBitWriter<'_> {
+    fn write(&mut self, nbits: u8, bits: u32) {
+        for idx in (0..nbits).rev() {
+            self.acc = (self.acc << 1) | (bits >> idx) as u8 & 1;
+            self.num_bits += 1;
+            if self.num_bits == 8 {
+                self.out.push(self.acc);
+                if self.acc == 0xff {
+                    self.out.push(0);
+                }
+                self.acc = 0;
+                self.num_bits = 0;
+            }
+        }
+    }
+
+    /// Pads the partial byte with one bits.
+    fn pad(&mut self) {
+        while self.num_bits != 0 {
+            self.write(1, 1);
+        }
+    }
+}
+
+/// Returns the magnitude category of `value`, and its extra bits.
+fn magnitude(value: i32) -> (u8, u32) {
+    let nbits = 32 - value.unsigned_abs().leading_zeros();
+    let bits = if value < 0 { value - 1 } else { value } as u32;
+    (nbits as u8, bits & ((1u32 << nbits) - 1))
+}
+
+/// Creates APPn marker data starting from the marker byte, with a tag followed by the payload.
+fn app_marker(marker: u8, tag: &[u8], payload: impl FnOnce(&mut Vec<u8>)) -> Vec<u8> {
+    let mut data = vec![marker, 0, 0];
+    data.extend_from_slice(tag);
+    payload(&mut data);
+    let len = data.len() as u16 - 1;
+    data[1..3].copy_from_slice(&len.to_be_bytes());
+    data
+}
+
+/// Writes `value` with the given `(bits, offset)` distributions, where zero bits mean a constant.
+fn write_u32(writer: &mut BitWriter, value: u32, dists: [(usize, u32); 4]) {
+    let dists = dists.map(|(bits, offset)| {
+        if bits == 0 {
+            U32Distribution::Constant(offset)
+        } else {
+            U32Distribution::BitsOffset { bits, offset }
+        }
+    });
+    writer.write_u32(value, dists).unwrap();
+}
@@ -1,5 +1,11 @@
 #![allow(dead_code)]
 
+pub mod container;
+pub mod jpeg;
+pub mod vardct;
+
+use jxl_oxide::{JxlImage, LosslessEncoder};
+
 pub fn conformance_path(name: &str) -> std::path::PathBuf {
     let mut path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     path.push("tests/conformance/testcases");
@@ -7,3 +13,15 @@ pub fn conformance_path(name: &str) -> std::path::PathBuf {
     path.push("input.jxl");
     path
 }
+
+/// Reads an image from the bytes in memory.
+pub fn read_image(data: impl AsRef<[u8]>) -> JxlImage {
+    JxlImage::builder()
+        .read(std::io::Cursor::new(data))
+        .unwrap()
+}
+
+/// Encodes interleaved samples with `encoder`, and reads the encoded image.
+pub fn encode_image<T: Copy + Into<i32>>(encoder: &LosslessEncoder, samples: &[T]) -> JxlImage {
+    read_image(encoder.encode(samples).unwrap())
+}
@@ -0,0 +1,662 @@
+//! Writer of small VarDCT images with known quantized coefficients.
+//!
+//! The writer stores every Modular sub-image with a single-leaf MA tree using the zero predictor,
+//! and every HF coefficient with the default block context map and the natural coefficient order.
+//! Images are limited to a single LF group, in XYB without chroma subsampling.
+
+use jxl_bitstream::{pack_signed, BitWriter, BundleWrite, U32Distribution};
+use jxl_coding::{EncodedStreams, Encoder};
+use jxl_frame::data::Toc;
+use jxl_frame::filter::{EdgePreservingFilter, Gabor};
+use jxl_frame::header::{Encoding, Passes};
+use jxl_frame::FrameHeader;
+use jxl_grid::SimpleGrid;
+use jxl_image::ImageHeader;
+use jxl_oxide::{Quantizer, TransformType};
+
+/// Varblock of [`VarDctImage`], with its position in 8x8 blocks.
+#[derive(Debug, Copy, Clone)]
+pub struct Block {
+    pub left: u32,
+    pub top: u32,
+    pub transform_type: TransformType,
+    pub hf_mul: i32,
+}
+
+/// VarDCT image in quantized form.
+#[derive(Debug)]
+pub struct VarDctImage {
+    pub width: u32,
+    pub height: u32,
+    pub orientation: u32,
+    pub gabor: bool,
+    /// Number of EPF iterations, `0` disables EPF.
+    pub epf_iters: u32,
+    /// Whether to split HF coefficients into two passes, where the first pass has every
+    /// coefficient needed for 1:2 downscaled rendering.
+    pub two_passes: bool,
+    pub global_scale: u32,
+    pub quant_lf: u32,
+    /// Varblocks covering every 8x8 block, in raster order of their top-left blocks.
+    pub blocks: Vec<Block>,
+    /// Quantized LF image in X, Y, B order, one sample per 8x8 block.
+    pub lf_quant: [SimpleGrid<i32>; 3],
+    /// Quantized HF coefficients in X, Y, B order, in the layout of
+    /// [`VarDctCoefficients::hf_quant`][jxl_oxide::VarDctCoefficients::hf_quant].
+    pub hf_quant: [SimpleGrid<i32>; 3],
+    pub x_from_y: SimpleGrid<i32>,
+    pub b_from_y: SimpleGrid<i32>,
+    /// EPF sharpness of each 8x8 block, in range `0..8`.
+    pub sharpness: SimpleGrid<i32>,
+}
+
+impl VarDctImage {
+    /// Creates an image with a mix of every transform type up to 32x32, smooth LF image, sparse
+    /// HF coefficients and varying chroma-from-luma factors, without restoration filters.
+    pub fn sample(width: u32, height: u32) -> Self {
+        let bw = width.div_ceil(8) as usize;
+        let bh = height.div_ceil(8) as usize;
+        let mut rng = Lcg(0x1234_5678);
+
+        let blocks = sample_blocks(bw, bh);
+        let mut lf_quant = std::array::from_fn(|_| new_grid(bw, bh));
+        for y in 0..bh {
+            for x in 0..bw {
+                let [lf_x, lf_y, lf_b] = &mut lf_quant;
+                *lf_x.get_mut(x, y).unwrap() = ((x + 2 * y) % 16) as i32 * 3 - 24;
+                *lf_y.get_mut(x, y).unwrap() = 1000 + 60 * x as i32 + 40 * y as i32;
+                *lf_b.get_mut(x, y).unwrap() = ((x + y) % 32) as i32 * 3 - 48;
+            }
+        }
+
+        let mut hf_quant = std::array::from_fn(|_| new_grid(bw * 8, bh * 8));
+        for block in &blocks {
+            let (w, h) = block.transform_type.dct_select_size();
+            let (w, h) = (w as usize, h as usize);
+            let left = block.left as usize * 8;
+            let top = block.top as usize * 8;
+            for (c, grid) in hf_quant.iter_mut().enumerate() {
+                // Chroma channels have fewer and smaller coefficients.
+                let (density, amplitude) = if c == 1 { (2, 8) } else { (8, 1) };
+                for y in 0..h * 8 {
+                    for x in 0..w * 8 {
+                        if x < w && y < h {
+                            continue;
+                        }
+                        let r = rng.next();
+                        if r < (1 << 31) / (density * (1 + (x / w + y / h) as u32)) {
+                            *grid.get_mut(left + x, top + y).unwrap() =
+                                (r >> 8) as i32 % (2 * amplitude + 1) - amplitude;
+                        }
+                    }
+                }
+            }
+        }
+
+        let tw = width.div_ceil(64) as usize;
+        let th = height.div_ceil(64) as usize;
+        let mut x_from_y = new_grid(tw, th);
+        let mut b_from_y = new_grid(tw, th);
+        for y in 0..th {
+            for x in 0..tw {
+                *x_from_y.get_mut(x, y).unwrap() = 4 * x as i32 - 3 * y as i32;
+                *b_from_y.get_mut(x, y).unwrap() = 5 * y as i32 - 2 * x as i32;
+            }
+        }
+
+        let mut sharpness = new_grid(bw, bh);
+        for y in 0..bh {
+            for x in 0..bw {
+                *sharpness.get_mut(x, y).unwrap() = ((x / 3 + y / 2) % 8) as i32;
+            }
+        }
+
+        Self {
+            width,
+            height,
+            orientation: 1,
+            gabor: false,
+            epf_iters: 0,
+            two_passes: false,
+            global_scale: 16384,
+            quant_lf: 1,
+            blocks,
+            lf_quant,
+            hf_quant,
+            x_from_y,
+            b_from_y,
+            sharpness,
+        }
+    }
+
+    /// Writes the image as a bare codestream.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut image_header = ImageHeader::new(self.width, self.height).unwrap();
+        image_header
+            .metadata
+            .set_orientation(self.orientation)
+            .unwrap();
+
+        // Start from a non-default Modular header, so that every field is written.
+        let mut frame_header = FrameHeader::lossless_modular(&image_header);
+        frame_header.encoding = Encoding::VarDct;
+        frame_header.x_qm_scale = 3;
+        frame_header.b_qm_scale = 2;
+        if self.gabor {
+            frame_header.restoration_filter.gab = Gabor::default();
+        }
+        if self.epf_iters > 0 {
+            let mut epf = EdgePreservingFilter::default();
+            if let EdgePreservingFilter::Enabled { iters, .. } = &mut epf {
+                *iters = self.epf_iters;
+            }
+            frame_header.restoration_filter.epf = epf;
+        }
+        if self.two_passes {
+            frame_header.passes = Passes {
+                num_passes: 2,
+                num_ds: 1,
+                shift: vec![0],
+                downsample: vec![2],
+                last_pass: vec![0],
+            };
+        }
+        assert_eq!(frame_header.num_lf_groups(), 1);
+
+        let num_passes = frame_header.passes.num_passes;
+        let bw = self.width.div_ceil(8) as usize;
+        let bh = self.height.div_ceil(8) as usize;
+
+        let tree = single_leaf_tree();
+
+        let mut modular = Encoder::new(1);
+        modular.begin_stream();
+        for c in [1, 0, 2] {
+            push_channel(&mut modular, &self.lf_quant[c]);
+        }
+        modular.begin_stream();
+        push_channel(&mut modular, &self.x_from_y);
+        push_channel(&mut modular, &self.b_from_y);
+        for &Block { transform_type, .. } in &self.blocks {
+            modular.push(0, pack_signed(transform_type as i32));
+        }
+        for block in &self.blocks {
+            modular.push(0, pack_signed(block.hf_mul - 1));
+        }
+        push_channel(&mut modular, &self.sharpness);
+        let modular = modular.build().unwrap();
+
+        let mut block_map = new_grid::<Option<Block>>(bw, bh);
+        for block in &self.blocks {
+            *block_map
+                .get_mut(block.left as usize, block.top as usize)
+                .unwrap() = Some(*block);
+        }
+        let hf_streams = (0..num_passes)
+            .map(|pass_idx| {
+                encode_hf_coeffs(&frame_header, &block_map, |c, block, dx, dy| {
+                    let transform_type = block.transform_type;
+                    let (w8, h8) = transform_type.dct_select_size();
+                    // Transforms smaller than 8x8 are rendered in full.
+                    let in_first_pass = order_id(transform_type) == 1
+                        || (dx < w8 as usize * 4 && dy < h8 as usize * 4);
+                    if self.two_passes && in_first_pass != (pass_idx == 0) {
+                        return 0;
+                    }
+                    let left = block.left as usize * 8;
+                    let top = block.top as usize * 8;
+                    *self.hf_quant[c].get(left + dx, top + dy).unwrap()
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut sections = Vec::new();
+
+        // LfGlobal
+        let mut writer = BitWriter::new();
+        // All-default LF channel dequantization.
+        writer.write_bool(true).unwrap();
+        let quantizer = Quantizer {
+            global_scale: self.global_scale,
+            quant_lf: self.quant_lf,
+        };
+        quantizer.write(&mut writer, ()).unwrap();
+        // Default HF block context and LF channel correlation.
+        writer.write_bool(true).unwrap();
+        writer.write_bool(true).unwrap();
+        // GlobalModular with the global MA tree, and no channels.
+        writer.write_bool(true).unwrap();
+        tree.write_header(&mut writer).unwrap();
+        tree.write_stream(&mut writer, 0).unwrap();
+        modular.write_header(&mut writer).unwrap();
+        sections.push(writer);
+
+        // LfGroup
+        let mut writer = BitWriter::new();
+        // Extra precision of LF coefficients.
+        writer.write_bits(2, 0).unwrap();
+        write_group_header(&mut writer);
+        modular.write_stream(&mut writer, 0).unwrap();
+        let nb_blocks_bits = (bw * bh).next_power_of_two().trailing_zeros();
+        writer
+            .write_bits(nb_blocks_bits as usize, self.blocks.len() as u32 - 1)
+            .unwrap();
+        write_group_header(&mut writer);
+        modular.write_stream(&mut writer, 1).unwrap();
+        sections.push(writer);
+
+        // HfGlobal
+        let mut writer = BitWriter::new();
+        // Default dequantization matrices, and a single HF preset.
+        writer.write_bool(true).unwrap();
+        write_hf_passes(&mut writer, &mut sections, &frame_header, &hf_streams);
+
+        let mut writer = BitWriter::new();
+        image_header.write(&mut writer, ()).unwrap();
+        writer.zero_pad_to_byte();
+        let mut out = writer.finish();
+        write_frame(&mut out, &image_header, &frame_header, sections);
+        out
+    }
+}
+
+/// Single-leaf MA tree with the zero predictor.
+pub(super) fn single_leaf_tree() -> EncodedStreams {
+    let mut tree = Encoder::new(6);
+    for ctx in 1..6 {
+        tree.push(ctx, 0);
+    }
+    tree.build().unwrap()
+}
+
+/// Finishes HfGlobal with the number of HF presets and the per-pass headers, and appends the
+/// sections of the pass groups.
+pub(super) fn write_hf_passes(
+    writer: &mut BitWriter,
+    sections: &mut Vec<BitWriter>,
+    frame_header: &FrameHeader,
+    hf_streams: &[EncodedStreams],
+) {
+    let num_groups = frame_header.num_groups();
+    let num_hf_presets_bits = num_groups.next_power_of_two().trailing_zeros();
+    writer.write_bits(num_hf_presets_bits as usize, 0).unwrap();
+    for hf_stream in hf_streams {
+        // Natural coefficient orders.
+        writer
+            .write_u32(
+                0,
+                [
+                    U32Distribution::Constant(0x5f),
+                    U32Distribution::Constant(0x13),
+                    U32Distribution::Constant(0),
+                    U32Distribution::BitsOffset {
+                        bits: 13,
+                        offset: 0,
+                    },
+                ],
+            )
+            .unwrap();
+        hf_stream.write_header(writer).unwrap();
+    }
+    sections.push(std::mem::replace(writer, BitWriter::new()));
+
+    for hf_stream in hf_streams {
+        for group_idx in 0..num_groups {
+            let mut writer = BitWriter::new();
+            hf_stream
+                .write_stream(&mut writer, group_idx as usize)
+                .unwrap();
+            sections.push(writer);
+        }
+    }
+}
+
+/// Appends the frame header, TOC and sections to `out`, which should end at a byte boundary.
+pub(super) fn write_frame(
+    out: &mut Vec<u8>,
+    image_header: &ImageHeader,
+    frame_header: &FrameHeader,
+    sections: Vec<BitWriter>,
+) {
+    let sections = if frame_header.num_groups() == 1 && frame_header.passes.num_passes == 1 {
+        // Every section is written in a single TOC entry without padding.
+        let mut writer = BitWriter::new();
+        for section in sections {
+            let num_bits = section.num_written_bits();
+            let bytes = section.finish();
+            for idx in 0..num_bits {
+                writer
+                    .write_bits(1, (bytes[idx / 8] >> (idx % 8)) as u32 & 1)
+                    .unwrap();
+            }
+        }
+        vec![writer.finish()]
+    } else {
+        sections.into_iter().map(BitWriter::finish).collect()
+    };
+
+    let sizes = sections
+        .iter()
+        .map(|section| section.len() as u32)
+        .collect::<Vec<_>>();
+    let toc = Toc::from_sizes(frame_header, &sizes).unwrap();
+
+    let mut writer = BitWriter::new();
+    frame_header.write(&mut writer, image_header).unwrap();
+    toc.write(&mut writer, frame_header).unwrap();
+    out.extend(writer.finish());
+    for section in sections {
+        out.extend(section);
+    }
+}
+
+/// Pushes HF coefficients of every group in a pass, mirroring the contexts computed by the
+/// decoder.
+///
+/// `coeff_at(c, block, dx, dy)` returns the quantized coefficient of channel `c` at `(dx, dy)` of
+/// the varblock, in the layout of the decoded coefficients. Chroma subsampled channels are
+/// written only for varblocks at even positions in the subsampled direction.
+pub(super) fn encode_hf_coeffs(
+    frame_header: &FrameHeader,
+    block_map: &SimpleGrid<Option<Block>>,
+    coeff_at: impl Fn(usize, &Block, usize, usize) -> i32,
+) -> EncodedStreams {
+    const COEFF_FREQ_CONTEXT: [u32; 63] = [
+        0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 15, 16, 16, 17, 17, 18, 18, 19, 19,
+        20, 20, 21, 21, 22, 22, 23, 23, 23, 23, 24, 24, 24, 24, 25, 25, 25, 25, 26, 26, 26, 26, 27,
+        27, 27, 27, 28, 28, 28, 28, 29, 29, 29, 29, 30, 30, 30, 30,
+    ];
+    const COEFF_NUM_NONZERO_CONTEXT: [u32; 63] = [
+        0, 31, 62, 62, 93, 93, 93, 93, 123, 123, 123, 123, 152, 152, 152, 152, 152, 152, 152, 152,
+        180, 180, 180, 180, 180, 180, 180, 180, 180, 180, 180, 180, 206, 206, 206, 206, 206, 206,
+        206, 206, 206, 206, 206, 206, 206, 206, 206, 206, 206, 206, 206, 206, 206, 206, 206, 206,
+        206, 206, 206, 206, 206, 206, 206,
+    ];
+    #[rustfmt::skip]
+    const DEFAULT_BLOCK_CTX_MAP: [u32; 39] = [
+        0, 1, 2, 2, 3, 3, 4, 5, 6, 6, 6, 6, 6, 7, 8, 9, 9, 10, 11, 12, 13, 14, 14, 14,
+        14, 14, 7, 8, 9, 9, 10, 11, 12, 13, 14, 14, 14, 14, 14,
+    ];
+    const NUM_BLOCK_CLUSTERS: u32 = 15;
+
+    let shifts = channel_shifts(frame_header.jpeg_upsampling);
+    let group_dim = (frame_header.group_dim() / 8) as usize;
+    let groups_per_row = frame_header.groups_per_row();
+    let mut encoder = Encoder::new(495 * NUM_BLOCK_CLUSTERS);
+    for group_idx in 0..frame_header.num_groups() {
+        encoder.begin_stream();
+        let group_left = (group_idx % groups_per_row) as usize * group_dim;
+        let group_top = (group_idx / groups_per_row) as usize * group_dim;
+        let group_width = (block_map.width() - group_left).min(group_dim);
+        let group_height = (block_map.height() - group_top).min(group_dim);
+
+        let mut non_zeros_grid: [SimpleGrid<u32>; 3] = std::array::from_fn(|c| {
+            let (hshift, vshift) = shifts[c];
+            new_grid(
+                (group_width + hshift) >> hshift,
+                (group_height + vshift) >> vshift,
+            )
+        });
+        for y in 0..group_height {
+            for x in 0..group_width {
+                let Some(block) = block_map.get(group_left + x, group_top + y).unwrap() else {
+                    continue;
+                };
+                let transform_type = block.transform_type;
+                let (w8, h8) = transform_type.dct_select_size();
+                let num_blocks = w8 * h8;
+                let num_blocks_log = num_blocks.trailing_zeros();
+                let size = num_blocks * 64;
+                let order_id = order_id(transform_type);
+                let order = natural_order(order_id);
+
+                for c in [1, 0, 2] {
+                    let (hshift, vshift) = shifts[c];
+                    let (sx, sy) = (x >> hshift, y >> vshift);
+                    if sx << hshift != x || sy << vshift != y {
+                        continue;
+                    }
+
+                    let block_ctx = DEFAULT_BLOCK_CTX_MAP[[1, 0, 2][c] * 13 + order_id];
+                    let coeffs = order
+                        .iter()
+                        .skip(num_blocks as usize)
+                        .map(|&(ox, oy)| {
+                            let (ox, oy) = (ox as usize, oy as usize);
+                            if transform_type.need_transpose() {
+                                coeff_at(c, block, oy, ox)
+                            } else {
+                                coeff_at(c, block, ox, oy)
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    let non_zeros = coeffs.iter().filter(|&&coeff| coeff != 0).count() as u32;
+
+                    let grid = &mut non_zeros_grid[c];
+                    let predicted = match (sx, sy) {
+                        (0, 0) => 32,
+                        (0, _) => *grid.get(sx, sy - 1).unwrap(),
+                        (_, 0) => *grid.get(sx - 1, sy).unwrap(),
+                        _ => {
+                            (*grid.get(sx, sy - 1).unwrap() + *grid.get(sx - 1, sy).unwrap() + 1)
+                                >> 1
+                        }
+                    }
+                    .min(64);
+                    let idx = if predicted >= 8 {
+                        4 + predicted / 2
+                    } else {
+                        predicted
+                    };
+                    encoder.push(block_ctx + idx * NUM_BLOCK_CLUSTERS, non_zeros);
+                    let non_zeros_val = (non_zeros + num_blocks - 1) >> num_blocks_log;
+                    for dy in 0..h8 as usize {
+                        for dx in 0..w8 as usize {
+                            *grid.get_mut(sx + dx, sy + dy).unwrap() = non_zeros_val;
+                        }
+                    }
+
+                    let coeff_ctx_base = block_ctx * 458 + 37 * NUM_BLOCK_CLUSTERS;
+                    let mut remaining = non_zeros;
+                    let mut is_prev_coeff_nonzero = non_zeros <= size / 16;
+                    for (idx, &coeff) in coeffs.iter().enumerate() {
+                        if remaining == 0 {
+                            break;
+                        }
+                        let ctx = (COEFF_NUM_NONZERO_CONTEXT
+                            [((remaining - 1) >> num_blocks_log) as usize]
+                            + COEFF_FREQ_CONTEXT[idx >> num_blocks_log])
+                            * 2
+                            + is_prev_coeff_nonzero as u32;
+                        encoder.push(coeff_ctx_base + ctx, pack_signed(coeff));
+                        is_prev_coeff_nonzero = coeff != 0;
+                        if coeff != 0 {
+                            remaining -= 1;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    encoder.build().unwrap()
+}
+
+/// Returns the horizontal and vertical shifts of each channel, computed from `jpeg_upsampling`
+/// of the frame header.
+pub(super) fn channel_shifts(jpeg_upsampling: [u32; 3]) -> [(usize, usize); 3] {
+    let hscale = jpeg_upsampling.iter().any(|&v| v == 1 || v == 2) as usize;
+    let vscale = jpeg_upsampling.iter().any(|&v| v == 1 || v == 3) as usize;
+    jpeg_upsampling.map(|v| match v {
+        0 => (hscale, vscale),
+        1 => (0, 0),
+        2 => (0, vscale),
+        _ => (hscale, 0),
+    })
+}
+
+/// Fills the frame with repeating 32x32 cells of varblocks, using 8x8 DCT in cells crossing the
+/// edges of the frame.
+fn sample_blocks(bw: usize, bh: usize) -> Vec<Block> {
+    use TransformType::*;
+
+    const SMALL: [TransformType; 16] = [
+        Dct8, Dct2, Dct4, Hornuss, Dct4x8, Dct8x4, Afv0, Afv1, Afv2, Afv3, Dct2, Dct4, Hornuss,
+        Afv0, Dct8x4, Dct4x8,
+    ];
+    let cells: [&[(u32, u32, TransformType)]; 8] = [
+        &[(0, 0, Dct32)],
+        &[(0, 0, Dct16), (2, 0, Dct16), (0, 2, Dct16), (2, 2, Dct16)],
+        &[
+            (0, 0, Dct16x8),
+            (1, 0, Dct16x8),
+            (2, 0, Dct16x8),
+            (3, 0, Dct16x8),
+            (0, 2, Dct8x16),
+            (2, 2, Dct8x16),
+            (0, 3, Dct8x16),
+            (2, 3, Dct8x16),
+        ],
+        &[
+            (0, 0, Dct32x8),
+            (1, 0, Dct32x8),
+            (2, 0, Dct16),
+            (2, 2, Dct16),
+        ],
+        &[
+            (0, 0, Dct8x32),
+            (0, 1, Dct8x32),
+            (0, 2, Dct8x16),
+            (2, 2, Dct8x16),
+            (0, 3, Dct8x16),
+            (2, 3, Dct8x16),
+        ],
+        &[(0, 0, Dct32x16), (2, 0, Dct32x16)],
+        &[(0, 0, Dct16x32), (0, 2, Dct16x32)],
+        &[],
+    ];
+
+    let mut map = new_grid::<Option<TransformType>>(bw, bh);
+    for cy in 0..bh / 4 {
+        for cx in 0..bw / 4 {
+            let cell = cells[(cx * 3 + cy) % cells.len()];
+            for &(x, y, ty) in cell {
+                *map.get_mut(cx * 4 + x as usize, cy * 4 + y as usize)
+                    .unwrap() = Some(ty);
+            }
+            if cell.is_empty() {
+                for (idx, &ty) in SMALL.iter().enumerate() {
+                    *map.get_mut(cx * 4 + idx % 4, cy * 4 + idx / 4).unwrap() = Some(ty);
+                }
+            }
+        }
+    }
+
+    let mut covered = new_grid::<bool>(bw, bh);
+    let mut blocks = Vec::new();
+    for y in 0..bh {
+        for x in 0..bw {
+            if *covered.get(x, y).unwrap() {
+                continue;
+            }
+            let transform_type = map.get(x, y).unwrap().unwrap_or(Dct8);
+            let (w, h) = transform_type.dct_select_size();
+            for dy in 0..h as usize {
+                for dx in 0..w as usize {
+                    *covered.get_mut(x + dx, y + dy).unwrap() = true;
+                }
+            }
+            blocks.push(Block {
+                left: x as u32,
+                top: y as u32,
+                transform_type,
+                hf_mul: 1 + ((x + 2 * y) % 4) as i32,
+            });
+        }
+    }
+    blocks
+}
+
+/// Writes the header of a Modular sub-image using the global MA tree, without transforms.
+pub(super) fn write_group_header(writer: &mut BitWriter) {
+    // use_global_tree, default weighted predictor parameters, and zero transforms.
+    writer.write_bool(true).unwrap();
+    writer.write_bool(true).unwrap();
+    writer.write_bits(2, 0).unwrap();
+}
+
+pub(super) fn new_grid<S: Default + Clone>(width: usize, height: usize) -> SimpleGrid<S> {
+    SimpleGrid::with_alloc_tracker(width, height, None).unwrap()
+}
+
+pub(super) fn push_channel(encoder: &mut Encoder, grid: &SimpleGrid<i32>) {
+    for &sample in grid.buf() {
+        encoder.push(0, pack_signed(sample));
+    }
+}
+
+fn order_id(transform_type: TransformType) -> usize {
+    let (w, h) = transform_type.dct_select_size();
+    match (w.max(h), w.min(h)) {
+        (1, 1) if transform_type == TransformType::Dct8 => 0,
+        (1, 1) => 1,
+        (2, 2) => 2,
+        (4, 4) => 3,
+        (2, 1) => 4,
+        (4, 1) => 5,
+        (4, 2) => 6,
+        _ => unimplemented!("varblocks larger than 32x32 are not supported"),
+    }
+}
+
+/// Computes the natural coefficient order of the given order ID, as (column, row) pairs of the
+/// wider orientation.
+fn natural_order(order_id: usize) -> Vec<(u8, u8)> {
+    const BLOCK_SIZES: [(usize, usize); 7] = [
+        (8, 8),
+        (8, 8),
+        (16, 16),
+        (32, 32),
+        (16, 8),
+        (32, 8),
+        (32, 16),
+    ];
+
+    let (bw, bh) = BLOCK_SIZES[order_id];
+    let y_scale = bw / bh;
+    let lbw = bw / 8;
+    let lbh = bh / 8;
+
+    let mut out = Vec::with_capacity(bw * bh);
+    for y in 0..lbh {
+        for x in 0..lbw {
+            out.push((x as u8, y as u8));
+        }
+    }
+    for dist in 1..(2 * bw) {
+        let margin = dist.saturating_sub(bw);
+        for order in margin..(dist - margin) {
+            let (x, y) = if dist % 2 == 1 {
+                (order, dist - 1 - order)
+            } else {
+                (dist - 1 - order, order)
+            };
+            if x < lbw && y < lbw {
+                continue;
+            }
+            if y % y_scale != 0 {
+                continue;
+            }
+            out.push((x as u8, (y / y_scale) as u8));
+        }
+    }
+    out
+}
+
+pub(super) struct Lcg(pub(super) u32);
+
+impl Lcg {
+    pub(super) fn next(&mut self) -> u32 {
+        self.0 = self.0.wrapping_mul(1103515245).wrapping_add(12345);
+        self.0 >> 1
+    }
+}
@@ -0,0 +1,84 @@
+use jxl_oxide::{LosslessEncoder, PixelFormat};
+
+mod util;
+
+#[test]
+fn vardct_coefficients() {
+    // Coefficients are only available for VarDCT frames.
+    let samples = (0..16 * 16)
+        .map(|i| (i * 7 % 256) as u8)
+        .collect::<Vec<_>>();
+    let image = util::encode_image(&LosslessEncoder::new(16, 16, PixelFormat::Gray), &samples);
+    assert!(image.vardct_coefficients(0).is_err());
+
+    // Malformed VarDCT frames fail the same way as rendering does.
+    let data = std::fs::read("tests/fuzz_findings/hf_coeff_non_zeros.fuzz").unwrap();
+    let image = util::read_image(data);
+    assert!(image.render_frame(0).is_err());
+    assert!(image.vardct_coefficients(0).is_err());
+}
+
+#[test]
+fn vardct_coefficients_fixture() {
+    let fixture = util::vardct::VarDctImage::sample(300, 200);
+    let image = util::read_image(fixture.encode());
+    let coeffs = image.vardct_coefficients(0).unwrap();
+
+    assert_eq!(coeffs.jpeg_upsampling(), [0; 3]);
+    assert_eq!(coeffs.quantizer().global_scale, fixture.global_scale);
+    assert_eq!(coeffs.quantizer().quant_lf, fixture.quant_lf);
+
+    let varblocks = coeffs.varblocks();
+    assert_eq!(varblocks.len(), fixture.blocks.len());
+    for (varblock, block) in varblocks.iter().zip(&fixture.blocks) {
+        assert_eq!((varblock.left(), varblock.top()), (block.left, block.top));
+        assert_eq!(varblock.transform_type(), block.transform_type);
+        assert_eq!(varblock.hf_mul(), block.hf_mul);
+    }
+
+    // Coefficients of transposed varblocks are stored in the layout of the inverse transform.
+    for c in 0..3 {
+        assert_eq!(coeffs.lf_quant(c).unwrap().buf(), fixture.lf_quant[c].buf());
+        assert_eq!(coeffs.hf_quant(c).buf(), fixture.hf_quant[c].buf());
+    }
+
+    let (x_from_y, b_from_y) = coeffs.cfl_factors();
+    assert_eq!(x_from_y.buf(), fixture.x_from_y.buf());
+    assert_eq!(b_from_y.buf(), fixture.b_from_y.buf());
+    let varblock = varblocks
+        .iter()
+        .find(|varblock| varblock.left() / 8 == 1 && varblock.top() / 8 == 2)
+        .unwrap();
+    let (x_factor, b_factor) = coeffs.varblock_cfl(varblock);
+    let x_expected = *fixture.x_from_y.get(1, 2).unwrap() as f32 / 84.0;
+    let b_expected = 1.0 + *fixture.b_from_y.get(1, 2).unwrap() as f32 / 84.0;
+    assert!((x_factor - x_expected).abs() < 1e-6);
+    assert!((b_factor - b_expected).abs() < 1e-6);
+
+    // Dequantized coefficients, used by the renderer, scale with the inverse of the quant
+    // multiplier of each varblock. Coefficients are biased towards zero with the default
+    // `quant_bias_numerator` of 0.145, and coefficients with magnitude 1 are biased differently.
+    let mut weights = std::collections::HashMap::new();
+    for varblock in varblocks {
+        for c in 0..3 {
+            let quant = coeffs.varblock_hf_quant(varblock, c).unwrap();
+            let dequant = coeffs.varblock_hf_dequant(varblock, c).unwrap();
+            for (idx, (&q, &dq)) in quant.iter().zip(&dequant).enumerate() {
+                if q == 0 {
+                    assert_eq!(dq, 0.0);
+                    continue;
+                }
+                if q.abs() == 1 {
+                    continue;
+                }
+                let q = q as f32;
+                let weight = dq / (q - 0.145 / q) * varblock.hf_mul() as f32;
+                let expected = *weights
+                    .entry((varblock.transform_type(), c, idx))
+                    .or_insert(weight);
+                assert!((weight - expected).abs() <= expected.abs() * 1e-5);
+            }
+        }
+    }
+    assert!(weights.len() > 1000);
+}
@@ -0,0 +1,262 @@
+use jxl_frame::{data::TocGroupKind, header::Encoding};
+use jxl_grid::SimpleGrid;
+use jxl_vardct::{BlockInfo, TransformType};
+
+use crate::{vardct, Error, IndexedFrame, Result};
+
+/// Kind of false-colour visualisation of a frame, rendered in place of pixels.
+///
+/// Every view except [`GroupBoundaries`][Self::GroupBoundaries] requires a VarDCT frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum DebugView {
+    /// Transform type of each varblock, one colour per type, with varblock boundaries.
+    TransformType,
+    /// Quantization multiplier `HfMul` of each varblock, from black (lowest in the frame) to white
+    /// (highest).
+    HfMul,
+    /// Sigma of the edge-preserving filter of each 8x8 block, from black (zero) to white (highest
+    /// in the frame). Black if the filter is disabled.
+    EpfSigma,
+    /// Chroma-from-luma multipliers of each 64x64 tile. X multiplier is mapped to red, with zero at
+    /// mid-grey, and B multiplier is mapped to blue, with one at mid-grey.
+    ChromaFromLuma,
+    /// Group boundaries in grey and LF group boundaries in red. Groups are shaded by compressed
+    /// size, if the frame has more than one TOC entry.
+    GroupBoundaries,
+}
+
+impl DebugView {
+    fn requires_vardct(self) -> bool {
+        self != Self::GroupBoundaries
+    }
+}
+
+/// Renders the debug view of the frame, in RGB with samples in the range of `[0, 1]`.
+pub(crate) fn render_debug_view(
+    frame: &IndexedFrame,
+    view: DebugView,
+    pool: &jxl_threadpool::JxlThreadPool,
+) -> Result<[SimpleGrid<f32>; 3]> {
+    let frame_header = frame.header();
+    if view.requires_vardct() && frame_header.encoding != Encoding::VarDct {
+        return Err(Error::NotSupported("debug view of Modular frame"));
+    }
+
+    let width = frame_header.color_sample_width() as usize;
+    let height = frame_header.color_sample_height() as usize;
+    let tracker = frame.alloc_tracker();
+    let mut out = DebugImage {
+        channels: [
+            SimpleGrid::with_alloc_tracker(width, height, tracker)?,
+            SimpleGrid::with_alloc_tracker(width, height, tracker)?,
+            SimpleGrid::with_alloc_tracker(width, height, tracker)?,
+        ],
+    };
+
+    if view == DebugView::GroupBoundaries {
+        render_group_boundaries(frame, &mut out);
+        return Ok(out.channels);
+    }
+
+    let (lf_global, _, lf_groups) = vardct::load_block_metadata(frame, pool)?;
+    let lf_chan_corr = &lf_global.vardct.as_ref().unwrap().lf_chan_corr;
+
+    // LF group covers `group_dim` blocks
+    let group_dim = frame_header.group_dim() as usize;
+    let lf_groups_per_row = frame_header.lf_groups_per_row();
+    let hf_metas = (0..frame_header.num_lf_groups()).map(|lf_group_idx| {
+        let left = (lf_group_idx % lf_groups_per_row) as usize * group_dim;
+        let top = (lf_group_idx / lf_groups_per_row) as usize * group_dim;
+        let hf_meta = lf_groups[&lf_group_idx].hf_meta.as_ref().unwrap();
+        (left, top, hf_meta)
+    });
+
+    match view {
+        DebugView::TransformType | DebugView::HfMul => {
+            let mut varblocks = Vec::new();
+            for (left, top, hf_meta) in hf_metas {
+                let block_info = &hf_meta.block_info;
+                for y in 0..block_info.height() {
+                    for x in 0..block_info.width() {
+                        if let &BlockInfo::Data { dct_select, hf_mul } =
+                            block_info.get(x, y).unwrap()
+                        {
+                            varblocks.push((left + x, top + y, dct_select, hf_mul));
+                        }
+                    }
+                }
+            }
+
+            let min_hf_mul = varblocks.iter().map(|vb| vb.3).min().unwrap_or(0);
+            let max_hf_mul = varblocks.iter().map(|vb| vb.3).max().unwrap_or(0);
+            for (bx, by, dct_select, hf_mul) in varblocks {
+                let colour = if view == DebugView::TransformType {
+                    transform_type_colour(dct_select)
+                } else {
+                    heat_colour(normalize(
+                        (hf_mul - min_hf_mul) as f32,
+                        (max_hf_mul - min_hf_mul) as f32,
+                    ))
+                };
+                let (bw, bh) = dct_select.dct_select_size();
+                let rect = (bx * 8, by * 8, bw as usize * 8, bh as usize * 8);
+                out.fill_rect(rect, colour);
+                out.outline_rect(rect, colour.map(|v| v * 0.5));
+            }
+        }
+        DebugView::EpfSigma => {
+            let hf_metas = hf_metas.collect::<Vec<_>>();
+            let max_sigma = hf_metas
+                .iter()
+                .flat_map(|(_, _, hf_meta)| hf_meta.epf_sigma.buf())
+                .fold(0f32, |max, &sigma| max.max(sigma));
+            for (left, top, hf_meta) in hf_metas {
+                let epf_sigma = &hf_meta.epf_sigma;
+                for y in 0..epf_sigma.height() {
+                    for x in 0..epf_sigma.width() {
+                        let sigma = *epf_sigma.get(x, y).unwrap();
+                        let rect = ((left + x) * 8, (top + y) * 8, 8, 8);
+                        out.fill_rect(rect, heat_colour(normalize(sigma, max_sigma)));
+                    }
+                }
+            }
+        }
+        DebugView::ChromaFromLuma => {
+            let colour_factor = lf_chan_corr.colour_factor as f32;
+            for (left, top, hf_meta) in hf_metas {
+                let x_from_y = &hf_meta.x_from_y;
+                let b_from_y = &hf_meta.b_from_y;
+                for y in 0..x_from_y.height() {
+                    for x in 0..x_from_y.width() {
+                        let x_factor = lf_chan_corr.base_correlation_x
+                            + *x_from_y.get(x, y).unwrap() as f32 / colour_factor;
+                        let b_factor = lf_chan_corr.base_correlation_b
+                            + *b_from_y.get(x, y).unwrap() as f32 / colour_factor;
+                        let colour = [
+                            (0.5 + x_factor).clamp(0.0, 1.0),
+                            0.5,
+                            (b_factor * 0.5).clamp(0.0, 1.0),
+                        ];
+                        let rect = ((left + x * 8) * 8, (top + y * 8) * 8, 64, 64);
+                        out.fill_rect(rect, colour);
+                        out.outline_rect(rect, colour.map(|v| v * 0.5));
+                    }
+                }
+            }
+        }
+        DebugView::GroupBoundaries => unreachable!(),
+    }
+
+    Ok(out.channels)
+}
+
+fn render_group_boundaries(frame: &IndexedFrame, out: &mut DebugImage) {
+    let frame_header = frame.header();
+    let toc = frame.toc();
+
+    let num_groups = frame_header.num_groups() as usize;
+    let mut group_sizes = vec![0u64; num_groups];
+    if !toc.is_single_entry() {
+        for group in toc.iter_bitstream_order() {
+            if let TocGroupKind::GroupPass { group_idx, .. } = group.kind {
+                group_sizes[group_idx as usize] += group.size as u64;
+            }
+        }
+    }
+    let max_size = group_sizes.iter().copied().max().unwrap_or(0);
+
+    let group_dim = frame_header.group_dim() as usize;
+    let groups_per_row = frame_header.groups_per_row() as usize;
+    for (group_idx, &size) in group_sizes.iter().enumerate() {
+        let left = (group_idx % groups_per_row) * group_dim;
+        let top = (group_idx / groups_per_row) * group_dim;
+        let shade = if max_size == 0 {
+            0.25
+        } else {
+            0.1 + 0.4 * size as f32 / max_size as f32
+        };
+        let rect = (left, top, group_dim, group_dim);
+        out.fill_rect(rect, [shade; 3]);
+        out.outline_rect(rect, [0.8; 3]);
+    }
+
+    let lf_group_dim = frame_header.lf_group_dim() as usize;
+    let lf_groups_per_row = frame_header.lf_groups_per_row() as usize;
+    for lf_group_idx in 0..frame_header.num_lf_groups() as usize {
+        let left = (lf_group_idx % lf_groups_per_row) * lf_group_dim;
+        let top = (lf_group_idx / lf_groups_per_row) * lf_group_dim;
+        out.outline_rect((left, top, lf_group_dim, lf_group_dim), [1.0, 0.0, 0.0]);
+    }
+}
+
+struct DebugImage {
+    channels: [SimpleGrid<f32>; 3],
+}
+
+impl DebugImage {
+    /// Fills the rectangle `(left, top, width, height)`, clipped to the image.
+    fn fill_rect(&mut self, rect: (usize, usize, usize, usize), colour: [f32; 3]) {
+        let (left, top, width, height) = rect;
+        for (grid, v) in self.channels.iter_mut().zip(colour) {
+            let grid_width = grid.width();
+            let grid_height = grid.height();
+            if left >= grid_width || top >= grid_height {
+                continue;
+            }
+            let right = (left + width).min(grid_width);
+            let bottom = (top + height).min(grid_height);
+            for row in grid
+                .buf_mut()
+                .chunks_exact_mut(grid_width)
+                .take(bottom)
+                .skip(top)
+            {
+                row[left..right].fill(v);
+            }
+        }
+    }
+
+    /// Draws one-pixel wide top and left edges of the rectangle, so that adjacent rectangles
+    /// don't have doubled boundaries.
+    fn outline_rect(&mut self, rect: (usize, usize, usize, usize), colour: [f32; 3]) {
+        let (left, top, width, height) = rect;
+        self.fill_rect((left, top, width, 1), colour);
+        self.fill_rect((left, top, 1, height), colour);
+    }
+}
+
+fn normalize(value: f32, max: f32) -> f32 {
+    if max > 0.0 {
+        (value / max).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// Maps `t` in `[0, 1]` to black-red-yellow-white gradient.
+fn heat_colour(t: f32) -> [f32; 3] {
+    [
+        (t * 3.0).clamp(0.0, 1.0),
+        (t * 3.0 - 1.0).clamp(0.0, 1.0),
+        (t * 3.0 - 2.0).clamp(0.0, 1.0),
+    ]
+}
+
+/// Picks a colour of the transform type, with hues spread by the golden angle so that types with
+/// adjacent indices look different.
+fn transform_type_colour(ty: TransformType) -> [f32; 3] {
+    let hue = (ty as u8 as f32 * 0.618_034).fract() * 6.0;
+    let (s, v) = (0.7f32, 0.95f32);
+    let c = v * s;
+    let x = c * (1.0 - (hue % 2.0 - 1.0).abs());
+    let m = v - c;
+    let (r, g, b) = match hue as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    [r + m, g + m, b + m]
+}
@@ -0,0 +1,357 @@
+use jxl_frame::header::Encoding;
+use jxl_grid::SimpleGrid;
+use jxl_image::ImageHeader;
+use jxl_modular::image::ChannelSection;
+
+use crate::{
+    filter, inner, modular, region::ImageWithRegion, state::RenderCache, vardct, Error,
+    IndexedFrame, Region, Result,
+};
+
+/// Returns the downscaling factor in log2, checking that it's one of the supported factors.
+pub(crate) fn downscale_shift(factor: u32) -> Result<u32> {
+    match factor {
+        1 | 2 | 4 | 8 => Ok(factor.trailing_zeros()),
+        _ => Err(Error::NotSupported(
+            "downscaling factor other than 1, 2, 4 or 8",
+        )),
+    }
+}
+
+/// Returns the size of an image of the given size downscaled by `1 << shift`, rounding up.
+pub(crate) fn downscaled_size(width: u32, height: u32, shift: u32) -> (u32, u32) {
+    let add = (1u32 << shift) - 1;
+    ((width + add) >> shift, (height + add) >> shift)
+}
+
+/// Maps `image_region` of the image downscaled by `1 << shift`, in the coordinates with
+/// orientation applied, to the region of the canvas in full resolution without orientation.
+///
+/// Returned region is aligned to `1 << shift` and clipped to the canvas, so that downscaling it
+/// results in the region of the downscaled canvas which corresponds to `image_region`.
+pub(crate) fn downscaled_region_to_canvas(
+    image_header: &ImageHeader,
+    image_region: Option<Region>,
+    shift: u32,
+) -> Region {
+    let metadata = &image_header.metadata;
+    let canvas = Region::with_size(image_header.size.width, image_header.size.height);
+    let Some(image_region) = image_region else {
+        return canvas;
+    };
+
+    let (width, height) = downscaled_size(canvas.width, canvas.height, shift);
+    let (width, height, _, _) = metadata.apply_orientation(width, height, 0, 0, false);
+    let image_region = image_region.intersection(Region::with_size(width, height));
+    if image_region.is_empty() {
+        return Region::empty();
+    }
+
+    let (_, _, left, top) =
+        metadata.apply_orientation(width, height, image_region.left, image_region.top, true);
+    let (_, _, right, bottom) = metadata.apply_orientation(
+        width,
+        height,
+        image_region.right() - 1,
+        image_region.bottom() - 1,
+        true,
+    );
+    Region {
+        left: left.min(right),
+        top: top.min(bottom),
+        width: right.abs_diff(left) + 1,
+        height: bottom.abs_diff(top) + 1,
+    }
+    .upsample(shift)
+    .intersection(canvas)
+}
+
+/// Maps the region of the canvas without orientation to the region of the image with
+/// orientation applied.
+pub(crate) fn canvas_region_to_image(image_header: &ImageHeader, region: Region) -> Region {
+    if region.is_empty() {
+        return region;
+    }
+
+    let metadata = &image_header.metadata;
+    let width = image_header.size.width;
+    let height = image_header.size.height;
+    let (_, _, left, top) =
+        metadata.apply_orientation(width, height, region.left, region.top, false);
+    let (_, _, right, bottom) = metadata.apply_orientation(
+        width,
+        height,
+        region.right() - 1,
+        region.bottom() - 1,
+        false,
+    );
+    Region {
+        left: left.min(right),
+        top: top.min(bottom),
+        width: right.abs_diff(left) + 1,
+        height: bottom.abs_diff(top) + 1,
+    }
+}
+
+/// Returns whether the frame covers the entire canvas by itself, without blending or an LF
+/// frame.
+fn is_self_contained(frame: &IndexedFrame) -> bool {
+    let image_header = frame.image_header();
+    let frame_header = frame.header();
+    frame_header.x0 == 0
+        && frame_header.y0 == 0
+        && frame_header.width == image_header.size.width
+        && frame_header.height == image_header.size.height
+        && frame_header.resets_canvas
+        && !frame_header.flags.use_lf_frame()
+}
+
+/// Renders a keyframe in 1:8 scale from the LF image, reading only LfGlobal and LF groups.
+///
+/// `region` is the region of the frame in full resolution, aligned to 8. Returned image covers
+/// `region` downscaled by 8.
+///
+/// Returns `None` if the keyframe cannot be rendered that way, which is the case if the frame is
+/// not a VarDCT frame covering the entire canvas by itself, uses upsampling or an LF frame, or has
+/// extra channels stored in pass groups. Image features, such as patches, splines and noise, and
+/// restoration filters are not rendered.
+///
+/// Returned image has the same set of channels as a post-processed keyframe.
+pub(crate) fn render_lf_only(
+    frame: &IndexedFrame,
+    region: Region,
+    pool: &jxl_threadpool::JxlThreadPool,
+) -> Result<Option<ImageWithRegion>> {
+    let image_header = frame.image_header();
+    let metadata = &image_header.metadata;
+    let frame_header = frame.header();
+
+    let ec_upsampled = frame_header
+        .ec_upsampling
+        .iter()
+        .zip(&metadata.ec_info)
+        .any(|(&upsampling, ec_info)| upsampling != 1 || ec_info.dim_shift != 0);
+    if frame_header.encoding != Encoding::VarDct
+        || !is_self_contained(frame)
+        || frame_header.upsampling != 1
+        || ec_upsampled
+    {
+        return Ok(None);
+    }
+
+    let (mut lf_xyb, mut gmodular) = vardct::render_lf_image(frame, pool)?;
+    let tracker = frame.alloc_tracker();
+    let region = region.downsample(3);
+
+    if frame_header.do_ycbcr {
+        // LF image always consists of three color channels.
+        let [cb, y, cr] = lf_xyb.buffer_mut() else {
+            unreachable!("LF image should have three channels");
+        };
+        filter::apply_jpeg_upsampling([cb, y, cr], frame_header.jpeg_upsampling);
+    }
+    let mut fb = lf_xyb.clone_intersection(region)?;
+
+    let extra_channel_from = gmodular.extra_channel_from();
+    if let Some(image) = gmodular.modular.image_mut() {
+        let preview = image.finish_preview(
+            frame.pass_shifts(),
+            |section| !matches!(section, ChannelSection::PassGroups(_)),
+            tracker,
+            pool,
+        )?;
+        if !preview.is_complete() {
+            tracing::debug!("Extra channels are stored in pass groups");
+            return Ok(None);
+        }
+
+        let hshift = preview.hshift();
+        let vshift = preview.vshift();
+        let channels = preview.into_channels();
+        let origin = (region.left as usize, region.top as usize);
+        for (g, ec_info) in channels
+            .iter()
+            .skip(extra_channel_from)
+            .zip(&metadata.ec_info)
+        {
+            let out = fb.add_channel()?;
+            downscale_channel(g, out, origin, (hshift, vshift), 3, |v| {
+                ec_info.bit_depth.parse_integer_sample(v)
+            });
+        }
+    }
+
+    finish_color(frame, &mut fb);
+    Ok(Some(fb))
+}
+
+/// Renders a keyframe in 1:2 or 1:4 scale, downscaled by `1 << shift`.
+///
+/// `region` is the region of the frame in full resolution, aligned to `1 << shift`. Returned
+/// image covers `region` downscaled by `1 << shift`.
+///
+/// VarDCT frames are rendered with reduced-size inverse transforms, using only low frequency
+/// coefficients of each varblock and only the passes needed for the downscaled image. Modular
+/// frames are rendered with the last squeeze steps skipped. Image features and restoration filters
+/// are not rendered.
+///
+/// Returns `None` if the keyframe cannot be rendered that way, which is the case if the frame
+/// doesn't cover the entire canvas by itself, uses upsampling or an LF frame, or is a Modular
+/// frame without squeeze.
+///
+/// Returned image has the same set of channels as a post-processed keyframe.
+pub(crate) fn render_reduced(
+    frame: &IndexedFrame,
+    region: Region,
+    shift: u32,
+    pool: &jxl_threadpool::JxlThreadPool,
+) -> Result<Option<ImageWithRegion>> {
+    let frame_header = frame.header();
+    if !is_self_contained(frame) || frame_header.upsampling != 1 {
+        return Ok(None);
+    }
+
+    let mut cache = RenderCache::new(frame);
+    let lf_global = frame
+        .try_parse_lf_global()
+        .ok_or(Error::IncompleteFrame)??;
+    let has_squeeze = lf_global.gmodular.modular.has_squeeze();
+    cache.lf_global = Some(lf_global);
+
+    let mut fb = match frame_header.encoding {
+        Encoding::Modular if !has_squeeze => return Ok(None),
+        Encoding::Modular => {
+            let full_frame_region = Region::with_size(
+                frame_header.color_sample_width(),
+                frame_header.color_sample_height(),
+            );
+            let padded_region = if frame_header.do_ycbcr {
+                // Chroma upsampling references adjacent samples.
+                region.pad(1).downsample(2).upsample(2)
+            } else {
+                region
+            }
+            .intersection(full_frame_region);
+
+            let (mut fb, gmodular) =
+                modular::render_modular(frame, &mut cache, padded_region, shift, pool)?;
+            if frame_header.do_ycbcr {
+                // Extra channels are not appended yet, and frames with YCbCr have three color
+                // channels.
+                let [cb, y, cr] = fb.buffer_mut() else {
+                    unreachable!("YCbCr frame should have three color channels");
+                };
+                filter::apply_jpeg_upsampling([cb, y, cr], frame_header.jpeg_upsampling);
+            }
+            if fb.region() != region {
+                fb = fb.clone_intersection(region)?;
+            }
+            inner::append_extra_channels(frame, &mut fb, gmodular, region)?;
+            downscale_box(&fb, shift)?
+        }
+        Encoding::VarDct => {
+            // Color channels are rendered in reduced resolution, including chroma channels of
+            // subsampled frames.
+            let (mut fb, gmodular) =
+                vardct::render_vardct(frame, None, &mut cache, region, None, shift, pool)?;
+            let reduced_region = region.downsample(shift);
+            if fb.region() != reduced_region {
+                fb = fb.clone_intersection(reduced_region)?;
+            }
+
+            // Extra channels are decoded in full resolution.
+            let mut ec =
+                ImageWithRegion::from_region_and_tracker(0, region, frame.alloc_tracker())?;
+            inner::append_extra_channels(frame, &mut ec, gmodular, region)?;
+            for g in downscale_box(&ec, shift)?.buffer() {
+                fb.add_channel()?.buf_mut().copy_from_slice(g.buf());
+            }
+            fb
+        }
+    };
+
+    finish_color(frame, &mut fb);
+    Ok(Some(fb))
+}
+
+/// Converts color channels of the downscaled image to the output color space, and removes
+/// unused color channels of grayscale images.
+fn finish_color(frame: &IndexedFrame, fb: &mut ImageWithRegion) {
+    let image_header = frame.image_header();
+    if frame.header().do_ycbcr {
+        // Color channels are removed below, so there are at least three channels here.
+        let [cb, y, cr, ..] = fb.buffer_mut() else {
+            unreachable!("YCbCr frame should have three color channels");
+        };
+        jxl_color::ycbcr_to_rgb([cb, y, cr]);
+    }
+    inner::convert_color(image_header, fb.buffer_mut());
+
+    let channels = if image_header.metadata.grayscale() {
+        1
+    } else {
+        3
+    };
+    fb.remove_channels(channels..3);
+}
+
+/// Downscales every channel of the image by `1 << shift` with box filter.
+///
+/// The region of the image should be aligned to `1 << shift`, except for the right and bottom
+/// edges. Returned image covers the region downscaled by `1 << shift`.
+pub(crate) fn downscale_box(image: &ImageWithRegion, shift: u32) -> Result<ImageWithRegion> {
+    let mut out = ImageWithRegion::from_region_and_tracker(
+        image.channels(),
+        image.region().downsample(shift),
+        image.alloc_tracker(),
+    )?;
+    for (g, out) in image.buffer().iter().zip(out.buffer_mut()) {
+        downscale_channel(g, out, (0, 0), (0, 0), shift, |v| v);
+    }
+    Ok(out)
+}
+
+/// Downscales `input`, which is already downscaled by `(1 << hshift, 1 << vshift)`, to `out`
+/// which is downscaled by `1 << shift`. `origin` is the position of `out` in the downscaled
+/// coordinates of `input`.
+///
+/// Samples are averaged if `input` has higher resolution, and repeated otherwise.
+fn downscale_channel<T: Copy>(
+    input: &SimpleGrid<T>,
+    out: &mut SimpleGrid<f32>,
+    (left, top): (usize, usize),
+    (hshift, vshift): (u32, u32),
+    shift: u32,
+    map: impl Fn(T) -> f32,
+) {
+    let in_width = input.width();
+    let in_height = input.height();
+    let out_width = out.width();
+    let out_height = out.height();
+    let in_buf = input.buf();
+    let out_buf = out.buf_mut();
+
+    let scale_range = |pos: usize, shift_in: u32, len: usize| -> (usize, usize) {
+        if shift_in >= shift {
+            let pos = pos >> (shift_in - shift);
+            (pos.min(len - 1), pos.min(len - 1) + 1)
+        } else {
+            let diff = shift - shift_in;
+            let start = (pos << diff).min(len - 1);
+            let end = ((pos + 1) << diff).min(len);
+            (start, end)
+        }
+    };
+
+    for y in 0..out_height {
+        let (y0, y1) = scale_range(top + y, vshift, in_height);
+        for x in 0..out_width {
+            let (x0, x1) = scale_range(left + x, hshift, in_width);
+            let mut sum = 0f32;
+            for row in in_buf[y0 * in_width..y1 * in_width].chunks_exact(in_width) {
+                sum += row[x0..x1].iter().map(|&v| map(v)).sum::<f32>();
+            }
+            out_buf[y * out_width + x] = sum / ((y1 - y0) * (x1 - x0)) as f32;
+        }
+    }
+}
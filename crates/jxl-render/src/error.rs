@@ -101,6 +101,38 @@ impl Error {
             _ => false,
         }
     }
+
+    /// Returns the TOC group where the error occurred, if it's known.
+    pub fn group(&self) -> Option<jxl_frame::data::TocGroupKind> {
+        match self {
+            Error::Frame(e) => e.group(),
+            _ => None,
+        }
+    }
+
+    /// Returns the location where the error occurred, if it's known.
+    ///
+    /// See [`jxl_frame::Error::location`] for how the bit offset is measured.
+    pub fn location(&self) -> Option<&jxl_bitstream::ErrorLocation> {
+        match self {
+            Error::Bitstream(e) => e.location(),
+            Error::Decoder(e) => e.location(),
+            Error::Modular(e) => e.location(),
+            Error::Frame(e) => e.location(),
+            _ => None,
+        }
+    }
+
+    /// Returns the mutable reference to the location where the error occurred, if it's known.
+    pub fn location_mut(&mut self) -> Option<&mut jxl_bitstream::ErrorLocation> {
+        match self {
+            Error::Bitstream(e) => e.location_mut(),
+            Error::Decoder(e) => e.location_mut(),
+            Error::Modular(e) => e.location_mut(),
+            Error::Frame(e) => e.location_mut(),
+            _ => None,
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -86,7 +86,7 @@ pub(crate) fn render_frame(
     let (mut fb, gmodular) = match frame_header.encoding {
         Encoding::Modular => {
             let (grid, gmodular) =
-                modular::render_modular(frame, cache, color_padded_region, &pool)?;
+                modular::render_modular(frame, cache, color_padded_region, 0, &pool)?;
             (grid, Some(gmodular))
         }
         Encoding::VarDct => {
@@ -96,6 +96,7 @@ pub(crate) fn render_frame(
                 cache,
                 color_padded_region,
                 image_region,
+                0,
                 &pool,
             );
             match (result, reference_frames.lf) {
@@ -204,7 +205,7 @@ fn upsample_color_channels(
     })
 }
 
-fn append_extra_channels(
+pub(crate) fn append_extra_channels(
     frame: &IndexedFrame,
     fb: &mut ImageWithRegion,
     gmodular: GlobalModular,
@@ -4,14 +4,16 @@ use std::sync::Arc;
 use jxl_bitstream::{Bitstream, Bundle};
 use jxl_frame::{
     data::{LfGlobalVarDct, LfGroup},
-    header::FrameType,
+    header::{Encoding, FrameType},
     Frame, FrameContext,
 };
 use jxl_grid::AllocTracker;
-use jxl_image::{ImageHeader, ImageMetadata};
+use jxl_image::{BitDepth, ImageHeader, ImageMetadata, Level};
 
 mod blend;
 mod dct;
+mod debug_view;
+mod downscale;
 mod error;
 mod features;
 mod filter;
@@ -20,11 +22,13 @@ mod modular;
 mod region;
 mod state;
 mod vardct;
+pub use debug_view::DebugView;
 pub use error::{Error, Result};
 pub use features::render_spot_color;
 use jxl_modular::{image::TransformedModularSubimage, MaConfig};
 use jxl_threadpool::JxlThreadPool;
 pub use region::Region;
+pub use vardct::{VarDctCoefficients, Varblock};
 
 use region::ImageWithRegion;
 use state::*;
@@ -35,9 +39,11 @@ pub struct RenderContext {
     image_header: Arc<ImageHeader>,
     pool: JxlThreadPool,
     tracker: Option<AllocTracker>,
+    level: Option<Level>,
     pub(crate) frames: Vec<Arc<IndexedFrame>>,
     pub(crate) renders: Vec<Arc<FrameRenderHandle>>,
     pub(crate) keyframes: Vec<usize>,
+    pub(crate) skipped_keyframes: usize,
     pub(crate) keyframe_in_progress: Option<usize>,
     pub(crate) refcounts: Vec<usize>,
     pub(crate) frame_deps: Vec<FrameDependence>,
@@ -58,6 +64,7 @@ impl RenderContext {
 pub struct RenderContextBuilder {
     pool: Option<JxlThreadPool>,
     tracker: Option<AllocTracker>,
+    level: Option<Level>,
 }
 
 impl RenderContextBuilder {
@@ -71,14 +78,22 @@ impl RenderContextBuilder {
         self
     }
 
+    /// Enforces the limits of the given codestream level while loading frames.
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = Some(level);
+        self
+    }
+
     pub fn build(self, image_header: Arc<ImageHeader>) -> RenderContext {
         RenderContext {
             image_header,
             tracker: self.tracker,
+            level: self.level,
             pool: self.pool.unwrap_or_else(JxlThreadPool::none),
             frames: Vec::new(),
             renders: Vec::new(),
             keyframes: Vec::new(),
+            skipped_keyframes: 0,
             keyframe_in_progress: None,
             refcounts: Vec::new(),
             frame_deps: Vec::new(),
@@ -112,9 +127,11 @@ impl RenderContext {
     }
 
     /// Returns the number of loaded keyframes in the context.
+    ///
+    /// Keyframes skipped by [`reset_to_keyframe`][Self::reset_to_keyframe] are counted as loaded.
     #[inline]
     pub fn loaded_keyframes(&self) -> usize {
-        self.keyframes.len()
+        self.skipped_keyframes + self.keyframes.len()
     }
 
     /// Returns the number of loaded frames in the context, including frames that are not shown
@@ -221,6 +238,7 @@ impl RenderContext {
                 image_header: image_header.clone(),
                 tracker: self.tracker.as_ref(),
                 pool: self.pool.clone(),
+                level: self.level,
             },
         ) {
             Ok(frame) => frame,
@@ -240,6 +258,24 @@ impl RenderContext {
         Ok(self.loading_frame.as_mut().unwrap())
     }
 
+    /// Discards all loaded frames, and prepares to load frames starting from the given keyframe.
+    ///
+    /// The next frame to be loaded should be the start of the keyframe, which doesn't depend on
+    /// any of the previous frames, such as frames listed in the frame index. Keyframes before
+    /// `keyframe_idx` are not available after the reset, and frame indices are counted from the
+    /// next frame.
+    pub fn reset_to_keyframe(&mut self, keyframe_idx: usize) {
+        let mut builder = RenderContext::builder().pool(self.pool.clone());
+        if let Some(tracker) = self.tracker.take() {
+            builder = builder.alloc_tracker(tracker);
+        }
+        if let Some(level) = self.level {
+            builder = builder.level(level);
+        }
+        *self = builder.build(Arc::clone(&self.image_header));
+        self.skipped_keyframes = keyframe_idx;
+    }
+
     pub fn current_loading_frame(&mut self) -> Option<&mut IndexedFrame> {
         self.try_finalize_current_frame();
         self.loading_frame.as_mut()
@@ -266,6 +302,7 @@ impl RenderContext {
     /// Returns the frame with the keyframe index, or `None` if the keyframe does not exist.
     #[inline]
     pub fn keyframe(&self, keyframe_idx: usize) -> Option<&IndexedFrame> {
+        let keyframe_idx = keyframe_idx.checked_sub(self.skipped_keyframes)?;
         if keyframe_idx == self.keyframes.len() {
             self.loading_frame()
         } else if let Some(&idx) = self.keyframes.get(keyframe_idx) {
@@ -283,6 +320,15 @@ impl RenderContext {
             self.frames.get(frame_idx).map(|x| &**x)
         }
     }
+
+    /// Returns the frame index of the completely loaded keyframe.
+    fn keyframe_frame_idx(&self, keyframe_idx: usize) -> Result<usize> {
+        keyframe_idx
+            .checked_sub(self.skipped_keyframes)
+            .and_then(|idx| self.keyframes.get(idx))
+            .copied()
+            .ok_or(Error::IncompleteFrame)
+    }
 }
 
 impl RenderContext {
@@ -343,7 +389,7 @@ impl RenderContext {
         };
         let prev_keyframes = &self.keyframes[..keyframe_idx];
 
-        let visible_frames_num = keyframe_idx + is_keyframe as usize;
+        let visible_frames_num = self.skipped_keyframes + keyframe_idx + is_keyframe as usize;
 
         let invisible_frames_num = if is_keyframe {
             0
@@ -460,10 +506,7 @@ impl RenderContext {
         keyframe_idx: usize,
         image_region: Option<Region>,
     ) -> Result<ImageWithRegion> {
-        let idx = *self
-            .keyframes
-            .get(keyframe_idx)
-            .ok_or(Error::IncompleteFrame)?;
+        let idx = self.keyframe_frame_idx(keyframe_idx)?;
         let mut grid = self.render_by_index(idx, image_region)?;
         let frame = &*self.frames[idx];
 
@@ -471,6 +514,326 @@ impl RenderContext {
         Ok(grid)
     }
 
+    /// Renders the keyframe in reduced resolution, downscaled by `factor`.
+    ///
+    /// `factor` should be one of 1, 2, 4 and 8. `image_region` is the region of the downscaled
+    /// image, with orientation applied, which can be mapped to the region in full resolution with
+    /// [`full_resolution_region`][Self::full_resolution_region].
+    ///
+    /// VarDCT keyframes are rendered in 1:8 scale from the LF image if possible, without decoding
+    /// HF coefficients, and in 1:2 and 1:4 scale with reduced-size inverse transforms. Modular
+    /// keyframes with squeeze are rendered in 1:2 and 1:4 scale with the last squeeze steps
+    /// skipped. Image features and restoration filters are skipped in those cases. Other
+    /// keyframes are rendered in full resolution, then downscaled with box filter.
+    ///
+    /// Returned image covers the keyframe downscaled by `factor`, rounded up, or the region of it
+    /// corresponding to `image_region`. Orientation is not applied.
+    pub fn render_keyframe_downscaled(
+        &self,
+        keyframe_idx: usize,
+        factor: u32,
+        image_region: Option<Region>,
+    ) -> Result<ImageWithRegion> {
+        let shift = downscale::downscale_shift(factor)?;
+        if shift == 0 {
+            return self.render_keyframe(keyframe_idx, image_region);
+        }
+
+        let idx = self.keyframe_frame_idx(keyframe_idx)?;
+        let frame = &*self.frames[idx];
+        let region =
+            downscale::downscaled_region_to_canvas(&self.image_header, image_region, shift);
+
+        let grid = if shift == 3 {
+            downscale::render_lf_only(frame, region, &self.pool)?
+        } else {
+            downscale::render_reduced(frame, region, shift, &self.pool)?
+        };
+        if let Some(grid) = grid {
+            return Ok(grid);
+        }
+
+        tracing::debug!("Cannot render in reduced resolution, downscaling full resolution image");
+        let full_image_region = downscale::canvas_region_to_image(&self.image_header, region);
+        let mut grid = self.render_keyframe(keyframe_idx, Some(full_image_region))?;
+        if grid.region() != region {
+            grid = grid.clone_intersection(region)?;
+        }
+        downscale::downscale_box(&grid, shift)
+    }
+
+    /// Renders the keyframe in bands of group rows, passing each band to `sink` as soon as it's
+    /// rendered.
+    ///
+    /// Each band has the width of the image and the height of a group, except for the last one,
+    /// and they are passed in top-to-bottom order. Bands are post-processed in the same way as
+    /// [`render_keyframe`][Self::render_keyframe], including restoration filters, upsampling and
+    /// colour conversion, and orientation is not applied. Regions of returned images are in the
+    /// coordinates of the canvas.
+    ///
+    /// Bands of keyframes covering the entire image by themselves are rendered one by one, so
+    /// that peak memory usage is proportional to the width of the image times the group size.
+    /// Other keyframes, such as ones referencing other frames, ones with an LF frame, and
+    /// Modular frames with global palette or squeeze, are rendered in full before being split
+    /// into bands, so peak memory usage is the same as [`render_keyframe`][Self::render_keyframe].
+    ///
+    /// Rendering stops at the first error returned by `sink`.
+    pub fn render_keyframe_streaming<E: From<Error>>(
+        &self,
+        keyframe_idx: usize,
+        mut sink: impl FnMut(ImageWithRegion) -> std::result::Result<(), E>,
+    ) -> std::result::Result<(), E> {
+        let idx = self.keyframe_frame_idx(keyframe_idx)?;
+        let frame = &*self.frames[idx];
+        let image_header = frame.image_header();
+        let frame_header = frame.header();
+
+        let canvas_width = image_header.size.width;
+        let canvas_height = image_header.size.height;
+        let band_height = frame_header.group_dim() * frame_header.upsampling;
+        let bands = (0..canvas_height)
+            .step_by(band_height as usize)
+            .map(|top| Region {
+                left: 0,
+                top: top as i32,
+                width: canvas_width,
+                height: band_height.min(canvas_height - top),
+            });
+
+        let mut cache = RenderCache::new(frame);
+        let lf_global = frame
+            .try_parse_lf_global()
+            .ok_or(Error::IncompleteFrame)?
+            .map_err(Error::from)?;
+        let has_global_transform =
+            lf_global.gmodular.modular.has_palette() || lf_global.gmodular.modular.has_squeeze();
+        cache.lf_global = Some(lf_global);
+
+        let streamable = self.frame_deps[idx].indices().next().is_none()
+            && !has_global_transform
+            && frame_header.x0 == 0
+            && frame_header.y0 == 0
+            && frame_header.width == canvas_width
+            && frame_header.height == canvas_height
+            && frame_header.resets_canvas;
+        if !streamable {
+            tracing::debug!("Cannot render keyframe in bands, splitting full image");
+            let mut grid = self.render_keyframe(keyframe_idx, None)?;
+            // Split from the bottom, so that rows are moved into bands as the image shrinks, and
+            // the first band keeps the buffer of the image.
+            let top = grid.region().top;
+            let mut band_grids = bands
+                .skip(1)
+                .rev()
+                .map(|band| grid.split_off_rows(band.top.abs_diff(top)))
+                .collect::<Vec<_>>();
+            band_grids.push(grid);
+            while let Some(band) = band_grids.pop() {
+                sink(band)?;
+            }
+            return Ok(());
+        }
+
+        let frame_visibility = self.get_previous_frames_visibility(frame);
+        let lf_group_dim = frame_header.lf_group_dim();
+        let lf_groups_per_row = frame_header.lf_groups_per_row();
+        for band in bands {
+            let _guard = tracing::trace_span!("Render band", top = band.top).entered();
+
+            let image_region = downscale::canvas_region_to_image(image_header, band);
+            let mut grid = inner::render_frame(
+                frame,
+                ReferenceFrames::default(),
+                &mut cache,
+                Some(image_region),
+                self.pool.clone(),
+                frame_visibility,
+            )?;
+            self.postprocess_keyframe(frame, &mut grid, Some(image_region))?;
+            sink(grid)?;
+
+            // Drop LF groups which are not referenced by following bands, keeping one group row
+            // of margin for filters.
+            let next_top = band.bottom() as u32 / frame_header.upsampling;
+            let keep_from = next_top.saturating_sub(frame_header.group_dim());
+            cache
+                .lf_groups
+                .retain(|&idx, _| (idx / lf_groups_per_row + 1) * lf_group_dim > keep_from);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the region of the image in full resolution which corresponds to `image_region` of
+    /// the image downscaled by `factor`.
+    ///
+    /// Both regions are in the coordinates with orientation applied. Returned region is clipped to
+    /// the image.
+    pub fn full_resolution_region(&self, factor: u32, image_region: Region) -> Result<Region> {
+        let shift = downscale::downscale_shift(factor)?;
+        let region =
+            downscale::downscaled_region_to_canvas(&self.image_header, Some(image_region), shift);
+        Ok(downscale::canvas_region_to_image(
+            &self.image_header,
+            region,
+        ))
+    }
+
+    /// Returns the size of the image downscaled by `factor`, rounded up, with orientation
+    /// applied.
+    pub fn downscaled_size(&self, factor: u32) -> Result<(u32, u32)> {
+        let shift = downscale::downscale_shift(factor)?;
+        let (width, height) = downscale::downscaled_size(
+            self.image_header.width_with_orientation(),
+            self.image_header.height_with_orientation(),
+            shift,
+        );
+        Ok((width, height))
+    }
+
+    /// Decodes the keyframe into integer samples of Modular channels, without conversion to
+    /// floating point samples.
+    ///
+    /// Returned channels are color channels (one for grayscale images, three otherwise) followed
+    /// by extra channels, in the original bit depth of each channel. Orientation is not applied.
+    ///
+    /// Only self-contained lossless Modular frames are supported: the frame should cover the
+    /// entire image without referencing other frames, and should not use XYB, YCbCr, upsampling,
+    /// restoration filters or image features. Samples should be integers.
+    pub fn render_keyframe_integer(
+        &self,
+        keyframe_idx: usize,
+    ) -> Result<Vec<jxl_grid::SimpleGrid<i32>>> {
+        let frame = self.lossless_keyframe(keyframe_idx)?;
+        let frame_header = frame.header();
+        let mut cache = RenderCache::new(frame);
+        let region = Region::with_size(frame_header.width, frame_header.height);
+        let gmodular = modular::decode_modular(frame, &mut cache, region, true, 0, &self.pool)?;
+        let Some(image) = gmodular.modular.into_image() else {
+            return Ok(Vec::new());
+        };
+        Ok(image.into_image_channels())
+    }
+
+    /// Decodes the keyframe into the palette and the index channel, if the keyframe is coded with
+    /// a single palette transform covering color channels.
+    ///
+    /// The palette may cover some of the extra channels following color channels. Returned
+    /// channels are integer samples of the remaining extra channels.
+    ///
+    /// The same requirements as [`render_keyframe_integer`][Self::render_keyframe_integer] apply.
+    pub fn render_keyframe_indexed(
+        &self,
+        keyframe_idx: usize,
+    ) -> Result<(
+        jxl_modular::image::PaletteImage,
+        Vec<jxl_grid::SimpleGrid<i32>>,
+    )> {
+        let frame = self.lossless_keyframe(keyframe_idx)?;
+        let frame_header = frame.header();
+        let num_color_channels = frame.image_header().metadata.encoded_color_channels();
+        let mut cache = RenderCache::new(frame);
+        let region = Region::with_size(frame_header.width, frame_header.height);
+        let gmodular = modular::decode_modular(frame, &mut cache, region, false, 0, &self.pool)?;
+        let image = gmodular.modular.into_image().ok_or(Error::NotSupported(
+            "indexed output of frame without palette",
+        ))?;
+
+        let palette = image
+            .single_palette()?
+            .filter(|palette| {
+                palette.first_channel() == 0
+                    && palette.num_channels() as usize >= num_color_channels
+            })
+            .ok_or(Error::NotSupported(
+                "indexed output of frame without single palette over color channels",
+            ))?;
+        let mut channels = image.into_image_channels();
+        channels.drain(..palette.num_channels() as usize);
+        Ok((palette, channels))
+    }
+
+    /// Decodes quantized coefficients and block structure of the VarDCT keyframe.
+    ///
+    /// The keyframe should be loaded completely.
+    pub fn keyframe_vardct_coefficients(&self, keyframe_idx: usize) -> Result<VarDctCoefficients> {
+        let idx = self.keyframe_frame_idx(keyframe_idx)?;
+        let frame = &*self.frames[idx];
+        if frame.header().encoding != Encoding::VarDct {
+            return Err(Error::NotSupported("coefficients of Modular frame"));
+        }
+
+        vardct::decode_coefficients(frame, &self.pool)
+    }
+
+    /// Renders a false-colour visualisation of the keyframe, in RGB with samples in the range of
+    /// `[0, 1]`.
+    ///
+    /// The visualisation has the size of color channels of the frame, before upsampling. The
+    /// keyframe should be loaded completely for views other than
+    /// [`DebugView::GroupBoundaries`].
+    pub fn render_keyframe_debug(
+        &self,
+        keyframe_idx: usize,
+        view: DebugView,
+    ) -> Result<[jxl_grid::SimpleGrid<f32>; 3]> {
+        let idx = self.keyframe_frame_idx(keyframe_idx)?;
+        let frame = &*self.frames[idx];
+
+        debug_view::render_debug_view(frame, view, &self.pool)
+    }
+
+    /// Returns the keyframe if it can be decoded into integer samples exactly.
+    fn lossless_keyframe(&self, keyframe_idx: usize) -> Result<&IndexedFrame> {
+        let idx = self.keyframe_frame_idx(keyframe_idx)?;
+        let frame = &*self.frames[idx];
+        let image_header = frame.image_header();
+        let metadata = &image_header.metadata;
+        let frame_header = frame.header();
+
+        if frame_header.encoding != Encoding::Modular {
+            return Err(Error::NotSupported("integer output of VarDCT frame"));
+        }
+        if metadata.xyb_encoded || frame_header.do_ycbcr {
+            return Err(Error::NotSupported("integer output of XYB or YCbCr frame"));
+        }
+        if self.frame_deps[idx].indices().next().is_some()
+            || frame_header.x0 != 0
+            || frame_header.y0 != 0
+            || frame_header.width != image_header.size.width
+            || frame_header.height != image_header.size.height
+        {
+            return Err(Error::NotSupported(
+                "integer output of frame depending on other frames",
+            ));
+        }
+        let has_upsampling = frame_header.upsampling != 1
+            || frame_header.ec_upsampling.iter().any(|&u| u != 1)
+            || metadata.ec_info.iter().any(|ec| ec.dim_shift != 0);
+        if has_upsampling {
+            return Err(Error::NotSupported("integer output of upsampled frame"));
+        }
+        let flags = frame_header.flags;
+        if frame_header.restoration_filter.gab.enabled()
+            || frame_header.restoration_filter.epf.enabled()
+            || flags.noise()
+            || flags.patches()
+            || flags.splines()
+        {
+            return Err(Error::NotSupported(
+                "integer output of frame with filters or features",
+            ));
+        }
+        let is_integer = std::iter::once(metadata.bit_depth)
+            .chain(metadata.ec_info.iter().map(|ec| ec.bit_depth))
+            .all(|bit_depth| matches!(bit_depth, BitDepth::IntegerSample { .. }));
+        if !is_integer {
+            return Err(Error::NotSupported("integer output of float samples"));
+        }
+
+        Ok(frame)
+    }
+
     pub fn render_loading_keyframe(
         &mut self,
         image_region: Option<Region>,
@@ -607,6 +970,7 @@ impl RenderContext {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn load_lf_groups(
     frame: &IndexedFrame,
     lf_global_vardct: Option<&LfGlobalVarDct>,
@@ -614,6 +978,7 @@ fn load_lf_groups(
     global_ma_config: Option<&MaConfig>,
     mlf_groups: Vec<TransformedModularSubimage>,
     lf_region: Region,
+    redecode_modular: bool,
     pool: &JxlThreadPool,
 ) -> Result<()> {
     #[derive(Default)]
@@ -640,8 +1005,10 @@ fn load_lf_groups(
         })
         .zip(mlf_groups)
         .filter_map(|(job, modular)| {
+            // Modular LF channels decoded into the discarded image of a previous render are decoded
+            // again, so that those are available in the image of this render.
             let loaded = lf_groups.get(&job.idx).map(|g| !g.partial).unwrap_or(false);
-            if loaded {
+            if loaded && !(redecode_modular && modular.is_some()) {
                 return None;
             }
 
@@ -1,7 +1,13 @@
-use jxl_frame::{data::GlobalModular, FrameHeader};
+use jxl_frame::{
+    data::{GlobalModular, TocGroupKind},
+    FrameHeader,
+};
 use jxl_grid::SimpleGrid;
 use jxl_image::BitDepth;
-use jxl_modular::{image::TransformedModularSubimage, ChannelShift};
+use jxl_modular::{
+    image::{ChannelSection, TransformedModularSubimage},
+    ChannelShift,
+};
 
 use crate::{region::ImageWithRegion, Error, IndexedFrame, Region, RenderCache, Result};
 
@@ -9,14 +15,91 @@ pub(crate) fn render_modular(
     frame: &IndexedFrame,
     cache: &mut RenderCache,
     region: Region,
+    downscale_shift: u32,
     pool: &jxl_threadpool::JxlThreadPool,
 ) -> Result<(ImageWithRegion, GlobalModular)> {
     let image_header = frame.image_header();
     let frame_header = frame.header();
-    let tracker = frame.alloc_tracker();
     let metadata = &image_header.metadata;
     let xyb_encoded = image_header.metadata.xyb_encoded;
 
+    let jpeg_upsampling = frame_header.jpeg_upsampling;
+    let shifts_cbycr =
+        [0, 1, 2].map(|idx| ChannelShift::from_jpeg_upsampling(jpeg_upsampling, idx));
+    let channels = metadata.encoded_color_channels();
+
+    let bit_depth = metadata.bit_depth;
+    let mut fb_xyb =
+        ImageWithRegion::from_region_and_tracker(channels, region, frame.alloc_tracker())?;
+
+    let mut gmodular = decode_modular(frame, cache, region, true, downscale_shift, pool)?;
+    let lf_global = cache.lf_global.as_ref().unwrap();
+    let modular_image = gmodular.modular.image_mut().unwrap();
+
+    tracing::trace_span!("Convert to float samples", xyb_encoded).in_scope(|| -> Result<_> {
+        let channel_data = modular_image.image_channels();
+        for ((g, shift), buffer) in channel_data
+            .iter()
+            .zip(shifts_cbycr)
+            .zip(fb_xyb.buffer_mut())
+        {
+            let region = region.downsample_separate(shift.hshift() as u32, shift.vshift() as u32);
+            copy_modular_groups(g, buffer, region, bit_depth, xyb_encoded);
+        }
+
+        if channels == 1 {
+            fb_xyb.add_channel()?;
+            fb_xyb.add_channel()?;
+            let fb_xyb = fb_xyb.buffer_mut();
+            fb_xyb[1] = fb_xyb[0].try_clone()?;
+            fb_xyb[2] = fb_xyb[0].try_clone()?;
+        }
+        if xyb_encoded {
+            let fb_xyb = fb_xyb.buffer_mut();
+            // Make Y'X'B' to X'Y'B'
+            fb_xyb.swap(0, 1);
+            let [x, y, b] = fb_xyb else { panic!() };
+            let x = x.buf_mut();
+            let y = y.buf_mut();
+            let b = b.buf_mut();
+            for ((x, y), b) in x.iter_mut().zip(y).zip(b) {
+                *b += *y;
+                *x *= lf_global.lf_dequant.m_x_lf_unscaled();
+                *y *= lf_global.lf_dequant.m_y_lf_unscaled();
+                *b *= lf_global.lf_dequant.m_b_lf_unscaled();
+            }
+        }
+
+        Ok(())
+    })?;
+
+    Ok((fb_xyb, gmodular))
+}
+
+/// Decodes Modular image of the frame within the region, and applies inverse transforms.
+///
+/// Decoded samples are left as integers, which can be accessed through the returned
+/// [`GlobalModular`]. If the image is squeezed and some of the groups are not loaded yet, squeeze
+/// steps are applied only up to the available resolution, and the result is upsampled with
+/// nearest neighbor.
+///
+/// If `downscale_shift` is nonzero, squeeze steps are applied only up to the resolution
+/// downscaled by `1 << downscale_shift`, even if the image is fully loaded. The result is
+/// upsampled in the same way.
+///
+/// If `apply_transforms` is `false`, inverse transforms are not applied, leaving samples as
+/// decoded from the bitstream.
+pub(crate) fn decode_modular(
+    frame: &IndexedFrame,
+    cache: &mut RenderCache,
+    region: Region,
+    apply_transforms: bool,
+    downscale_shift: u32,
+    pool: &jxl_threadpool::JxlThreadPool,
+) -> Result<GlobalModular> {
+    let frame_header = frame.header();
+    let tracker = frame.alloc_tracker();
+
     let lf_global = if let Some(x) = &cache.lf_global {
         x
     } else {
@@ -29,22 +112,19 @@ pub(crate) fn render_modular(
     let mut gmodular = lf_global.gmodular.try_clone()?;
     let modular_region = compute_modular_region(frame_header, &gmodular, region, false);
 
-    let jpeg_upsampling = frame_header.jpeg_upsampling;
-    let shifts_cbycr =
-        [0, 1, 2].map(|idx| ChannelShift::from_jpeg_upsampling(jpeg_upsampling, idx));
-    let channels = metadata.encoded_color_channels();
-
-    let bit_depth = metadata.bit_depth;
-    let mut fb_xyb =
-        ImageWithRegion::from_region_and_tracker(channels, region, frame.alloc_tracker())?;
-
     let modular_image = gmodular.modular.image_mut().unwrap();
     let groups = modular_image.prepare_groups(frame.pass_shifts())?;
     let lf_group_image = groups.lf_groups;
     let pass_group_image = groups.pass_groups;
+    let pass_loaded = pass_group_image
+        .iter()
+        .map(|_| std::sync::atomic::AtomicBool::new(true))
+        .collect::<Vec<_>>();
 
     tracing::trace_span!("Decode").in_scope(|| {
         let result = std::sync::RwLock::new(Result::Ok(()));
+        let redecode_modular = cache.lf_groups_modular_discarded;
+        cache.lf_groups_modular_discarded |= !lf_group_image.is_empty();
         pool.scope(|scope| {
             let lf_groups = &mut cache.lf_groups;
             scope.spawn(|_| {
@@ -55,6 +135,7 @@ pub(crate) fn render_modular(
                     gmodular.ma_config.as_ref(),
                     lf_group_image,
                     modular_region.downsample(3),
+                    redecode_modular,
                     pool,
                 );
                 if r.is_err() {
@@ -111,32 +192,51 @@ pub(crate) fn render_modular(
                      group_idx,
                      modular,
                  }| {
+                    let loaded = &pass_loaded[pass_idx as usize];
                     let bitstream = match frame.pass_group_bitstream(pass_idx, group_idx) {
                         Some(Ok(bitstream)) => bitstream,
                         Some(Err(e)) => {
                             *result.write().unwrap() = Err(e.into());
                             return;
                         }
-                        None => return,
+                        None => {
+                            loaded.store(false, std::sync::atomic::Ordering::Relaxed);
+                            return;
+                        }
                     };
 
                     let allow_partial = bitstream.partial;
-                    let mut bitstream = bitstream.bitstream;
+                    if allow_partial {
+                        loaded.store(false, std::sync::atomic::Ordering::Relaxed);
+                    }
+                    let mut bitstream = bitstream.bitstream();
                     let global_ma_config = gmodular.ma_config.as_ref();
                     let result = &result;
-                    let r = jxl_frame::data::decode_pass_group_modular(
-                        &mut bitstream,
-                        frame_header,
-                        global_ma_config,
+                    let group = TocGroupKind::GroupPass {
                         pass_idx,
                         group_idx,
-                        modular,
-                        allow_partial,
-                        tracker,
-                        pool,
-                    );
-                    if !allow_partial && r.is_err() {
-                        *result.write().unwrap() = r.map_err(From::from);
+                    };
+                    let r = frame.record_group_stats(group, || {
+                        jxl_frame::data::decode_pass_group_modular(
+                            &mut bitstream,
+                            frame_header,
+                            global_ma_config,
+                            pass_idx,
+                            group_idx,
+                            modular,
+                            allow_partial,
+                            tracker,
+                            pool,
+                        )
+                    });
+                    if let (false, Err(e)) = (allow_partial, r) {
+                        let e = frame.locate_pass_group_error(
+                            e,
+                            pass_idx,
+                            group_idx,
+                            bitstream.num_read_bits(),
+                        );
+                        *result.write().unwrap() = Err(e.into());
                     }
                 },
             );
@@ -144,48 +244,51 @@ pub(crate) fn render_modular(
         result.into_inner().unwrap()
     })?;
 
-    tracing::trace_span!("Inverse Modular transform").in_scope(|| {
-        modular_image.prepare_subimage().unwrap().finish(pool);
-    });
+    if !apply_transforms {
+        return Ok(gmodular);
+    }
 
-    tracing::trace_span!("Convert to float samples", xyb_encoded).in_scope(|| -> Result<_> {
-        let channel_data = modular_image.image_channels();
-        for ((g, shift), buffer) in channel_data
-            .iter()
-            .zip(shifts_cbycr)
-            .zip(fb_xyb.buffer_mut())
-        {
-            let region = region.downsample_separate(shift.hshift() as u32, shift.vshift() as u32);
-            copy_modular_groups(g, buffer, region, bit_depth, xyb_encoded);
-        }
+    let lf_region = (
+        modular_region.left.max(0) as u32,
+        modular_region.top.max(0) as u32,
+        modular_region.width,
+        modular_region.height,
+    );
+    let lf_groups_loaded = (0..frame_header.num_lf_groups())
+        .filter(|&idx| frame_header.is_lf_group_collides_region(idx, lf_region))
+        .all(|idx| cache.lf_groups.get(&idx).is_some_and(|g| !g.partial));
+    let pass_loaded = pass_loaded
+        .into_iter()
+        .map(|loaded| loaded.into_inner())
+        .collect::<Vec<_>>();
+    let is_loaded = |section| match section {
+        ChannelSection::Global => true,
+        ChannelSection::LfGroups => lf_groups_loaded,
+        ChannelSection::PassGroups(pass_idx) => pass_loaded[pass_idx as usize],
+    };
+    let fully_loaded = lf_groups_loaded && pass_loaded.iter().all(|&loaded| loaded);
 
-        if channels == 1 {
-            fb_xyb.add_channel()?;
-            fb_xyb.add_channel()?;
-            let fb_xyb = fb_xyb.buffer_mut();
-            fb_xyb[1] = fb_xyb[0].try_clone()?;
-            fb_xyb[2] = fb_xyb[0].try_clone()?;
-        }
-        if xyb_encoded {
-            let fb_xyb = fb_xyb.buffer_mut();
-            // Make Y'X'B' to X'Y'B'
-            fb_xyb.swap(0, 1);
-            let [x, y, b] = fb_xyb else { panic!() };
-            let x = x.buf_mut();
-            let y = y.buf_mut();
-            let b = b.buf_mut();
-            for ((x, y), b) in x.iter_mut().zip(y).zip(b) {
-                *b += *y;
-                *x *= lf_global.lf_dequant.m_x_lf_unscaled();
-                *y *= lf_global.lf_dequant.m_y_lf_unscaled();
-                *b *= lf_global.lf_dequant.m_b_lf_unscaled();
-            }
+    tracing::trace_span!("Inverse Modular transform").in_scope(|| -> Result<_> {
+        if modular_image.has_squeeze() && (!fully_loaded || downscale_shift > 0) {
+            let preview = modular_image.finish_preview_downscaled(
+                frame.pass_shifts(),
+                is_loaded,
+                downscale_shift,
+                tracker,
+                pool,
+            )?;
+            tracing::debug!(
+                hshift = preview.hshift(),
+                vshift = preview.vshift(),
+                "Squeezed image partially loaded or downscaled, rendered preview"
+            );
+        } else {
+            modular_image.prepare_subimage().unwrap().finish(pool);
         }
-
         Ok(())
     })?;
 
-    Ok((fb_xyb, gmodular))
+    Ok(gmodular)
 }
 
 #[inline]
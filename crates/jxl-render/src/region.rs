@@ -325,6 +325,29 @@ impl ImageWithRegion {
         Ok(out)
     }
 
+    /// Splits the image at row `y` of the region, and returns rows from `y` in a new image.
+    ///
+    /// Rows are moved without cloning the whole image; see [`SimpleGrid::split_off_rows`].
+    pub fn split_off_rows(&mut self, y: u32) -> Self {
+        let y = y.min(self.region.height);
+        let buffer = self
+            .buffer
+            .iter_mut()
+            .map(|grid| grid.split_off_rows(y as usize))
+            .collect();
+        let region = Region {
+            top: self.region.top + y as i32,
+            height: self.region.height - y,
+            ..self.region
+        };
+        self.region.height = y;
+        Self {
+            region,
+            buffer,
+            tracker: self.tracker.clone(),
+        }
+    }
+
     pub(crate) fn clone_region_channel(
         &self,
         out_region: Region,
@@ -386,15 +409,31 @@ impl ImageWithRegion {
         &mut self,
         frame_header: &jxl_frame::FrameHeader,
     ) -> Vec<(u32, [CutGrid<'_, f32>; 3])> {
+        self.groups_with_group_id_downscaled(frame_header, 0)
+    }
+
+    /// Splits the image, which is the frame downscaled by `1 << shift`, into groups.
+    ///
+    /// Every channel has the same resolution if `shift` is not zero.
+    pub(crate) fn groups_with_group_id_downscaled(
+        &mut self,
+        frame_header: &jxl_frame::FrameHeader,
+        shift: u32,
+    ) -> Vec<(u32, [CutGrid<'_, f32>; 3])> {
+        let jpeg_upsampling = if shift == 0 {
+            frame_header.jpeg_upsampling
+        } else {
+            [0; 3]
+        };
         let shifts_cbycr: [_; 3] = std::array::from_fn(|idx| {
-            jxl_modular::ChannelShift::from_jpeg_upsampling(frame_header.jpeg_upsampling, idx)
+            jxl_modular::ChannelShift::from_jpeg_upsampling(jpeg_upsampling, idx)
         });
 
         let [fb_x, fb_y, fb_b, ..] = self.buffer.as_mut_slice() else {
             panic!();
         };
 
-        let group_dim = frame_header.group_dim();
+        let group_dim = frame_header.group_dim() >> shift;
         let base_group_x = self.region.left as u32 / group_dim;
         let base_group_y = self.region.top as u32 / group_dim;
         let width = self.region.width;
@@ -15,6 +15,10 @@ pub struct RenderCache {
     pub(crate) lf_global: Option<LfGlobal>,
     pub(crate) hf_global: Option<HfGlobal>,
     pub(crate) lf_groups: HashMap<u32, LfGroup>,
+    /// Whether cached LF groups have decoded their Modular LF channels into the image of a
+    /// previous render. The image isn't kept, so those groups are decoded again if the cache is
+    /// reused.
+    pub(crate) lf_groups_modular_discarded: bool,
 }
 
 impl RenderCache {
@@ -36,6 +40,7 @@ impl RenderCache {
             lf_global: None,
             hf_global: None,
             lf_groups: HashMap::new(),
+            lf_groups_modular_discarded: false,
         }
     }
 }
@@ -0,0 +1,522 @@
+use std::collections::HashMap;
+
+use jxl_frame::data::{
+    GlobalModular, LfGlobal, LfGroup, PassGroupParams, PassGroupParamsVardct, TocGroupKind,
+};
+use jxl_grid::{CutGrid, SimpleGrid};
+use jxl_modular::ChannelShift;
+use jxl_vardct::{BlockInfo, LfChannelCorrelation, Quantizer, TransformType};
+
+use crate::{region::ImageWithRegion, Error, IndexedFrame, Region, Result};
+
+/// Varblock of a VarDCT frame.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Varblock {
+    left: u32,
+    top: u32,
+    transform_type: TransformType,
+    hf_mul: i32,
+}
+
+impl Varblock {
+    /// Returns the horizontal position of the top-left 8x8 block of the varblock, in 8x8 blocks.
+    #[inline]
+    pub fn left(&self) -> u32 {
+        self.left
+    }
+
+    /// Returns the vertical position of the top-left 8x8 block of the varblock, in 8x8 blocks.
+    #[inline]
+    pub fn top(&self) -> u32 {
+        self.top
+    }
+
+    /// Returns the size of the varblock, in 8x8 blocks.
+    #[inline]
+    pub fn size(&self) -> (u32, u32) {
+        self.transform_type.dct_select_size()
+    }
+
+    /// Returns the transform type of the varblock.
+    #[inline]
+    pub fn transform_type(&self) -> TransformType {
+        self.transform_type
+    }
+
+    /// Returns the quantization multiplier of HF coefficients, `HfMul` in the specification.
+    #[inline]
+    pub fn hf_mul(&self) -> i32 {
+        self.hf_mul
+    }
+}
+
+/// Quantized coefficients and block structure of a VarDCT frame.
+///
+/// Channels are in X, Y, B order. Chroma subsampled channels have smaller grids, and varblocks
+/// which are not aligned to the subsampled grid don't have coefficients in those channels.
+#[derive(Debug)]
+pub struct VarDctCoefficients {
+    jpeg_upsampling: [u32; 3],
+    quantizer: Quantizer,
+    lf_chan_corr: LfChannelCorrelation,
+    varblocks: Vec<Varblock>,
+    lf_quant: Option<[SimpleGrid<i32>; 3]>,
+    hf_quant: [SimpleGrid<i32>; 3],
+    hf_dequant: [SimpleGrid<f32>; 3],
+    x_from_y: SimpleGrid<i32>,
+    b_from_y: SimpleGrid<i32>,
+}
+
+impl VarDctCoefficients {
+    /// Returns the chroma subsampling mode of each channel, `jpeg_upsampling` of the frame header.
+    #[inline]
+    pub fn jpeg_upsampling(&self) -> [u32; 3] {
+        self.jpeg_upsampling
+    }
+
+    /// Returns the global quantizer multipliers.
+    #[inline]
+    pub fn quantizer(&self) -> &Quantizer {
+        &self.quantizer
+    }
+
+    /// Returns the channel correlation parameters used by chroma-from-luma.
+    #[inline]
+    pub fn lf_channel_correlation(&self) -> &LfChannelCorrelation {
+        &self.lf_chan_corr
+    }
+
+    /// Returns the varblocks of the frame in raster order of their top-left 8x8 blocks.
+    #[inline]
+    pub fn varblocks(&self) -> &[Varblock] {
+        &self.varblocks
+    }
+
+    /// Returns the quantized LF image of the given channel, one sample per 8x8 block.
+    ///
+    /// Returns `None` if the LF image is stored in a separate LF frame.
+    #[inline]
+    pub fn lf_quant(&self, channel: usize) -> Option<&SimpleGrid<i32>> {
+        self.lf_quant.as_ref().map(|lf_quant| &lf_quant[channel])
+    }
+
+    /// Returns the quantized HF coefficients of the given channel.
+    ///
+    /// Coefficients of each varblock are stored in the area covered by the varblock, in the layout
+    /// used by the inverse transform. The LF part of each varblock is left as zero.
+    #[inline]
+    pub fn hf_quant(&self, channel: usize) -> &SimpleGrid<i32> {
+        &self.hf_quant[channel]
+    }
+
+    /// Returns the dequantized HF coefficients of the given channel, before chroma-from-luma is
+    /// applied.
+    ///
+    /// Layout of coefficients is the same as [`hf_quant`][Self::hf_quant].
+    #[inline]
+    pub fn hf_dequant(&self, channel: usize) -> &SimpleGrid<f32> {
+        &self.hf_dequant[channel]
+    }
+
+    /// Returns the quantized HF coefficients of the varblock in the given channel, in raster
+    /// order.
+    ///
+    /// Returns `None` if the varblock doesn't have coefficients in the channel due to chroma
+    /// subsampling.
+    pub fn varblock_hf_quant(&self, varblock: &Varblock, channel: usize) -> Option<Vec<i32>> {
+        let (left, top, width, height) = self.varblock_area(varblock, channel)?;
+        let grid = &self.hf_quant[channel];
+        let mut out = Vec::with_capacity(width * height);
+        for y in top..top + height {
+            out.extend_from_slice(&grid.buf()[y * grid.width()..][left..left + width]);
+        }
+        Some(out)
+    }
+
+    /// Returns the dequantized HF coefficients of the varblock in the given channel, in raster
+    /// order.
+    ///
+    /// Returns `None` if the varblock doesn't have coefficients in the channel due to chroma
+    /// subsampling.
+    pub fn varblock_hf_dequant(&self, varblock: &Varblock, channel: usize) -> Option<Vec<f32>> {
+        let (left, top, width, height) = self.varblock_area(varblock, channel)?;
+        let grid = &self.hf_dequant[channel];
+        let mut out = Vec::with_capacity(width * height);
+        for y in top..top + height {
+            out.extend_from_slice(&grid.buf()[y * grid.width()..][left..left + width]);
+        }
+        Some(out)
+    }
+
+    /// Returns the raw chroma-from-luma factors of X and B channels, one per 64x64 tile.
+    #[inline]
+    pub fn cfl_factors(&self) -> (&SimpleGrid<i32>, &SimpleGrid<i32>) {
+        (&self.x_from_y, &self.b_from_y)
+    }
+
+    /// Returns the chroma-from-luma multipliers of X and B channels applied to the varblock.
+    pub fn varblock_cfl(&self, varblock: &Varblock) -> (f32, f32) {
+        let x = varblock.left as usize / 8;
+        let y = varblock.top as usize / 8;
+        let corr = &self.lf_chan_corr;
+        let x_from_y = *self.x_from_y.get(x, y).unwrap();
+        let b_from_y = *self.b_from_y.get(x, y).unwrap();
+        (
+            corr.base_correlation_x + x_from_y as f32 / corr.colour_factor as f32,
+            corr.base_correlation_b + b_from_y as f32 / corr.colour_factor as f32,
+        )
+    }
+
+    fn varblock_area(
+        &self,
+        varblock: &Varblock,
+        channel: usize,
+    ) -> Option<(usize, usize, usize, usize)> {
+        let shift = ChannelShift::from_jpeg_upsampling(self.jpeg_upsampling, channel);
+        let hshift = shift.hshift();
+        let vshift = shift.vshift();
+        let bx = varblock.left as usize >> hshift;
+        let by = varblock.top as usize >> vshift;
+        if (bx << hshift) != varblock.left as usize || (by << vshift) != varblock.top as usize {
+            return None;
+        }
+
+        let (bw, bh) = varblock.size();
+        Some((bx * 8, by * 8, bw as usize * 8, bh as usize * 8))
+    }
+}
+
+/// Decodes quantized coefficients and block structure of a fully loaded VarDCT frame.
+pub(crate) fn decode_coefficients(
+    frame: &IndexedFrame,
+    pool: &jxl_threadpool::JxlThreadPool,
+) -> Result<VarDctCoefficients> {
+    let image_header = frame.image_header();
+    let frame_header = frame.header();
+    let tracker = frame.alloc_tracker();
+
+    let jpeg_upsampling = frame_header.jpeg_upsampling;
+    let shifts_cbycr: [_; 3] =
+        std::array::from_fn(|idx| ChannelShift::from_jpeg_upsampling(jpeg_upsampling, idx));
+
+    let (lf_global, _, lf_groups) = load_block_metadata(frame, pool)?;
+    let lf_global_vardct = lf_global.vardct.as_ref().unwrap();
+    let (width_rounded, height_rounded) = super::rounded_color_size(frame_header);
+    let num_lf_groups = frame_header.num_lf_groups();
+    let hf_global = frame
+        .try_parse_hf_global(Some(&lf_global))
+        .ok_or(Error::IncompleteFrame)??;
+
+    // Quantized coefficients are decoded into integer buffers, so that they are kept exact.
+    let mut hf_quant = Vec::with_capacity(3);
+    for shift in shifts_cbycr {
+        let (width, height) = shift.shift_size((width_rounded as u32, height_rounded as u32));
+        hf_quant.push(SimpleGrid::<i32>::with_alloc_tracker(
+            width as usize,
+            height as usize,
+            tracker,
+        )?);
+    }
+    let mut hf_quant = <[_; 3]>::try_from(hf_quant).unwrap();
+    tracing::trace_span!("Decode HF coefficients").in_scope(|| -> Result<_> {
+        let result = std::sync::RwLock::new(Result::Ok(()));
+        let num_passes = frame_header.passes.num_passes;
+        let global_ma_config = lf_global.gmodular.ma_config.as_ref();
+        pool.for_each_vec(
+            split_groups(&mut hf_quant, frame_header),
+            |(group_idx, mut grid_xyb)| {
+                let lf_group_idx = frame_header.lf_group_idx_from_group_idx(group_idx);
+                let lf_group = &lf_groups[&lf_group_idx];
+                for pass_idx in 0..num_passes {
+                    let bitstream = match frame.pass_group_bitstream(pass_idx, group_idx) {
+                        Some(Ok(bitstream)) if !bitstream.partial => bitstream,
+                        Some(Err(e)) => {
+                            *result.write().unwrap() = Err(e.into());
+                            return;
+                        }
+                        _ => {
+                            *result.write().unwrap() = Err(Error::IncompleteFrame);
+                            return;
+                        }
+                    };
+                    let mut bitstream = bitstream.bitstream();
+
+                    let vardct = Some(PassGroupParamsVardct {
+                        lf_vardct: lf_global_vardct,
+                        hf_global: &hf_global,
+                        hf_coeff_output: &mut grid_xyb,
+                    });
+                    let group = TocGroupKind::GroupPass {
+                        pass_idx,
+                        group_idx,
+                    };
+                    let r = frame.record_group_stats(group, || {
+                        jxl_frame::data::decode_pass_group(
+                            &mut bitstream,
+                            PassGroupParams {
+                                frame_header,
+                                lf_group,
+                                pass_idx,
+                                group_idx,
+                                global_ma_config,
+                                modular: None,
+                                vardct,
+                                allow_partial: false,
+                                tracker,
+                                pool,
+                            },
+                        )
+                    });
+                    if let Err(e) = r {
+                        let e = frame.locate_pass_group_error(
+                            e,
+                            pass_idx,
+                            group_idx,
+                            bitstream.num_read_bits(),
+                        );
+                        *result.write().unwrap() = Err(e.into());
+                        return;
+                    }
+                }
+            },
+        );
+        result.into_inner().unwrap()
+    })?;
+
+    let mut fb_dequant = ImageWithRegion::from_region_and_tracker(
+        3,
+        Region::with_size(width_rounded as u32, height_rounded as u32),
+        tracker,
+    )?;
+    for (out, quant) in fb_dequant.buffer_mut().iter_mut().zip(&hf_quant) {
+        let out_width = out.width();
+        for (out_row, row) in out
+            .buf_mut()
+            .chunks_exact_mut(out_width)
+            .zip(quant.buf().chunks_exact(quant.width()))
+        {
+            for (out, &v) in out_row.iter_mut().zip(row) {
+                *out = v as f32;
+            }
+        }
+    }
+    tracing::trace_span!("Dequantize HF coefficients").in_scope(|| {
+        pool.for_each_vec(
+            fb_dequant.groups_with_group_id(frame_header),
+            |(group_idx, mut grid_xyb)| {
+                super::dequant_hf_varblock_grouped(
+                    &mut grid_xyb,
+                    group_idx,
+                    image_header,
+                    frame_header,
+                    &lf_global,
+                    &lf_groups,
+                    &hf_global,
+                );
+            },
+        );
+    });
+
+    let hf_dequant = collect_channels(&fb_dequant, shifts_cbycr)?;
+
+    let width_blocks = width_rounded / 8;
+    let height_blocks = height_rounded / 8;
+    let width_tiles = width_blocks.div_ceil(8);
+    let height_tiles = height_blocks.div_ceil(8);
+    let mut x_from_y = SimpleGrid::with_alloc_tracker(width_tiles, height_tiles, tracker)?;
+    let mut b_from_y = SimpleGrid::with_alloc_tracker(width_tiles, height_tiles, tracker)?;
+    let mut lf_quant = if frame_header.flags.use_lf_frame() {
+        None
+    } else {
+        let mut lf_quant = Vec::with_capacity(3);
+        for shift in shifts_cbycr {
+            let (width, height) = shift.shift_size((width_blocks as u32, height_blocks as u32));
+            lf_quant.push(SimpleGrid::with_alloc_tracker(
+                width as usize,
+                height as usize,
+                tracker,
+            )?);
+        }
+        Some(<[_; 3]>::try_from(lf_quant).unwrap())
+    };
+
+    let group_dim = frame_header.group_dim() as usize;
+    let lf_groups_per_row = frame_header.lf_groups_per_row();
+    let mut varblocks = Vec::new();
+    for lf_group_idx in 0..num_lf_groups {
+        let lf_group = &lf_groups[&lf_group_idx];
+        let hf_meta = lf_group.hf_meta.as_ref().unwrap();
+        // LF group covers `group_dim` blocks
+        let left = (lf_group_idx % lf_groups_per_row) as usize * group_dim;
+        let top = (lf_group_idx / lf_groups_per_row) as usize * group_dim;
+
+        let block_info = &hf_meta.block_info;
+        for y in 0..block_info.height() {
+            for x in 0..block_info.width() {
+                if let &BlockInfo::Data { dct_select, hf_mul } = block_info.get(x, y).unwrap() {
+                    varblocks.push(Varblock {
+                        left: (left + x) as u32,
+                        top: (top + y) as u32,
+                        transform_type: dct_select,
+                        hf_mul,
+                    });
+                }
+            }
+        }
+
+        copy_grid(&hf_meta.x_from_y, &mut x_from_y, left / 8, top / 8);
+        copy_grid(&hf_meta.b_from_y, &mut b_from_y, left / 8, top / 8);
+
+        if let (Some(lf_quant), Some(lf_coeff)) = (&mut lf_quant, &lf_group.lf_coeff) {
+            // LfQuant is stored in Y, X, B order
+            let channel_data = lf_coeff.lf_quant.image().unwrap().image_channels();
+            for (c, out) in lf_quant.iter_mut().enumerate() {
+                let shift = shifts_cbycr[c];
+                copy_grid(
+                    &channel_data[[1, 0, 2][c]],
+                    out,
+                    left >> shift.hshift(),
+                    top >> shift.vshift(),
+                );
+            }
+        }
+    }
+    varblocks.sort_by_key(|vb| (vb.top, vb.left));
+
+    let mut coeffs = VarDctCoefficients {
+        jpeg_upsampling,
+        quantizer: lf_global_vardct.quantizer.clone(),
+        lf_chan_corr: lf_global_vardct.lf_chan_corr.clone(),
+        varblocks,
+        lf_quant,
+        hf_quant,
+        hf_dequant,
+        x_from_y,
+        b_from_y,
+    };
+
+    // Dequantization matrices of some transforms are not finite in the LF part, which is
+    // overwritten with the LF image when rendering.
+    for idx in 0..coeffs.varblocks.len() {
+        let varblock = &coeffs.varblocks[idx];
+        let (bw, bh) = varblock.size();
+        let areas: [_; 3] = std::array::from_fn(|c| coeffs.varblock_area(varblock, c));
+        for (grid, area) in coeffs.hf_dequant.iter_mut().zip(areas) {
+            let Some((left, top, _, _)) = area else {
+                continue;
+            };
+            let stride = grid.width();
+            for y in top..top + bh as usize {
+                grid.buf_mut()[y * stride + left..][..bw as usize].fill(0.0);
+            }
+        }
+    }
+
+    Ok(coeffs)
+}
+
+/// Loads LfGlobal and every LF group of a VarDCT frame, including HF metadata.
+///
+/// Returned `GlobalModular` has Modular channels of LF groups decoded.
+pub(crate) fn load_block_metadata(
+    frame: &IndexedFrame,
+    pool: &jxl_threadpool::JxlThreadPool,
+) -> Result<(LfGlobal, GlobalModular, HashMap<u32, LfGroup>)> {
+    let frame_header = frame.header();
+    let lf_global = frame
+        .try_parse_lf_global()
+        .ok_or(Error::IncompleteFrame)??;
+    let lf_global_vardct = lf_global.vardct.as_ref().unwrap();
+
+    let (width_rounded, height_rounded) = super::rounded_color_size(frame_header);
+    let lf_region = Region::with_size(width_rounded as u32 / 8, height_rounded as u32 / 8);
+
+    // Modular LF groups are decoded along with LF groups, even though extra channels are not
+    // returned, to keep track of group offsets.
+    let mut gmodular = lf_global.gmodular.try_clone()?;
+    let lf_group_image = gmodular
+        .modular
+        .image_mut()
+        .map(|x| x.prepare_groups(frame.pass_shifts()))
+        .transpose()?
+        .map(|x| x.lf_groups)
+        .unwrap_or_default();
+    let mut lf_groups = HashMap::new();
+    crate::load_lf_groups(
+        frame,
+        Some(lf_global_vardct),
+        &mut lf_groups,
+        gmodular.ma_config.as_ref(),
+        lf_group_image,
+        lf_region,
+        false,
+        pool,
+    )?;
+    let lf_groups_loaded = (0..frame_header.num_lf_groups()).all(|idx| {
+        lf_groups
+            .get(&idx)
+            .is_some_and(|g| !g.partial && g.hf_meta.is_some())
+    });
+    if !lf_groups_loaded {
+        return Err(Error::IncompleteFrame);
+    }
+
+    Ok((lf_global, gmodular, lf_groups))
+}
+
+/// Splits coefficient buffers of the entire frame into groups, paired with group indices.
+fn split_groups<'g>(
+    buffers: &'g mut [SimpleGrid<i32>; 3],
+    frame_header: &jxl_frame::FrameHeader,
+) -> Vec<(u32, [CutGrid<'g, i32>; 3])> {
+    let group_dim = frame_header.group_dim() as usize;
+    let jpeg_upsampling = frame_header.jpeg_upsampling;
+    let [fb_x, fb_y, fb_b] = buffers;
+    let [fb_x, fb_y, fb_b] = [(0usize, fb_x), (1, fb_y), (2, fb_b)].map(|(idx, fb)| {
+        let shift = ChannelShift::from_jpeg_upsampling(jpeg_upsampling, idx);
+        CutGrid::from_simple_grid(fb)
+            .into_groups(group_dim >> shift.hshift(), group_dim >> shift.vshift())
+    });
+
+    fb_x.into_iter()
+        .zip(fb_y)
+        .zip(fb_b)
+        .enumerate()
+        .map(|(group_idx, ((fb_x, fb_y), fb_b))| (group_idx as u32, [fb_x, fb_y, fb_b]))
+        .collect()
+}
+
+fn collect_channels(
+    fb: &ImageWithRegion,
+    shifts_cbycr: [ChannelShift; 3],
+) -> Result<[SimpleGrid<f32>; 3]> {
+    let mut out = Vec::with_capacity(3);
+    for (grid, shift) in fb.buffer().iter().zip(shifts_cbycr) {
+        let (width, height) = shift.shift_size((grid.width() as u32, grid.height() as u32));
+        let width = width as usize;
+        let height = height as usize;
+        let mut channel = SimpleGrid::with_alloc_tracker(width, height, grid.tracker().as_ref())?;
+        for (out_row, row) in channel
+            .buf_mut()
+            .chunks_exact_mut(width)
+            .zip(grid.buf().chunks_exact(grid.width()))
+            .take(height)
+        {
+            for (out, &v) in out_row.iter_mut().zip(row) {
+                *out = v;
+            }
+        }
+        out.push(channel);
+    }
+    Ok(<[_; 3]>::try_from(out).unwrap())
+}
+
+fn copy_grid(from: &SimpleGrid<i32>, to: &mut SimpleGrid<i32>, left: usize, top: usize) {
+    let width = from.width().min(to.width().saturating_sub(left));
+    let height = from.height().min(to.height().saturating_sub(top));
+    for y in 0..height {
+        let from_row = &from.buf()[y * from.width()..][..width];
+        let to_width = to.width();
+        to.buf_mut()[(top + y) * to_width + left..][..width].copy_from_slice(from_row);
+    }
+}
@@ -1,14 +1,17 @@
 use std::collections::HashMap;
 
 use jxl_frame::{
-    data::{GlobalModular, HfGlobal, LfGlobal, LfGroup, PassGroupParams, PassGroupParamsVardct},
+    data::{
+        GlobalModular, HfGlobal, LfGlobal, LfGroup, PassGroupParams, PassGroupParamsVardct,
+        TocGroupKind,
+    },
     FrameHeader,
 };
-use jxl_grid::{CutGrid, SimpleGrid};
+use jxl_grid::{AllocTracker, CutGrid, SimpleGrid};
 use jxl_image::ImageHeader;
 use jxl_modular::{image::TransformedModularSubimage, ChannelShift};
 use jxl_vardct::{
-    BlockInfo, LfChannelCorrelation, LfChannelDequantization, Quantizer, TransformType,
+    BlockInfo, LfChannelCorrelation, LfChannelDequantization, LfCoeff, Quantizer, TransformType,
 };
 
 use crate::{
@@ -16,8 +19,11 @@ use crate::{
     Result,
 };
 
+mod coeff;
 mod transform;
-pub use transform::transform;
+pub(crate) use coeff::{decode_coefficients, load_block_metadata};
+pub use coeff::{VarDctCoefficients, Varblock};
+pub use transform::{transform, transform_reduced};
 
 #[cfg(target_arch = "x86_64")]
 mod x86_64;
@@ -39,6 +45,7 @@ pub(crate) fn render_vardct(
     cache: &mut RenderCache,
     region: Region,
     image_region: Option<Region>,
+    downscale_shift: u32,
     pool: &jxl_threadpool::JxlThreadPool,
 ) -> Result<(ImageWithRegion, GlobalModular)> {
     let span = tracing::span!(tracing::Level::TRACE, "Render VarDCT");
@@ -65,21 +72,7 @@ pub(crate) fn render_vardct(
     let mut gmodular = lf_global.gmodular.try_clone()?;
     let lf_global_vardct = lf_global.vardct.as_ref().unwrap();
 
-    let width = frame_header.color_sample_width() as usize;
-    let height = frame_header.color_sample_height() as usize;
-    let (width_rounded, height_rounded) = {
-        let mut bw = (width + 7) / 8;
-        let mut bh = (height + 7) / 8;
-        let h_upsample = jpeg_upsampling.into_iter().any(|j| j == 1 || j == 2);
-        let v_upsample = jpeg_upsampling.into_iter().any(|j| j == 1 || j == 3);
-        if h_upsample {
-            bw = (bw + 1) / 2 * 2;
-        }
-        if v_upsample {
-            bh = (bh + 1) / 2 * 2;
-        }
-        (bw * 8, bh * 8)
-    };
+    let (width_rounded, height_rounded) = rounded_color_size(frame_header);
 
     let aligned_region = region.container_aligned(frame_header.group_dim());
     let aligned_lf_region = {
@@ -115,7 +108,13 @@ pub(crate) fn render_vardct(
                 height_rounded as u32 / 8,
             ));
 
-    let mut fb_xyb = ImageWithRegion::from_region_and_tracker(3, modular_region, tracker)?;
+    // Coefficients are decoded to per-group buffers if the image is downscaled, and only the
+    // downscaled image is kept.
+    let mut fb_xyb = ImageWithRegion::from_region_and_tracker(
+        3,
+        modular_region.downsample(downscale_shift),
+        tracker,
+    )?;
 
     let mut modular_image = gmodular.modular.image_mut();
     let groups = modular_image
@@ -131,6 +130,8 @@ pub(crate) fn render_vardct(
         ret
     });
 
+    let redecode_modular = cache.lf_groups_modular_discarded;
+    cache.lf_groups_modular_discarded |= !lf_group_image.is_empty();
     let lf_groups = &mut cache.lf_groups;
     tracing::trace_span!("Load LF groups").in_scope(|| {
         crate::load_lf_groups(
@@ -140,6 +141,7 @@ pub(crate) fn render_vardct(
             gmodular.ma_config.as_ref(),
             lf_group_image,
             modular_lf_region,
+            redecode_modular,
             pool,
         )
     })?;
@@ -191,38 +193,14 @@ pub(crate) fn render_vardct(
                 let top = top - modular_lf_region.top as u32;
 
                 if lf_frame.is_none() {
-                    let quantizer = &lf_global_vardct.quantizer;
                     let lf_coeff = lf_group.lf_coeff.as_ref().unwrap();
-                    let channel_data = lf_coeff.lf_quant.image().unwrap().image_channels();
-                    let [lf_x, lf_y, lf_b] = lf_xyb.buffer_mut() else {
-                        panic!()
-                    };
-                    copy_lf_dequant(
-                        lf_x,
-                        left as usize >> shifts_cbycr[0].hshift(),
-                        top as usize >> shifts_cbycr[0].vshift(),
-                        quantizer,
-                        lf_global.lf_dequant.m_x_lf,
-                        &channel_data[1],
-                        lf_coeff.extra_precision,
-                    );
-                    copy_lf_dequant(
-                        lf_y,
-                        left as usize >> shifts_cbycr[1].hshift(),
-                        top as usize >> shifts_cbycr[1].vshift(),
-                        quantizer,
-                        lf_global.lf_dequant.m_y_lf,
-                        &channel_data[0],
-                        lf_coeff.extra_precision,
-                    );
-                    copy_lf_dequant(
-                        lf_b,
-                        left as usize >> shifts_cbycr[2].hshift(),
-                        top as usize >> shifts_cbycr[2].vshift(),
-                        quantizer,
-                        lf_global.lf_dequant.m_b_lf,
-                        &channel_data[2],
-                        lf_coeff.extra_precision,
+                    dequant_lf_group(
+                        lf_xyb.buffer_mut(),
+                        left as usize,
+                        top as usize,
+                        lf_global,
+                        lf_coeff,
+                        shifts_cbycr,
                     );
                 }
 
@@ -262,21 +240,7 @@ pub(crate) fn render_vardct(
         })?;
 
     if lf_frame.is_none() {
-        if !subsampled {
-            tracing::trace_span!("LF CfL").in_scope(|| {
-                chroma_from_luma_lf(lf_xyb.buffer_mut(), &lf_global_vardct.lf_chan_corr);
-            });
-        }
-
-        if !frame_header.flags.skip_adaptive_lf_smoothing() {
-            tracing::trace_span!("Adaptive LF smoothing").in_scope(|| {
-                adaptive_lf_smoothing(
-                    lf_xyb.buffer_mut(),
-                    &lf_global.lf_dequant,
-                    &lf_global_vardct.quantizer,
-                )
-            })?;
-        }
+        finish_lf(lf_xyb.buffer_mut(), frame_header, lf_global)?;
     }
 
     let hf_global = if let Some(x) = &cache.hf_global {
@@ -295,9 +259,14 @@ pub(crate) fn render_vardct(
         }
 
         let num_passes = frame_header.passes.num_passes;
+        // Passes which are not needed for the downscaled image are skipped, unless they have
+        // extra channels.
+        let num_hf_passes = frame_header
+            .passes
+            .num_passes_for_downsample(1 << downscale_shift);
         let groups_per_row = frame_header.groups_per_row();
         let mut it = fb_xyb
-            .groups_with_group_id(frame_header)
+            .groups_with_group_id_downscaled(frame_header, downscale_shift)
             .into_iter()
             .filter_map(|(group_idx, grid_xyb)| {
                 let lf_group_idx = frame_header.lf_group_idx_from_group_idx(group_idx);
@@ -336,10 +305,29 @@ pub(crate) fn render_vardct(
             it,
             |PassGroupJob {
                  group_idx,
-                 mut grid_xyb,
+                 grid_xyb,
                  mut pass_modular,
                  lf_group,
              }| {
+                let mut scratch = None;
+                if downscale_shift != 0 {
+                    match group_scratch(frame_header, group_idx, tracker) {
+                        Ok(x) => scratch = Some(x),
+                        Err(e) => {
+                            *result.write().unwrap() = Err(e);
+                            return;
+                        }
+                    }
+                }
+                let mut out_xyb = None;
+                let mut grid_xyb = match &mut scratch {
+                    Some(scratch) => {
+                        out_xyb = Some(grid_xyb);
+                        scratch.each_mut().map(CutGrid::from_simple_grid)
+                    }
+                    None => grid_xyb,
+                };
+
                 if lf_group.hf_meta.is_none() || hf_global.is_none() {
                     transform_with_lf_grouped(
                         &lf_xyb,
@@ -347,7 +335,11 @@ pub(crate) fn render_vardct(
                         group_idx,
                         frame_header,
                         lf_groups,
+                        downscale_shift,
                     );
+                    if let Some(out_xyb) = &mut out_xyb {
+                        copy_reduced_group(&grid_xyb, out_xyb, shifts_cbycr, downscale_shift);
+                    }
                     return;
                 }
                 let hf_global = hf_global.unwrap();
@@ -370,6 +362,9 @@ pub(crate) fn render_vardct(
                 let global_ma_config = gmodular.ma_config.as_ref();
                 for pass_idx in 0..num_passes {
                     let modular = pass_modular.remove(&pass_idx);
+                    if pass_idx >= num_hf_passes && modular.is_none() {
+                        continue;
+                    }
 
                     let bitstream = match frame.pass_group_bitstream(pass_idx, group_idx) {
                         Some(Ok(bitstream)) => bitstream,
@@ -380,7 +375,7 @@ pub(crate) fn render_vardct(
                         None => continue,
                     };
                     let allow_partial = bitstream.partial;
-                    let mut bitstream = bitstream.bitstream;
+                    let mut bitstream = bitstream.bitstream();
 
                     let vardct = Some(PassGroupParamsVardct {
                         lf_vardct: lf_global_vardct,
@@ -388,23 +383,35 @@ pub(crate) fn render_vardct(
                         hf_coeff_output: &mut grid_xyb,
                     });
 
-                    let r = jxl_frame::data::decode_pass_group(
-                        &mut bitstream,
-                        PassGroupParams {
-                            frame_header,
-                            lf_group,
+                    let group = TocGroupKind::GroupPass {
+                        pass_idx,
+                        group_idx,
+                    };
+                    let r = frame.record_group_stats(group, || {
+                        jxl_frame::data::decode_pass_group(
+                            &mut bitstream,
+                            PassGroupParams {
+                                frame_header,
+                                lf_group,
+                                pass_idx,
+                                group_idx,
+                                global_ma_config,
+                                modular,
+                                vardct,
+                                allow_partial,
+                                tracker,
+                                pool,
+                            },
+                        )
+                    });
+                    if let (false, Err(e)) = (allow_partial, r) {
+                        let e = frame.locate_pass_group_error(
+                            e,
                             pass_idx,
                             group_idx,
-                            global_ma_config,
-                            modular,
-                            vardct,
-                            allow_partial,
-                            tracker,
-                            pool,
-                        },
-                    );
-                    if !allow_partial && r.is_err() {
-                        *result.write().unwrap() = r.map_err(From::from);
+                            bitstream.num_read_bits(),
+                        );
+                        *result.write().unwrap() = Err(e.into());
                     }
                 }
 
@@ -429,14 +436,19 @@ pub(crate) fn render_vardct(
                         group_idx,
                         frame_header,
                         lf_groups,
+                        downscale_shift,
                     );
                 }
+                if let Some(out_xyb) = &mut out_xyb {
+                    copy_reduced_group(&grid_xyb, out_xyb, shifts_cbycr, downscale_shift);
+                }
             },
         );
 
         result.into_inner().unwrap()
     })?;
 
+    let aligned_region = aligned_region.downsample(downscale_shift);
     if fb_xyb.region() != aligned_region {
         fb_xyb = fb_xyb.clone_intersection(aligned_region)?;
     }
@@ -450,6 +462,175 @@ pub(crate) fn render_vardct(
     Ok((fb_xyb, gmodular))
 }
 
+/// Renders the LF image of a VarDCT frame, which is the frame downscaled by 8, without decoding
+/// HF coefficients.
+///
+/// Every LF group should be loaded, and the frame should not use an LF frame. Returned image
+/// covers color channels rounded up to whole varblocks, and `GlobalModular` has Modular channels of
+/// LF groups decoded.
+pub(crate) fn render_lf_image(
+    frame: &IndexedFrame,
+    pool: &jxl_threadpool::JxlThreadPool,
+) -> Result<(ImageWithRegion, GlobalModular)> {
+    let span = tracing::span!(tracing::Level::TRACE, "Render VarDCT LF image");
+    let _guard = span.enter();
+
+    let frame_header = frame.header();
+    let jpeg_upsampling = frame_header.jpeg_upsampling;
+    let shifts_cbycr: [_; 3] =
+        std::array::from_fn(|idx| ChannelShift::from_jpeg_upsampling(jpeg_upsampling, idx));
+
+    let (lf_global, gmodular, lf_groups) = load_block_metadata(frame, pool)?;
+
+    let (width_rounded, height_rounded) = rounded_color_size(frame_header);
+    let mut lf_xyb = ImageWithRegion::from_region_and_tracker(
+        3,
+        Region::with_size(width_rounded as u32 / 8, height_rounded as u32 / 8),
+        frame.alloc_tracker(),
+    )?;
+    tracing::trace_span!("Copy LFQuant").in_scope(|| {
+        let group_dim = frame_header.group_dim() as usize;
+        let lf_groups_per_row = frame_header.lf_groups_per_row();
+        for (&idx, lf_group) in &lf_groups {
+            let left = (idx % lf_groups_per_row) as usize * group_dim;
+            let top = (idx / lf_groups_per_row) as usize * group_dim;
+            let lf_coeff = lf_group.lf_coeff.as_ref().unwrap();
+            dequant_lf_group(
+                lf_xyb.buffer_mut(),
+                left,
+                top,
+                &lf_global,
+                lf_coeff,
+                shifts_cbycr,
+            );
+        }
+    });
+    finish_lf(lf_xyb.buffer_mut(), frame_header, &lf_global)?;
+
+    Ok((lf_xyb, gmodular))
+}
+
+/// Returns the size of color channels rounded up to whole varblocks, considering chroma
+/// subsampling.
+/// Allocates buffers for the coefficients of a group in full resolution.
+fn group_scratch(
+    frame_header: &FrameHeader,
+    group_idx: u32,
+    tracker: Option<&AllocTracker>,
+) -> Result<[SimpleGrid<f32>; 3]> {
+    let (width_rounded, height_rounded) = rounded_color_size(frame_header);
+    let group_dim = frame_header.group_dim() as usize;
+    let groups_per_row = frame_header.groups_per_row();
+    let left = (group_idx % groups_per_row) as usize * group_dim;
+    let top = (group_idx / groups_per_row) as usize * group_dim;
+    let width = group_dim.min(width_rounded - left) as u32;
+    let height = group_dim.min(height_rounded - top) as u32;
+
+    let mut scratch = Vec::with_capacity(3);
+    for idx in 0..3 {
+        let shift = ChannelShift::from_jpeg_upsampling(frame_header.jpeg_upsampling, idx);
+        let (width, height) = shift.shift_size((width, height));
+        scratch.push(SimpleGrid::with_alloc_tracker(
+            width as usize,
+            height as usize,
+            tracker,
+        )?);
+    }
+    Ok(<[_; 3]>::try_from(scratch).unwrap())
+}
+
+/// Copies a group transformed in reduced resolution to the image downscaled by
+/// `1 << downscale_shift`, where every channel has the same resolution.
+fn copy_reduced_group(
+    grid_xyb: &[CutGrid<'_, f32>; 3],
+    out_xyb: &mut [CutGrid<'_, f32>; 3],
+    shifts_cbycr: [ChannelShift; 3],
+    downscale_shift: u32,
+) {
+    for ((grid, out), shift) in grid_xyb.iter().zip(out_xyb).zip(shifts_cbycr) {
+        // Each downscaled area of varblocks is filled with the same sample.
+        let shift_x = downscale_shift - shift.hshift() as u32;
+        let shift_y = downscale_shift - shift.vshift() as u32;
+        for y in 0..out.height() {
+            let row = grid.get_row(y << shift_y);
+            for (x, v) in out.get_row_mut(y).iter_mut().enumerate() {
+                *v = row[x << shift_x];
+            }
+        }
+    }
+}
+
+fn rounded_color_size(frame_header: &FrameHeader) -> (usize, usize) {
+    let jpeg_upsampling = frame_header.jpeg_upsampling;
+    let width = frame_header.color_sample_width() as usize;
+    let height = frame_header.color_sample_height() as usize;
+    let mut bw = width.div_ceil(8);
+    let mut bh = height.div_ceil(8);
+    let h_upsample = jpeg_upsampling.into_iter().any(|j| j == 1 || j == 2);
+    let v_upsample = jpeg_upsampling.into_iter().any(|j| j == 1 || j == 3);
+    if h_upsample {
+        bw = bw.div_ceil(2) * 2;
+    }
+    if v_upsample {
+        bh = bh.div_ceil(2) * 2;
+    }
+    (bw * 8, bh * 8)
+}
+
+/// Dequantizes LF coefficients of an LF group into `lf_xyb`, where the top-left sample of the LF
+/// group is at `(left, top)` before chroma subsampling.
+fn dequant_lf_group(
+    lf_xyb: &mut [SimpleGrid<f32>],
+    left: usize,
+    top: usize,
+    lf_global: &LfGlobal,
+    lf_coeff: &LfCoeff,
+    shifts_cbycr: [ChannelShift; 3],
+) {
+    let quantizer = &lf_global.vardct.as_ref().unwrap().quantizer;
+    let lf_dequant = &lf_global.lf_dequant;
+    let channel_data = lf_coeff.lf_quant.image().unwrap().image_channels();
+    let [lf_x, lf_y, lf_b] = lf_xyb else { panic!() };
+    // LfQuant is stored in Y, X, B order
+    for (out, m_lf, channel_data, shift) in [
+        (lf_x, lf_dequant.m_x_lf, &channel_data[1], shifts_cbycr[0]),
+        (lf_y, lf_dequant.m_y_lf, &channel_data[0], shifts_cbycr[1]),
+        (lf_b, lf_dequant.m_b_lf, &channel_data[2], shifts_cbycr[2]),
+    ] {
+        copy_lf_dequant(
+            out,
+            left >> shift.hshift(),
+            top >> shift.vshift(),
+            quantizer,
+            m_lf,
+            channel_data,
+            lf_coeff.extra_precision,
+        );
+    }
+}
+
+/// Applies LF chroma-from-luma and adaptive LF smoothing to dequantized LF image.
+fn finish_lf(
+    lf_xyb: &mut [SimpleGrid<f32>],
+    frame_header: &FrameHeader,
+    lf_global: &LfGlobal,
+) -> Result<()> {
+    let lf_global_vardct = lf_global.vardct.as_ref().unwrap();
+    let subsampled = frame_header.jpeg_upsampling.into_iter().any(|x| x != 0);
+    if !subsampled {
+        tracing::trace_span!("LF CfL").in_scope(|| {
+            chroma_from_luma_lf(lf_xyb, &lf_global_vardct.lf_chan_corr);
+        });
+    }
+
+    if !frame_header.flags.skip_adaptive_lf_smoothing() {
+        tracing::trace_span!("Adaptive LF smoothing").in_scope(|| {
+            adaptive_lf_smoothing(lf_xyb, &lf_global.lf_dequant, &lf_global_vardct.quantizer)
+        })?;
+    }
+    Ok(())
+}
+
 pub fn copy_lf_dequant(
     out: &mut SimpleGrid<f32>,
     left: usize,
@@ -698,6 +879,7 @@ pub fn transform_with_lf_grouped(
     group_idx: u32,
     frame_header: &FrameHeader,
     lf_groups: &HashMap<u32, LfGroup>,
+    downscale_shift: u32,
 ) {
     use TransformType::*;
 
@@ -810,7 +992,13 @@ pub fn transform_with_lf_grouped(
                 }
 
                 let mut block = coeff.subgrid_mut(left..(left + bw * 8), top..(top + bh * 8));
-                transform(&mut block, dct_select);
+                if downscale_shift == 0 {
+                    transform(&mut block, dct_select);
+                } else {
+                    let shift_x = downscale_shift.saturating_sub(hshift as u32);
+                    let shift_y = downscale_shift.saturating_sub(vshift as u32);
+                    transform_reduced(&mut block, dct_select, (shift_x, shift_y));
+                }
             }
         }
     }
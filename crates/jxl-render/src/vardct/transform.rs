@@ -222,6 +222,99 @@ pub fn transform(coeff: &mut CutGrid<'_>, dct_select: TransformType) {
     }
 }
 
+/// Applies inverse transform in reduced resolution, downscaled by `(1 << shift_x, 1 << shift_y)`,
+/// and fills each downscaled area of the block with the resulting sample.
+///
+/// DCT varblocks are transformed with a smaller IDCT using only low frequency coefficients, scaled
+/// so that the result is the box filtered samples of the full IDCT without aliasing of higher
+/// frequencies. Averages of 4x4 quadrants of DCT2, DCT4 and Hornuss varblocks are computed
+/// directly from their DC coefficients. Other varblocks, which are 8x8, are transformed in full
+/// resolution and then box filtered, which costs about the same as computing the averages from the
+/// coefficients.
+pub fn transform_reduced(
+    coeff: &mut CutGrid<'_>,
+    dct_select: TransformType,
+    (shift_x, shift_y): (u32, u32),
+) {
+    use TransformType::*;
+
+    let width = coeff.width();
+    let height = coeff.height();
+    let reduced_width = (width >> shift_x).max(1);
+    let reduced_height = (height >> shift_y).max(1);
+    let mut scratch = vec![0f32; reduced_width * reduced_height];
+
+    if matches!(dct_select, Hornuss | Dct2 | Dct4) && shift_x >= 2 && shift_y >= 2 {
+        // Quadrants are 4x4 blocks with DC coefficients stored in the top-left 2x2 coefficients,
+        // which is the average of the quadrant.
+        aux_idct2_in_place::<2>(coeff);
+        let quadrants = [
+            [coeff.get(0, 0), coeff.get(1, 0)],
+            [coeff.get(0, 1), coeff.get(1, 1)],
+        ];
+        for y in 0..height {
+            let row = &quadrants[y / 4];
+            for (x, v) in coeff.get_row_mut(y)[..width].iter_mut().enumerate() {
+                *v = row[x / 4];
+            }
+        }
+        return;
+    }
+
+    if matches!(
+        dct_select,
+        Hornuss | Dct2 | Dct4 | Dct4x8 | Dct8x4 | Afv0 | Afv1 | Afv2 | Afv3
+    ) {
+        transform(coeff, dct_select);
+        let mul = 1.0 / ((width / reduced_width) * (height / reduced_height)) as f32;
+        for y in 0..height {
+            let row = coeff.get_row(y);
+            let out_row = &mut scratch[(y >> shift_y) * reduced_width..][..reduced_width];
+            for (x, &v) in row[..width].iter().enumerate() {
+                out_row[x >> shift_x] += v * mul;
+            }
+        }
+    } else {
+        let scale_x = reduced_idct_scale(width, reduced_width);
+        let scale_y = reduced_idct_scale(height, reduced_height);
+        for (y, out_row) in scratch.chunks_exact_mut(reduced_width).enumerate() {
+            let row = &coeff.get_row(y)[..reduced_width];
+            for ((out, &v), &scale) in out_row.iter_mut().zip(row).zip(&scale_x) {
+                *out = v * scale * scale_y[y];
+            }
+        }
+        let mut reduced =
+            CutGrid::from_buf(&mut scratch, reduced_width, reduced_height, reduced_width);
+        dct_2d(&mut reduced, DctDirection::Inverse);
+    }
+
+    for y in 0..height {
+        let reduced_row = &scratch[(y >> shift_y) * reduced_width..][..reduced_width];
+        for (x, v) in coeff.get_row_mut(y)[..width].iter_mut().enumerate() {
+            *v = reduced_row[x >> shift_x];
+        }
+    }
+}
+
+/// Returns the factors to multiply to the first `reduced_size` DCT coefficients of size `size`,
+/// so that IDCT of size `reduced_size` results in the box filtered samples of the full IDCT.
+///
+/// Averaging `m = size / reduced_size` samples of a cosine with frequency `k` attenuates it by
+/// `sin(m * t) / (m * sin(t))`, where `t = pi * k / (2 * size)`, compared with the sample at the
+/// center, which is what the smaller IDCT computes. For `m = 2` this is `cos(t)`.
+fn reduced_idct_scale(size: usize, reduced_size: usize) -> Vec<f32> {
+    let m = (size / reduced_size) as f64;
+    (0..reduced_size)
+        .map(|k| {
+            if k == 0 {
+                return 1.0;
+            }
+            let t = std::f64::consts::PI * k as f64 / (2 * size) as f64;
+            ((m * t).sin() / (m * t.sin())) as f32
+        })
+        .collect()
+}
+
 #[allow(clippy::excessive_precision)]
 #[rustfmt::skip]
 const AFV_BASIS: [[f32; 16]; 16] = [
@@ -322,3 +415,70 @@ const AFV_BASIS: [[f32; 16]; 16] = [
         -0.07417504595810233, 0.2191868483885728, -0.25468277124066413, 0.1135498731499429,
     ],
 ];
+
+#[cfg(test)]
+mod tests {
+    use jxl_grid::CutGrid;
+    use jxl_vardct::TransformType;
+
+    #[test]
+    fn reduced_transform_matches_box_filter() {
+        use TransformType::*;
+
+        let mut state = 1u32;
+        let mut next_coeff = move || {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            ((state >> 16) & 0x7fff) as f32 / 32768.0 - 0.5
+        };
+
+        let transform_types = [
+            Dct8, Dct16, Dct32, Dct16x8, Dct8x16, Dct32x8, Dct8x32, Dct32x16, Dct16x32, Dct2, Dct4,
+            Hornuss, Dct4x8, Dct8x4, Afv0, Afv3,
+        ];
+        let shifts = [(1, 1), (1, 0), (0, 1), (2, 2), (2, 1), (1, 2)];
+        for dct_select in transform_types {
+            let (bw, bh) = dct_select.dct_select_size();
+            let (width, height) = (bw as usize * 8, bh as usize * 8);
+            for (shift_x, shift_y) in shifts {
+                // Frequencies higher than the reduced size alias, so only low frequency
+                // coefficients are set for DCT varblocks.
+                let is_dct = dct_select.need_transpose() || bw != bh;
+                let mut coeff = vec![0f32; width * height];
+                for (idx, v) in coeff.iter_mut().enumerate() {
+                    let (x, y) = (idx % width, idx / width);
+                    if !is_dct || (x < width >> shift_x && y < height >> shift_y) {
+                        *v = next_coeff();
+                    }
+                }
+
+                let mut full = coeff.clone();
+                super::transform(
+                    &mut CutGrid::from_buf(&mut full, width, height, width),
+                    dct_select,
+                );
+                let mut reduced = coeff;
+                super::transform_reduced(
+                    &mut CutGrid::from_buf(&mut reduced, width, height, width),
+                    dct_select,
+                    (shift_x, shift_y),
+                );
+
+                let (bw, bh) = (1usize << shift_x, 1usize << shift_y);
+                for y in 0..height {
+                    for x in 0..width {
+                        let (left, top) = (x / bw * bw, y / bh * bh);
+                        let sum = (top..top + bh)
+                            .flat_map(|y| full[y * width..][left..left + bw].iter())
+                            .sum::<f32>();
+                        let expected = sum / (bw * bh) as f32;
+                        let actual = reduced[y * width + x];
+                        assert!(
+                            (actual - expected).abs() < 1e-4,
+                            "{dct_select:?} at ({x}, {y}) with shift ({shift_x}, {shift_y})",
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -551,6 +551,28 @@ impl Bundle<DequantMatrixSetParams<'_, '_, '_>> for DequantMatrixParams {
     }
 }
 
+impl DequantMatrixParams {
+    /// Returns the integer quantization table if the parameters describe a JPEG quantization
+    /// table, that is, raw DCT8 table with denominator of `1 / (8 * 255)`.
+    fn jpeg_quant_table(&self) -> Option<[Vec<i32>; 3]> {
+        let DequantMatrixParamsEncoding::Raw {
+            denominator,
+            params,
+        } = &self.encoding
+        else {
+            return None;
+        };
+        if self.dct_select != TransformType::Dct8
+            || (denominator - 1.0 / (8.0 * 255.0)).abs() > 1e-8
+        {
+            return None;
+        }
+
+        let channel_data = params.image()?.image_channels();
+        Some(std::array::from_fn(|c| channel_data[c].buf().to_vec()))
+    }
+}
+
 impl BundleDefault<TransformType> for DequantMatrixParams {
     fn default_with_context(dct_select: TransformType) -> Self {
         Self::default_with(dct_select)
@@ -562,6 +584,7 @@ impl BundleDefault<TransformType> for DequantMatrixParams {
 pub struct DequantMatrixSet {
     matrices: Vec<[Vec<f32>; 3]>,
     matrices_tr: Vec<[Vec<f32>; 3]>,
+    jpeg_quant_table: Option<[Vec<i32>; 3]>,
 }
 
 impl Bundle<DequantMatrixSetParams<'_, '_, '_>> for DequantMatrixSet {
@@ -594,6 +617,7 @@ impl Bundle<DequantMatrixSetParams<'_, '_, '_>> for DequantMatrixSet {
                 .collect::<Result<_>>()?
         };
 
+        let jpeg_quant_table = param_list[0].jpeg_quant_table();
         let matrices: Vec<_> = param_list
             .into_iter()
             .map(|params| params.into_matrix())
@@ -618,6 +642,7 @@ impl Bundle<DequantMatrixSetParams<'_, '_, '_>> for DequantMatrixSet {
         Ok(Self {
             matrices,
             matrices_tr,
+            jpeg_quant_table,
         })
     }
 }
@@ -651,6 +676,14 @@ impl DequantMatrixSet {
         &self.matrices[idx][channel]
     }
 
+    /// Returns the quantization table of DCT8 used by a recompressed JPEG image, in the raster
+    /// order.
+    ///
+    /// Returns `None` if the DCT8 matrix is not signalled as a JPEG quantization table.
+    pub fn jpeg_quant_table(&self, channel: usize) -> Option<&[i32]> {
+        self.jpeg_quant_table.as_ref().map(|table| &*table[channel])
+    }
+
     /// Returns the transposed dequantization matrix for the given channel and transform type.
     ///
     /// The coefficients is in the raster order.
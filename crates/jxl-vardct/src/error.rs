@@ -66,6 +66,37 @@ impl Error {
             _ => false,
         }
     }
+
+    /// Returns the location where the error occurred, if it's known.
+    pub fn location(&self) -> Option<&jxl_bitstream::ErrorLocation> {
+        match self {
+            Error::Bitstream(e) => e.location(),
+            Error::Decoder(e) => e.location(),
+            Error::Modular(e) => e.location(),
+            _ => None,
+        }
+    }
+
+    /// Returns the mutable reference to the location where the error occurred, if it's known.
+    pub fn location_mut(&mut self) -> Option<&mut jxl_bitstream::ErrorLocation> {
+        match self {
+            Error::Bitstream(e) => e.location_mut(),
+            Error::Decoder(e) => e.location_mut(),
+            Error::Modular(e) => e.location_mut(),
+            _ => None,
+        }
+    }
+}
+
+impl jxl_bitstream::LocateError for Error {
+    fn in_field(self, bundle: &'static str, field: &'static str, bit_offset: usize) -> Self {
+        match self {
+            Error::Bitstream(e) => Error::Bitstream(e.in_field(bundle, field, bit_offset)),
+            Error::Decoder(e) => Error::Decoder(e.in_field(bundle, field, bit_offset)),
+            Error::Modular(e) => Error::Modular(e.in_field(bundle, field, bit_offset)),
+            e => e,
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -17,10 +17,33 @@ pub struct HfCoeffParams<'a, 'b> {
     pub tracker: Option<&'b AllocTracker>,
 }
 
-pub fn write_hf_coeff(
+/// Sample type of HF coefficient buffers.
+///
+/// Decoded coefficients are added to the buffer, so that coefficients of every pass are
+/// accumulated.
+pub trait HfCoeffSample: Copy {
+    /// Adds a quantized coefficient to the sample.
+    fn add_coeff(&mut self, coeff: i32);
+}
+
+impl HfCoeffSample for f32 {
+    #[inline]
+    fn add_coeff(&mut self, coeff: i32) {
+        *self += coeff as f32;
+    }
+}
+
+impl HfCoeffSample for i32 {
+    #[inline]
+    fn add_coeff(&mut self, coeff: i32) {
+        *self = self.wrapping_add(coeff);
+    }
+}
+
+pub fn write_hf_coeff<S: HfCoeffSample>(
     bitstream: &mut Bitstream,
     params: HfCoeffParams,
-    hf_coeff_output: &mut [CutGrid<'_, f32>; 3],
+    hf_coeff_output: &mut [CutGrid<'_, S>; 3],
 ) -> Result<()> {
     const COEFF_FREQ_CONTEXT: [u32; 63] = [
         0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 15, 16, 16, 17, 17, 18, 18, 19, 19,
@@ -223,7 +246,7 @@ pub fn write_hf_coeff(
                             sy * 8 + coeff_coord.1 as usize,
                         )
                     };
-                    *coeff_grid.get_mut(x, y) += coeff as f32;
+                    coeff_grid.get_mut(x, y).add_coeff(coeff);
                     is_prev_coeff_nonzero = true;
                     non_zeros -= 1;
                 }
@@ -1,6 +1,6 @@
 use jxl_bitstream::{Bitstream, Bundle};
 use jxl_grid::{AllocTracker, SimpleGrid};
-use jxl_modular::{MaConfig, Modular, ModularChannelParams, ModularParams};
+use jxl_modular::{MaConfig, MaTreeLimits, Modular, ModularChannelParams, ModularParams};
 
 use crate::{Result, TransformType};
 
@@ -14,6 +14,7 @@ pub struct HfMetadataParams<'ma, 'pool, 'tracker> {
     pub jpeg_upsampling: [u32; 3],
     pub bits_per_sample: u32,
     pub global_ma_config: Option<&'ma MaConfig>,
+    pub ma_tree_limits: Option<MaTreeLimits>,
     pub epf: Option<(f32, [f32; 8])>,
     pub quantizer_global_scale: u32,
     pub tracker: Option<&'tracker AllocTracker>,
@@ -60,6 +61,7 @@ impl Bundle<HfMetadataParams<'_, '_, '_>> for HfMetadata {
             jpeg_upsampling,
             bits_per_sample,
             global_ma_config,
+            ma_tree_limits,
             epf,
             quantizer_global_scale,
             tracker,
@@ -88,7 +90,8 @@ impl Bundle<HfMetadataParams<'_, '_, '_>> for HfMetadata {
             ModularChannelParams::new(bw as u32, bh as u32),
         ];
         let params =
-            ModularParams::with_channels(0, bits_per_sample, channels, global_ma_config, tracker);
+            ModularParams::with_channels(0, bits_per_sample, channels, global_ma_config, tracker)
+                .with_ma_tree_limits(ma_tree_limits);
         let mut modular = Modular::parse(bitstream, params)?;
         let image = modular.image_mut().unwrap();
         let mut subimage = image.prepare_subimage()?;
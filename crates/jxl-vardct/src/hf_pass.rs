@@ -39,6 +39,9 @@ impl Bundle<HfPassParams<'_>> for HfPass {
         let mut decoder = (used_orders != 0)
             .then(|| Decoder::parse(bitstream, 8))
             .transpose()?;
+        if let Some(decoder) = &mut decoder {
+            decoder.set_label("coefficient orders");
+        }
 
         let mut permutation: [_; 13] =
             std::array::from_fn(|_| [Vec::new(), Vec::new(), Vec::new()]);
@@ -58,10 +61,11 @@ impl Bundle<HfPassParams<'_>> for HfPass {
             decoder.finalize()?;
         }
 
-        let hf_dist = Decoder::parse(
+        let mut hf_dist = Decoder::parse(
             bitstream,
             495 * num_hf_presets * hf_block_ctx.num_block_clusters,
         )?;
+        hf_dist.set_label("HF coefficients");
 
         Ok(Self {
             permutation,
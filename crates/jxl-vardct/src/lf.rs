@@ -1,12 +1,14 @@
-use jxl_bitstream::{define_bundle, read_bits, Bitstream, Bundle};
+use jxl_bitstream::{
+    define_bundle, read_bits, write_bits, BitWriter, Bitstream, Bundle, BundleWrite,
+};
 use jxl_grid::AllocTracker;
-use jxl_modular::{ChannelShift, MaConfig, Modular, ModularParams};
+use jxl_modular::{ChannelShift, MaConfig, MaTreeLimits, Modular, ModularParams};
 
 use crate::Result;
 
 define_bundle! {
     /// Dequantization information for each channel.
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct LfChannelDequantization error(crate::Error) {
         all_default: ty(Bool) default(true),
         pub m_x_lf: ty(F16) cond(!all_default) default(1.0 / 32.0),
@@ -15,14 +17,14 @@ define_bundle! {
     }
 
     /// Global quantizer multipliers.
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct Quantizer error(crate::Error) {
         pub global_scale: ty(U32(1 + u(11), 2049 + u(11), 4097 + u(12), 8193 + u(16))),
         pub quant_lf: ty(U32(16, 1 + u(5), 1 + u(8), 1 + u(16))),
     }
 
     /// Channel correlation data, used by chroma-from-luma procedure.
-    #[derive(Debug)]
+    #[derive(Debug, Clone)]
     pub struct LfChannelCorrelation error(crate::Error) {
         all_default: ty(Bool) default(true),
         pub colour_factor: ty(U32(84, 256, 2 + u(8), 258 + u(16))) cond(!all_default) default(84),
@@ -51,7 +53,7 @@ impl LfChannelDequantization {
 }
 
 /// Context information for the entropy decoder of HF coefficients.
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone)]
 pub struct HfBlockContext {
     pub qf_thresholds: Vec<u32>,
     pub lf_thresholds: [Vec<i32>; 3],
@@ -66,13 +68,7 @@ impl<Ctx> Bundle<Ctx> for HfBlockContext {
         let mut qf_thresholds = Vec::new();
         let mut lf_thresholds = [Vec::new(), Vec::new(), Vec::new()];
         let (num_block_clusters, block_ctx_map) = if bitstream.read_bool()? {
-            (
-                15,
-                vec![
-                    0, 1, 2, 2, 3, 3, 4, 5, 6, 6, 6, 6, 6, 7, 8, 9, 9, 10, 11, 12, 13, 14, 14, 14,
-                    14, 14, 7, 8, 9, 9, 10, 11, 12, 13, 14, 14, 14, 14, 14,
-                ],
-            )
+            (15, Self::DEFAULT_BLOCK_CTX_MAP.to_vec())
         } else {
             let mut bsize = 1;
             for thr in &mut lf_thresholds {
@@ -114,6 +110,70 @@ impl<Ctx> Bundle<Ctx> for HfBlockContext {
     }
 }
 
+impl<Ctx> BundleWrite<Ctx> for HfBlockContext {
+    fn write(&self, writer: &mut BitWriter, _: Ctx) -> jxl_bitstream::Result<()> {
+        let is_default = self.qf_thresholds.is_empty()
+            && self.lf_thresholds.iter().all(|thr| thr.is_empty())
+            && self.block_ctx_map == Self::DEFAULT_BLOCK_CTX_MAP
+            && self.num_block_clusters == 15;
+        writer.write_bool(is_default)?;
+        if is_default {
+            return Ok(());
+        }
+
+        let mut bsize = 1;
+        for thr in &self.lf_thresholds {
+            writer.write_bits(4, thr.len() as u32)?;
+            bsize *= thr.len() + 1;
+            for t in thr {
+                write_bits!(
+                    writer,
+                    U32(u(4), 16 + u(8), 272 + u(16), 65808 + u(32)); UnpackSigned,
+                    t
+                )?;
+            }
+        }
+        writer.write_bits(4, self.qf_thresholds.len() as u32)?;
+        bsize *= self.qf_thresholds.len() + 1;
+        for &t in &self.qf_thresholds {
+            write_bits!(
+                writer,
+                U32(u(2), 4 + u(3), 12 + u(5), 44 + u(8)),
+                &t.wrapping_sub(1)
+            )?;
+        }
+
+        if self.block_ctx_map.len() != bsize * 39 {
+            return Err(jxl_bitstream::Error::Unrepresentable(
+                "block context map size mismatch",
+            ));
+        }
+
+        // Only simple cluster map is supported, which can have up to 8 clusters.
+        let max_cluster = self.block_ctx_map.iter().copied().max().unwrap_or(0);
+        let nbits = 8 - max_cluster.leading_zeros();
+        if nbits > 3 {
+            return Err(jxl_bitstream::Error::Unrepresentable(
+                "block context map has too many clusters",
+            ));
+        }
+        writer.write_bool(true)?;
+        writer.write_bits(2, nbits)?;
+        for &cluster in &self.block_ctx_map {
+            writer.write_bits(nbits as usize, cluster as u32)?;
+        }
+        Ok(())
+    }
+}
+
+impl HfBlockContext {
+    #[rustfmt::skip]
+    const DEFAULT_BLOCK_CTX_MAP: [u8; 39] = [
+        0, 1, 2, 2, 3, 3, 4, 5, 6, 6, 6, 6, 6, 7, 8, 9, 9, 10, 11, 12, 13, 14, 14, 14,
+        14, 14, 7, 8, 9, 9, 10, 11, 12, 13, 14, 14, 14, 14, 14,
+    ];
+}
+
 /// Paramters for decoding `LfCoeff`.
 #[derive(Debug)]
 pub struct LfCoeffParams<'ma, 'pool, 'tracker> {
@@ -123,6 +183,7 @@ pub struct LfCoeffParams<'ma, 'pool, 'tracker> {
     pub jpeg_upsampling: [u32; 3],
     pub bits_per_sample: u32,
     pub global_ma_config: Option<&'ma MaConfig>,
+    pub ma_tree_limits: Option<MaTreeLimits>,
     pub allow_partial: bool,
     pub tracker: Option<&'tracker AllocTracker>,
     pub pool: &'pool jxl_threadpool::JxlThreadPool,
@@ -147,6 +208,7 @@ impl Bundle<LfCoeffParams<'_, '_, '_>> for LfCoeff {
             jpeg_upsampling,
             bits_per_sample,
             global_ma_config,
+            ma_tree_limits,
             allow_partial,
             tracker,
             pool,
@@ -168,7 +230,8 @@ impl Bundle<LfCoeffParams<'_, '_, '_>> for LfCoeff {
             channel_shifts,
             global_ma_config,
             tracker,
-        );
+        )
+        .with_ma_tree_limits(ma_tree_limits);
         let mut lf_quant = Modular::parse(bitstream, lf_quant_params)?;
         let image = lf_quant.image_mut().unwrap();
         let mut subimage = image.prepare_subimage()?;